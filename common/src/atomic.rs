@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 #[macro_export]
 macro_rules! may_advance_atomic {
     ($atomic:expr, $val:expr) => {
@@ -10,3 +12,102 @@ macro_rules! may_advance_atomic {
         }
     };
 }
+
+/// [`AtomicF64`] is an [`f64`] that can be safely shared between threads, stored as its bits in an
+/// [`AtomicU64`].
+///
+/// Note: Values are compared by their bits in [`AtomicF64::compare_exchange`], so `0.0` and `-0.0`
+/// differ, while a NaN equals the NaN with the same bits.
+#[derive(Default, Debug)]
+pub struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    pub fn new(val: f64) -> Self {
+        Self(AtomicU64::new(val.to_bits()))
+    }
+
+    pub fn load(&self, order: Ordering) -> f64 {
+        f64::from_bits(self.0.load(order))
+    }
+
+    pub fn store(&self, val: f64, order: Ordering) {
+        self.0.store(val.to_bits(), order)
+    }
+
+    /// Store `new` if the current value is `current`. Returns the previous value, wrapped in `Ok`
+    /// if it is stored.
+    pub fn compare_exchange(
+        &self,
+        current: f64,
+        new: f64,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<f64, f64> {
+        self.0
+            .compare_exchange(current.to_bits(), new.to_bits(), success, failure)
+            .map(f64::from_bits)
+            .map_err(f64::from_bits)
+    }
+
+    /// Add `val` to the current value. Returns the previous value.
+    pub fn fetch_add(&self, val: f64, order: Ordering) -> f64 {
+        let bits = self
+            .0
+            .fetch_update(order, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + val).to_bits())
+            })
+            .unwrap();
+        f64::from_bits(bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_atomic_f64() {
+        let v = AtomicF64::new(1.5);
+        assert_eq!(v.load(Ordering::Relaxed), 1.5);
+        v.store(-2.25, Ordering::Relaxed);
+        assert_eq!(v.fetch_add(0.25, Ordering::Relaxed), -2.25);
+        assert_eq!(v.load(Ordering::Relaxed), -2.0);
+        assert_eq!(
+            v.compare_exchange(1.0, 3.0, Ordering::SeqCst, Ordering::Relaxed),
+            Err(-2.0)
+        );
+        assert_eq!(
+            v.compare_exchange(-2.0, 3.0, Ordering::SeqCst, Ordering::Relaxed),
+            Ok(-2.0)
+        );
+        assert_eq!(v.load(Ordering::Relaxed), 3.0);
+        assert_eq!(AtomicF64::default().load(Ordering::Relaxed), 0.0);
+    }
+
+    #[test]
+    fn test_atomic_f64_concurrent_fetch_add() {
+        const THREADS: usize = 8;
+        const ADDS: usize = 10000;
+
+        let v = Arc::new(AtomicF64::default());
+        let handles = (0..THREADS)
+            .map(|_| {
+                let v = v.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..ADDS {
+                        // Sums of 0.5 are exact, so no increment may be lost.
+                        v.fetch_add(0.5, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(v.load(Ordering::Relaxed), (THREADS * ADDS) as f64 * 0.5);
+    }
+}
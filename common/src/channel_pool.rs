@@ -1,19 +1,72 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::Duration;
 
-use tokio::sync::Mutex;
+use async_trait::async_trait;
+use tokio::sync::{Mutex, MutexGuard, Notify};
+use tokio::time::Instant;
 use tonic::transport::{Channel, Endpoint};
+use tracing::{debug, warn};
 
 use crate::config::Node;
+use crate::LoopWorker;
+
+/// Behaviour of [`ChannelPool`] when a new channel is connected while the pool is full.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BackpressurePolicy {
+    /// Return the new channel without pooling it, and count it as dropped.
+    Drop,
+    /// Wait until a pooled channel is released with [`ChannelPool::release`], or return an error
+    /// after [`ChannelPoolOptions::block_timeout`].
+    Block,
+}
+
+const DEFAULT_RECONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_BLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug)]
+pub struct ChannelPoolOptions {
+    /// Max count of pooled channels.
+    pub capacity: usize,
+    pub backpressure: BackpressurePolicy,
+    /// Max time to wait for a release with [`BackpressurePolicy::Block`].
+    pub block_timeout: Duration,
+    /// Timeout of reconnecting to a node marked unhealthy.
+    pub reconnect_timeout: Duration,
+    /// Min interval between reconnect attempts to a node marked unhealthy.
+    pub reconnect_interval: Duration,
+}
+
+impl Default for ChannelPoolOptions {
+    fn default() -> Self {
+        Self {
+            capacity: usize::MAX,
+            backpressure: BackpressurePolicy::Drop,
+            block_timeout: DEFAULT_BLOCK_TIMEOUT,
+            reconnect_timeout: DEFAULT_RECONNECT_TIMEOUT,
+            reconnect_interval: DEFAULT_RECONNECT_INTERVAL,
+        }
+    }
+}
 
 struct ChannelPoolCore {
     endpoints: BTreeMap<u64, Endpoint>,
     channels: BTreeMap<u64, Channel>,
+    /// Nodes that failed the last health check.
+    unhealthy: BTreeSet<u64>,
+    /// `{ node -> instant }` of the last reconnect attempt to each unhealthy node.
+    last_reconnects: BTreeMap<u64, Instant>,
+    /// Count of channels not pooled for the pool is full.
+    dropped_count: u64,
 }
 
 #[derive(Clone)]
 pub struct ChannelPool {
+    options: ChannelPoolOptions,
     core: Arc<Mutex<ChannelPoolCore>>,
+    /// Notified when a pooled channel is released.
+    released: Arc<Notify>,
 }
 
 fn endpoint(node: &Node) -> Endpoint {
@@ -28,39 +81,449 @@ impl Default for ChannelPool {
 
 impl ChannelPool {
     pub fn with_nodes(nodes: Vec<Node>) -> Self {
+        Self::new(nodes, ChannelPoolOptions::default())
+    }
+
+    pub fn new(nodes: Vec<Node>, options: ChannelPoolOptions) -> Self {
         Self {
+            options,
             core: Arc::new(Mutex::new(ChannelPoolCore {
                 endpoints: BTreeMap::from_iter(
                     nodes.into_iter().map(|node| (node.id, endpoint(&node))),
                 ),
                 channels: BTreeMap::default(),
+                unhealthy: BTreeSet::default(),
+                last_reconnects: BTreeMap::default(),
+                dropped_count: 0,
             })),
+            released: Arc::new(Notify::new()),
         }
     }
 
+    /// Max count of pooled channels.
+    pub fn capacity(&self) -> usize {
+        self.options.capacity
+    }
+
+    /// Count of pooled channels.
+    pub async fn len(&self) -> usize {
+        self.core.lock().await.channels.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.core.lock().await.channels.is_empty()
+    }
+
+    /// Count of channels returned without being pooled for the pool is full, with
+    /// [`BackpressurePolicy::Drop`].
+    pub async fn dropped_count(&self) -> u64 {
+        self.core.lock().await.dropped_count
+    }
+
     pub async fn put_node(&self, node: Node) {
         let mut guard = self.core.lock().await;
         guard.endpoints.insert(node.id, endpoint(&node));
     }
 
+    /// Get the channel to `node`, connect if not connected.
+    ///
+    /// A channel marked unhealthy by the health check is reconnected, see
+    /// [`ChannelPool::reconnect_if_unhealthy`]. If reconnecting fails or is throttled, the stale
+    /// channel is returned, which will try to reconnect by itself on requests. Use
+    /// [`ChannelPool::get_healthy`] to never get a known-dead channel.
     pub async fn get(&self, node: u64) -> anyhow::Result<Channel> {
-        let mut guard = self.core.lock().await;
-        if let Some(channel) = guard.channels.get(&node) {
-            return Ok(channel.clone());
+        match self.reconnect_if_unhealthy(node).await {
+            Ok(Some(channel)) => return Ok(channel),
+            Ok(None) => {}
+            Err(e) => match self.core.lock().await.channels.get(&node) {
+                Some(channel) => {
+                    debug!(
+                        "reconnect to node {} failed, use stale channel: {}",
+                        node, e
+                    );
+                    return Ok(channel.clone());
+                }
+                None => return Err(e),
+            },
         }
-        if let Some(endpoint) = guard.endpoints.get(&node) {
-            let channel = endpoint.connect().await?;
-            guard.channels.insert(node, channel.clone());
+        let mut guard = self.lock_for(node).await?;
+        connect(&mut guard, node, self.options.capacity).await
+    }
+
+    /// Get the channel to `node` like [`ChannelPool::get`], but returns an error instead of a
+    /// channel known to be dead.
+    pub async fn get_healthy(&self, node: u64) -> anyhow::Result<Channel> {
+        if let Some(channel) = self.reconnect_if_unhealthy(node).await? {
             return Ok(channel);
         }
-        Err(anyhow::anyhow!("endpoint of node {} not found", node))
+        let mut guard = self.lock_for(node).await?;
+        connect(&mut guard, node, self.options.capacity).await
+    }
+
+    /// Reconnect to `node` if it is marked unhealthy. Returns `None` if it is not.
+    ///
+    /// The pool is not locked while connecting, and connecting is bounded by the reconnect
+    /// timeout, so gets to other nodes are never blocked by an unreachable node. Reconnect attempts
+    /// to a node are made at most once per reconnect interval, an error is returned in between.
+    async fn reconnect_if_unhealthy(&self, node: u64) -> anyhow::Result<Option<Channel>> {
+        let endpoint = {
+            let mut guard = self.core.lock().await;
+            if !guard.unhealthy.contains(&node) {
+                return Ok(None);
+            }
+            let now = Instant::now();
+            if let Some(last_reconnect) = guard.last_reconnects.get(&node) {
+                if now.duration_since(*last_reconnect) < self.options.reconnect_interval {
+                    return Err(anyhow::anyhow!("reconnect to node {} is throttled", node));
+                }
+            }
+            let endpoint = match guard.endpoints.get(&node) {
+                Some(endpoint) => endpoint.clone(),
+                None => return Err(anyhow::anyhow!("endpoint of node {} not found", node)),
+            };
+            guard.last_reconnects.insert(node, now);
+            endpoint
+        };
+        let channel = tokio::time::timeout(self.options.reconnect_timeout, endpoint.connect())
+            .await
+            .map_err(|_| anyhow::anyhow!("reconnect to node {} timed out", node))??;
+        let mut guard = self.core.lock().await;
+        guard.unhealthy.remove(&node);
+        guard.last_reconnects.remove(&node);
+        guard.channels.insert(node, channel.clone());
+        Ok(Some(channel))
     }
 
+    /// Remove the pooled channel to `node`, and wake up a get blocked by
+    /// [`BackpressurePolicy::Block`].
+    ///
+    /// The pool never evicts channels by itself. The owner of the pool is expected to release the
+    /// channels to nodes it no longer talks to, e.g. nodes removed from the cluster.
     pub async fn release(&self, node: u64) -> anyhow::Result<()> {
         let mut guard = self.core.lock().await;
+        guard.unhealthy.remove(&node);
+        guard.last_reconnects.remove(&node);
         match guard.channels.remove(&node) {
-            Some(_) => Ok(()),
+            Some(_) => {
+                self.released.notify_one();
+                Ok(())
+            }
             None => Err(anyhow::anyhow!("channel to node {} not exists", node)),
         }
     }
+
+    /// Lock the pool for getting the channel to `node`.
+    ///
+    /// With [`BackpressurePolicy::Block`], waits until there is room for a new channel if `node`
+    /// has no pooled channel. Returns an error if no room is made within the block timeout.
+    async fn lock_for(&self, node: u64) -> anyhow::Result<MutexGuard<'_, ChannelPoolCore>> {
+        let deadline = Instant::now() + self.options.block_timeout;
+        loop {
+            let guard = self.core.lock().await;
+            if self.options.backpressure == BackpressurePolicy::Drop
+                || guard.channels.contains_key(&node)
+                || guard.channels.len() < self.options.capacity
+            {
+                return Ok(guard);
+            }
+            drop(guard);
+            // A release between unlocking and waiting leaves a permit, so it won't be missed.
+            if tokio::time::timeout_at(deadline, self.released.notified())
+                .await
+                .is_err()
+            {
+                return Err(anyhow::anyhow!(
+                    "channel pool is full, no channel is released in {:?}",
+                    self.options.block_timeout
+                ));
+            }
+        }
+    }
+
+    /// Probe nodes with channels or marked unhealthy, and update their health marks.
+    async fn health_check(&self, timeout: Duration) {
+        let endpoints = {
+            let guard = self.core.lock().await;
+            guard
+                .endpoints
+                .iter()
+                .filter(|(node, _)| {
+                    guard.channels.contains_key(node) || guard.unhealthy.contains(node)
+                })
+                .map(|(node, endpoint)| (*node, endpoint.clone()))
+                .collect::<Vec<_>>()
+        };
+        for (node, endpoint) in endpoints {
+            let healthy = matches!(
+                tokio::time::timeout(timeout, endpoint.connect()).await,
+                Ok(Ok(_))
+            );
+            let mut guard = self.core.lock().await;
+            if healthy {
+                guard.unhealthy.remove(&node);
+                guard.last_reconnects.remove(&node);
+            } else if guard.unhealthy.insert(node) {
+                warn!("node {} is marked unhealthy", node);
+            }
+        }
+    }
+}
+
+async fn connect(
+    core: &mut ChannelPoolCore,
+    node: u64,
+    capacity: usize,
+) -> anyhow::Result<Channel> {
+    if let Some(channel) = core.channels.get(&node) {
+        return Ok(channel.clone());
+    }
+    if let Some(endpoint) = core.endpoints.get(&node) {
+        let channel = endpoint.connect().await?;
+        if core.channels.len() >= capacity {
+            core.dropped_count += 1;
+            debug!(
+                "channel pool is full, channel to node {} is not pooled",
+                node
+            );
+            return Ok(channel);
+        }
+        core.channels.insert(node, channel.clone());
+        return Ok(channel);
+    }
+    Err(anyhow::anyhow!("endpoint of node {} not found", node))
+}
+
+pub struct ChannelPoolHealthCheckerOptions {
+    pub channel_pool: ChannelPool,
+    /// Interval between health checks, also used as the timeout of probing a node.
+    pub interval: Duration,
+}
+
+/// [`ChannelPoolHealthChecker`] periodically probes the nodes of a [`ChannelPool`] and marks the
+/// unreachable ones unhealthy, so their channels are reconnected on the next get.
+pub struct ChannelPoolHealthChecker {
+    options: ChannelPoolHealthCheckerOptions,
+}
+
+impl ChannelPoolHealthChecker {
+    pub fn new(options: ChannelPoolHealthCheckerOptions) -> Self {
+        Self { options }
+    }
+}
+
+#[async_trait]
+impl LoopWorker for ChannelPoolHealthChecker {
+    fn name(&self) -> &str {
+        "channel pool health checker"
+    }
+
+    async fn run_once(&mut self) -> anyhow::Result<()> {
+        self.options
+            .channel_pool
+            .health_check(self.options.interval)
+            .await;
+        Ok(())
+    }
+
+    fn interval(&self) -> Duration {
+        self.options.interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use test_log::test;
+    use tokio::net::{TcpListener, TcpSocket};
+
+    use super::*;
+
+    fn bind(addr: SocketAddr) -> TcpListener {
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.set_reuseaddr(true).unwrap();
+        socket.bind(addr).unwrap();
+        socket.listen(16).unwrap()
+    }
+
+    async fn assert_connected(listener: &TcpListener) {
+        tokio::time::timeout(Duration::from_secs(1), listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test(tokio::test)]
+    async fn test_health_check() {
+        let listener = bind("127.0.0.1:0".parse().unwrap());
+        let addr = listener.local_addr().unwrap();
+        let pool = ChannelPool::new(
+            vec![Node {
+                id: 1,
+                host: addr.ip().to_string(),
+                port: addr.port(),
+            }],
+            ChannelPoolOptions {
+                reconnect_interval: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+        let mut checker = ChannelPoolHealthChecker::new(ChannelPoolHealthCheckerOptions {
+            channel_pool: pool.clone(),
+            interval: Duration::from_millis(100),
+        });
+
+        pool.get(1).await.unwrap();
+        assert_connected(&listener).await;
+        checker.run_once().await.unwrap();
+        assert_connected(&listener).await;
+        assert!(pool.core.lock().await.unhealthy.is_empty());
+
+        // The node goes down.
+        drop(listener);
+        checker.run_once().await.unwrap();
+        assert!(pool.core.lock().await.unhealthy.contains(&1));
+        assert!(pool.get_healthy(1).await.is_err());
+        // `get` falls back to the stale channel.
+        pool.get(1).await.unwrap();
+
+        // The node comes back, `get` reconnects.
+        let listener = bind(addr);
+        pool.get(1).await.unwrap();
+        assert_connected(&listener).await;
+        assert!(pool.core.lock().await.unhealthy.is_empty());
+        pool.get_healthy(1).await.unwrap();
+
+        // The node goes down and comes back between health checks.
+        drop(listener);
+        checker.run_once().await.unwrap();
+        assert!(pool.get_healthy(1).await.is_err());
+        let listener = bind(addr);
+        checker.run_once().await.unwrap();
+        assert_connected(&listener).await;
+        assert!(pool.core.lock().await.unhealthy.is_empty());
+        pool.get_healthy(1).await.unwrap();
+    }
+
+    #[test(tokio::test)]
+    async fn test_reconnect_throttled() {
+        let listener = bind("127.0.0.1:0".parse().unwrap());
+        let addr = listener.local_addr().unwrap();
+        let pool = ChannelPool::new(
+            vec![Node {
+                id: 1,
+                host: addr.ip().to_string(),
+                port: addr.port(),
+            }],
+            ChannelPoolOptions {
+                reconnect_interval: Duration::from_millis(200),
+                ..Default::default()
+            },
+        );
+        let mut checker = ChannelPoolHealthChecker::new(ChannelPoolHealthCheckerOptions {
+            channel_pool: pool.clone(),
+            interval: Duration::from_millis(100),
+        });
+        pool.get(1).await.unwrap();
+        assert_connected(&listener).await;
+
+        drop(listener);
+        checker.run_once().await.unwrap();
+        assert!(pool.get_healthy(1).await.is_err());
+        // The node comes back, but reconnecting is throttled until the interval elapses.
+        let listener = bind(addr);
+        assert!(pool.get_healthy(1).await.is_err());
+        pool.get(1).await.unwrap();
+        assert!(pool.core.lock().await.unhealthy.contains(&1));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        pool.get_healthy(1).await.unwrap();
+        assert_connected(&listener).await;
+        assert!(pool.core.lock().await.unhealthy.is_empty());
+        assert!(pool.core.lock().await.last_reconnects.is_empty());
+    }
+
+    fn nodes(listeners: &[&TcpListener]) -> Vec<Node> {
+        listeners
+            .iter()
+            .enumerate()
+            .map(|(i, listener)| {
+                let addr = listener.local_addr().unwrap();
+                Node {
+                    id: i as u64 + 1,
+                    host: addr.ip().to_string(),
+                    port: addr.port(),
+                }
+            })
+            .collect()
+    }
+
+    #[test(tokio::test)]
+    async fn test_backpressure_drop() {
+        let l1 = bind("127.0.0.1:0".parse().unwrap());
+        let l2 = bind("127.0.0.1:0".parse().unwrap());
+        let pool = ChannelPool::new(
+            nodes(&[&l1, &l2]),
+            ChannelPoolOptions {
+                capacity: 1,
+                backpressure: BackpressurePolicy::Drop,
+                ..Default::default()
+            },
+        );
+        assert_eq!(pool.capacity(), 1);
+        assert!(pool.is_empty().await);
+
+        pool.get(1).await.unwrap();
+        assert_eq!(pool.len().await, 1);
+        // The pool is full, the channel is returned without being pooled.
+        pool.get(2).await.unwrap();
+        assert_eq!(pool.len().await, 1);
+        assert_eq!(pool.dropped_count().await, 1);
+        pool.get(1).await.unwrap();
+        assert_eq!(pool.dropped_count().await, 1);
+
+        pool.release(1).await.unwrap();
+        pool.get(2).await.unwrap();
+        assert_eq!(pool.len().await, 1);
+        assert_eq!(pool.dropped_count().await, 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_backpressure_block() {
+        let l1 = bind("127.0.0.1:0".parse().unwrap());
+        let l2 = bind("127.0.0.1:0".parse().unwrap());
+        let pool = ChannelPool::new(
+            nodes(&[&l1, &l2]),
+            ChannelPoolOptions {
+                capacity: 1,
+                backpressure: BackpressurePolicy::Block,
+                block_timeout: Duration::from_millis(200),
+                ..Default::default()
+            },
+        );
+
+        pool.get(1).await.unwrap();
+        // Pooled channels are not blocked.
+        pool.get(1).await.unwrap();
+        let mut handle = {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.get(2).await.unwrap() })
+        };
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut handle)
+            .await
+            .is_err());
+
+        pool.release(1).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pool.len().await, 1);
+        assert!(pool.core.lock().await.channels.contains_key(&2));
+        assert_eq!(pool.dropped_count().await, 0);
+
+        // Gets blocked without a release time out.
+        assert!(pool.get(1).await.is_err());
+        assert!(!pool.core.lock().await.channels.contains_key(&1));
+    }
 }
@@ -1,26 +1,44 @@
 use bytes::{Buf, BufMut};
 use serde::Deserialize;
 
-#[derive(Deserialize, Clone, Copy, Debug)]
+pub const ZSTD_MIN_LEVEL: i32 = 1;
+pub const ZSTD_MAX_LEVEL: i32 = 22;
+pub const ZSTD_DEFAULT_LEVEL: i32 = 3;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum CompressionAlgorithm {
     None,
     Lz4,
+    /// Zstd with compression level in `[ZSTD_MIN_LEVEL, ZSTD_MAX_LEVEL]`.
+    Zstd {
+        level: i32,
+    },
 }
 
 impl CompressionAlgorithm {
+    /// Encode the tag of the algorithm.
+    ///
+    /// Note: The zstd level is not encoded, for it is not needed for decompression.
     pub fn encode(&self, buf: &mut impl BufMut) {
-        let v = match self {
-            Self::None => 0,
-            Self::Lz4 => 1,
-        };
-        buf.put_u8(v);
+        buf.put_u8((*self).into());
     }
 
+    /// Decode the tag of the algorithm. Zstd is decoded with [`ZSTD_DEFAULT_LEVEL`].
     pub fn decode(buf: &mut impl Buf) -> Result<Self, anyhow::Error> {
-        match buf.get_u8() {
-            0 => Ok(Self::None),
-            1 => Ok(Self::Lz4),
-            _ => Err(anyhow::anyhow!("not valid compression algorithm")),
+        Self::try_from(buf.get_u8())
+    }
+
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        match self {
+            Self::Zstd { level } if !(ZSTD_MIN_LEVEL..=ZSTD_MAX_LEVEL).contains(level) => {
+                Err(anyhow::anyhow!(
+                    "zstd level {} out of range [{}, {}]",
+                    level,
+                    ZSTD_MIN_LEVEL,
+                    ZSTD_MAX_LEVEL
+                ))
+            }
+            _ => Ok(()),
         }
     }
 }
@@ -30,16 +48,19 @@ impl From<CompressionAlgorithm> for u8 {
         match ca {
             CompressionAlgorithm::None => 0,
             CompressionAlgorithm::Lz4 => 1,
+            CompressionAlgorithm::Zstd { .. } => 2,
         }
     }
 }
 
+/// Format: `| zstd level (4B) | tag (1B) |` in low bytes, with zero zstd level for others.
 impl From<CompressionAlgorithm> for u64 {
     fn from(ca: CompressionAlgorithm) -> Self {
-        match ca {
-            CompressionAlgorithm::None => 0,
-            CompressionAlgorithm::Lz4 => 1,
-        }
+        let level = match ca {
+            CompressionAlgorithm::Zstd { level } => level as u32 as u64,
+            _ => 0,
+        };
+        level << 8 | u8::from(ca) as u64
     }
 }
 
@@ -49,7 +70,128 @@ impl TryFrom<u8> for CompressionAlgorithm {
         match v {
             0 => Ok(Self::None),
             1 => Ok(Self::Lz4),
+            2 => Ok(Self::Zstd {
+                level: ZSTD_DEFAULT_LEVEL,
+            }),
             _ => Err(anyhow::anyhow!("not valid compression algorithm")),
         }
     }
 }
+
+impl TryFrom<u64> for CompressionAlgorithm {
+    type Error = anyhow::Error;
+    fn try_from(v: u64) -> core::result::Result<Self, Self::Error> {
+        let ca = match Self::try_from(v as u8)? {
+            Self::Zstd { .. } => Self::Zstd {
+                level: (v >> 8) as u32 as i32,
+            },
+            ca => ca,
+        };
+        ca.validate()?;
+        Ok(ca)
+    }
+}
+
+/// Encode `value` as a varint: 7 bits per byte, least significant group first, with the high bit
+/// of each byte set if more bytes follow. Takes 1 to 10 bytes.
+pub fn encode_varint(buf: &mut impl BufMut, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+pub fn decode_varint(buf: &mut impl Buf) -> Result<u64, anyhow::Error> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        if !buf.has_remaining() {
+            return Err(anyhow::anyhow!("varint truncated"));
+        }
+        let byte = buf.get_u8();
+        if shift == 63 && byte > 1 {
+            return Err(anyhow::anyhow!("varint overflows u64"));
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(anyhow::anyhow!("varint overflows u64"))
+}
+
+/// Length of `value` encoded by [`encode_varint`].
+pub fn varint_len(value: u64) -> usize {
+    std::cmp::max(1, (64 - value.leading_zeros() as usize + 6) / 7)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_compression_algorithm_u64() {
+        for ca in [
+            CompressionAlgorithm::None,
+            CompressionAlgorithm::Lz4,
+            CompressionAlgorithm::Zstd { level: 1 },
+            CompressionAlgorithm::Zstd { level: 19 },
+        ] {
+            assert_eq!(CompressionAlgorithm::try_from(u64::from(ca)).unwrap(), ca);
+        }
+        assert!(
+            CompressionAlgorithm::try_from(u64::from(CompressionAlgorithm::Zstd { level: 23 }))
+                .is_err()
+        );
+        assert!(CompressionAlgorithm::Zstd { level: 0 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_varint_enc_dec() {
+        let cases = [
+            (0, 1),
+            (1, 1),
+            (127, 1),
+            (128, 2),
+            ((1 << 14) - 1, 2),
+            (1 << 14, 3),
+            (u32::MAX as u64, 5),
+            ((1 << 63) - 1, 9),
+            (1 << 63, 10),
+            (u64::MAX, 10),
+        ];
+        for (value, len) in cases {
+            let mut buf = vec![];
+            encode_varint(&mut buf, value);
+            assert_eq!(len, buf.len());
+            assert_eq!(len, varint_len(value));
+            let mut rbuf = &buf[..];
+            assert_eq!(value, decode_varint(&mut rbuf).unwrap());
+            assert!(rbuf.is_empty());
+        }
+
+        let mut buf = vec![];
+        encode_varint(&mut buf, 128);
+        encode_varint(&mut buf, 0);
+        let mut rbuf = &buf[..];
+        assert_eq!(128, decode_varint(&mut rbuf).unwrap());
+        assert_eq!(0, decode_varint(&mut rbuf).unwrap());
+        assert!(rbuf.is_empty());
+    }
+
+    #[test]
+    fn test_varint_decode_error() {
+        // Truncated.
+        assert!(decode_varint(&mut &[][..]).is_err());
+        assert!(decode_varint(&mut &[0x80][..]).is_err());
+        // Overflow.
+        let mut buf = vec![0xff; 9];
+        buf.push(0x02);
+        assert!(decode_varint(&mut &buf[..]).is_err());
+        let mut buf = vec![0xff; 10];
+        buf.push(0x01);
+        assert!(decode_varint(&mut &buf[..]).is_err());
+    }
+}
@@ -1,9 +1,30 @@
 use std::str::FromStr;
 
+use bytesize::ByteSize;
 use serde::Deserialize;
 
 use crate::coding::CompressionAlgorithm;
 
+#[derive(thiserror::Error, PartialEq, Eq, Debug)]
+pub enum ConfigError {
+    #[error("invalid value of {field}: {value}, {reason}")]
+    InvalidValue {
+        field: &'static str,
+        value: String,
+        reason: String,
+    },
+}
+
+impl ConfigError {
+    fn invalid_value(field: &'static str, value: impl ToString, reason: impl ToString) -> Self {
+        Self::InvalidValue {
+            field,
+            value: value.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
 #[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum LevelCompactionStrategy {
     Overlap,
@@ -41,6 +62,66 @@ impl FromStr for LsmTreeConfig {
     }
 }
 
+impl LsmTreeConfig {
+    /// Check the values that would otherwise only fail after data is written.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(self.bloom_false_positive > 0.0 && self.bloom_false_positive < 1.0) {
+            return Err(ConfigError::invalid_value(
+                "bloom_false_positive",
+                self.bloom_false_positive,
+                "must be in (0.0, 1.0)",
+            ));
+        }
+        let block_capacity = Self::parse_capacity("block_capacity", &self.block_capacity)?;
+        if !block_capacity.is_power_of_two() {
+            return Err(ConfigError::invalid_value(
+                "block_capacity",
+                &self.block_capacity,
+                "must be a power of 2",
+            ));
+        }
+        let sstable_capacity = Self::parse_capacity("sstable_capacity", &self.sstable_capacity)?;
+        if sstable_capacity % block_capacity != 0 {
+            return Err(ConfigError::invalid_value(
+                "sstable_capacity",
+                &self.sstable_capacity,
+                format!(
+                    "must be a multiple of block capacity {}",
+                    self.block_capacity
+                ),
+            ));
+        }
+        if self.restart_interval < 1 {
+            return Err(ConfigError::invalid_value(
+                "restart_interval",
+                self.restart_interval,
+                "must be at least 1",
+            ));
+        }
+        for options in self.levels_options.iter() {
+            options.compression_algorithm.validate().map_err(|e| {
+                ConfigError::invalid_value(
+                    "levels_options.compression_algorithm",
+                    format!("{:?}", options.compression_algorithm),
+                    e,
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    fn parse_capacity(field: &'static str, value: &str) -> Result<u64, ConfigError> {
+        let capacity = value
+            .parse::<ByteSize>()
+            .map_err(|e| ConfigError::invalid_value(field, value, e))?
+            .0;
+        if capacity == 0 {
+            return Err(ConfigError::invalid_value(field, value, "must be non-zero"));
+        }
+        Ok(capacity)
+    }
+}
+
 // TODO: Fill me.
 #[derive(Deserialize, Clone, Debug)]
 pub struct S3Config {
@@ -72,9 +153,7 @@ mod tests {
 
     use super::*;
 
-    #[test]
-    fn lsm_tree_config_serde() {
-        let s = r#"
+    const LSM_TREE_CONFIG: &str = r#"
         l1_capacity = "1 MiB"
         level_multiplier = 10
         
@@ -117,6 +196,44 @@ mod tests {
         [[levels_options]]
         compaction_strategy = "NonOverlap"
         compression_algorithm = "Lz4""#;
-        LsmTreeConfig::from_str(s).unwrap();
+
+    #[test]
+    fn lsm_tree_config_serde() {
+        LsmTreeConfig::from_str(LSM_TREE_CONFIG).unwrap();
+    }
+
+    #[test]
+    fn test_lsm_tree_config_validate() {
+        let config = LsmTreeConfig::from_str(LSM_TREE_CONFIG).unwrap();
+        config.validate().unwrap();
+
+        let field = |config: LsmTreeConfig| match config.validate() {
+            Err(ConfigError::InvalidValue { field, .. }) => field,
+            Ok(()) => panic!("config should be invalid"),
+        };
+        for bloom_false_positive in [0.0, 1.0, -0.1, f64::NAN] {
+            let config = LsmTreeConfig {
+                bloom_false_positive,
+                ..config.clone()
+            };
+            assert_eq!(field(config), "bloom_false_positive");
+        }
+        for block_capacity in ["3 KiB", "0 B", "four"] {
+            let config = LsmTreeConfig {
+                block_capacity: block_capacity.to_string(),
+                ..config.clone()
+            };
+            assert_eq!(field(config), "block_capacity");
+        }
+        let c = LsmTreeConfig {
+            sstable_capacity: "66 KiB".to_string(),
+            ..config.clone()
+        };
+        assert_eq!(field(c), "sstable_capacity");
+        let c = LsmTreeConfig {
+            restart_interval: 0,
+            ..config
+        };
+        assert_eq!(field(c), "restart_interval");
     }
 }
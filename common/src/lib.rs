@@ -3,12 +3,244 @@ pub mod channel_pool;
 pub mod coding;
 pub mod config;
 pub mod notify_pool;
+pub mod supervisor;
+pub mod time;
+
+use std::time::Duration;
 
 use async_trait::async_trait;
+use tokio::task::JoinHandle;
+pub use tokio_util::sync::CancellationToken;
+use tracing::warn;
 
 #[async_trait]
 pub trait Worker: Sync + Send + 'static {
-    async fn run(&mut self) -> anyhow::Result<()>;
+    /// Run the worker until it finishes or `cancel` is triggered.
+    ///
+    /// Once `cancel` is triggered, the worker should finish the operation in progress and return
+    /// `Ok(())` promptly.
+    async fn run(&mut self, cancel: CancellationToken) -> anyhow::Result<()>;
+
+    /// Release the resources held by the worker. It is called once the worker has stopped running
+    /// and will not be run again.
+    async fn shutdown(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 pub type BoxedWorker = Box<dyn Worker>;
+
+/// [`WorkerHandle`] owns a spawned worker. The worker is cancelled and shut down when the handle
+/// is dropped.
+pub struct WorkerHandle {
+    cancel: CancellationToken,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl WorkerHandle {
+    pub fn spawn(mut worker: BoxedWorker) -> Self {
+        let cancel = CancellationToken::new();
+        let handle = tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                if let Err(e) = worker.run(cancel).await {
+                    warn!("error raised when running worker: {}", e);
+                }
+                if let Err(e) = worker.shutdown().await {
+                    warn!("error raised when shutting down worker: {}", e);
+                }
+            }
+        });
+        Self {
+            cancel,
+            handle: Some(handle),
+        }
+    }
+
+    /// Cancel the worker and wait for it to shut down.
+    pub async fn shutdown(mut self) -> anyhow::Result<()> {
+        self.cancel.cancel();
+        if let Some(handle) = self.handle.take() {
+            handle.await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WorkerHandle {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// [`LoopWorker`] does its work in rounds. [`Worker`] is implemented for it by running rounds
+/// until cancelled. Errors of rounds are logged and don't stop the worker.
+#[async_trait]
+pub trait LoopWorker: Sync + Send + 'static {
+    /// Name of the worker, used in logs.
+    fn name(&self) -> &str;
+
+    async fn run_once(&mut self) -> anyhow::Result<()>;
+
+    /// Interval between rounds.
+    fn interval(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+#[async_trait]
+impl<T: LoopWorker> Worker for T {
+    async fn run(&mut self, cancel: CancellationToken) -> anyhow::Result<()> {
+        while !cancel.is_cancelled() {
+            if let Err(e) = self.run_once().await {
+                warn!("error occur when {} running: {}", self.name(), e);
+            }
+            let interval = self.interval();
+            if !interval.is_zero() {
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = cancel.cancelled() => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use test_log::test;
+
+    use super::*;
+
+    struct CountWorker {
+        rounds: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl LoopWorker for CountWorker {
+        fn name(&self) -> &str {
+            "count worker"
+        }
+
+        async fn run_once(&mut self) -> anyhow::Result<()> {
+            let rounds = self.rounds.fetch_add(1, Ordering::SeqCst) + 1;
+            if rounds % 2 == 0 {
+                return Err(anyhow::anyhow!("even round {}", rounds));
+            }
+            Ok(())
+        }
+
+        fn interval(&self) -> Duration {
+            Duration::from_millis(1)
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_loop_worker_cancel() {
+        let rounds = Arc::new(AtomicUsize::new(0));
+        let mut worker: BoxedWorker = Box::new(CountWorker {
+            rounds: rounds.clone(),
+        });
+        let cancel = CancellationToken::new();
+        let handle = tokio::spawn({
+            let cancel = cancel.clone();
+            async move { worker.run(cancel).await }
+        });
+
+        // Failed rounds don't stop the worker.
+        while rounds.load(Ordering::SeqCst) < 4 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        cancel.cancel();
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let rounds_on_cancel = rounds.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(rounds_on_cancel, rounds.load(Ordering::SeqCst));
+    }
+
+    #[test(tokio::test)]
+    async fn test_loop_worker_cancel_while_waiting() {
+        struct SlowWorker;
+
+        #[async_trait]
+        impl LoopWorker for SlowWorker {
+            fn name(&self) -> &str {
+                "slow worker"
+            }
+
+            async fn run_once(&mut self) -> anyhow::Result<()> {
+                Ok(())
+            }
+
+            fn interval(&self) -> Duration {
+                Duration::from_secs(3600)
+            }
+        }
+
+        let cancel = CancellationToken::new();
+        let handle = tokio::spawn({
+            let cancel = cancel.clone();
+            let mut worker = SlowWorker;
+            async move { worker.run(cancel).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        cancel.cancel();
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    struct ShutdownWorker {
+        shutdowns: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Worker for ShutdownWorker {
+        async fn run(&mut self, cancel: CancellationToken) -> anyhow::Result<()> {
+            cancel.cancelled().await;
+            Ok(())
+        }
+
+        async fn shutdown(&mut self) -> anyhow::Result<()> {
+            self.shutdowns.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_worker_handle() {
+        let shutdowns = Arc::new(AtomicUsize::new(0));
+
+        let handle = WorkerHandle::spawn(Box::new(ShutdownWorker {
+            shutdowns: shutdowns.clone(),
+        }));
+        tokio::time::timeout(Duration::from_secs(1), handle.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+
+        // Dropping the handle shuts down the worker as well.
+        let handle = WorkerHandle::spawn(Box::new(ShutdownWorker {
+            shutdowns: shutdowns.clone(),
+        }));
+        drop(handle);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while shutdowns.load(Ordering::SeqCst) < 2 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
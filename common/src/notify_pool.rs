@@ -1,13 +1,48 @@
 use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashSet;
 use std::fmt::Display;
+use std::future::Future;
 use std::hash::Hash;
 use std::sync::Arc;
+use std::time::Duration;
 
 use parking_lot::Mutex;
 use tokio::sync::oneshot;
 
+#[derive(thiserror::Error, PartialEq, Eq, Debug)]
+pub enum NotifyError {
+    #[error("wait for notification timeout")]
+    Timeout,
+    #[error("notifier dropped without notification")]
+    Dropped,
+}
+
 struct NotifyPoolCore<I: Eq + Hash + Copy + Clone + Display, R> {
     notifiers: HashMap<I, oneshot::Sender<R>>,
+    /// Ids failed by the last [`NotifyPool::fail_all`] that are neither notified nor registered
+    /// again.
+    failed: HashSet<I>,
+}
+
+/// Receiver of an id registered by [`NotifyPool::register_with_timeout`], which removes the id from
+/// the pool on drop if it is not notified, no matter whether the waiter timed out or was dropped
+/// before resolving.
+struct TimeoutWaiter<I: Eq + Hash + Copy + Clone + Display, R> {
+    core: Arc<Mutex<NotifyPoolCore<I, R>>>,
+    id: I,
+    rx: Option<oneshot::Receiver<R>>,
+}
+
+impl<I: Eq + Hash + Copy + Clone + Display, R> Drop for TimeoutWaiter<I, R> {
+    fn drop(&mut self) {
+        drop(self.rx.take());
+        // The id may have been notified and registered again by others.
+        if let Entry::Occupied(o) = self.core.lock().notifiers.entry(self.id) {
+            if o.get().is_closed() {
+                o.remove();
+            }
+        }
+    }
 }
 
 pub struct NotifyPool<I: Eq + Hash + Copy + Clone + Display, R> {
@@ -19,6 +54,7 @@ impl<I: Eq + Hash + Copy + Clone + Display, R> Default for NotifyPool<I, R> {
         Self {
             core: Arc::new(Mutex::new(NotifyPoolCore {
                 notifiers: HashMap::default(),
+                failed: HashSet::default(),
             })),
         }
     }
@@ -27,26 +63,87 @@ impl<I: Eq + Hash + Copy + Clone + Display, R> Default for NotifyPool<I, R> {
 impl<I: Eq + Hash + Copy + Clone + Display, R> NotifyPool<I, R> {
     pub fn register(&self, id: I) -> anyhow::Result<oneshot::Receiver<R>> {
         let (tx, rx) = oneshot::channel();
-        match self.core.lock().notifiers.entry(id) {
+        let mut core = self.core.lock();
+        match core.notifiers.entry(id) {
             Entry::Occupied(_) => return Err(anyhow::anyhow!("id {} already exists", id)),
             Entry::Vacant(v) => {
                 v.insert(tx);
             }
         }
+        core.failed.remove(&id);
         Ok(rx)
     }
 
+    /// Like [`NotifyPool::register`], but the returned future resolves with
+    /// [`NotifyError::Timeout`] if no notification arrives in `timeout`, which counts from the
+    /// first poll of the future rather than from the registration.
+    ///
+    /// The registered id is removed on timeout, or when the future is dropped before resolving.
+    /// Notifications arriving later are rejected by [`NotifyPool::notify`].
+    pub fn register_with_timeout(
+        &self,
+        id: I,
+        timeout: Duration,
+    ) -> anyhow::Result<impl Future<Output = Result<R, NotifyError>>> {
+        let mut waiter = TimeoutWaiter {
+            core: self.core.clone(),
+            id,
+            rx: Some(self.register(id)?),
+        };
+        Ok(async move {
+            let rx = waiter.rx.as_mut().unwrap();
+            match tokio::time::timeout(timeout, &mut *rx).await {
+                Ok(Ok(result)) => Ok(result),
+                Ok(Err(_)) => Err(NotifyError::Dropped),
+                // Notified right after timeout.
+                Err(_) => rx.try_recv().map_err(|_| NotifyError::Timeout),
+            }
+        })
+    }
+
+    /// Notify the waiter of `id` with `result`.
+    ///
+    /// Notifications of ids failed by the last [`NotifyPool::fail_all`] are dropped silently.
     pub fn notify(&self, id: I, result: R) -> anyhow::Result<()> {
-        let tx = self
-            .core
-            .lock()
-            .notifiers
-            .remove(&id)
-            .ok_or_else(|| anyhow::anyhow!("id {} does not exists", id))?;
+        let tx = {
+            let mut core = self.core.lock();
+            match core.notifiers.remove(&id) {
+                Some(tx) => tx,
+                None if core.failed.remove(&id) => return Ok(()),
+                None => return Err(anyhow::anyhow!("id {} does not exists", id)),
+            }
+        };
         tx.send(result)
             .map_err(|_| anyhow::anyhow!("error raised to send result to notifier {}", id))?;
         Ok(())
     }
+
+    /// Count of registered ids that are not notified yet.
+    pub fn pending_count(&self) -> usize {
+        self.core.lock().notifiers.len()
+    }
+}
+
+impl<I: Eq + Hash + Copy + Clone + Display, T, E: Clone> NotifyPool<I, Result<T, E>> {
+    /// Resolve all pending waiters with `err` and clear the pool, e.g. when the connection that
+    /// the waiters depend on drops. Returns the count of failed waiters.
+    ///
+    /// Notifications of the failed ids that arrive later are dropped silently, until the id is
+    /// registered again or the next [`NotifyPool::fail_all`].
+    pub fn fail_all(&self, err: E) -> usize {
+        let notifiers = {
+            let mut core = self.core.lock();
+            let notifiers = std::mem::take(&mut core.notifiers);
+            core.failed = notifiers.keys().copied().collect();
+            notifiers
+        };
+        let count = notifiers.len();
+        for (_, tx) in notifiers {
+            // The waiter may have been dropped.
+            let _ = tx.send(Err(err.clone()));
+        }
+        count
+    }
 }
 
 #[cfg(test)]
@@ -60,4 +157,96 @@ mod tests {
     fn ensure_send_sync() {
         is_send_sync::<NotifyPool<u64, ()>>();
     }
+
+    #[test(tokio::test)]
+    async fn test_register_with_timeout() {
+        let pool: NotifyPool<u64, u64> = NotifyPool::default();
+
+        let wait = pool
+            .register_with_timeout(1, Duration::from_millis(10))
+            .unwrap();
+        assert!(pool.register(1).is_err());
+        assert_eq!(wait.await, Err(NotifyError::Timeout));
+        assert!(pool.core.lock().notifiers.is_empty());
+        // Late notification is rejected.
+        assert!(pool.notify(1, 1).is_err());
+
+        let wait = pool
+            .register_with_timeout(2, Duration::from_secs(10))
+            .unwrap();
+        pool.notify(2, 2).unwrap();
+        assert_eq!(wait.await, Ok(2));
+        assert!(pool.core.lock().notifiers.is_empty());
+
+        // Notified before waiting, the id can be registered again.
+        let wait = pool
+            .register_with_timeout(3, Duration::from_millis(10))
+            .unwrap();
+        pool.notify(3, 3).unwrap();
+        let _rx = pool.register(3).unwrap();
+        assert_eq!(wait.await, Ok(3));
+        assert_eq!(pool.core.lock().notifiers.len(), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_register_with_timeout_dropped() {
+        let pool: NotifyPool<u64, u64> = NotifyPool::default();
+
+        // Dropped before polled.
+        let wait = pool
+            .register_with_timeout(1, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(pool.pending_count(), 1);
+        drop(wait);
+        assert_eq!(pool.pending_count(), 0);
+        assert!(pool.notify(1, 1).is_err());
+
+        // Dropped while waiting.
+        let wait = pool
+            .register_with_timeout(1, Duration::from_secs(10))
+            .unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(10), wait)
+            .await
+            .is_err());
+        assert_eq!(pool.pending_count(), 0);
+
+        // Ids registered again by others are kept.
+        let wait = pool
+            .register_with_timeout(2, Duration::from_secs(10))
+            .unwrap();
+        pool.notify(2, 2).unwrap();
+        let _rx = pool.register(2).unwrap();
+        drop(wait);
+        assert_eq!(pool.pending_count(), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_fail_all() {
+        let pool: NotifyPool<u64, Result<u64, String>> = NotifyPool::default();
+
+        let rxs = (1..=4)
+            .map(|id| pool.register(id).unwrap())
+            .collect::<Vec<_>>();
+        let wait = pool
+            .register_with_timeout(5, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(pool.pending_count(), 5);
+
+        assert_eq!(pool.fail_all("disconnected".to_string()), 5);
+        assert_eq!(pool.pending_count(), 0);
+        for rx in rxs {
+            assert_eq!(rx.await.unwrap(), Err("disconnected".to_string()));
+        }
+        assert_eq!(wait.await, Ok(Err("disconnected".to_string())));
+
+        // Late notifications of failed ids are dropped silently.
+        pool.notify(1, Ok(1)).unwrap();
+        assert!(pool.notify(6, Ok(6)).is_err());
+
+        // Failed ids can be registered again.
+        let rx = pool.register(2).unwrap();
+        pool.notify(2, Ok(2)).unwrap();
+        assert_eq!(rx.await.unwrap(), Ok(2));
+        assert!(pool.notify(2, Ok(2)).is_err());
+    }
 }
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tracing::{trace, warn};
+
+use crate::{BoxedWorker, CancellationToken};
+
+#[derive(Clone, Debug)]
+pub struct SupervisorOptions {
+    /// Backoff before the first restart, doubled after each consecutive failure.
+    pub backoff_base: Duration,
+    /// Max backoff between restarts.
+    pub backoff_max: Duration,
+}
+
+impl Default for SupervisorOptions {
+    fn default() -> Self {
+        Self {
+            backoff_base: Duration::from_millis(100),
+            backoff_max: Duration::from_secs(10),
+        }
+    }
+}
+
+/// [`Supervisor`] spawns workers and restarts them with exponential backoff when they return an
+/// error. A worker that returns `Ok` is regarded as finished and will not be restarted.
+pub struct Supervisor {
+    options: SupervisorOptions,
+    cancel: CancellationToken,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Supervisor {
+    pub fn new(options: SupervisorOptions) -> Self {
+        Self {
+            options,
+            cancel: CancellationToken::new(),
+            handles: vec![],
+        }
+    }
+
+    pub fn spawn(&mut self, worker: BoxedWorker) {
+        let options = self.options.clone();
+        let cancel = self.cancel.clone();
+        let id = self.handles.len();
+        let handle = tokio::spawn(async move { supervise(id, worker, options, cancel).await });
+        self.handles.push(handle);
+    }
+
+    pub fn spawn_all(&mut self, workers: Vec<BoxedWorker>) {
+        for worker in workers {
+            self.spawn(worker);
+        }
+    }
+
+    /// Signal all workers to stop and wait for them to exit. Workers will not be restarted after
+    /// they exit.
+    pub async fn shutdown(self) {
+        self.cancel.cancel();
+        for handle in self.handles {
+            if let Err(e) = handle.await {
+                warn!("error raised when joining supervised worker: {}", e);
+            }
+        }
+    }
+}
+
+async fn supervise(
+    id: usize,
+    mut worker: BoxedWorker,
+    options: SupervisorOptions,
+    cancel: CancellationToken,
+) {
+    run_until_stopped(id, &mut worker, options, cancel).await;
+    if let Err(e) = worker.shutdown().await {
+        warn!(
+            "error raised when shutting down supervised worker {}: {}",
+            id, e
+        );
+    }
+}
+
+async fn run_until_stopped(
+    id: usize,
+    worker: &mut BoxedWorker,
+    options: SupervisorOptions,
+    cancel: CancellationToken,
+) {
+    let mut failures = 0;
+    loop {
+        let result = worker.run(cancel.clone()).await;
+        if cancel.is_cancelled() {
+            trace!("supervised worker {} cancelled", id);
+            return;
+        }
+        match result {
+            Ok(()) => {
+                trace!("supervised worker {} finished", id);
+                return;
+            }
+            Err(e) => {
+                let backoff = backoff(&options, failures);
+                failures += 1;
+                warn!(
+                    "supervised worker {} failed {} time(s), restart after {:?}: {}",
+                    id, failures, backoff, e
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = cancel.cancelled() => return,
+                }
+            }
+        }
+    }
+}
+
+fn backoff(options: &SupervisorOptions, failures: u32) -> Duration {
+    options
+        .backoff_base
+        .saturating_mul(2u32.saturating_pow(failures))
+        .min(options.backoff_max)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use test_log::test;
+
+    use super::*;
+    use crate::Worker;
+
+    struct FlakyWorker {
+        failures: usize,
+        runs: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Worker for FlakyWorker {
+        async fn run(&mut self, _cancel: CancellationToken) -> anyhow::Result<()> {
+            let runs = self.runs.fetch_add(1, Ordering::SeqCst) + 1;
+            if runs <= self.failures {
+                Err(anyhow::anyhow!("flaky worker failed at run {}", runs))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    struct IdleWorker {
+        shutdowns: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Worker for IdleWorker {
+        async fn run(&mut self, cancel: CancellationToken) -> anyhow::Result<()> {
+            cancel.cancelled().await;
+            Ok(())
+        }
+
+        async fn shutdown(&mut self) -> anyhow::Result<()> {
+            self.shutdowns.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let options = SupervisorOptions {
+            backoff_base: Duration::from_millis(100),
+            backoff_max: Duration::from_millis(500),
+        };
+        assert_eq!(backoff(&options, 0), Duration::from_millis(100));
+        assert_eq!(backoff(&options, 1), Duration::from_millis(200));
+        assert_eq!(backoff(&options, 2), Duration::from_millis(400));
+        assert_eq!(backoff(&options, 3), Duration::from_millis(500));
+        assert_eq!(backoff(&options, 100), Duration::from_millis(500));
+    }
+
+    #[test(tokio::test)]
+    async fn test_restart_failed_worker() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut supervisor = Supervisor::new(SupervisorOptions {
+            backoff_base: Duration::from_millis(1),
+            backoff_max: Duration::from_millis(10),
+        });
+        supervisor.spawn(Box::new(FlakyWorker {
+            failures: 2,
+            runs: runs.clone(),
+        }));
+        // Wait for the worker to finish without being cancelled.
+        for handle in supervisor.handles.drain(..) {
+            handle.await.unwrap();
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        supervisor.shutdown().await;
+    }
+
+    #[test(tokio::test)]
+    async fn test_shutdown() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let shutdowns = Arc::new(AtomicUsize::new(0));
+        let mut supervisor = Supervisor::new(SupervisorOptions {
+            backoff_base: Duration::from_secs(3600),
+            backoff_max: Duration::from_secs(3600),
+        });
+        supervisor.spawn_all(vec![
+            Box::new(IdleWorker {
+                shutdowns: shutdowns.clone(),
+            }),
+            Box::new(FlakyWorker {
+                failures: usize::MAX,
+                runs: runs.clone(),
+            }),
+        ]);
+        tokio::time::timeout(Duration::from_secs(10), supervisor.shutdown())
+            .await
+            .unwrap();
+        assert!(runs.load(Ordering::SeqCst) <= 1);
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+    }
+}
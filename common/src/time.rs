@@ -0,0 +1,64 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// [`TimeSource`] abstracts the clock so that time-driven behaviours can be tested with a mocked
+/// clock.
+pub trait TimeSource: Send + Sync + 'static {
+    fn now(&self) -> Instant;
+}
+
+pub type TimeSourceRef = Arc<dyn TimeSource>;
+
+/// [`TimeSource`] backed by the system monotonic clock.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// [`TimeSource`] that only moves forward when `advance` is called.
+#[derive(Debug)]
+pub struct MockTimeSource {
+    now: Mutex<Instant>,
+}
+
+impl Default for MockTimeSource {
+    fn default() -> Self {
+        Self {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl MockTimeSource {
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock() += duration;
+    }
+}
+
+impl TimeSource for MockTimeSource {
+    fn now(&self) -> Instant {
+        *self.now.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_mock_time_source() {
+        let time_source = MockTimeSource::default();
+        let start = time_source.now();
+        assert_eq!(start, time_source.now());
+        time_source.advance(Duration::from_secs(10));
+        assert_eq!(Duration::from_secs(10), time_source.now() - start);
+    }
+}
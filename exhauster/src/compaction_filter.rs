@@ -1,22 +1,41 @@
+use std::ops::RangeInclusive;
+
 use bytes::Bytes;
+use runkv_storage::components::RangeTombstone;
 
 pub trait CompactionFilter {
     /// Keep the key value pair if `filter` returns true.
     fn filter(&mut self, key: &[u8], value: Option<&[u8]>, timestamp: u64) -> bool;
+
+    /// Keep the range tombstone if `filter_range_tombstone` returns true.
+    fn filter_range_tombstone(&mut self, _range_tombstone: &RangeTombstone) -> bool {
+        true
+    }
 }
 
 pub struct DefaultCompactionFilter {
     last_key: Bytes,
     watermark: u64,
-    _remove_tombstone: bool,
+    remove_tombstone: bool,
+    /// Range tombstones of the compaction inputs.
+    range_tombstones: Vec<RangeTombstone>,
+    /// User key range of the point keys of the compaction inputs.
+    input_key_range: RangeInclusive<Vec<u8>>,
 }
 
 impl DefaultCompactionFilter {
-    pub fn new(watermark: u64, remove_tombstone: bool) -> Self {
+    pub fn new(
+        watermark: u64,
+        remove_tombstone: bool,
+        range_tombstones: Vec<RangeTombstone>,
+        input_key_range: RangeInclusive<Vec<u8>>,
+    ) -> Self {
         Self {
             last_key: Bytes::default(),
             watermark,
-            _remove_tombstone: remove_tombstone,
+            remove_tombstone,
+            range_tombstones,
+            input_key_range,
         }
     }
 }
@@ -28,9 +47,24 @@ impl CompactionFilter for DefaultCompactionFilter {
         if key == self.last_key && timestamp < self.watermark {
             retain = false;
         }
+        // Versions deleted by range tombstones below the watermark are invisible to all readers.
+        if self.range_tombstones.iter().any(|range_tombstone| {
+            range_tombstone.timestamp < self.watermark && range_tombstone.covers(key, timestamp)
+        }) {
+            retain = false;
+        }
         self.last_key = Bytes::copy_from_slice(key);
         retain
     }
+
+    /// Range tombstones below the watermark are removed if there is no older data to delete. Range
+    /// tombstones that reach out of the key range of the inputs are kept, for the sstables out of
+    /// the compaction may still hold the versions they delete.
+    fn filter_range_tombstone(&mut self, range_tombstone: &RangeTombstone) -> bool {
+        let covered_by_inputs = self.input_key_range.start() <= &range_tombstone.start
+            && range_tombstone.end <= *self.input_key_range.end();
+        !(self.remove_tombstone && range_tombstone.timestamp < self.watermark && covered_by_inputs)
+    }
 }
 
 #[cfg(test)]
@@ -57,9 +91,59 @@ mod tests {
             (b"k4", Some(b"v4-8"), 8, false),
             (b"k4", None, 1, false),
         ];
-        let mut filter = DefaultCompactionFilter::new(10, false);
+        let mut filter =
+            DefaultCompactionFilter::new(10, false, vec![], b"k1".to_vec()..=b"k4".to_vec());
         for data in dataset {
             assert_eq!(filter.filter(data.0, data.1, data.2), data.3)
         }
     }
+
+    #[test]
+    fn test_range_tombstone_compaction_filter() {
+        let range_tombstones = vec![
+            RangeTombstone::new(b"k1".to_vec(), b"k3".to_vec(), 5),
+            RangeTombstone::new(b"k3".to_vec(), b"k5".to_vec(), 20),
+        ];
+        #[allow(clippy::type_complexity)]
+        let dataset: Vec<(&[u8], Option<&[u8]>, u64, bool)> = vec![
+            (b"k1", Some(b"v1-8"), 8, true),
+            (b"k1", Some(b"v1-5"), 5, false),
+            (b"k2", Some(b"v2-3"), 3, false),
+            // Covered, but the range tombstone is above the watermark.
+            (b"k3", Some(b"v3-15"), 15, true),
+            (b"k5", Some(b"v5-1"), 1, true),
+        ];
+        let input_key_range = b"k1".to_vec()..=b"k5".to_vec();
+        let mut filter = DefaultCompactionFilter::new(
+            10,
+            true,
+            range_tombstones.clone(),
+            input_key_range.clone(),
+        );
+        for data in dataset {
+            assert_eq!(filter.filter(data.0, data.1, data.2), data.3)
+        }
+        assert!(!filter.filter_range_tombstone(&range_tombstones[0]));
+        assert!(filter.filter_range_tombstone(&range_tombstones[1]));
+
+        let mut filter =
+            DefaultCompactionFilter::new(10, false, range_tombstones.clone(), input_key_range);
+        assert!(filter.filter_range_tombstone(&range_tombstones[0]));
+
+        // Range tombstones reaching out of the inputs are kept.
+        let mut filter = DefaultCompactionFilter::new(
+            10,
+            true,
+            range_tombstones.clone(),
+            b"k2".to_vec()..=b"k5".to_vec(),
+        );
+        assert!(filter.filter_range_tombstone(&range_tombstones[0]));
+        let mut filter = DefaultCompactionFilter::new(
+            10,
+            true,
+            range_tombstones.clone(),
+            b"k1".to_vec()..=b"k2".to_vec(),
+        );
+        assert!(filter.filter_range_tombstone(&range_tombstones[0]));
+    }
 }
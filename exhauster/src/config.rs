@@ -9,6 +9,7 @@ pub struct ExhausterConfig {
     pub data_path: String,
     pub meta_path: String,
     pub heartbeat_interval: String,
+    pub channel_health_check_interval: String,
     pub rudder: Node,
     pub s3: Option<S3Config>,
     pub minio: Option<MinioConfig>,
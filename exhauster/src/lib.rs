@@ -10,8 +10,10 @@ use std::sync::Arc;
 use bytesize::ByteSize;
 use config::ExhausterConfig;
 use error::{config_err, err, Result};
-use runkv_common::channel_pool::ChannelPool;
-use runkv_common::BoxedWorker;
+use runkv_common::channel_pool::{
+    ChannelPool, ChannelPoolHealthChecker, ChannelPoolHealthCheckerOptions,
+};
+use runkv_common::{BoxedWorker, WorkerHandle};
 use runkv_proto::common::Endpoint as PbEndpoint;
 use runkv_proto::exhauster::exhauster_service_server::ExhausterServiceServer;
 use runkv_storage::components::{BlockCache, SstableStore, SstableStoreOptions, SstableStoreRef};
@@ -28,9 +30,11 @@ pub async fn bootstrap_exhauster(
 ) -> Result<()> {
     let addr_str = format!("{}:{}", config.host, config.port);
 
-    for mut worker in workers.into_iter() {
-        tokio::spawn(async move { worker.run().await });
-    }
+    // Workers are shut down when the handles are dropped.
+    let _worker_handles = workers
+        .into_iter()
+        .map(WorkerHandle::spawn)
+        .collect::<Vec<_>>();
 
     Server::builder()
         .add_service(ExhausterServiceServer::new(exhauster))
@@ -59,6 +63,16 @@ pub async fn build_exhauster_with_object_store(
 
     let channel_pool = build_channel_pool(config);
 
+    let channel_health_checker = Box::new(ChannelPoolHealthChecker::new(
+        ChannelPoolHealthCheckerOptions {
+            channel_pool: channel_pool.clone(),
+            interval: config
+                .channel_health_check_interval
+                .parse::<humantime::Duration>()?
+                .into(),
+        },
+    ));
+
     let heartbeater_options = HeartbeaterOptions {
         node_id: config.id,
         endpoint: PbEndpoint {
@@ -76,7 +90,7 @@ pub async fn build_exhauster_with_object_store(
 
     let exhauster = Exhauster::new(options);
 
-    Ok((exhauster, vec![heartbeater]))
+    Ok((exhauster, vec![heartbeater, channel_health_checker]))
 }
 
 async fn build_object_store(config: &ExhausterConfig) -> ObjectStoreRef {
@@ -97,17 +111,17 @@ fn build_sstable_store(
     object_store: ObjectStoreRef,
 ) -> Result<SstableStoreRef> {
     let block_cache = BlockCache::new(0);
-    let sstable_store_options = SstableStoreOptions {
-        path: config.data_path.clone(),
+    let sstable_store_options = SstableStoreOptions::new(
+        config.data_path.clone(),
         object_store,
         block_cache,
-        meta_cache_capacity: config
+        config
             .cache
             .meta_cache_capacity
             .parse::<ByteSize>()
             .map_err(config_err)?
             .0 as usize,
-    };
+    );
     let sstable_store = SstableStore::new(sstable_store_options);
     Ok(Arc::new(sstable_store))
 }
@@ -9,7 +9,8 @@ use runkv_proto::exhauster::exhauster_service_server::ExhausterService;
 use runkv_proto::exhauster::{CompactionRequest, CompactionResponse};
 use runkv_proto::manifest::SstableInfo;
 use runkv_storage::components::{
-    CachePolicy, Sstable, SstableBuilder, SstableBuilderOptions, SstableStoreRef,
+    CachePolicy, RangeTombstone, Sstable, SstableBuilder, SstableBuilderOptions, SstableStoreRef,
+    SstableUploader,
 };
 use runkv_storage::iterator::{BoxedIterator, Iterator, MergeIterator, Seek, SstableIterator};
 use runkv_storage::utils::{timestamp, user_key, value};
@@ -55,6 +56,8 @@ impl ExhausterService for Exhauster {
         let req = request.into_inner();
         let mut old_sst_infos = Vec::with_capacity(req.sst_ids.len());
         let mut iters: Vec<BoxedIterator> = Vec::with_capacity(req.sst_ids.len());
+        let mut range_tombstones = vec![];
+        let mut input_key_range: Option<(Vec<u8>, Vec<u8>)> = None;
         for sst_id in &req.sst_ids {
             let sst = self
                 .sstable_store
@@ -65,7 +68,17 @@ impl ExhausterService for Exhauster {
                 id: *sst_id,
                 data_size: sst.data_size() as u64,
             });
-            let iter = SstableIterator::new(self.sstable_store.clone(), sst, CachePolicy::Fill);
+            range_tombstones.extend_from_slice(sst.range_tombstones());
+            let (first_key, last_key) = (user_key(sst.first_key()), user_key(sst.last_key()));
+            input_key_range = Some(match input_key_range {
+                Some((start, end)) => (
+                    std::cmp::min(start, first_key.to_vec()),
+                    std::cmp::max(end, last_key.to_vec()),
+                ),
+                None => (first_key.to_vec(), last_key.to_vec()),
+            });
+            // Compaction reads each block once, don't pollute the block cache.
+            let iter = SstableIterator::new(self.sstable_store.clone(), sst, CachePolicy::NotFill);
             iters.push(Box::new(iter));
         }
         let mut iter = MergeIterator::new(iters);
@@ -74,14 +87,28 @@ impl ExhausterService for Exhauster {
             block_capacity: req.block_capacity as usize,
             restart_interval: req.restart_interval as usize,
             bloom_false_positive: req.bloom_false_positive,
-            compression_algorithm: CompressionAlgorithm::try_from(req.compression_algorithm as u8)
+            compression_algorithm: CompressionAlgorithm::try_from(req.compression_algorithm)
                 .map_err(internal)?,
+            ..Default::default()
         };
-        let mut sstable_builder = None;
+        // Output sstable data are uploaded block by block while building, so neither the input nor
+        // the output sstables are held in memory entirely.
+        let mut output = None;
         iter.seek(Seek::First).await.map_err(internal)?;
-        let mut sst_id = 0;
-        let mut compaction_filter =
-            DefaultCompactionFilter::new(req.watermark, req.remove_tombstone);
+        range_tombstones.sort();
+        range_tombstones.dedup();
+        let (input_start, input_end) = input_key_range.unwrap_or_default();
+        let mut compaction_filter = DefaultCompactionFilter::new(
+            req.watermark,
+            req.remove_tombstone,
+            range_tombstones.clone(),
+            input_start..=input_end,
+        );
+        // Range tombstones to keep, each is clipped into every output sstable it overlaps.
+        let range_tombstones = range_tombstones
+            .into_iter()
+            .filter(|range_tombstone| compaction_filter.filter_range_tombstone(range_tombstone))
+            .collect_vec();
         let partition_points = req
             .partition_points
             .into_iter()
@@ -94,42 +121,68 @@ impl ExhausterService for Exhauster {
         };
         let mut new_sst_infos = Vec::with_capacity(req.sst_ids.len());
         let mut last_user_key = vec![];
+        // Lower bound of the key range of the output sstable being built, `None` for unbounded.
+        let mut output_lower_bound: Option<Vec<u8>> = None;
         // Filter key value pairs.
         while iter.is_valid() {
             let uk = user_key(iter.key());
             let ts = timestamp(iter.key());
             let v = value(iter.value());
 
-            if sstable_builder.is_none() {
-                sst_id = self.gen_sstable_id();
-                sstable_builder = Some(SstableBuilder::new(sstable_builder_options.clone()));
+            if output.is_none() {
+                let sst_id = self.gen_sstable_id();
+                let builder = SstableBuilder::new(sstable_builder_options.clone());
+                let uploader = self
+                    .sstable_store
+                    .uploader(sst_id)
+                    .await
+                    .map_err(internal)?;
+                output = Some((sst_id, builder, uploader));
             }
-            if !sstable_builder.as_ref().unwrap().is_empty()
+            let (_, builder, _) = output.as_ref().unwrap();
+            if !builder.is_empty()
             // Pervent multi versions of one user key being split in multi ssts.
                 && uk != last_user_key
-                && (sstable_builder.as_ref().unwrap().approximate_len()
-                    >= sstable_builder_options.capacity
+                && (builder.approximate_len() >= sstable_builder_options.capacity
                     || partitioner.partition(uk, v, ts))
             {
-                let builder = sstable_builder.take().unwrap();
+                let (sst_id, mut builder, uploader) = output.take().unwrap();
+                // The current user key is the first one of the next output sstable.
+                add_range_tombstones(
+                    &mut builder,
+                    &range_tombstones,
+                    output_lower_bound.as_deref(),
+                    Some(uk),
+                );
+                output_lower_bound = Some(uk.to_vec());
                 let sst_info = self
-                    .build_and_upload_sst(sst_id, builder)
+                    .build_and_upload_sst(sst_id, builder, uploader)
                     .await
                     .map_err(internal)?;
                 new_sst_infos.push(sst_info);
                 continue;
             }
-            let builder = sstable_builder.as_mut().unwrap();
+            let (_, builder, uploader) = output.as_mut().unwrap();
 
             if compaction_filter.filter(uk, v, ts) {
                 builder.add(uk, ts, v).map_err(internal)?;
+                uploader
+                    .write(builder.drain_data())
+                    .await
+                    .map_err(internal)?;
                 last_user_key = uk.to_vec();
             }
             iter.next().await.map_err(internal)?;
         }
-        if let Some(builder) = sstable_builder.take() {
+        if let Some((sst_id, mut builder, uploader)) = output.take() {
+            add_range_tombstones(
+                &mut builder,
+                &range_tombstones,
+                output_lower_bound.as_deref(),
+                None,
+            );
             let sst_info = self
-                .build_and_upload_sst(sst_id, builder)
+                .build_and_upload_sst(sst_id, builder, uploader)
                 .await
                 .map_err(internal)?;
             new_sst_infos.push(sst_info);
@@ -142,6 +195,36 @@ impl ExhausterService for Exhauster {
     }
 }
 
+/// Add the parts of `range_tombstones` within `[lower_bound, upper_bound)` to `builder`, `None`
+/// stands for unbounded.
+///
+/// The output sstables partition the whole key space, so that every key covered by a range
+/// tombstone is still covered by the output sstable whose key range includes it.
+fn add_range_tombstones(
+    builder: &mut SstableBuilder,
+    range_tombstones: &[RangeTombstone],
+    lower_bound: Option<&[u8]>,
+    upper_bound: Option<&[u8]>,
+) {
+    for range_tombstone in range_tombstones {
+        let start = match lower_bound {
+            Some(lower_bound) if lower_bound > &range_tombstone.start[..] => lower_bound,
+            _ => &range_tombstone.start[..],
+        };
+        let end = match upper_bound {
+            Some(upper_bound) if upper_bound < &range_tombstone.end[..] => upper_bound,
+            _ => &range_tombstone.end[..],
+        };
+        if start < end {
+            builder.add_range_tombstone(RangeTombstone::new(
+                start.to_vec(),
+                end.to_vec(),
+                range_tombstone.timestamp,
+            ));
+        }
+    }
+}
+
 impl Exhauster {
     fn gen_sstable_id(&self) -> u64 {
         let sequential_id = self.sstable_sequential_id.fetch_add(1, Ordering::SeqCst);
@@ -153,15 +236,14 @@ impl Exhauster {
         &self,
         sst_id: u64,
         builder: SstableBuilder,
+        uploader: SstableUploader<'_>,
     ) -> Result<SstableInfo> {
-        // TODO: Async upload.
+        // Only the data of the last block are left in the builder.
         let (meta, data) = builder.build()?;
         let data_size = meta.data_size as u64;
         let sst = Sstable::new(sst_id, Arc::new(meta));
         trace!("build sst: {:#?}", sst);
-        self.sstable_store
-            .put(&sst, data, CachePolicy::Fill)
-            .await?;
+        uploader.finish(&sst, data).await?;
         debug!("sst {} uploaded", sst_id);
         Ok(SstableInfo {
             id: sst_id,
@@ -169,3 +251,423 @@ impl Exhauster {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use std::sync::atomic::AtomicUsize;
+
+    use runkv_storage::components::{BlockCache, SstableStore, SstableStoreOptions};
+    use runkv_storage::{
+        BoxedObjectUploader, MemObjectStore, ObjectStore, ObjectUploader, Result as StorageResult,
+    };
+    use test_log::test;
+
+    use super::*;
+
+    /// [`MemObjectStore`] wrapper that records the max buffer handed over to or fetched from the
+    /// object store for sstable data, and the count of whole sstable data fetches.
+    #[derive(Default)]
+    struct MeteredObjectStore {
+        inner: MemObjectStore,
+        max_buffer: AtomicUsize,
+        data_gets: AtomicUsize,
+    }
+
+    impl MeteredObjectStore {
+        fn record(&self, len: usize) {
+            self.max_buffer.fetch_max(len, Ordering::SeqCst);
+        }
+
+        fn reset(&self) {
+            self.max_buffer.store(0, Ordering::SeqCst);
+            self.data_gets.store(0, Ordering::SeqCst);
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for MeteredObjectStore {
+        async fn put(&self, path: &str, obj: Vec<u8>) -> StorageResult<()> {
+            if path.ends_with(".data") {
+                self.record(obj.len());
+            }
+            self.inner.put(path, obj).await
+        }
+
+        async fn upload<'a>(&'a self, path: &str) -> StorageResult<BoxedObjectUploader<'a>> {
+            Ok(Box::new(MeteredObjectUploader {
+                object_store: self,
+                path: path.to_string(),
+                parts: vec![],
+            }))
+        }
+
+        async fn get(&self, path: &str) -> StorageResult<Option<Vec<u8>>> {
+            if path.ends_with(".data") {
+                self.data_gets.fetch_add(1, Ordering::SeqCst);
+            }
+            self.inner.get(path).await
+        }
+
+        async fn get_range(
+            &self,
+            path: &str,
+            range: Range<usize>,
+        ) -> StorageResult<Option<Vec<u8>>> {
+            self.record(range.len());
+            self.inner.get_range(path, range).await
+        }
+
+        async fn remove(&self, path: &str) -> StorageResult<()> {
+            self.inner.remove(path).await
+        }
+
+        async fn list(&self, prefix: &str) -> StorageResult<Vec<String>> {
+            self.inner.list(prefix).await
+        }
+    }
+
+    struct MeteredObjectUploader<'a> {
+        object_store: &'a MeteredObjectStore,
+        path: String,
+        /// Uploaded parts, which are regarded as remote.
+        parts: Vec<Vec<u8>>,
+    }
+
+    #[async_trait]
+    impl<'a> ObjectUploader for MeteredObjectUploader<'a> {
+        async fn write(&mut self, buf: Vec<u8>) -> StorageResult<()> {
+            self.object_store.record(buf.len());
+            self.parts.push(buf);
+            Ok(())
+        }
+
+        async fn finish(self: Box<Self>) -> StorageResult<()> {
+            self.object_store
+                .inner
+                .put(&self.path, self.parts.concat())
+                .await
+        }
+    }
+
+    const INPUTS: u64 = 4;
+    const KEYS_PER_INPUT: u64 = 2000;
+    const BLOCK_CAPACITY: usize = 1024;
+    const MEMORY_BUDGET: usize = 4 * BLOCK_CAPACITY;
+
+    #[test(tokio::test)]
+    async fn test_compaction_bounded_memory() {
+        let object_store = Arc::new(MeteredObjectStore::default());
+        let sstable_store = Arc::new(SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            object_store.clone(),
+            BlockCache::new(0),
+            1024,
+        )));
+        let sstable_builder_options = SstableBuilderOptions {
+            capacity: 64 << 20,
+            block_capacity: BLOCK_CAPACITY,
+            restart_interval: 16,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        let mut input_data_size = 0;
+        for sst_id in 1..=INPUTS {
+            let mut builder = SstableBuilder::new(sstable_builder_options.clone());
+            for i in 0..KEYS_PER_INPUT {
+                builder
+                    .add(&key(i * INPUTS + sst_id), 1, Some(&[b'v'; 100]))
+                    .unwrap();
+            }
+            let (meta, data) = builder.build().unwrap();
+            input_data_size += data.len();
+            let sst = Sstable::new(sst_id, Arc::new(meta));
+            sstable_store
+                .put(&sst, data, CachePolicy::Disable)
+                .await
+                .unwrap();
+        }
+        assert!(input_data_size > 100 * MEMORY_BUDGET);
+        object_store.reset();
+
+        let exhauster = Exhauster::new(ExhausterOptions {
+            node_id: 1,
+            sstable_store: sstable_store.clone(),
+            sstable_sequential_id: 1,
+        });
+        let rsp = exhauster
+            .compaction(Request::new(CompactionRequest {
+                sst_ids: (1..=INPUTS).collect_vec(),
+                watermark: 0,
+                sstable_capacity: sstable_builder_options.capacity as u64,
+                block_capacity: BLOCK_CAPACITY as u64,
+                restart_interval: 16,
+                bloom_false_positive: 0.1,
+                compression_algorithm: 0,
+                remove_tombstone: false,
+                partition_points: vec![],
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(rsp.new_sst_infos.len(), 1);
+        assert!(rsp.new_sst_infos[0].data_size as usize > 100 * MEMORY_BUDGET);
+        assert_eq!(object_store.data_gets.load(Ordering::SeqCst), 0);
+        let max_buffer = object_store.max_buffer.load(Ordering::SeqCst);
+        assert!(
+            max_buffer <= MEMORY_BUDGET,
+            "max buffer {} exceeds budget {}",
+            max_buffer,
+            MEMORY_BUDGET
+        );
+
+        let sst = sstable_store
+            .sstable(rsp.new_sst_infos[0].id)
+            .await
+            .unwrap();
+        let mut iter = SstableIterator::new(sstable_store, sst, CachePolicy::Disable);
+        iter.seek(Seek::First).await.unwrap();
+        let mut i = 0;
+        while iter.is_valid() {
+            i += 1;
+            assert_eq!(user_key(iter.key()), &key(i)[..]);
+            iter.next().await.unwrap();
+        }
+        assert_eq!(i, INPUTS * KEYS_PER_INPUT);
+    }
+
+    #[test(tokio::test)]
+    async fn test_compaction_range_tombstones() {
+        let sstable_store = Arc::new(SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            Arc::new(MemObjectStore::default()),
+            BlockCache::new(0),
+            1024,
+        )));
+        let sstable_builder_options = SstableBuilderOptions {
+            capacity: 64 << 10,
+            block_capacity: BLOCK_CAPACITY,
+            restart_interval: 16,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        // Older sstable.
+        let mut builder = SstableBuilder::new(sstable_builder_options.clone());
+        for i in 1..=10 {
+            builder.add(&key(i), i, Some(b"v")).unwrap();
+        }
+        let (meta, data) = builder.build().unwrap();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+        // Newer sstable flushed from a memtable with range tombstone `[3, 6) @ 20`.
+        let mut builder = SstableBuilder::new(sstable_builder_options.clone());
+        builder.add_range_tombstone(RangeTombstone::new(key(3), key(6), 20));
+        builder.add_tombstone(&key(3), 20).unwrap();
+        builder.add(&key(4), 30, Some(b"v")).unwrap();
+        let (meta, data) = builder.build().unwrap();
+        let sst = Sstable::new(2, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+
+        let exhauster = Exhauster::new(ExhausterOptions {
+            node_id: 1,
+            sstable_store: sstable_store.clone(),
+            sstable_sequential_id: 1,
+        });
+        let request = CompactionRequest {
+            sst_ids: vec![1, 2],
+            watermark: 25,
+            sstable_capacity: sstable_builder_options.capacity as u64,
+            block_capacity: BLOCK_CAPACITY as u64,
+            restart_interval: 16,
+            bloom_false_positive: 0.1,
+            compression_algorithm: 0,
+            remove_tombstone: true,
+            partition_points: vec![],
+        };
+
+        // Covered versions are dropped whether bottommost or not.
+        let expected = vec![
+            (key(1), 1),
+            (key(2), 2),
+            (key(4), 30),
+            (key(6), 6),
+            (key(7), 7),
+            (key(8), 8),
+            (key(9), 9),
+            (key(10), 10),
+        ];
+
+        // Bottommost compaction drops the range tombstone as well.
+        let rsp = exhauster
+            .compaction(Request::new(request.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(rsp.new_sst_infos.len(), 1);
+        let sst = sstable_store
+            .sstable(rsp.new_sst_infos[0].id)
+            .await
+            .unwrap();
+        assert!(sst.range_tombstones().is_empty());
+        assert_eq!(versions(&sstable_store, sst).await, expected);
+
+        let rsp = exhauster
+            .compaction(Request::new(CompactionRequest {
+                remove_tombstone: false,
+                ..request
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(rsp.new_sst_infos.len(), 1);
+        let sst = sstable_store
+            .sstable(rsp.new_sst_infos[0].id)
+            .await
+            .unwrap();
+        assert_eq!(
+            sst.range_tombstones(),
+            &[RangeTombstone::new(key(3), key(6), 20)]
+        );
+        assert_eq!(versions(&sstable_store, sst).await, expected);
+    }
+
+    #[test(tokio::test)]
+    async fn test_compaction_partitioned_range_tombstone() {
+        let sstable_store = Arc::new(SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            Arc::new(MemObjectStore::default()),
+            BlockCache::new(0),
+            1024,
+        )));
+        let sstable_builder_options = SstableBuilderOptions {
+            capacity: 64 << 10,
+            block_capacity: BLOCK_CAPACITY,
+            restart_interval: 16,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        let mut builder = SstableBuilder::new(sstable_builder_options.clone());
+        for i in 1..=10 {
+            builder.add(&key(i), i, Some(b"v")).unwrap();
+        }
+        let (meta, data) = builder.build().unwrap();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+        let mut builder = SstableBuilder::new(sstable_builder_options.clone());
+        builder.add_range_tombstone(RangeTombstone::new(key(3), key(8), 20));
+        builder.add_tombstone(&key(3), 20).unwrap();
+        let (meta, data) = builder.build().unwrap();
+        let sst = Sstable::new(2, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+
+        let exhauster = Exhauster::new(ExhausterOptions {
+            node_id: 1,
+            sstable_store: sstable_store.clone(),
+            sstable_sequential_id: 1,
+        });
+        let request = CompactionRequest {
+            sst_ids: vec![1, 2],
+            watermark: 0,
+            sstable_capacity: sstable_builder_options.capacity as u64,
+            block_capacity: BLOCK_CAPACITY as u64,
+            restart_interval: 16,
+            bloom_false_positive: 0.1,
+            compression_algorithm: 0,
+            remove_tombstone: false,
+            partition_points: vec![key(6)],
+        };
+
+        // The range tombstone is clipped into both outputs.
+        let rsp = exhauster
+            .compaction(Request::new(request.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(rsp.new_sst_infos.len(), 2);
+        let first = sstable_store
+            .sstable(rsp.new_sst_infos[0].id)
+            .await
+            .unwrap();
+        let second = sstable_store
+            .sstable(rsp.new_sst_infos[1].id)
+            .await
+            .unwrap();
+        assert_eq!(
+            first.range_tombstones(),
+            &[RangeTombstone::new(key(3), key(6), 20)]
+        );
+        assert_eq!(
+            second.range_tombstones(),
+            &[RangeTombstone::new(key(6), key(8), 20)]
+        );
+
+        // Bottommost compaction of only the first output drops the covered versions, but keeps
+        // the range tombstone that reaches out of its key range.
+        let rsp = exhauster
+            .compaction(Request::new(CompactionRequest {
+                sst_ids: vec![first.id()],
+                watermark: 25,
+                remove_tombstone: true,
+                partition_points: vec![],
+                ..request
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(rsp.new_sst_infos.len(), 1);
+        let sst = sstable_store
+            .sstable(rsp.new_sst_infos[0].id)
+            .await
+            .unwrap();
+        assert_eq!(
+            sst.range_tombstones(),
+            &[RangeTombstone::new(key(3), key(6), 20)]
+        );
+        assert_eq!(
+            versions(&sstable_store, sst).await,
+            vec![(key(1), 1), (key(2), 2)]
+        );
+
+        // Versions in the sibling output stay deleted.
+        for (i, expected) in [(6, None), (7, None), (8, Some(Bytes::from("v")))] {
+            assert_eq!(
+                sstable_store
+                    .get(&second, &key(i), u64::MAX, CachePolicy::Disable)
+                    .await
+                    .unwrap(),
+                expected
+            );
+        }
+    }
+
+    async fn versions(sstable_store: &SstableStoreRef, sst: Sstable) -> Vec<(Vec<u8>, u64)> {
+        let mut iter = SstableIterator::new(sstable_store.clone(), sst, CachePolicy::Disable);
+        iter.seek(Seek::First).await.unwrap();
+        let mut versions = vec![];
+        while iter.is_valid() {
+            versions.push((user_key(iter.key()).to_vec(), timestamp(iter.key())));
+            iter.next().await.unwrap();
+        }
+        versions
+    }
+
+    fn key(i: u64) -> Vec<u8> {
+        format!("k{:010}", i).into_bytes()
+    }
+}
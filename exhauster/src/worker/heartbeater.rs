@@ -2,12 +2,11 @@ use std::time::Duration;
 
 use async_trait::async_trait;
 use runkv_common::channel_pool::ChannelPool;
-use runkv_common::Worker;
+use runkv_common::LoopWorker;
 use runkv_proto::common::Endpoint as PbEndpoint;
 use runkv_proto::rudder::rudder_service_client::RudderServiceClient;
 use runkv_proto::rudder::{heartbeat_request, ExhausterHeartbeatRequest, HeartbeatRequest};
 use tonic::Request;
-use tracing::warn;
 
 use crate::error::{err, Result};
 
@@ -39,7 +38,6 @@ impl Heartbeater {
     }
 
     async fn run_inner(&mut self) -> Result<()> {
-        tokio::time::sleep(self.heartbeat_interval).await;
         let req = HeartbeatRequest {
             node_id: self.node_id,
             endpoint: Some(self.endpoint.clone()),
@@ -60,16 +58,16 @@ impl Heartbeater {
 }
 
 #[async_trait]
-impl Worker for Heartbeater {
-    async fn run(&mut self) -> anyhow::Result<()> {
-        // TODO: Gracefully kill.
-        loop {
-            match self.run_inner().await {
-                Ok(_) => {}
-                Err(e) => {
-                    warn!("error occur when heartbeater running: {}", e);
-                }
-            }
-        }
+impl LoopWorker for Heartbeater {
+    fn name(&self) -> &str {
+        "heartbeater"
+    }
+
+    async fn run_once(&mut self) -> anyhow::Result<()> {
+        self.run_inner().await
+    }
+
+    fn interval(&self) -> Duration {
+        self.heartbeat_interval
     }
 }
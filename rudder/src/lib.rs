@@ -12,7 +12,7 @@ use error::{Error, Result};
 use meta::mem::MemoryMetaStore;
 use meta::MetaStoreRef;
 use runkv_common::channel_pool::ChannelPool;
-use runkv_common::BoxedWorker;
+use runkv_common::{BoxedWorker, WorkerHandle};
 use runkv_proto::rudder::rudder_service_server::RudderServiceServer;
 use runkv_storage::components::{BlockCache, SstableStore, SstableStoreOptions, SstableStoreRef};
 use runkv_storage::manifest::{VersionManager, VersionManagerOptions};
@@ -29,9 +29,11 @@ pub async fn bootstrap_rudder(
 ) -> Result<()> {
     let addr_str = format!("{}:{}", config.host, config.port);
 
-    for mut worker in workers.into_iter() {
-        tokio::spawn(async move { worker.run().await });
-    }
+    // Workers are shut down when the handles are dropped.
+    let _worker_handles = workers
+        .into_iter()
+        .map(WorkerHandle::spawn)
+        .collect::<Vec<_>>();
 
     Server::builder()
         .add_service(RudderServiceServer::new(rudder))
@@ -94,17 +96,17 @@ fn build_sstable_store(
     object_store: ObjectStoreRef,
 ) -> Result<SstableStoreRef> {
     let block_cache = BlockCache::new(0);
-    let sstable_store_options = SstableStoreOptions {
-        path: config.data_path.clone(),
+    let sstable_store_options = SstableStoreOptions::new(
+        config.data_path.clone(),
         object_store,
         block_cache,
-        meta_cache_capacity: config
+        config
             .cache
             .meta_cache_capacity
             .parse::<ByteSize>()
             .map_err(Error::config_err)?
             .0 as usize,
-    };
+    );
     let sstable_store = SstableStore::new(sstable_store_options);
     Ok(Arc::new(sstable_store))
 }
@@ -9,7 +9,7 @@ use rand::prelude::SliceRandom;
 use rand::{thread_rng, Rng};
 use runkv_common::channel_pool::ChannelPool;
 use runkv_common::config::{LevelCompactionStrategy, LevelOptions};
-use runkv_common::Worker;
+use runkv_common::{CancellationToken, Worker};
 use runkv_proto::exhauster::exhauster_service_client::ExhausterServiceClient;
 use runkv_proto::exhauster::CompactionRequest;
 use runkv_proto::manifest::{SstableDiff, SstableOp, VersionDiff};
@@ -142,16 +142,16 @@ pub struct CompactionDetector {
 
 #[async_trait]
 impl Worker for CompactionDetector {
-    async fn run(&mut self) -> anyhow::Result<()> {
-        // TODO: Gracefully kill.
-        loop {
-            match self.run_inner().await {
+    async fn run(&mut self, cancel: CancellationToken) -> anyhow::Result<()> {
+        while !cancel.is_cancelled() {
+            match self.run_inner(&cancel).await {
                 Ok(_) => {}
                 Err(e) => {
                     warn!("error occur when compactor running: {}", e);
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -179,7 +179,8 @@ impl CompactionDetector {
         }
     }
 
-    async fn run_inner(&mut self) -> Result<()> {
+    /// Trigger compactions periodically until `cancel` is triggered.
+    async fn run_inner(&mut self, cancel: &CancellationToken) -> Result<()> {
         let mut trigger_l0_ticker =
             tokio::time::interval(self.lsm_tree_config.trigger_l0_compaction_interval);
         let mut trigger_lmax_ticker =
@@ -190,6 +191,7 @@ impl CompactionDetector {
 
         loop {
             tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
                 _ = trigger_l0_ticker.tick() => {
                     trace!("tick l0 compaction [interval: {:?}]", self.lsm_tree_config.trigger_l0_compaction_interval);
                     let ctx = self.create_context(0);
@@ -84,10 +84,10 @@ fn build_block(t: u32, i: u64) -> Block {
     let mut builder = BlockBuilder::new(options);
     for tt in 1..=t {
         for ii in 1..=i {
-            builder.add(&key(tt, ii), &value(ii));
+            builder.add(&key(tt, ii), &value(ii)).unwrap();
         }
     }
-    let data = builder.build();
+    let data = builder.build().unwrap();
     Block::decode(&data[..]).unwrap()
 }
 
@@ -0,0 +1,40 @@
+use bytes::BufMut;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use runkv_storage::utils::{compare_full_key, full_key};
+
+const KEYS: u64 = 100000;
+
+// Run with and without `--features varint-timestamp` to compare the layouts.
+fn bench_full_key(c: &mut Criterion) {
+    let full_keys = (0..KEYS).map(|i| full_key(&key(i), i)).collect::<Vec<_>>();
+
+    c.bench_function(&format!("full key - encode - {} keys", KEYS), |b| {
+        b.iter(|| {
+            for i in 0..KEYS {
+                full_key(&key(i), i);
+            }
+        })
+    });
+
+    c.bench_with_input(
+        BenchmarkId::new(format!("full key - compare - {} keys", KEYS), ""),
+        &full_keys,
+        |b, full_keys| {
+            b.iter(|| {
+                for (lhs, rhs) in full_keys.iter().zip(full_keys.iter().skip(1)) {
+                    compare_full_key(lhs, rhs);
+                }
+            })
+        },
+    );
+}
+
+criterion_group!(benches, bench_full_key);
+criterion_main!(benches);
+
+fn key(i: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.put_u8(b'k');
+    buf.put_u64(i);
+    buf
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use bytes::BufMut;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use runkv_common::coding::CompressionAlgorithm;
+use runkv_storage::components::{
+    BlockCache, CachePolicy, Sstable, SstableBuilder, SstableBuilderOptions, SstableStore,
+    SstableStoreOptions,
+};
+use runkv_storage::MemObjectStore;
+use tokio::runtime::Runtime;
+
+const KEYS_PER_SSTABLE: u64 = 10000;
+const LOOKUPS: u64 = 1000;
+const RESTART_INTERVAL: usize = 16;
+const BLOCK_CAPACITY: usize = 64 * 1024;
+const SSTABLE_CAPACITY: usize = 4 * 1024 * 1024;
+
+async fn sstable_get_absent(sstable_store: &SstableStore, sst: &Sstable) {
+    for i in 0..LOOKUPS {
+        // Odd keys are never written.
+        let v = sstable_store
+            .get(sst, &key(i * 2 + 1), u64::MAX, CachePolicy::Disable)
+            .await
+            .unwrap();
+        assert!(v.is_none());
+    }
+}
+
+fn bench_sstable_get(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+
+    for bloom_false_positive in [0.0, 0.01] {
+        let sstable_store = SstableStore::new(SstableStoreOptions::new(
+            "bench".to_string(),
+            Arc::new(MemObjectStore::default()),
+            BlockCache::new(0),
+            1024,
+        ));
+        let sst = rt.block_on(build_sstable(&sstable_store, bloom_false_positive));
+
+        c.bench_with_input(
+            BenchmarkId::new(
+                format!(
+                    "sstable - get absent - {} lookups - bloom false positive {}",
+                    LOOKUPS, bloom_false_positive
+                ),
+                "",
+            ),
+            &(sstable_store, sst),
+            |b, (sstable_store, sst)| {
+                b.iter(|| rt.block_on(sstable_get_absent(sstable_store, sst)));
+            },
+        );
+    }
+}
+
+criterion_group!(benches, bench_sstable_get);
+criterion_main!(benches);
+
+async fn build_sstable(sstable_store: &SstableStore, bloom_false_positive: f64) -> Sstable {
+    let options = SstableBuilderOptions {
+        capacity: SSTABLE_CAPACITY,
+        block_capacity: BLOCK_CAPACITY,
+        restart_interval: RESTART_INTERVAL,
+        bloom_false_positive,
+        compression_algorithm: CompressionAlgorithm::None,
+        ..Default::default()
+    };
+    let mut builder = SstableBuilder::new(options);
+    for i in 0..KEYS_PER_SSTABLE {
+        builder.add(&key(i * 2), 1, Some(&value(i))).unwrap();
+    }
+    let (meta, data) = builder.build().unwrap();
+    let sst = Sstable::new(1, Arc::new(meta));
+    sstable_store
+        .put(&sst, data, CachePolicy::Disable)
+        .await
+        .unwrap();
+    sst
+}
+
+fn key(i: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.put_u8(b'k');
+    buf.put_u64(i);
+    buf
+}
+
+fn value(i: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.put_u64(i);
+    buf
+}
@@ -8,6 +8,20 @@ pub enum Error {
     EncodeError(String),
     #[error("decode error: {0}")]
     DecodeError(String),
+    #[error("block checksum mismatch")]
+    BlockChecksumMismatch,
+    #[error("checksum mismatch: [expected: {expected}] [actual: {actual}]")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+    #[error("data key {key_id} of sstable {sst_id} is unavailable")]
+    KeyUnavailable { sst_id: u64, key_id: u64 },
+    #[error("failed to decrypt sstable {sst_id} with data key {key_id}")]
+    DecryptError { sst_id: u64, key_id: u64 },
+    #[error("unsupported format version {version} of sstable {sst_id}")]
+    UnsupportedSstableVersion { sst_id: u64, version: u8 },
+    #[error("duplicate key: [user key: {user_key:?}] [timestamp: {timestamp}]")]
+    DuplicateKey { user_key: Vec<u8>, timestamp: u64 },
+    #[error("{field} overflow: {value}")]
+    Overflow { field: &'static str, value: u64 },
     #[error("object store error: {0}")]
     ObjectStoreError(#[from] ObjectStoreError),
     #[error("manifest error: {0}")]
@@ -6,27 +6,57 @@ use bytes::{Buf, BufMut};
 use lz4::Decoder;
 use runkv_common::coding::CompressionAlgorithm;
 
+use super::sstable::to_u32;
 use crate::lsm_tree::{
     DEFAULT_BLOCK_SIZE, DEFAULT_ENTRY_SIZE, DEFAULT_RESTART_INTERVAL, TEST_DEFAULT_RESTART_INTERVAL,
 };
 use crate::utils::{
-    compare_full_key, crc32check, crc32sum, key_diff, var_u32_len, BufExt, BufMutExt,
+    compare_full_key, crc32check, crc32sum, full_key, key_diff, raw_value, var_u32_len, BufExt,
+    BufMutExt,
 };
 use crate::{Error, Result};
 
+#[derive(Clone, Copy, Debug)]
+pub struct BlockDecodeOptions {
+    /// Verify the checksum of the block. Blocks that have been verified, e.g. cached ones, can
+    /// skip it.
+    pub verify_checksum: bool,
+}
+
+impl Default for BlockDecodeOptions {
+    fn default() -> Self {
+        Self {
+            verify_checksum: true,
+        }
+    }
+}
+
 pub struct Block {
     /// Uncompressed entries data.
     data: Vec<u8>,
     /// Restart points.
     restart_points: Vec<u32>,
+    /// Count of entries, counted when decoding.
+    entry_count: usize,
+    /// Whether the checksum has been verified when decoding.
+    verified: bool,
 }
 
 impl Block {
+    /// Decode block with checksum verified.
     pub fn decode(buf: &[u8]) -> Result<Self> {
+        Self::decode_with_options(buf, BlockDecodeOptions::default())
+    }
+
+    /// Decode block with `options`. Returns [`Error::BlockChecksumMismatch`] if the checksum is
+    /// verified and mismatches.
+    pub fn decode_with_options(buf: &[u8], options: BlockDecodeOptions) -> Result<Self> {
         // Verify checksum.
-        let crc32sum = (&buf[buf.len() - 4..]).get_u32_le();
-        if !crc32check(&buf[..buf.len() - 4], crc32sum) {
-            return Err(Error::DecodeError("invalid checksum".to_string()));
+        if options.verify_checksum {
+            let crc32sum = (&buf[buf.len() - 4..]).get_u32_le();
+            if !crc32check(&buf[..buf.len() - 4], crc32sum) {
+                return Err(Error::BlockChecksumMismatch);
+            }
         }
 
         // Decompress.
@@ -45,6 +75,9 @@ impl Block {
                     .unwrap();
                 decoded
             }
+            CompressionAlgorithm::Zstd { .. } => {
+                zstd::stream::decode_all(&buf[..buf.len() - 5]).map_err(Error::decode_error)?
+            }
         };
 
         // Decode restart points.
@@ -56,12 +89,32 @@ impl Block {
             restart_points.push(restart_points_buf.get_u32_le());
         }
 
+        let data = buf[..data_len].to_vec();
+        let entry_count = Self::count_entries(&data);
+
         Ok(Block {
-            data: buf[..data_len].to_vec(),
+            data,
             restart_points,
+            entry_count,
+            verified: options.verify_checksum,
         })
     }
 
+    fn count_entries(data: &[u8]) -> usize {
+        let mut offset = 0;
+        let mut count = 0;
+        while offset < data.len() {
+            offset += KeyPrefix::decode(&mut &data[offset..], offset).entry_len();
+            count += 1;
+        }
+        count
+    }
+
+    /// Whether the checksum has been verified when decoding.
+    pub fn is_verified(&self) -> bool {
+        self.verified
+    }
+
     /// Entries data len.
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
@@ -69,6 +122,16 @@ impl Block {
         self.data.len()
     }
 
+    /// Count of entries.
+    pub fn entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    /// Uncompressed size of entries data.
+    pub fn data_size(&self) -> usize {
+        self.data.len()
+    }
+
     /// Get restart point by index.
     pub fn restart_point(&self, index: usize) -> u32 {
         self.restart_points[index]
@@ -107,24 +170,24 @@ impl Block {
 /// [`KeyPrefix`] contains info for prefix compression.
 #[derive(Debug)]
 pub struct KeyPrefix {
-    overlap: usize,
-    diff: usize,
-    value: usize,
+    overlap: u32,
+    diff: u32,
+    value: u32,
     /// Used for calculating range, won't be encoded.
     offset: usize,
 }
 
 impl KeyPrefix {
     pub fn encode(&self, mut buf: &mut impl BufMut) {
-        buf.put_var_u32(self.overlap as u32);
-        buf.put_var_u32(self.diff as u32);
-        buf.put_var_u32(self.value as u32);
+        buf.put_var_u32(self.overlap);
+        buf.put_var_u32(self.diff);
+        buf.put_var_u32(self.value);
     }
 
     pub fn decode(mut buf: &mut impl Buf, offset: usize) -> Self {
-        let overlap = buf.get_var_u32() as usize;
-        let diff = buf.get_var_u32() as usize;
-        let value = buf.get_var_u32() as usize;
+        let overlap = buf.get_var_u32();
+        let diff = buf.get_var_u32();
+        let value = buf.get_var_u32();
         Self {
             overlap,
             diff,
@@ -135,29 +198,29 @@ impl KeyPrefix {
 
     /// Encoded length.
     fn len(&self) -> usize {
-        var_u32_len(self.overlap as u32)
-            + var_u32_len(self.diff as u32)
-            + var_u32_len(self.value as u32)
+        var_u32_len(self.overlap) + var_u32_len(self.diff) + var_u32_len(self.value)
     }
 
     /// Get overlap len.
     pub fn overlap_len(&self) -> usize {
-        self.overlap
+        self.overlap as usize
     }
 
     /// Get diff key range.
     pub fn diff_key_range(&self) -> Range<usize> {
-        self.offset + self.len()..self.offset + self.len() + self.diff
+        let start = self.offset + self.len();
+        start..start + self.diff as usize
     }
 
     /// Get value range.
     pub fn value_range(&self) -> Range<usize> {
-        self.offset + self.len() + self.diff..self.offset + self.len() + self.diff + self.value
+        let start = self.offset + self.len() + self.diff as usize;
+        start..start + self.value as usize
     }
 
     /// Get entry len.
     pub fn entry_len(&self) -> usize {
-        self.len() + self.diff + self.value
+        self.len() + self.diff as usize + self.value as usize
     }
 
     pub fn max_len() -> usize {
@@ -228,10 +291,13 @@ impl BlockBuilder {
     /// entry (kv pair): | overlap len (2B) | diff len (2B) | value len(4B) | diff key | value |
     /// ```
     ///
+    /// Returns [`Error::Overflow`] if any of the lengths or the restart point doesn't fit in 4
+    /// bytes, in which case nothing is appended.
+    ///
     /// # Panics
     ///
     /// Panic if key is not added in ASCEND order.
-    pub fn add(&mut self, key: &[u8], value: &[u8]) {
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
         if self.entry_count > 0 {
             // TODO: Remove me.
             // if self.last_key >= key {
@@ -243,28 +309,37 @@ impl BlockBuilder {
             // }
             debug_assert_eq!(compare_full_key(&self.last_key, key), Ordering::Less);
         }
-        // Update restart point if needed and calculate diff key.
-        let diff_key = if self.entry_count % self.restart_count == 0 {
-            self.restart_points.push(self.buf.len() as u32);
-            self.last_key = key.to_vec();
-            key
+        // Calculate diff key and the restart point if needed.
+        let (diff_key, restart_point) = if self.entry_count % self.restart_count == 0 {
+            (key, Some(to_u32("restart point", self.buf.len())?))
         } else {
-            key_diff(&self.last_key, key)
+            (key_diff(&self.last_key, key), None)
         };
 
         let prefix = KeyPrefix {
-            overlap: key.len() - diff_key.len(),
-            diff: diff_key.len(),
-            value: value.len(),
+            overlap: to_u32("key overlap len", key.len() - diff_key.len())?,
+            diff: to_u32("key diff len", diff_key.len())?,
+            value: to_u32("value len", value.len())?,
             offset: self.buf.len(),
         };
 
+        if let Some(restart_point) = restart_point {
+            self.restart_points.push(restart_point);
+        }
         prefix.encode(&mut self.buf);
         self.buf.put_slice(diff_key);
         self.buf.put_slice(value);
 
         self.last_key = key.to_vec();
         self.entry_count += 1;
+        Ok(())
+    }
+
+    /// Append a tombstone of `user_key` at `timestamp` to the block.
+    ///
+    /// NOTE: Key must be added in ASCEND order.
+    pub fn add_tombstone(&mut self, user_key: &[u8], timestamp: u64) -> Result<()> {
+        self.add(&full_key(user_key, timestamp), &raw_value(None))
     }
 
     /// Finish building block.
@@ -276,36 +351,35 @@ impl BlockBuilder {
     /// uncompressed: | compression method (1B) | crc32sum (4B) |
     /// ```
     ///
-    /// # Panics
-    ///
-    /// Panic if there is compression error.
-    pub fn build(mut self) -> Vec<u8> {
+    /// Returns [`Error::Overflow`] if the count of restart points doesn't fit in 4 bytes, or
+    /// [`Error::EncodeError`] if there is compression error.
+    pub fn build(mut self) -> Result<Vec<u8>> {
         assert!(self.entry_count > 0);
+        let restart_points_len = to_u32("restart points len", self.restart_points.len())?;
         for restart_point in &self.restart_points {
             self.buf.put_u32_le(*restart_point);
         }
-        self.buf.put_u32_le(self.restart_points.len() as u32);
+        self.buf.put_u32_le(restart_points_len);
         let mut buf = match self.compression_algorithm {
             CompressionAlgorithm::None => self.buf,
             CompressionAlgorithm::Lz4 => {
                 let mut encoder = lz4::EncoderBuilder::new()
                     .level(4)
                     .build(Vec::with_capacity(self.buf.len()).writer())
-                    .map_err(Error::encode_error)
-                    .unwrap();
-                encoder
-                    .write(&self.buf[..])
-                    .map_err(Error::encode_error)
-                    .unwrap();
+                    .map_err(Error::encode_error)?;
+                encoder.write(&self.buf[..]).map_err(Error::encode_error)?;
                 let (writer, result) = encoder.finish();
-                result.map_err(Error::encode_error).unwrap();
+                result.map_err(Error::encode_error)?;
                 writer.into_inner()
             }
+            CompressionAlgorithm::Zstd { level } => {
+                zstd::bulk::compress(&self.buf[..], level).map_err(Error::encode_error)?
+            }
         };
         self.compression_algorithm.encode(&mut buf);
         let checksum = crc32sum(&buf);
         buf.put_u32_le(checksum);
-        buf
+        Ok(buf)
     }
 
     /// Approximate block len (uncompressed).
@@ -329,11 +403,11 @@ mod tests {
     fn test_block_enc_dec() {
         let options = BlockBuilderOptions::default();
         let mut builder = BlockBuilder::new(options);
-        builder.add(&full_key(b"k1", 1), b"v01");
-        builder.add(&full_key(b"k2", 2), b"v02");
-        builder.add(&full_key(b"k3", 3), b"v03");
-        builder.add(&full_key(b"k4", 4), b"v04");
-        let buf = builder.build();
+        builder.add(&full_key(b"k1", 1), b"v01").unwrap();
+        builder.add(&full_key(b"k2", 2), b"v02").unwrap();
+        builder.add(&full_key(b"k3", 3), b"v03").unwrap();
+        builder.add(&full_key(b"k4", 4), b"v04").unwrap();
+        let buf = builder.build().unwrap();
         let block = Arc::new(Block::decode(&buf).unwrap());
         let mut bi = BlockIterator::new(block);
 
@@ -361,6 +435,78 @@ mod tests {
         assert!(!bi.is_valid());
     }
 
+    #[test]
+    fn test_block_checksum() {
+        let mut builder = BlockBuilder::new(BlockBuilderOptions::default());
+        builder.add(&full_key(b"k1", 1), b"v01").unwrap();
+        builder.add(&full_key(b"k2", 2), b"v02").unwrap();
+        let mut buf = builder.build().unwrap();
+        let block = Block::decode(&buf).unwrap();
+        assert!(block.is_verified());
+
+        let offset = buf.windows(3).position(|w| w == b"v02").unwrap();
+        buf[offset] = b'x';
+        assert!(matches!(
+            Block::decode(&buf),
+            Err(Error::BlockChecksumMismatch)
+        ));
+        let block = Arc::new(
+            Block::decode_with_options(
+                &buf,
+                BlockDecodeOptions {
+                    verify_checksum: false,
+                },
+            )
+            .unwrap(),
+        );
+        assert!(!block.is_verified());
+        let mut bi = BlockIterator::new(block);
+        bi.seek(Seek::Last).unwrap();
+        assert_eq!(b"x02", bi.value());
+    }
+
+    #[test]
+    fn test_entry_count() {
+        for compression_algorithm in [
+            CompressionAlgorithm::None,
+            CompressionAlgorithm::Lz4,
+            CompressionAlgorithm::Zstd { level: 3 },
+        ] {
+            for count in [
+                1,
+                3,
+                DEFAULT_RESTART_INTERVAL,
+                DEFAULT_RESTART_INTERVAL * 4 + 5,
+            ] {
+                let mut builder = BlockBuilder::new(BlockBuilderOptions {
+                    compression_algorithm,
+                    ..Default::default()
+                });
+                let mut data_size = 0;
+                for i in 0..count {
+                    let key = full_key(format!("k{:04}", i).as_bytes(), i as u64);
+                    let value = format!("v{}", i).into_bytes();
+                    data_size += key.len() + value.len();
+                    builder.add(&key, &value).unwrap();
+                }
+                let block = Block::decode(&builder.build().unwrap()).unwrap();
+                assert_eq!(block.entry_count(), count);
+                // Prefixes are shared and lengths are encoded.
+                assert!(block.data_size() > 0);
+                assert!(block.data_size() <= data_size + count * KeyPrefix::max_len());
+
+                let mut bi = BlockIterator::new(Arc::new(block));
+                bi.seek(Seek::First).unwrap();
+                let mut iterated = 0;
+                while bi.is_valid() {
+                    iterated += 1;
+                    bi.next().unwrap();
+                }
+                assert_eq!(iterated, count);
+            }
+        }
+    }
+
     #[test]
     fn test_compressed_block_enc_dec() {
         let options = BlockBuilderOptions {
@@ -368,11 +514,11 @@ mod tests {
             ..Default::default()
         };
         let mut builder = BlockBuilder::new(options);
-        builder.add(&full_key(b"k1", 1), b"v01");
-        builder.add(&full_key(b"k2", 2), b"v02");
-        builder.add(&full_key(b"k3", 3), b"v03");
-        builder.add(&full_key(b"k4", 4), b"v04");
-        let buf = builder.build();
+        builder.add(&full_key(b"k1", 1), b"v01").unwrap();
+        builder.add(&full_key(b"k2", 2), b"v02").unwrap();
+        builder.add(&full_key(b"k3", 3), b"v03").unwrap();
+        builder.add(&full_key(b"k4", 4), b"v04").unwrap();
+        let buf = builder.build().unwrap();
         let block = Arc::new(Block::decode(&buf).unwrap());
         let mut bi = BlockIterator::new(block);
 
@@ -404,11 +550,13 @@ mod tests {
     fn test_asc() {
         let options = BlockBuilderOptions::default();
         let mut builder = BlockBuilder::new(options);
-        builder.add(&full_key(b"k1", u64::MAX / 2), b"v11");
-        builder.add(&full_key(b"k1", u64::MAX / 2 - 1), b"v12");
-        builder.add(&full_key(b"k2", u64::MAX / 2), b"v21");
-        builder.add(&full_key(b"k20000", u64::MAX), b"v22");
-        let buf = builder.build();
+        builder.add(&full_key(b"k1", u64::MAX / 2), b"v11").unwrap();
+        builder
+            .add(&full_key(b"k1", u64::MAX / 2 - 1), b"v12")
+            .unwrap();
+        builder.add(&full_key(b"k2", u64::MAX / 2), b"v21").unwrap();
+        builder.add(&full_key(b"k20000", u64::MAX), b"v22").unwrap();
+        let buf = builder.build().unwrap();
         let block = Arc::new(Block::decode(&buf).unwrap());
         let mut bi = BlockIterator::new(block);
 
@@ -1,15 +1,26 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use bytes::BufMut;
 use futures::Future;
 use moka::future::Cache;
+use serde_derive::Serialize;
 
 use super::Block;
 use crate::lsm_tree::DEFAULT_BLOCK_SIZE;
 use crate::{Error, Result};
 
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize)]
+pub struct BlockCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Clone)]
 pub struct BlockCache {
     inner: Cache<Vec<u8>, Arc<Block>>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
 }
 
 impl BlockCache {
@@ -19,11 +30,17 @@ impl BlockCache {
             .initial_capacity(capacity / DEFAULT_BLOCK_SIZE)
             .max_capacity(capacity as u64)
             .build();
-        Self { inner: cache }
+        Self {
+            inner: cache,
+            hits: Arc::new(AtomicU64::new(0)),
+            misses: Arc::new(AtomicU64::new(0)),
+        }
     }
 
     pub fn get(&self, sst_id: u64, block_idx: usize) -> Option<Arc<Block>> {
-        self.inner.get(&Self::key(sst_id, block_idx))
+        let block = self.inner.get(&Self::key(sst_id, block_idx));
+        self.record(block.is_some());
+        block
     }
 
     pub async fn insert(&self, sst_id: u64, block_idx: usize, block: Arc<Block>) {
@@ -39,16 +56,41 @@ impl BlockCache {
     where
         F: Future<Output = Result<Arc<Block>>>,
     {
-        match self
-            .inner
-            .get_or_try_insert_with(Self::key(sst_id, block_idx), f)
-            .await
-        {
+        let key = Self::key(sst_id, block_idx);
+        if let Some(block) = self.inner.get(&key) {
+            self.record(true);
+            return Ok(block);
+        }
+        self.record(false);
+        match self.inner.get_or_try_insert_with(key, f).await {
             Ok(block) => Ok(block),
             Err(arc_error) => Err(Error::Other(arc_error.to_string())),
         }
     }
 
+    /// Evict blocks `[0, blocks_len)` of sstable `sst_id`.
+    pub async fn invalidate_sstable(&self, sst_id: u64, blocks_len: usize) {
+        for block_idx in 0..blocks_len {
+            self.inner.invalidate(&Self::key(sst_id, block_idx)).await;
+        }
+    }
+
+    /// Hit and miss counts of lookups since the cache is created.
+    pub fn stats(&self) -> BlockCacheStats {
+        BlockCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, hit: bool) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     fn key(sst_id: u64, block_idx: usize) -> Vec<u8> {
         let mut key = Vec::with_capacity(16);
         key.put_u64_le(sst_id);
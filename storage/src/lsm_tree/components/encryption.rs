@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use aes::Aes256;
+use async_trait::async_trait;
+use bytes::{Buf, BufMut};
+use ctr::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+
+use crate::Result;
+
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+/// Magic number at the end of encrypted sstable meta objects.
+///
+/// Unencrypted meta objects end with the data size (8B, little endian), whose high 4 bytes never
+/// equal the magic number in practice.
+const ENCRYPTED_META_MAGIC: u32 = 0x454e_4331;
+
+/// Length of the footer of encrypted sstable meta objects.
+const ENCRYPTED_META_FOOTER_LEN: usize = 12;
+
+/// Keystream domain of sstable data.
+const DOMAIN_DATA: u8 = 0;
+/// Keystream domain of sstable meta.
+const DOMAIN_META: u8 = 1;
+
+/// [`DataKey`] is the key to encrypt sstables with. Only its `id` is persisted with sstables.
+#[derive(Clone)]
+pub struct DataKey {
+    pub id: u64,
+    pub key: [u8; 32],
+}
+
+impl std::fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// [`KeyProvider`] supplies data keys for sstable encryption, e.g. backed by a KMS.
+#[async_trait]
+pub trait KeyProvider: Send + Sync + 'static {
+    /// Get the data key to encrypt sstable `sst_id` with.
+    async fn data_key(&self, sst_id: u64) -> Result<DataKey>;
+
+    /// Get the data key of `key_id` to decrypt sstables with. Returns `None` if the key is
+    /// unavailable, e.g. destroyed after rotation.
+    async fn key(&self, key_id: u64) -> Result<Option<DataKey>>;
+}
+
+pub type KeyProviderRef = Arc<dyn KeyProvider>;
+
+/// [`SstableCipher`] encrypts and decrypts data and meta of an sstable with AES-256-CTR.
+///
+/// The keystream is seekable, so blocks can be decrypted separately and the encrypted data has the
+/// same layout as the plain data. Integrity relies on the checksums of blocks and meta.
+#[derive(Clone, Debug)]
+pub struct SstableCipher {
+    sst_id: u64,
+    key: Arc<DataKey>,
+}
+
+impl SstableCipher {
+    pub fn new(sst_id: u64, key: DataKey) -> Self {
+        Self {
+            sst_id,
+            key: Arc::new(key),
+        }
+    }
+
+    pub fn key_id(&self) -> u64 {
+        self.key.id
+    }
+
+    /// Encrypt or decrypt `buf` in place, which is at `offset` of the sstable data.
+    pub fn apply_data(&self, offset: usize, buf: &mut [u8]) {
+        self.apply(DOMAIN_DATA, offset, buf)
+    }
+
+    /// Encrypt or decrypt the sstable meta in place.
+    pub fn apply_meta(&self, buf: &mut [u8]) {
+        self.apply(DOMAIN_META, 0, buf)
+    }
+
+    fn apply(&self, domain: u8, offset: usize, buf: &mut [u8]) {
+        // | sst id (8B) | domain (1B) | counter (7B) |
+        let mut iv = [0; 16];
+        (&mut iv[..8]).put_u64(self.sst_id);
+        iv[8] = domain;
+        let mut cipher = Aes256Ctr::new((&self.key.key).into(), (&iv).into());
+        cipher.seek(offset as u64);
+        cipher.apply_keystream(buf);
+    }
+}
+
+/// Append the footer to the encrypted sstable meta.
+///
+/// Format:
+///
+/// ```plain
+/// | encrypted meta | key id (8B) | magic (4B) |
+/// ```
+pub fn encode_encrypted_meta_footer(buf: &mut Vec<u8>, key_id: u64) {
+    buf.put_u64_le(key_id);
+    buf.put_u32_le(ENCRYPTED_META_MAGIC);
+}
+
+/// Returns the key id and the encrypted meta if `buf` is an encrypted sstable meta.
+pub fn decode_encrypted_meta_footer(buf: &[u8]) -> Option<(u64, &[u8])> {
+    if buf.len() < ENCRYPTED_META_FOOTER_LEN
+        || (&buf[buf.len() - 4..]).get_u32_le() != ENCRYPTED_META_MAGIC
+    {
+        return None;
+    }
+    let payload_len = buf.len() - ENCRYPTED_META_FOOTER_LEN;
+    let key_id = (&buf[payload_len..]).get_u64_le();
+    Some((key_id, &buf[..payload_len]))
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_sstable_cipher() {
+        let cipher = SstableCipher::new(
+            1,
+            DataKey {
+                id: 1,
+                key: [7; 32],
+            },
+        );
+        let plain = (0..100u8).collect::<Vec<_>>();
+        let mut data = plain.clone();
+        cipher.apply_data(0, &mut data);
+        assert_ne!(data, plain);
+
+        // Parts can be decrypted separately.
+        let mut part = data[37..64].to_vec();
+        cipher.apply_data(37, &mut part);
+        assert_eq!(part, &plain[37..64]);
+
+        // Data and meta use different keystreams.
+        let mut meta = plain.clone();
+        cipher.apply_meta(&mut meta);
+        assert_ne!(meta, data);
+        cipher.apply_meta(&mut meta);
+        assert_eq!(meta, plain);
+
+        encode_encrypted_meta_footer(&mut meta, 42);
+        assert_eq!(decode_encrypted_meta_footer(&meta), Some((42, &plain[..])));
+        assert_eq!(decode_encrypted_meta_footer(&plain), None);
+    }
+}
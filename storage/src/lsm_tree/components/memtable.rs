@@ -1,25 +1,48 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
 use bytes::Bytes;
+use parking_lot::RwLock;
 
-use super::{IterRef, Skiplist};
+use super::{max_covering_timestamp, IterRef, RangeTombstone, Skiplist, SkiplistOptions};
 use crate::utils::{full_key, raw_value, value, FullKeyComparator};
 
 #[derive(Clone)]
 pub struct Memtable {
     inner: Skiplist<FullKeyComparator>,
+    /// Range tombstones, which are converted to sstable range tombstones on flush.
+    range_tombstones: Arc<RwLock<Vec<RangeTombstone>>>,
+    /// Count of entries inserted.
+    len: Arc<AtomicUsize>,
     capacity: usize,
 }
 
 impl Memtable {
     pub fn new(capacity: usize) -> Self {
+        Self::with_options(capacity, SkiplistOptions::default())
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the skiplist max height is out of range, see [`Skiplist::with_options`].
+    pub fn with_options(capacity: usize, options: SkiplistOptions) -> Self {
         Self {
-            inner: Skiplist::with_capacity(FullKeyComparator, capacity as u32),
+            inner: Skiplist::with_options(FullKeyComparator, capacity as u32, options),
+            range_tombstones: Arc::new(RwLock::new(vec![])),
+            len: Arc::new(AtomicUsize::new(0)),
             capacity,
         }
     }
 
     pub fn put(&self, key: &Bytes, value: Option<&Bytes>, timestamp: u64) {
         let key = full_key(key, timestamp);
-        self.inner.put(key, raw_value(value.map(|v| &v[..])));
+        if self
+            .inner
+            .put(key, raw_value(value.map(|v| &v[..])))
+            .is_none()
+        {
+            self.len.fetch_add(1, Ordering::Relaxed);
+        }
     }
 
     pub fn get(&self, key: &Bytes, timestamp: u64) -> Option<Bytes> {
@@ -27,9 +50,38 @@ impl Memtable {
         value(&raw).map(Bytes::copy_from_slice)
     }
 
+    /// Get the raw value of the newest version of `key` not newer than `timestamp`. Returns the
+    /// raw value of tombstone if the key is deleted by a range tombstone.
     pub fn get_raw(&self, key: &Bytes, timestamp: u64) -> Option<Bytes> {
-        let key = full_key(key, timestamp);
-        self.inner.get(&key).cloned()
+        let covering_timestamp =
+            max_covering_timestamp(self.range_tombstones.read().iter(), key, timestamp);
+        let fk = full_key(key, timestamp);
+        match self.inner.get_with_key(&fk) {
+            Some((fk, raw))
+                if covering_timestamp
+                    .map_or(true, |covering| crate::utils::timestamp(fk) > covering) =>
+            {
+                Some(raw.clone())
+            }
+            _ => covering_timestamp.map(|_| Bytes::from(raw_value(None))),
+        }
+    }
+
+    /// Delete all versions of keys in `[start, end)` not newer than `timestamp`.
+    ///
+    /// A point tombstone of `start` is written as well, so that memtables and sstables with range
+    /// tombstones are never empty.
+    pub fn delete_range(&self, start: &Bytes, end: &Bytes, timestamp: u64) {
+        self.put(start, None, timestamp);
+        self.range_tombstones.write().push(RangeTombstone::new(
+            start.to_vec(),
+            end.to_vec(),
+            timestamp,
+        ));
+    }
+
+    pub fn range_tombstones(&self) -> Vec<RangeTombstone> {
+        self.range_tombstones.read().clone()
     }
 
     pub fn mem_remain(&self) -> usize {
@@ -50,6 +102,14 @@ impl Memtable {
         self.inner.is_empty()
     }
 
+    /// Count of entries, including tombstones and all versions of each key.
+    ///
+    /// Note: Only puts that insert a new full key are counted, a put of an existing full key is
+    /// not.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
     pub fn unwrap(self) -> Skiplist<FullKeyComparator> {
         self.inner
     }
@@ -66,6 +126,7 @@ mod tests {
     use test_log::test;
 
     use super::*;
+    use crate::lsm_tree::components::SKIPLIST_NODE_TOWER_MAX_HEIGHT;
     use crate::lsm_tree::iterator::{Iterator, MemtableIterator, Seek};
     use crate::lsm_tree::DEFAULT_MEMTABLE_SIZE;
 
@@ -182,6 +243,99 @@ mod tests {
         .await;
     }
 
+    #[test]
+    fn test_delete_range() {
+        let memtable = Memtable::new(DEFAULT_MEMTABLE_SIZE);
+        for i in 1..=10 {
+            memtable.put(&key(i), Some(&value(i)), i);
+        }
+        memtable.delete_range(&key(3), &key(6), 20);
+        memtable.put(&key(4), Some(&value(4)), 30);
+
+        for i in 1..=10 {
+            let expected = match i {
+                3 | 5 => None,
+                _ => Some(value(i)),
+            };
+            assert_eq!(memtable.get(&key(i), 40), expected, "key {}", i);
+        }
+        // Range tombstone is invisible to older reads.
+        assert_eq!(memtable.get(&key(5), 10), Some(value(5)));
+        // Deleted key without newer versions reads as tombstone, not absent.
+        assert_eq!(
+            memtable.get_raw(&key(5), 40),
+            Some(Bytes::from(raw_value(None)))
+        );
+        assert_eq!(
+            memtable.get_raw(&key(4), 25),
+            Some(Bytes::from(raw_value(None)))
+        );
+        assert_eq!(
+            memtable.range_tombstones(),
+            vec![RangeTombstone::new(key(3).to_vec(), key(6).to_vec(), 20)]
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_max_height() {
+        let memtables = [1, 4, SKIPLIST_NODE_TOWER_MAX_HEIGHT].map(|max_height| {
+            Memtable::with_options(DEFAULT_MEMTABLE_SIZE, SkiplistOptions { max_height })
+        });
+        let mut rng = thread_rng();
+        for _ in 0..1000 {
+            let i = rng.gen_range(0..500);
+            let ts = rng.gen_range(0..10);
+            for memtable in memtables.iter() {
+                memtable.put(&key(i), Some(&value(ts)), ts);
+            }
+        }
+
+        let mut collected = vec![];
+        for memtable in memtables.iter() {
+            let mut iter = MemtableIterator::new(memtable, u64::MAX);
+            iter.seek(Seek::First).await.unwrap();
+            let mut kvs = vec![];
+            while iter.is_valid() {
+                kvs.push((iter.key().to_vec(), iter.value().to_vec()));
+                iter.next().await.unwrap();
+            }
+            collected.push(kvs);
+        }
+        assert!(!collected[0].is_empty());
+        assert_eq!(collected[0], collected[1]);
+        assert_eq!(collected[0], collected[2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_max_height_out_of_range() {
+        Memtable::with_options(
+            DEFAULT_MEMTABLE_SIZE,
+            SkiplistOptions {
+                max_height: SKIPLIST_NODE_TOWER_MAX_HEIGHT + 1,
+            },
+        );
+    }
+
+    #[test]
+    fn test_len() {
+        let memtable = Memtable::new(DEFAULT_MEMTABLE_SIZE);
+        assert!(memtable.is_empty());
+        assert_eq!(memtable.len(), 0);
+
+        for i in 1..=10 {
+            memtable.put(&key(i), Some(&value(i)), i);
+        }
+        // Versions and tombstones of existing keys are counted.
+        memtable.put(&key(1), Some(&value(100)), 100);
+        memtable.put(&key(2), None, 100);
+        let clone = memtable.clone();
+        clone.delete_range(&key(3), &key(6), 100);
+        assert!(!memtable.is_empty());
+        assert_eq!(memtable.len(), 13);
+        assert_eq!(clone.len(), 13);
+    }
+
     fn key(i: u64) -> Bytes {
         Bytes::from(format!("k{:08}", i))
     }
@@ -2,6 +2,8 @@ mod block;
 pub use block::*;
 mod block_cache;
 pub use block_cache::*;
+mod encryption;
+pub use encryption::*;
 mod memtable;
 pub use memtable::*;
 mod sstable;
@@ -50,8 +50,24 @@ impl Node {
 unsafe impl Send for Node {}
 unsafe impl Sync for Node {}
 
+#[derive(Clone, Copy, Debug)]
+pub struct SkiplistOptions {
+    /// Max height of node towers, in `[1, SKIPLIST_NODE_TOWER_MAX_HEIGHT]`. Lower heights save
+    /// memory of small skiplists, while higher heights keep searches in large ones fast.
+    pub max_height: usize,
+}
+
+impl Default for SkiplistOptions {
+    fn default() -> Self {
+        Self {
+            max_height: SKIPLIST_NODE_TOWER_MAX_HEIGHT,
+        }
+    }
+}
+
 struct SkiplistCore {
     height: AtomicUsize,
+    max_height: usize,
     head: NonNull<Node>,
     arena: Arena,
 }
@@ -64,6 +80,19 @@ pub struct Skiplist<C> {
 
 impl<C> Skiplist<C> {
     pub fn with_capacity(c: C, arena_size: u32) -> Skiplist<C> {
+        Self::with_options(c, arena_size, SkiplistOptions::default())
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `options.max_height` is not in `[1, SKIPLIST_NODE_TOWER_MAX_HEIGHT]`.
+    pub fn with_options(c: C, arena_size: u32, options: SkiplistOptions) -> Skiplist<C> {
+        assert!(
+            (1..=SKIPLIST_NODE_TOWER_MAX_HEIGHT).contains(&options.max_height),
+            "skiplist max height {} out of range [1, {}]",
+            options.max_height,
+            SKIPLIST_NODE_TOWER_MAX_HEIGHT
+        );
         let arena = Arena::with_capacity(arena_size);
         let head_offset = Node::alloc(
             &arena,
@@ -75,6 +104,7 @@ impl<C> Skiplist<C> {
         Skiplist {
             core: Arc::new(SkiplistCore {
                 height: AtomicUsize::new(0),
+                max_height: options.max_height,
                 head,
                 arena,
             }),
@@ -84,12 +114,12 @@ impl<C> Skiplist<C> {
 
     fn random_height(&self) -> usize {
         let mut rng = rand::thread_rng();
-        for h in 0..(SKIPLIST_NODE_TOWER_MAX_HEIGHT - 1) {
+        for h in 0..(self.core.max_height - 1) {
             if !rng.gen_ratio(HEIGHT_INCREASE, u32::MAX) {
                 return h;
             }
         }
-        SKIPLIST_NODE_TOWER_MAX_HEIGHT - 1
+        self.core.max_height - 1
     }
 
     fn height(&self) -> usize {
@@ -7,4 +7,4 @@ mod list;
 pub const SKIPLIST_NODE_TOWER_MAX_HEIGHT: usize = 20;
 
 pub use key::{FixedLengthSuffixComparator, KeyComparator};
-pub use list::{IterRef, Skiplist};
+pub use list::{IterRef, Skiplist, SkiplistOptions};
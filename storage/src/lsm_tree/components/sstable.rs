@@ -6,11 +6,48 @@ use runkv_common::coding::CompressionAlgorithm;
 
 use super::{BlockBuilder, BlockBuilderOptions};
 use crate::lsm_tree::{
-    DEFAULT_BLOCK_SIZE, DEFAULT_BLOOM_FALSE_POSITIVE, DEFAULT_ENTRY_SIZE, DEFAULT_RESTART_INTERVAL,
+    DEFAULT_BLOCK_SIZE, DEFAULT_BLOOM_FALSE_POSITIVE, DEFAULT_ENTRY_SIZE,
+    DEFAULT_MAX_BLOOM_BITS_PER_KEY, DEFAULT_MIN_BLOOM_BITS_PER_KEY, DEFAULT_RESTART_INTERVAL,
     DEFAULT_SSTABLE_META_SIZE, DEFAULT_SSTABLE_SIZE, TEST_DEFAULT_RESTART_INTERVAL,
 };
-use crate::utils::{crc32check, crc32sum, full_key, raw_value, user_key, Bloom};
-use crate::Result;
+use crate::utils::{crc32check, crc32sum, full_key, key_range_overlap, raw_value, user_key, Bloom};
+use crate::{Error, Result};
+
+/// Magic number at the beginning of sstable data objects.
+const SSTABLE_DATA_MAGIC: u32 = 0x5253_5354;
+
+/// Format version of sstable data written by [`SstableBuilder`].
+pub const SSTABLE_FORMAT_VERSION: u8 = 1;
+
+/// Length of the header of sstable data.
+pub const SSTABLE_DATA_HEADER_LEN: usize = 5;
+
+/// Format:
+///
+/// ```plain
+/// | magic (4B) | version (1B) |
+/// ```
+fn encode_data_header(buf: &mut impl BufMut) {
+    buf.put_u32_le(SSTABLE_DATA_MAGIC);
+    buf.put_u8(SSTABLE_FORMAT_VERSION);
+}
+
+/// Decode the header of the data of sstable `sst_id`, returns the format version.
+///
+/// Returns [`Error::UnsupportedSstableVersion`] if the version is unknown.
+pub fn decode_data_header(sst_id: u64, mut buf: &[u8]) -> Result<u8> {
+    if buf.len() < SSTABLE_DATA_HEADER_LEN || buf.get_u32_le() != SSTABLE_DATA_MAGIC {
+        return Err(Error::DecodeError(format!(
+            "invalid data header of sstable {}",
+            sst_id
+        )));
+    }
+    let version = buf.get_u8();
+    if version != SSTABLE_FORMAT_VERSION {
+        return Err(Error::UnsupportedSstableVersion { sst_id, version });
+    }
+    Ok(version)
+}
 
 /// [`BlockMeta`] contains block metadata, served as a part of [`Sstable`] meta.
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -27,13 +64,20 @@ impl BlockMeta {
     /// ```plain
     /// | offset (4B) | len (4B) | first key len (4B) | last key len(4B) | first key | last key |
     /// ```
-    pub fn encode(&self, buf: &mut impl BufMut) {
-        buf.put_u32_le(self.offset as u32);
-        buf.put_u32_le(self.len as u32);
-        buf.put_u32_le(self.first_key.len() as u32);
-        buf.put_u32_le(self.last_key.len() as u32);
+    ///
+    /// Returns [`Error::Overflow`] if any of the fields doesn't fit in 4 bytes.
+    pub fn encode(&self, buf: &mut impl BufMut) -> Result<()> {
+        let offset = to_u32("block offset", self.offset)?;
+        let len = to_u32("block len", self.len)?;
+        let first_key_len = to_u32("block first key len", self.first_key.len())?;
+        let last_key_len = to_u32("block last key len", self.last_key.len())?;
+        buf.put_u32_le(offset);
+        buf.put_u32_le(len);
+        buf.put_u32_le(first_key_len);
+        buf.put_u32_le(last_key_len);
         buf.put_slice(&self.first_key);
         buf.put_slice(&self.last_key);
+        Ok(())
     }
 
     pub fn decode(buf: &mut impl Buf) -> Self {
@@ -58,6 +102,86 @@ impl BlockMeta {
     }
 }
 
+/// [`RangeTombstone`] deletes all versions of user keys in `[start, end)` whose timestamps are not
+/// greater than `timestamp`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct RangeTombstone {
+    pub start: Vec<u8>,
+    pub end: Vec<u8>,
+    pub timestamp: u64,
+}
+
+impl RangeTombstone {
+    pub fn new(start: Vec<u8>, end: Vec<u8>, timestamp: u64) -> Self {
+        Self {
+            start,
+            end,
+            timestamp,
+        }
+    }
+
+    /// Judge whether `user_key` is in the range, regardless of timestamp.
+    pub fn contains(&self, user_key: &[u8]) -> bool {
+        &self.start[..] <= user_key && user_key < &self.end[..]
+    }
+
+    /// Judge whether the version of `user_key` at `timestamp` is deleted.
+    pub fn covers(&self, user_key: &[u8], timestamp: u64) -> bool {
+        timestamp <= self.timestamp && self.contains(user_key)
+    }
+
+    pub fn is_overlap_with_user_key_range(&self, user_key_range: RangeInclusive<&[u8]>) -> bool {
+        &self.start[..] <= *user_key_range.end() && *user_key_range.start() < &self.end[..]
+    }
+
+    /// Format:
+    ///
+    /// ```plain
+    /// | start len (4B) | end len (4B) | start | end | timestamp (8B) |
+    /// ```
+    ///
+    /// Returns [`Error::Overflow`] if any of the lengths doesn't fit in 4 bytes.
+    pub fn encode(&self, buf: &mut impl BufMut) -> Result<()> {
+        let start_len = to_u32("range tombstone start len", self.start.len())?;
+        let end_len = to_u32("range tombstone end len", self.end.len())?;
+        buf.put_u32_le(start_len);
+        buf.put_u32_le(end_len);
+        buf.put_slice(&self.start);
+        buf.put_slice(&self.end);
+        buf.put_u64_le(self.timestamp);
+        Ok(())
+    }
+
+    pub fn decode(buf: &mut impl Buf) -> Self {
+        let start_len = buf.get_u32_le() as usize;
+        let end_len = buf.get_u32_le() as usize;
+        let start = buf.copy_to_bytes(start_len).to_vec();
+        let end = buf.copy_to_bytes(end_len).to_vec();
+        let timestamp = buf.get_u64_le();
+        Self {
+            start,
+            end,
+            timestamp,
+        }
+    }
+}
+
+/// Get the largest timestamp of `range_tombstones` visible at `timestamp` that covers `user_key`.
+/// Versions of `user_key` not newer than it are deleted.
+pub fn max_covering_timestamp<'a>(
+    range_tombstones: impl IntoIterator<Item = &'a RangeTombstone>,
+    user_key: &[u8],
+    timestamp: u64,
+) -> Option<u64> {
+    range_tombstones
+        .into_iter()
+        .filter(|range_tombstone| {
+            range_tombstone.timestamp <= timestamp && range_tombstone.contains(user_key)
+        })
+        .map(|range_tombstone| range_tombstone.timestamp)
+        .max()
+}
+
 /// [`Sstable`] serves as a handle to retrieve actuall sstable data from the object store.
 ///
 /// Note: Ensure [`Sstable`] is never empty.
@@ -76,6 +200,10 @@ impl Sstable {
         self.id
     }
 
+    pub fn meta(&self) -> &SstableMetaRef {
+        &self.meta
+    }
+
     pub fn data_size(&self) -> usize {
         self.meta.data_size
     }
@@ -117,7 +245,11 @@ impl Sstable {
         self.meta.block_metas.iter()
     }
 
-    pub fn encode_meta(&self) -> Vec<u8> {
+    pub fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.meta.range_tombstones
+    }
+
+    pub fn encode_meta(&self) -> Result<Vec<u8>> {
         self.meta.encode()
     }
 }
@@ -131,6 +263,12 @@ pub struct SstableMeta {
     pub bloom_filter_bytes: Vec<u8>,
     /// Data file size.
     pub data_size: usize,
+    /// Range tombstones, which are not in the data.
+    pub range_tombstones: Vec<RangeTombstone>,
+    /// Count of distinct user keys. `0` for metas encoded before it is introduced.
+    pub key_count: u64,
+    /// Format version of the data, same as the one in the data header.
+    pub format_version: u8,
 }
 
 impl SstableMeta {
@@ -139,26 +277,51 @@ impl SstableMeta {
     /// ```plain
     /// | checksum (4B) | N (4B) | block meta 0 | ... | block meta N-1 |
     /// | bloom filter len (4B) | bloom filter | data size (8B) |
+    /// | M (4B) | range tombstone 0 | ... | range tombstone M-1 | key count (8B) |
+    /// | format version (1B) |
     /// ```
-    pub fn encode(&self) -> Vec<u8> {
+    ///
+    /// Metas encoded before range tombstones are introduced end with the data size, metas encoded
+    /// before key count is introduced end with the range tombstones, and metas encoded before
+    /// format version is introduced end with the key count. All of them can still be decoded.
+    ///
+    /// Returns [`Error::Overflow`] if any of the lengths doesn't fit in its field.
+    pub fn encode(&self) -> Result<Vec<u8>> {
         let mut buf = Vec::with_capacity(DEFAULT_SSTABLE_META_SIZE);
         buf.put_u32_le(0); // Reserved for checksum.
-        buf.put_u32_le(self.block_metas.len() as u32);
+        buf.put_u32_le(to_u32("block metas len", self.block_metas.len())?);
         for block_meta in &self.block_metas {
-            block_meta.encode(&mut buf);
+            block_meta.encode(&mut buf)?;
         }
-        buf.put_u32_le(self.bloom_filter_bytes.len() as u32);
+        buf.put_u32_le(to_u32("bloom filter len", self.bloom_filter_bytes.len())?);
         buf.put_slice(&self.bloom_filter_bytes);
         buf.put_u64_le(self.data_size as u64);
+        buf.put_u32_le(to_u32("range tombstones len", self.range_tombstones.len())?);
+        for range_tombstone in &self.range_tombstones {
+            range_tombstone.encode(&mut buf)?;
+        }
+        buf.put_u64_le(self.key_count);
+        buf.put_u8(self.format_version);
         let checksum = crc32sum(&buf[4..]);
         (&mut buf[..4]).put_u32_le(checksum);
-        buf
+        Ok(buf)
     }
 
-    pub fn decode(buf: &mut &[u8]) -> Self {
-        // let mut rbuf = &buf[..];
+    /// Decode sstable meta. Returns [`Error::ChecksumMismatch`] if the meta is corrupted.
+    pub fn decode(buf: &mut &[u8]) -> Result<Self> {
+        if buf.len() < 4 {
+            return Err(Error::DecodeError(format!(
+                "sstable meta too short: {} bytes",
+                buf.len()
+            )));
+        }
         let checksum = buf.get_u32_le();
-        crc32check(buf, checksum);
+        if !crc32check(buf, checksum) {
+            return Err(Error::ChecksumMismatch {
+                expected: checksum,
+                actual: crc32sum(buf),
+            });
+        }
         let block_metas_len = buf.get_u32_le() as usize;
         let mut block_metas = Vec::with_capacity(block_metas_len);
         for _ in 0..block_metas_len {
@@ -167,12 +330,33 @@ impl SstableMeta {
         let bloom_filter_len = buf.get_u32_le() as usize;
         let bloom_filter_bytes = buf.copy_to_bytes(bloom_filter_len).to_vec();
         let data_size = buf.get_u64_le() as usize;
+        let mut range_tombstones = vec![];
+        if !buf.is_empty() {
+            let range_tombstones_len = buf.get_u32_le() as usize;
+            range_tombstones.reserve(range_tombstones_len);
+            for _ in 0..range_tombstones_len {
+                range_tombstones.push(RangeTombstone::decode(buf));
+            }
+        }
+        let key_count = if buf.is_empty() { 0 } else { buf.get_u64_le() };
+        let format_version = if !buf.is_empty() {
+            buf.get_u8()
+        } else if block_metas.first().map_or(false, |meta| meta.offset == 0) {
+            // Data without the header starts with the first block.
+            0
+        } else {
+            // The header was introduced with version 1.
+            1
+        };
         debug_assert!(buf.is_empty());
-        Self {
+        Ok(Self {
             block_metas,
             bloom_filter_bytes,
             data_size,
-        }
+            range_tombstones,
+            key_count,
+            format_version,
+        })
     }
 
     fn is_overlap_with(&self, rhs: &Self) -> bool {
@@ -183,35 +367,41 @@ impl SstableMeta {
     }
 
     fn is_overlap_with_range(&self, range: RangeInclusive<&[u8]>) -> bool {
-        // println!("range: {:?}", range);
-        // println!(
-        //     "first: {:?}",
-        //     self.block_metas.first().as_ref().unwrap().first_key
-        // );
-        // println!(
-        //     "last: {:?}",
-        //     self.block_metas.last().as_ref().unwrap().last_key
-        // );
-
-        !(&self.block_metas.first().as_ref().unwrap().first_key[..] > *range.end()
-            || &self.block_metas.last().as_ref().unwrap().last_key[..] < *range.start())
+        key_range_overlap(
+            &self.block_metas.first().as_ref().unwrap().first_key,
+            &self.block_metas.last().as_ref().unwrap().last_key,
+            range.start(),
+            range.end(),
+        )
     }
 
     fn is_overlap_with_user_key_range(&self, user_key_range: RangeInclusive<&[u8]>) -> bool {
         let first_user_key = user_key(&self.block_metas.first().as_ref().unwrap().first_key);
         let last_user_key = user_key(&self.block_metas.last().as_ref().unwrap().last_key);
-        // println!("range: {:?}", user_key_range);
-        // println!("first: {:?}", Bytes::copy_from_slice(first_user_key));
-        // println!("last: {:?}", Bytes::copy_from_slice(last_user_key));
-        // println!(
-        //     "result: {}",
-        //     !(&first_user_key > user_key_range.end() || &last_user_key < user_key_range.start())
-        // );
-        !(&first_user_key > user_key_range.end() || &last_user_key < user_key_range.start())
+        key_range_overlap(
+            first_user_key,
+            last_user_key,
+            user_key_range.start(),
+            user_key_range.end(),
+        ) || self.range_tombstones.iter().any(|range_tombstone| {
+            range_tombstone.is_overlap_with_user_key_range(user_key_range.clone())
+        })
     }
 
-    /// Judge whether the given `key` may be in the sstable with bloom filter.
+    /// Judge whether the given `key` may be in the sstable with bloom filter. Keys covered by
+    /// range tombstones are always regarded as contained.
     fn may_contain_key(&self, key: &[u8]) -> bool {
+        if self
+            .range_tombstones
+            .iter()
+            .any(|range_tombstone| range_tombstone.contains(key))
+        {
+            return true;
+        }
+        // Bloom filter is disabled when building the sstable.
+        if self.bloom_filter_bytes.is_empty() {
+            return true;
+        }
         let bloom_filter = Bloom::new(&self.bloom_filter_bytes);
         bloom_filter.may_contain(farmhash::fingerprint32(key))
     }
@@ -231,6 +421,11 @@ pub struct SstableBuilderOptions {
     pub bloom_false_positive: f64,
     /// Compression algorithm.
     pub compression_algorithm: CompressionAlgorithm,
+    /// Min bloom filter bits per key, the bits per key computed from the false positive
+    /// probability are clamped to `[min, max]`.
+    pub min_bloom_bits_per_key: usize,
+    /// Max bloom filter bits per key.
+    pub max_bloom_bits_per_key: usize,
 }
 
 impl Default for SstableBuilderOptions {
@@ -245,6 +440,8 @@ impl Default for SstableBuilderOptions {
             },
             bloom_false_positive: DEFAULT_BLOOM_FALSE_POSITIVE,
             compression_algorithm: CompressionAlgorithm::None,
+            min_bloom_bits_per_key: DEFAULT_MIN_BLOOM_BITS_PER_KEY,
+            max_bloom_bits_per_key: DEFAULT_MAX_BLOOM_BITS_PER_KEY,
         }
     }
 }
@@ -254,6 +451,8 @@ pub struct SstableBuilder {
     options: SstableBuilderOptions,
     /// Write buffer.
     buf: Vec<u8>,
+    /// Length of data drained from the write buffer.
+    drained_len: usize,
     /// Current block builder.
     block_builder: Option<BlockBuilder>,
     /// Block metadata vec.
@@ -262,22 +461,45 @@ pub struct SstableBuilder {
     user_key_hashes: Vec<u32>,
     /// Last added full key.
     last_full_key: Vec<u8>,
+    /// Range tombstones.
+    range_tombstones: Vec<RangeTombstone>,
+    /// Count of distinct user keys added.
+    key_count: u64,
 }
 
 impl SstableBuilder {
     pub fn new(options: SstableBuilderOptions) -> Self {
+        let mut buf = Vec::with_capacity(options.capacity);
+        encode_data_header(&mut buf);
         Self {
             options: options.clone(),
-            buf: Vec::with_capacity(options.capacity),
+            buf,
+            drained_len: 0,
             block_builder: None,
             block_metas: Vec::with_capacity(options.capacity / options.block_capacity + 1),
             user_key_hashes: Vec::with_capacity(options.capacity / DEFAULT_ENTRY_SIZE + 1),
             last_full_key: Vec::default(),
+            range_tombstones: vec![],
+            key_count: 0,
         }
     }
 
     /// Add kv pair to sstable.
+    ///
+    /// Returns [`Error::DuplicateKey`] if the user key and timestamp equal the last added entry's.
     pub fn add(&mut self, user_key: &[u8], timestamp: u64, value: Option<&[u8]>) -> Result<()> {
+        let full_key = full_key(user_key, timestamp);
+        if self.last_added_key() == Some(&full_key[..]) {
+            return Err(Error::DuplicateKey {
+                user_key: user_key.to_vec(),
+                timestamp,
+            });
+        }
+        // Versions of a user key are added adjacently.
+        if self.last_user_key() != Some(user_key) {
+            self.key_count += 1;
+        }
+
         // Rotate block builder if the previous one has been built.
         if self.block_builder.is_none() {
             self.block_builder = Some(BlockBuilder::new(BlockBuilderOptions {
@@ -286,7 +508,7 @@ impl SstableBuilder {
                 compression_algorithm: self.options.compression_algorithm,
             }));
             self.block_metas.push(BlockMeta {
-                offset: self.buf.len(),
+                offset: self.data_len(),
                 len: 0,
                 first_key: Vec::default(),
                 last_key: Vec::default(),
@@ -294,9 +516,8 @@ impl SstableBuilder {
         }
 
         let block_builder = self.block_builder.as_mut().unwrap();
-        let full_key = full_key(user_key, timestamp);
 
-        block_builder.add(&full_key, &raw_value(value));
+        block_builder.add(&full_key, &raw_value(value))?;
 
         self.user_key_hashes.push(farmhash::fingerprint32(user_key));
 
@@ -306,26 +527,59 @@ impl SstableBuilder {
         self.last_full_key = full_key;
 
         if block_builder.approximate_len() >= self.options.block_capacity {
-            self.build_block();
+            self.build_block()?;
         }
         Ok(())
     }
 
+    /// Add tombstone to sstable.
+    pub fn add_tombstone(&mut self, user_key: &[u8], timestamp: u64) -> Result<()> {
+        self.add(user_key, timestamp, None)
+    }
+
+    /// Add range tombstone to sstable, which is kept in the meta.
+    ///
+    /// Note: The sstable still needs at least one key.
+    pub fn add_range_tombstone(&mut self, range_tombstone: RangeTombstone) {
+        self.range_tombstones.push(range_tombstone);
+    }
+
+    /// Take the data of built blocks out of the write buffer, so that they can be uploaded before
+    /// the sstable is finished. The remaining data are returned by [`SstableBuilder::build`].
+    pub fn drain_data(&mut self) -> Vec<u8> {
+        self.drained_len += self.buf.len();
+        std::mem::replace(
+            &mut self.buf,
+            Vec::with_capacity(self.options.block_capacity),
+        )
+    }
+
+    /// Length of data in the write buffer.
+    pub fn buffered_len(&self) -> usize {
+        self.buf.len()
+    }
+
     /// Finish building sst.
     ///
     /// Unlike most LSM-Tree implementations, sstable meta and data are encoded separately.
     /// Both meta and data has its own object (file).
     ///
+    /// The returned data doesn't contain the data taken by [`SstableBuilder::drain_data`].
+    ///
     /// # Format
     ///
     /// data:
     ///
     /// ```plain
-    /// | Block 0 | ... | Block N-1 | N (4B) |
+    /// | magic (4B) | version (1B) | Block 0 | ... | Block N-1 | N (4B) |
     /// ```
+    ///
+    /// Data written before the header is introduced starts with block 0 directly, which is
+    /// regarded as format version 0.
     pub fn build(mut self) -> Result<(SstableMeta, Vec<u8>)> {
-        self.build_block();
-        self.buf.put_u32_le(self.block_metas.len() as u32);
+        self.build_block()?;
+        self.buf
+            .put_u32_le(to_u32("block metas len", self.block_metas.len())?);
 
         let meta = SstableMeta {
             block_metas: self.block_metas,
@@ -333,32 +587,60 @@ impl SstableBuilder {
                 let bits_per_key = Bloom::bloom_bits_per_key(
                     self.user_key_hashes.len(),
                     self.options.bloom_false_positive,
+                )
+                .clamp(
+                    self.options.min_bloom_bits_per_key,
+                    self.options.max_bloom_bits_per_key,
                 );
                 Bloom::build_from_key_hashes(&self.user_key_hashes, bits_per_key).to_vec()
             } else {
                 vec![]
             },
-            data_size: self.buf.len(),
+            data_size: self.data_len(),
+            range_tombstones: self.range_tombstones,
+            key_count: self.key_count,
+            format_version: SSTABLE_FORMAT_VERSION,
         };
 
         Ok((meta, self.buf))
     }
 
     pub fn approximate_len(&self) -> usize {
-        self.buf.len() + 4
+        self.data_len() + 4
+    }
+
+    /// User key of the last added entry. Returns `None` if nothing has been added.
+    pub fn last_user_key(&self) -> Option<&[u8]> {
+        self.last_added_key().map(user_key)
+    }
+
+    /// Full key of the last added entry. Returns `None` if nothing has been added.
+    fn last_added_key(&self) -> Option<&[u8]> {
+        if !self.last_full_key.is_empty() {
+            return Some(&self.last_full_key);
+        }
+        // The last full key is cleared when a block is built, fall back to the last key of it.
+        self.block_metas
+            .last()
+            .map(|block_meta| &block_meta.last_key[..])
     }
 
-    fn build_block(&mut self) {
+    fn data_len(&self) -> usize {
+        self.drained_len + self.buf.len()
+    }
+
+    fn build_block(&mut self) -> Result<()> {
         // Skip empty block.
         if self.block_builder.is_none() {
-            return;
+            return Ok(());
         }
         let mut block_meta = self.block_metas.last_mut().unwrap();
-        let block = self.block_builder.take().unwrap().build();
+        let block = self.block_builder.take().unwrap().build()?;
         self.buf.put_slice(&block);
         block_meta.last_key = self.last_full_key.clone();
-        block_meta.len = self.buf.len() - block_meta.offset;
+        block_meta.len = self.drained_len + self.buf.len() - block_meta.offset;
         self.last_full_key.clear();
+        Ok(())
     }
 
     pub fn len(&self) -> usize {
@@ -370,6 +652,13 @@ impl SstableBuilder {
     }
 }
 
+pub(super) fn to_u32(field: &'static str, value: usize) -> Result<u32> {
+    u32::try_from(value).map_err(|_| Error::Overflow {
+        field,
+        value: value as u64,
+    })
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -389,6 +678,7 @@ mod tests {
             restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
             bloom_false_positive: 0.1,
             compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
         };
         let mut builder = SstableBuilder::new(options);
         builder.add(b"k01", 1, Some(b"v01")).unwrap();
@@ -439,6 +729,7 @@ mod tests {
             restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
             bloom_false_positive: 0.1,
             compression_algorithm: CompressionAlgorithm::Lz4,
+            ..Default::default()
         };
         let mut builder = SstableBuilder::new(options);
         builder.add(b"k01", 1, Some(b"v01")).unwrap();
@@ -481,6 +772,49 @@ mod tests {
         assert!(!bi.is_valid());
     }
 
+    #[test]
+    fn test_zstd_sstable_enc_dec() {
+        for level in [1, 19] {
+            let options = SstableBuilderOptions {
+                capacity: 1024,
+                block_capacity: 32,
+                restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+                bloom_false_positive: 0.1,
+                compression_algorithm: CompressionAlgorithm::Zstd { level },
+                ..Default::default()
+            };
+            let mut builder = SstableBuilder::new(options);
+            builder.add(b"k01", 1, Some(b"v01")).unwrap();
+            builder.add_tombstone(b"k02", 2).unwrap();
+            builder.add(b"k04", 4, Some(b"v04")).unwrap();
+            builder.add_tombstone(b"k05", 5).unwrap();
+            let (meta, data) = builder.build().unwrap();
+            assert_eq!(2, meta.block_metas.len());
+
+            let mut kvs = vec![];
+            for block_meta in meta.block_metas.iter() {
+                let begin = block_meta.offset;
+                let end = block_meta.offset + block_meta.len;
+                let mut bi =
+                    BlockIterator::new(Arc::new(Block::decode(&data[begin..end]).unwrap()));
+                bi.seek(Seek::First).unwrap();
+                while bi.is_valid() {
+                    kvs.push((bi.key().to_vec(), bi.value().to_vec()));
+                    bi.next().unwrap();
+                }
+            }
+            assert_eq!(
+                kvs,
+                vec![
+                    (full_key(b"k01", 1), raw_value(Some(b"v01"))),
+                    (full_key(b"k02", 2), raw_value(None)),
+                    (full_key(b"k04", 4), raw_value(Some(b"v04"))),
+                    (full_key(b"k05", 5), raw_value(None)),
+                ]
+            );
+        }
+    }
+
     #[test]
     fn test_sstable_meta_enc_dec() {
         let options = SstableBuilderOptions {
@@ -489,6 +823,7 @@ mod tests {
             restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
             bloom_false_positive: 0.1,
             compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
         };
         let mut builder = SstableBuilder::new(options);
         builder.add(b"k01", 1, Some(b"v01")).unwrap();
@@ -496,8 +831,8 @@ mod tests {
         builder.add(b"k04", 4, Some(b"v04")).unwrap();
         builder.add(b"k05", 5, None).unwrap();
         let (meta, _) = builder.build().unwrap();
-        let buf = meta.encode();
-        let decoded_meta = SstableMeta::decode(&mut &buf[..]);
+        let buf = meta.encode().unwrap();
+        let decoded_meta = SstableMeta::decode(&mut &buf[..]).unwrap();
         assert_eq!(meta.block_metas.len(), decoded_meta.block_metas.len());
         for (block_meta, decoded_block_meta) in
             meta.block_metas.iter().zip(decoded_meta.block_metas.iter())
@@ -508,5 +843,302 @@ mod tests {
             assert_eq!(block_meta.last_key, decoded_block_meta.last_key);
         }
         assert_eq!(meta.bloom_filter_bytes, decoded_meta.bloom_filter_bytes);
+        assert_eq!(meta.key_count, decoded_meta.key_count);
+        assert_eq!(decoded_meta.format_version, SSTABLE_FORMAT_VERSION);
+
+        // Corrupted meta is reported instead of panicking.
+        let mut corrupted = buf.clone();
+        let len = corrupted.len();
+        corrupted[len / 2] ^= 0xff;
+        assert!(matches!(
+            SstableMeta::decode(&mut &corrupted[..]),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            SstableMeta::decode(&mut &buf[..2]),
+            Err(Error::DecodeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_range_tombstones() {
+        let options = SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        let mut builder = SstableBuilder::new(options);
+        builder.add_tombstone(b"k02", 10).unwrap();
+        builder.add(b"k03", 3, Some(b"v03")).unwrap();
+        builder.add_range_tombstone(RangeTombstone::new(b"k02".to_vec(), b"k08".to_vec(), 10));
+        builder.add_range_tombstone(RangeTombstone::new(b"k05".to_vec(), b"k06".to_vec(), 20));
+        let (meta, _) = builder.build().unwrap();
+        let buf = meta.encode().unwrap();
+        let decoded_meta = SstableMeta::decode(&mut &buf[..]).unwrap();
+        assert_eq!(meta, decoded_meta);
+        assert_eq!(decoded_meta.range_tombstones.len(), 2);
+
+        // Metas without range tombstones encode an empty section.
+        let meta_without_range_tombstones = SstableMeta {
+            range_tombstones: vec![],
+            block_metas: meta.block_metas.clone(),
+            bloom_filter_bytes: meta.bloom_filter_bytes.clone(),
+            data_size: meta.data_size,
+            key_count: meta.key_count,
+            format_version: meta.format_version,
+        };
+        let buf_without_range_tombstones = meta_without_range_tombstones.encode().unwrap();
+        assert!(buf_without_range_tombstones.len() < buf.len());
+        assert_eq!(
+            SstableMeta::decode(&mut &buf_without_range_tombstones[..]).unwrap(),
+            meta_without_range_tombstones
+        );
+
+        // Keys covered by range tombstones are regarded as contained.
+        let sst = Sstable::new(1, Arc::new(meta));
+        assert!(sst.may_contain_key(b"k07"));
+        assert!(sst.is_overlap_with_user_key_range(&b"k07"[..]..=&b"k09"[..]));
+        assert!(!sst.is_overlap_with_user_key_range(&b"k08"[..]..=&b"k09"[..]));
+
+        assert_eq!(
+            max_covering_timestamp(sst.range_tombstones(), b"k05", 30),
+            Some(20)
+        );
+        assert_eq!(
+            max_covering_timestamp(sst.range_tombstones(), b"k05", 15),
+            Some(10)
+        );
+        assert_eq!(
+            max_covering_timestamp(sst.range_tombstones(), b"k06", 30),
+            Some(10)
+        );
+        assert_eq!(
+            max_covering_timestamp(sst.range_tombstones(), b"k05", 5),
+            None
+        );
+        assert_eq!(
+            max_covering_timestamp(sst.range_tombstones(), b"k08", 30),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sstable_drain_data() {
+        let options = SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::Lz4,
+            ..Default::default()
+        };
+        let mut builder = SstableBuilder::new(options.clone());
+        let mut draining_builder = SstableBuilder::new(options);
+        let mut drained_data = vec![];
+        for i in 0..100u64 {
+            let key = format!("k{:03}", i);
+            let value = format!("v{:03}", i);
+            builder
+                .add(key.as_bytes(), i, Some(value.as_bytes()))
+                .unwrap();
+            draining_builder
+                .add(key.as_bytes(), i, Some(value.as_bytes()))
+                .unwrap();
+            assert_eq!(
+                builder.approximate_len(),
+                draining_builder.approximate_len()
+            );
+            if i % 7 == 0 {
+                drained_data.extend(draining_builder.drain_data());
+                assert_eq!(0, draining_builder.buffered_len());
+            }
+        }
+        let (meta, data) = builder.build().unwrap();
+        let (drained_meta, remaining_data) = draining_builder.build().unwrap();
+        drained_data.extend(remaining_data);
+        assert_eq!(meta, drained_meta);
+        assert_eq!(data, drained_data);
+    }
+
+    #[test]
+    fn test_last_user_key() {
+        let options = SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        let mut builder = SstableBuilder::new(options);
+        assert_eq!(builder.last_user_key(), None);
+        for i in 0..100u64 {
+            let key = format!("k{:03}", i);
+            builder.add(key.as_bytes(), 100 - i, Some(b"v")).unwrap();
+            assert_eq!(builder.last_user_key(), Some(key.as_bytes()));
+            // Another version of the same user key.
+            builder.add_tombstone(key.as_bytes(), 0).unwrap();
+            assert_eq!(builder.last_user_key(), Some(key.as_bytes()));
+        }
+        assert!(builder.block_metas.len() > 1);
+    }
+
+    #[test]
+    fn test_duplicate_key() {
+        let options = SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        let mut builder = SstableBuilder::new(options);
+        for i in 0..100u64 {
+            let key = format!("k{:03}", i);
+            builder.add(key.as_bytes(), 2, Some(b"v")).unwrap();
+            // Duplicates are detected across blocks too.
+            assert!(matches!(
+                builder.add_tombstone(key.as_bytes(), 2),
+                Err(Error::DuplicateKey { user_key, timestamp: 2 }) if user_key == key.as_bytes()
+            ));
+            builder.add(key.as_bytes(), 1, Some(b"v")).unwrap();
+        }
+        assert!(builder.block_metas.len() > 1);
+        let (meta, _) = builder.build().unwrap();
+        assert_eq!(
+            meta.block_metas.last().unwrap().last_key,
+            full_key(b"k099", 1)
+        );
+    }
+
+    #[test]
+    fn test_key_count() {
+        let options = SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        let mut builder = SstableBuilder::new(options);
+        for i in 0..100u64 {
+            let key = format!("k{:03}", i);
+            // Multiple versions of a user key are counted once, across blocks too.
+            for ts in (1..=i % 3 + 1).rev() {
+                builder.add(key.as_bytes(), ts, Some(b"v")).unwrap();
+            }
+        }
+        assert!(builder.block_metas.len() > 1);
+        let (meta, _) = builder.build().unwrap();
+        assert_eq!(meta.key_count, 100);
+        let buf = meta.encode().unwrap();
+        assert_eq!(SstableMeta::decode(&mut &buf[..]).unwrap().key_count, 100);
+
+        // Metas encoded before format version is introduced are decoded with the version inferred
+        // from the data layout.
+        let mut legacy = buf[..buf.len() - 1].to_vec();
+        let checksum = crc32sum(&legacy[4..]);
+        (&mut legacy[..4]).put_u32_le(checksum);
+        let decoded_meta = SstableMeta::decode(&mut &legacy[..]).unwrap();
+        assert_eq!(decoded_meta.key_count, 100);
+        assert_eq!(decoded_meta.format_version, 1);
+
+        // Metas encoded before key count is introduced are decoded with zero key count.
+        let mut legacy = buf[..buf.len() - 9].to_vec();
+        let checksum = crc32sum(&legacy[4..]);
+        (&mut legacy[..4]).put_u32_le(checksum);
+        let decoded_meta = SstableMeta::decode(&mut &legacy[..]).unwrap();
+        assert_eq!(decoded_meta.key_count, 0);
+        assert_eq!(decoded_meta.block_metas, meta.block_metas);
+    }
+
+    #[test]
+    fn test_bloom_bits_per_key_clamped() {
+        let bloom_filter_len = |keys: usize, bloom_false_positive: f64| {
+            let mut builder = SstableBuilder::new(SstableBuilderOptions {
+                bloom_false_positive,
+                ..Default::default()
+            });
+            for i in 0..keys {
+                builder
+                    .add(format!("k{:06}", i).as_bytes(), 1, Some(b"v"))
+                    .unwrap();
+            }
+            let (meta, _) = builder.build().unwrap();
+            // Exclude the trailing hash function count.
+            meta.bloom_filter_bytes.len() - 1
+        };
+        // The filter of no keys has the minimal length of 64 bits.
+        assert_eq!(bloom_filter_len(0, 0.01), 8);
+        assert_eq!(
+            bloom_filter_len(1000, 1e-300),
+            1000 * DEFAULT_MAX_BLOOM_BITS_PER_KEY / 8
+        );
+        assert_eq!(
+            bloom_filter_len(1000, 0.999),
+            1000 * DEFAULT_MIN_BLOOM_BITS_PER_KEY / 8
+        );
+        let len = bloom_filter_len(1000, 0.01);
+        assert!(len > 1000 * DEFAULT_MIN_BLOOM_BITS_PER_KEY / 8);
+        assert!(len < 1000 * DEFAULT_MAX_BLOOM_BITS_PER_KEY / 8);
+    }
+
+    #[test]
+    fn test_block_meta_encode_overflow() {
+        let block_meta = BlockMeta {
+            offset: u32::MAX as usize,
+            len: u32::MAX as usize,
+            first_key: b"k01".to_vec(),
+            last_key: b"k02".to_vec(),
+        };
+        let mut buf = vec![];
+        block_meta.encode(&mut buf).unwrap();
+        assert_eq!(block_meta, BlockMeta::decode(&mut &buf[..]));
+
+        let overflowed = u32::MAX as usize + 1;
+        let cases = [
+            (
+                BlockMeta {
+                    offset: overflowed,
+                    ..block_meta.clone()
+                },
+                "block offset",
+            ),
+            (
+                BlockMeta {
+                    len: overflowed,
+                    ..block_meta.clone()
+                },
+                "block len",
+            ),
+        ];
+        for (block_meta, expected_field) in cases {
+            let mut buf = vec![];
+            match block_meta.encode(&mut buf) {
+                Err(Error::Overflow { field, value }) => {
+                    assert_eq!(expected_field, field);
+                    assert_eq!(overflowed as u64, value);
+                }
+                _ => panic!("expect overflow error"),
+            }
+
+            let meta = SstableMeta {
+                block_metas: vec![block_meta],
+                bloom_filter_bytes: vec![],
+                data_size: overflowed,
+                range_tombstones: vec![],
+                key_count: 0,
+                format_version: SSTABLE_FORMAT_VERSION,
+            };
+            assert!(matches!(
+                meta.encode(),
+                Err(Error::Overflow { field, .. }) if field == expected_field
+            ));
+        }
     }
 }
@@ -1,56 +1,297 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::mem::size_of;
+use std::ops::Range;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use bytes::{Buf, Bytes};
+use futures::future::try_join_all;
 use moka::future::Cache;
+use parking_lot::Mutex;
+use serde_derive::Serialize;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::warn;
 
-use super::{Block, BlockCache, Sstable, SstableMeta};
-use crate::object_store::ObjectStoreRef;
+use super::{
+    decode_encrypted_meta_footer, encode_encrypted_meta_footer, max_covering_timestamp, Block,
+    BlockCache, BlockCacheStats, BlockDecodeOptions, KeyProviderRef, Sstable, SstableCipher,
+    SstableMeta, SSTABLE_FORMAT_VERSION,
+};
+use crate::iterator::{BlockIterator, Seek};
+use crate::object_store::{BoxedObjectUploader, ObjectStoreRef};
+use crate::utils::{compare_full_key, crc32check, full_key, value};
 use crate::{Error, ObjectStoreError, Result};
 
 // TODO: Define policy based on use cases (read / comapction / ...).
 #[derive(Clone, Copy)]
 pub enum CachePolicy {
+    /// Bypass the block cache.
     Disable,
+    /// Read through the block cache, and insert blocks on misses.
     Fill,
+    /// Read through the block cache, but never insert blocks, e.g. for one-shot scans of
+    /// compaction.
     NotFill,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize)]
+pub struct MetaCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+struct MetaCacheCore {
+    /// `{ sst id -> (meta, last access tick) }`
+    entries: HashMap<u64, (Arc<SstableMeta>, u64)>,
+    /// `{ last access tick -> sst id }`, the least recently used entry comes first.
+    lru: BTreeMap<u64, u64>,
+    tick: u64,
+    stats: MetaCacheStats,
+}
+
+/// [`MetaCache`] caches sstable metas with LRU eviction, so metas of hot sstables stay in memory
+/// while metas of cold sstables are evicted.
+pub struct MetaCache {
+    capacity: usize,
+    core: Mutex<MetaCacheCore>,
+}
+
+impl MetaCache {
+    /// Create a [`MetaCache`] that holds at most `capacity` metas.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            core: Mutex::new(MetaCacheCore {
+                entries: HashMap::default(),
+                lru: BTreeMap::default(),
+                tick: 0,
+                stats: MetaCacheStats::default(),
+            }),
+        }
+    }
+
+    pub fn get(&self, sst_id: u64) -> Option<Arc<SstableMeta>> {
+        let mut guard = self.core.lock();
+        let core = &mut *guard;
+        core.tick += 1;
+        match core.entries.get_mut(&sst_id) {
+            Some((meta, tick)) => {
+                core.lru.remove(tick);
+                *tick = core.tick;
+                core.lru.insert(core.tick, sst_id);
+                core.stats.hits += 1;
+                Some(meta.clone())
+            }
+            None => {
+                core.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Insert `meta` as the most recently used entry, evict the least recently used entries if
+    /// the cache is full.
+    pub fn insert(&self, sst_id: u64, meta: Arc<SstableMeta>) {
+        let mut guard = self.core.lock();
+        let core = &mut *guard;
+        core.tick += 1;
+        if let Some((_, tick)) = core.entries.insert(sst_id, (meta, core.tick)) {
+            core.lru.remove(&tick);
+        }
+        core.lru.insert(core.tick, sst_id);
+        while core.entries.len() > self.capacity {
+            let (&tick, &sst_id) = core.lru.iter().next().unwrap();
+            core.lru.remove(&tick);
+            core.entries.remove(&sst_id);
+            core.stats.evictions += 1;
+        }
+    }
+
+    /// Remove the meta of `sst_id` from the cache, returns it if cached.
+    pub fn invalidate(&self, sst_id: u64) -> Option<Arc<SstableMeta>> {
+        let mut guard = self.core.lock();
+        let (meta, tick) = guard.entries.remove(&sst_id)?;
+        guard.lru.remove(&tick);
+        Some(meta)
+    }
+
+    pub fn len(&self) -> usize {
+        self.core.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hit, miss and eviction counts since the cache is created.
+    pub fn stats(&self) -> MetaCacheStats {
+        self.core.lock().stats
+    }
+}
+
+/// [`PathBuilder`] decides the paths of sstable objects in the object store.
+pub trait PathBuilder: Send + Sync + 'static {
+    fn meta_path(&self, sst_id: u64) -> String;
+
+    fn data_path(&self, sst_id: u64) -> String;
+
+    /// Common prefix of the paths of all sstable objects, used to list sstables.
+    fn prefix(&self) -> String;
+
+    /// Parse the sstable id from the path of its meta or data object. Returns `None` for paths of
+    /// other objects.
+    fn sst_id(&self, path: &str) -> Option<u64>;
+}
+
+pub type PathBuilderRef = Arc<dyn PathBuilder>;
+
+/// [`DefaultPathBuilder`] puts sstable objects at `<path>/<sst id>.meta` and `<path>/<sst
+/// id>.data`.
+pub struct DefaultPathBuilder {
+    path: String,
+}
+
+impl DefaultPathBuilder {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+}
+
+impl PathBuilder for DefaultPathBuilder {
+    fn meta_path(&self, sst_id: u64) -> String {
+        format!("{}/{}.meta", self.path, sst_id)
+    }
+
+    fn data_path(&self, sst_id: u64) -> String {
+        format!("{}/{}.data", self.path, sst_id)
+    }
+
+    fn prefix(&self) -> String {
+        format!("{}/", self.path)
+    }
+
+    fn sst_id(&self, path: &str) -> Option<u64> {
+        let name = path.strip_prefix(&self.prefix())?;
+        name.strip_suffix(".data")
+            .or_else(|| name.strip_suffix(".meta"))?
+            .parse()
+            .ok()
+    }
+}
+
 pub struct SstableStoreOptions {
     pub path: String,
     pub object_store: ObjectStoreRef,
     pub block_cache: BlockCache,
     pub meta_cache_capacity: usize,
+    /// Verify block checksum on reads from the object store. Blocks read without verification are
+    /// verified again before being served if cached.
+    pub verify_checksum: bool,
+    /// Alternate source of sstable data. Blocks that fail checksum verification when read from
+    /// `object_store` are read again from the replica if given.
+    pub replica_object_store: Option<ObjectStoreRef>,
+    /// Encrypt sstables with the data keys supplied if given. Sstables written without encryption
+    /// are still readable.
+    pub key_provider: Option<KeyProviderRef>,
+    /// Remember sstables whose meta is not found for the TTL, so that repeated lookups of them,
+    /// e.g. racing with GC, don't reach the object store. Disabled if zero.
+    pub negative_meta_cache_ttl: Duration,
+    /// Decide the paths of sstable objects if given, otherwise [`DefaultPathBuilder`] with `path`
+    /// is used.
+    pub path_builder: Option<PathBuilderRef>,
+    /// Max count of object uploads in flight of [`SstableStore::put`] and
+    /// [`SstableStore::put_batch`], so that bursts of uploads, e.g. from compactions, don't
+    /// overwhelm the object store. Unlimited if zero.
+    pub max_concurrent_uploads: usize,
+}
+
+impl SstableStoreOptions {
+    /// Options that verify block checksums, with no replica, encryption, negative meta cache,
+    /// custom path builder or upload limit. Use struct update syntax to enable any of them.
+    pub fn new(
+        path: String,
+        object_store: ObjectStoreRef,
+        block_cache: BlockCache,
+        meta_cache_capacity: usize,
+    ) -> Self {
+        Self {
+            path,
+            object_store,
+            block_cache,
+            meta_cache_capacity,
+            verify_checksum: true,
+            replica_object_store: None,
+            key_provider: None,
+            negative_meta_cache_ttl: Duration::ZERO,
+            path_builder: None,
+            max_concurrent_uploads: 0,
+        }
+    }
 }
 
 pub struct SstableStore {
-    path: String,
+    path_builder: PathBuilderRef,
     object_store: ObjectStoreRef,
     block_cache: BlockCache,
-    meta_cache: Cache<u64, Arc<SstableMeta>>,
+    meta_cache: MetaCache,
+    verify_checksum: bool,
+    replica_object_store: Option<ObjectStoreRef>,
+    key_provider: Option<KeyProviderRef>,
+    /// `{ sst id -> cipher }`, `None` for sstables written without encryption.
+    ciphers: Cache<u64, Option<SstableCipher>>,
+    negative_meta_cache_ttl: Duration,
+    /// `{ sst id -> expiry }` of sstables whose meta is not found.
+    negative_metas: Mutex<HashMap<u64, Instant>>,
+    /// Permits of object uploads, `None` for unlimited.
+    upload_permits: Option<Semaphore>,
 }
 
 impl SstableStore {
     pub fn new(options: SstableStoreOptions) -> Self {
+        let meta_cache_capacity = options.meta_cache_capacity / size_of::<SstableMeta>() + 1;
         Self {
-            path: options.path,
+            path_builder: options
+                .path_builder
+                .unwrap_or_else(|| Arc::new(DefaultPathBuilder::new(options.path))),
             object_store: options.object_store,
             block_cache: options.block_cache,
-            meta_cache: Cache::new(
-                (options.meta_cache_capacity / size_of::<SstableMeta>() + 1) as u64,
-            ),
+            meta_cache: MetaCache::new(meta_cache_capacity),
+            verify_checksum: options.verify_checksum,
+            replica_object_store: options.replica_object_store,
+            key_provider: options.key_provider,
+            ciphers: Cache::new(meta_cache_capacity as u64),
+            negative_meta_cache_ttl: options.negative_meta_cache_ttl,
+            negative_metas: Mutex::new(HashMap::default()),
+            upload_permits: match options.max_concurrent_uploads {
+                0 => None,
+                permits => Some(Semaphore::new(permits)),
+            },
         }
     }
 
+    /// Put data and meta of sstable `sst`, the meta is put after the data.
+    ///
+    /// An sstable must be put completely before it is recorded in the manifest. A crash in between
+    /// leaves an orphan sstable, which is never referenced and can be collected, see
+    /// [`crate::manifest::VersionManager::gc_orphan_ssts`].
     pub async fn put(&self, sst: &Sstable, data: Vec<u8>, policy: CachePolicy) -> Result<()> {
+        let cipher = self.cipher_for_write(sst.id()).await?;
+        let mut encoded_data = data.clone();
+        if let Some(cipher) = &cipher {
+            cipher.apply_data(0, &mut encoded_data);
+        }
         let data_path = self.data_path(sst.id());
-        self.object_store.put(&data_path, data.clone()).await?;
+        self.put_object(&data_path, encoded_data).await?;
 
-        let meta = sst.encode_meta();
+        let meta = Self::encode_meta(sst, cipher.as_ref())?;
         let meta_path = self.meta_path(sst.id());
-        if let Err(e) = self.object_store.put(&meta_path, meta).await {
+        if let Err(e) = self.put_object(&meta_path, meta).await {
             self.object_store.remove(&data_path).await?;
             return Err(e);
         }
+        self.negative_metas.lock().remove(&sst.id());
 
         if let CachePolicy::Fill = policy {
             for (block_idx, meta) in sst.block_metas_iter().enumerate() {
@@ -62,43 +303,229 @@ impl SstableStore {
         Ok(())
     }
 
+    /// Put data and meta of multiple sstables, e.g. the outputs of a compaction.
+    ///
+    /// Objects are uploaded concurrently, all data before all meta. Metas are inserted into the
+    /// meta cache only after all uploads succeed. On failure, objects of all given sstables are
+    /// removed on a best-effort basis and the first error is returned.
+    pub async fn put_batch(&self, sstables: Vec<(&Sstable, Bytes, CachePolicy)>) -> Result<()> {
+        let mut ciphers = Vec::with_capacity(sstables.len());
+        for (sst, _, _) in sstables.iter() {
+            ciphers.push(self.cipher_for_write(sst.id()).await?);
+        }
+
+        let upload = async {
+            try_join_all(sstables.iter().zip(ciphers.iter()).map(
+                |((sst, data, _), cipher)| async move {
+                    let mut encoded_data = data.to_vec();
+                    if let Some(cipher) = cipher {
+                        cipher.apply_data(0, &mut encoded_data);
+                    }
+                    self.put_object(&self.data_path(sst.id()), encoded_data)
+                        .await
+                },
+            ))
+            .await?;
+            try_join_all(sstables.iter().zip(ciphers.iter()).map(
+                |((sst, _, _), cipher)| async move {
+                    let meta = Self::encode_meta(sst, cipher.as_ref())?;
+                    self.put_object(&self.meta_path(sst.id()), meta).await
+                },
+            ))
+            .await?;
+            Ok::<_, Error>(())
+        };
+        if let Err(e) = upload.await {
+            for (sst, _, _) in sstables.iter() {
+                if let Err(e) = self.remove(sst.id()).await {
+                    warn!("failed to roll back sstable {}: {}", sst.id(), e);
+                }
+            }
+            return Err(e);
+        }
+
+        for (sst, data, policy) in sstables.iter() {
+            self.negative_metas.lock().remove(&sst.id());
+            self.meta_cache.insert(sst.id(), sst.meta().clone());
+            if let CachePolicy::Fill = policy {
+                for (block_idx, meta) in sst.block_metas_iter().enumerate() {
+                    let block = Arc::new(Block::decode(&data[meta.data_range()])?);
+                    self.block_cache.insert(sst.id(), block_idx, block).await
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Acquire an upload permit if uploads are limited.
+    async fn acquire_upload_permit(&self) -> Result<Option<SemaphorePermit<'_>>> {
+        match &self.upload_permits {
+            Some(permits) => Ok(Some(
+                permits
+                    .acquire()
+                    .await
+                    .map_err(|e| Error::Other(e.to_string()))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Put an object after acquiring an upload permit if uploads are limited.
+    async fn put_object(&self, path: &str, obj: Vec<u8>) -> Result<()> {
+        let _permit = self.acquire_upload_permit().await?;
+        self.object_store.put(path, obj).await
+    }
+
+    /// Start a streaming upload of the data of sstable `sst_id`. Data are written with
+    /// [`SstableUploader::write`] in order, and the meta is uploaded on
+    /// [`SstableUploader::finish`].
+    ///
+    /// The uploader holds an upload permit until the data upload is finished or it is dropped.
+    pub async fn uploader(&self, sst_id: u64) -> Result<SstableUploader<'_>> {
+        let permit = self.acquire_upload_permit().await?;
+        let cipher = self.cipher_for_write(sst_id).await?;
+        let data_uploader = self.object_store.upload(&self.data_path(sst_id)).await?;
+        Ok(SstableUploader {
+            sstable_store: self,
+            sst_id,
+            cipher,
+            offset: 0,
+            data_uploader,
+            permit,
+        })
+    }
+
     pub async fn block(
         &self,
         sst: &Sstable,
         block_index: usize,
         policy: CachePolicy,
     ) -> Result<Arc<Block>> {
-        let fetch_block = async move {
-            let block_meta = sst.block_meta(block_index as usize).ok_or_else(|| {
-                Error::Other(format!(
-                    "invalid block idx: [sst: {}], [block: {}]",
-                    sst.id(),
-                    block_index
-                ))
-            })?;
-            let data_path = self.data_path(sst.id());
-            let block_data = self
-                .object_store
-                .get_range(&data_path, block_meta.data_range())
-                .await?
-                .ok_or(Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(
-                    data_path,
-                )))?;
-            let block = Block::decode(&block_data)?;
-            Ok(Arc::new(block))
+        let block_meta = sst.block_meta(block_index as usize).ok_or_else(|| {
+            Error::Other(format!(
+                "invalid block idx: [sst: {}], [block: {}]",
+                sst.id(),
+                block_index
+            ))
+        })?;
+        let cipher = self.cipher(sst.id()).await?;
+        let fetch_block = |verify_checksum| {
+            Self::fetch_block(
+                self.object_store.clone(),
+                self.replica_object_store.clone(),
+                self.data_path(sst.id()),
+                block_meta.data_range(),
+                cipher.clone(),
+                verify_checksum,
+            )
         };
 
         match policy {
             CachePolicy::Fill => {
-                self.block_cache
-                    .get_or_insert_with(sst.id(), block_index, fetch_block)
-                    .await
+                let block = self
+                    .block_cache
+                    .get_or_insert_with(sst.id(), block_index, fetch_block(self.verify_checksum))
+                    .await?;
+                if self.verify_checksum && !block.is_verified() {
+                    // The cached block was filled without verification, refetch and verify it.
+                    let block = fetch_block(true).await?;
+                    self.block_cache
+                        .insert(sst.id(), block_index, block.clone())
+                        .await;
+                    return Ok(block);
+                }
+                Ok(block)
             }
             CachePolicy::NotFill => match self.block_cache.get(sst.id(), block_index) {
-                Some(block) => Ok(block),
-                None => fetch_block.await,
+                Some(block) if block.is_verified() || !self.verify_checksum => Ok(block),
+                _ => fetch_block(self.verify_checksum).await,
             },
-            CachePolicy::Disable => fetch_block.await,
+            CachePolicy::Disable => fetch_block(self.verify_checksum).await,
+        }
+    }
+
+    /// Get the latest visible value of `user_key` at `timestamp` in the given sstable.
+    ///
+    /// The bloom filter is checked before fetching any block, and at most one block is fetched.
+    /// Returns `None` if the key is not found or has been deleted.
+    pub async fn get(
+        &self,
+        sst: &Sstable,
+        user_key: &[u8],
+        timestamp: u64,
+        policy: CachePolicy,
+    ) -> Result<Option<Bytes>> {
+        if !sst.may_contain_key(user_key) {
+            return Ok(None);
+        }
+        let key = full_key(user_key, timestamp);
+        // Index of the first block whose last key is not smaller than the given `key`, which is the
+        // only block that may contain the first key that equals or is larger than `key`.
+        let block_index = sst
+            .block_metas_iter()
+            .as_slice()
+            .partition_point(|meta| compare_full_key(&meta.last_key, &key) == Ordering::Less);
+        if block_index >= sst.blocks_len() {
+            return Ok(None);
+        }
+        let block = self.block(sst, block_index, policy).await?;
+        let mut iter = BlockIterator::new(block);
+        iter.seek(Seek::RandomForward(&key))?;
+        if !iter.is_valid() || crate::utils::user_key(iter.key()) != user_key {
+            return Ok(None);
+        }
+        if let Some(covering) = max_covering_timestamp(sst.range_tombstones(), user_key, timestamp)
+        {
+            if crate::utils::timestamp(iter.key()) <= covering {
+                return Ok(None);
+            }
+        }
+        Ok(value(iter.value()).map(Bytes::copy_from_slice))
+    }
+
+    /// Fetch `count` blocks starting from `start_block` of the given sstable in the background and
+    /// fill them into the block cache. Blocks out of range are ignored.
+    ///
+    /// Prefetching only takes effect with [`CachePolicy::Fill`]. Concurrent reads of a block being
+    /// prefetched wait for the in-flight fetch instead of issuing another one. Blocks of encrypted
+    /// sstables whose data key has not been resolved yet are not prefetched.
+    pub fn prefetch(&self, sst: &Sstable, start_block: usize, count: usize, policy: CachePolicy) {
+        if !matches!(policy, CachePolicy::Fill) {
+            return;
+        }
+        let cipher = if self.key_provider.is_some() {
+            match self.ciphers.get(&sst.id()) {
+                Some(cipher) => cipher,
+                None => return,
+            }
+        } else {
+            None
+        };
+        let end_block = std::cmp::min(start_block.saturating_add(count), sst.blocks_len());
+        for block_index in start_block..end_block {
+            let sst_id = sst.id();
+            let data_range = sst.block_meta(block_index).unwrap().data_range();
+            let fetch_block = Self::fetch_block(
+                self.object_store.clone(),
+                self.replica_object_store.clone(),
+                self.data_path(sst_id),
+                data_range,
+                cipher.clone(),
+                self.verify_checksum,
+            );
+            let block_cache = self.block_cache.clone();
+            tokio::spawn(async move {
+                if let Err(e) = block_cache
+                    .get_or_insert_with(sst_id, block_index, fetch_block)
+                    .await
+                {
+                    warn!(
+                        "prefetch block failed: [sst: {}], [block: {}], [err: {}]",
+                        sst_id, block_index, e
+                    );
+                }
+            });
         }
     }
 
@@ -107,48 +534,319 @@ impl SstableStore {
         Ok(Sstable::new(sst_id, meta))
     }
 
-    async fn meta(&self, sst_id: u64) -> Result<Arc<SstableMeta>> {
-        if let Some(meta) = self.meta_cache.get(&sst_id) {
+    /// Get the meta of sstable `sst_id` from the meta cache, or fetch it from the object store and
+    /// cache it on miss, e.g. for sstables written before the process restarts.
+    pub async fn meta(&self, sst_id: u64) -> Result<Arc<SstableMeta>> {
+        if let Some(meta) = self.meta_cache.get(sst_id) {
             return Ok(meta);
         }
+        let (meta, _) = self.fetch_meta(sst_id).await?;
+        Ok(meta)
+    }
+
+    /// Fetch and decode the meta of sstable `sst_id`, decrypt it with the data key recorded in its
+    /// footer if encrypted. The format version recorded in the meta is checked before both the meta
+    /// and the cipher are cached.
+    async fn fetch_meta(&self, sst_id: u64) -> Result<(Arc<SstableMeta>, Option<SstableCipher>)> {
         let path = self.meta_path(sst_id);
-        let buf = self
-            .object_store
-            .get(&path)
-            .await?
-            .ok_or(Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(
-                path,
-            )))?;
-        let meta = Arc::new(SstableMeta::decode(&mut &buf[..]));
-        self.meta_cache.insert(sst_id, meta.clone()).await;
+        if self.is_negative_meta(sst_id) {
+            return Err(ObjectStoreError::ObjectNotFound(path).into());
+        }
+        let buf = match self.object_store.get(&path).await? {
+            Some(buf) => buf,
+            None => {
+                self.insert_negative_meta(sst_id);
+                return Err(ObjectStoreError::ObjectNotFound(path).into());
+            }
+        };
+        let (meta, cipher) = match decode_encrypted_meta_footer(&buf) {
+            None => (SstableMeta::decode(&mut &buf[..])?, None),
+            Some((key_id, encrypted)) => {
+                let key = match &self.key_provider {
+                    Some(key_provider) => key_provider.key(key_id).await?,
+                    None => None,
+                }
+                .ok_or(Error::KeyUnavailable { sst_id, key_id })?;
+                let cipher = SstableCipher::new(sst_id, key);
+                let mut buf = encrypted.to_vec();
+                cipher.apply_meta(&mut buf);
+                // Verify checksum to detect decrypting with a wrong key.
+                if buf.len() < 4 || !crc32check(&buf[4..], (&buf[..4]).get_u32_le()) {
+                    return Err(Error::DecryptError { sst_id, key_id });
+                }
+                (SstableMeta::decode(&mut &buf[..])?, Some(cipher))
+            }
+        };
+        Self::check_format_version(sst_id, &meta)?;
+        let meta = Arc::new(meta);
+        self.meta_cache.insert(sst_id, meta.clone());
+        self.ciphers.insert(sst_id, cipher.clone()).await;
+        Ok((meta, cipher))
+    }
+
+    /// Check the data format version recorded in the meta of sstable `sst_id`, so that no extra
+    /// request to the data is needed.
+    ///
+    /// Data without the header is of version 0, which is only readable with the
+    /// `legacy-sstable-format` feature.
+    fn check_format_version(sst_id: u64, meta: &SstableMeta) -> Result<()> {
+        match meta.format_version {
+            0 if cfg!(feature = "legacy-sstable-format") => Ok(()),
+            SSTABLE_FORMAT_VERSION => Ok(()),
+            version => Err(Error::UnsupportedSstableVersion { sst_id, version }),
+        }
+    }
+
+    /// Returns `true` if the meta of `sst_id` is remembered as not found.
+    fn is_negative_meta(&self, sst_id: u64) -> bool {
+        let mut negative_metas = self.negative_metas.lock();
+        match negative_metas.get(&sst_id) {
+            Some(expiry) if *expiry > Instant::now() => true,
+            Some(_) => {
+                negative_metas.remove(&sst_id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn insert_negative_meta(&self, sst_id: u64) {
+        if self.negative_meta_cache_ttl.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let mut negative_metas = self.negative_metas.lock();
+        negative_metas.retain(|_, expiry| *expiry > now);
+        negative_metas.insert(sst_id, now + self.negative_meta_cache_ttl);
+    }
+
+    /// Get the cipher to decrypt sstable `sst_id` with, `None` if the sstable is not encrypted.
+    async fn cipher(&self, sst_id: u64) -> Result<Option<SstableCipher>> {
+        if self.key_provider.is_none() {
+            return Ok(None);
+        }
+        if let Some(cipher) = self.ciphers.get(&sst_id) {
+            return Ok(cipher);
+        }
+        let (_, cipher) = self.fetch_meta(sst_id).await?;
+        Ok(cipher)
+    }
+
+    /// Get the cipher to encrypt the new sstable `sst_id` with, `None` if encryption is disabled.
+    async fn cipher_for_write(&self, sst_id: u64) -> Result<Option<SstableCipher>> {
+        let key_provider = match &self.key_provider {
+            Some(key_provider) => key_provider,
+            None => return Ok(None),
+        };
+        let cipher = SstableCipher::new(sst_id, key_provider.data_key(sst_id).await?);
+        self.ciphers.insert(sst_id, Some(cipher.clone())).await;
+        Ok(Some(cipher))
+    }
+
+    fn encode_meta(sst: &Sstable, cipher: Option<&SstableCipher>) -> Result<Vec<u8>> {
+        let mut meta = sst.encode_meta()?;
+        if let Some(cipher) = cipher {
+            cipher.apply_meta(&mut meta);
+            encode_encrypted_meta_footer(&mut meta, cipher.key_id());
+        }
         Ok(meta)
     }
 
+    /// Fetch and decode a block from `object_store`. If the block fails checksum verification, it
+    /// is read again from `replica_object_store` if given. The checksum mismatch error is returned
+    /// only if the replica fails too.
+    async fn fetch_block(
+        object_store: ObjectStoreRef,
+        replica_object_store: Option<ObjectStoreRef>,
+        data_path: String,
+        data_range: Range<usize>,
+        cipher: Option<SstableCipher>,
+        verify_checksum: bool,
+    ) -> Result<Arc<Block>> {
+        let result = Self::fetch_block_from(
+            &object_store,
+            &data_path,
+            data_range.clone(),
+            cipher.as_ref(),
+            verify_checksum,
+        )
+        .await;
+        let replica_object_store = match replica_object_store {
+            Some(replica_object_store) if matches!(result, Err(Error::BlockChecksumMismatch)) => {
+                replica_object_store
+            }
+            _ => return result,
+        };
+        warn!(
+            "block checksum mismatch, read from replica: [path: {}], [range: {:?}]",
+            data_path, data_range
+        );
+        Self::fetch_block_from(
+            &replica_object_store,
+            &data_path,
+            data_range,
+            cipher.as_ref(),
+            verify_checksum,
+        )
+        .await
+        .map_err(|e| {
+            warn!(
+                "read block from replica failed: [path: {}], [err: {}]",
+                data_path, e
+            );
+            Error::BlockChecksumMismatch
+        })
+    }
+
+    async fn fetch_block_from(
+        object_store: &ObjectStoreRef,
+        data_path: &str,
+        data_range: Range<usize>,
+        cipher: Option<&SstableCipher>,
+        verify_checksum: bool,
+    ) -> Result<Arc<Block>> {
+        let offset = data_range.start;
+        let mut block_data = object_store
+            .get_range(data_path, data_range)
+            .await?
+            .ok_or_else(|| {
+                Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(data_path.to_string()))
+            })?;
+        if let Some(cipher) = cipher {
+            cipher.apply_data(offset, &mut block_data);
+        }
+        let block =
+            Block::decode_with_options(&block_data, BlockDecodeOptions { verify_checksum })?;
+        Ok(Arc::new(block))
+    }
+
+    /// Ids of sstables that have data or meta objects, in ASC order.
+    pub async fn list_ssts(&self) -> Result<Vec<u64>> {
+        let paths = self.object_store.list(&self.path_builder.prefix()).await?;
+        let ssts = paths
+            .iter()
+            .filter_map(|path| self.path_builder.sst_id(path))
+            .collect::<BTreeSet<_>>();
+        Ok(ssts.into_iter().collect())
+    }
+
+    /// Remove data and meta of sstable `sst_id`, and evict them from caches. Missing objects are
+    /// ignored, so that sstables partially put can be removed.
+    ///
+    /// Note: Cached blocks are located with the cached meta. Blocks cached after the meta is
+    /// evicted are left to the block cache eviction.
+    pub async fn remove(&self, sst_id: u64) -> Result<()> {
+        for path in [self.meta_path(sst_id), self.data_path(sst_id)] {
+            match self.object_store.remove(&path).await {
+                Ok(()) | Err(Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(_))) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some(meta) = self.meta_cache.invalidate(sst_id) {
+            self.block_cache
+                .invalidate_sstable(sst_id, meta.block_metas.len())
+                .await;
+        }
+        self.ciphers.invalidate(&sst_id).await;
+        Ok(())
+    }
+
     pub fn meta_path(&self, sst_id: u64) -> String {
-        format!("{}/{}.meta", self.path, sst_id)
+        self.path_builder.meta_path(sst_id)
     }
 
     pub fn data_path(&self, sst_id: u64) -> String {
-        format!("{}/{}.data", self.path, sst_id)
+        self.path_builder.data_path(sst_id)
     }
 
     pub fn store(&self) -> ObjectStoreRef {
         self.object_store.clone()
     }
+
+    pub fn block_cache_stats(&self) -> BlockCacheStats {
+        self.block_cache.stats()
+    }
+
+    pub fn meta_cache_stats(&self) -> MetaCacheStats {
+        self.meta_cache.stats()
+    }
 }
 
 pub type SstableStoreRef = Arc<SstableStore>;
 
+/// [`SstableUploader`] uploads sstable data while the sstable is being built, so that the whole
+/// sstable data never need to be held in memory. See [`SstableStore::uploader`].
+pub struct SstableUploader<'a> {
+    sstable_store: &'a SstableStore,
+    sst_id: u64,
+    cipher: Option<SstableCipher>,
+    /// Length of data written.
+    offset: usize,
+    data_uploader: BoxedObjectUploader<'a>,
+    /// Upload permit of the data upload, `None` if uploads are not limited.
+    permit: Option<SemaphorePermit<'a>>,
+}
+
+impl<'a> SstableUploader<'a> {
+    pub async fn write(&mut self, mut data: Vec<u8>) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        if let Some(cipher) = &self.cipher {
+            cipher.apply_data(self.offset, &mut data);
+        }
+        self.offset += data.len();
+        self.data_uploader.write(data).await
+    }
+
+    /// Write the remaining `data`, finish the data upload and put the meta of `sst`. The meta is
+    /// inserted into the meta cache once put, as [`SstableStore::put_batch`] does.
+    pub async fn finish(mut self, sst: &Sstable, data: Vec<u8>) -> Result<()> {
+        debug_assert_eq!(self.sst_id, sst.id());
+        self.write(data).await?;
+        self.data_uploader.finish().await?;
+        // Release the permit before putting the meta, which acquires one again.
+        drop(self.permit);
+
+        let meta = SstableStore::encode_meta(sst, self.cipher.as_ref())?;
+        let meta_path = self.sstable_store.meta_path(sst.id());
+        if let Err(e) = self.sstable_store.put_object(&meta_path, meta).await {
+            if let Err(e) = self
+                .sstable_store
+                .object_store
+                .remove(&self.sstable_store.data_path(sst.id()))
+                .await
+            {
+                warn!("failed to roll back sstable {}: {}", sst.id(), e);
+            }
+            return Err(e);
+        }
+        self.sstable_store.negative_metas.lock().remove(&sst.id());
+        self.sstable_store
+            .meta_cache
+            .insert(sst.id(), sst.meta().clone());
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+    use async_trait::async_trait;
+    use parking_lot::Mutex;
     use runkv_common::coding::CompressionAlgorithm;
     use test_log::test;
 
     use super::*;
-    use crate::components::{SstableBuilder, SstableBuilderOptions};
+    use crate::components::{
+        decode_data_header, DataKey, KeyProvider, SstableBuilder, SstableBuilderOptions,
+        SSTABLE_DATA_HEADER_LEN,
+    };
     use crate::lsm_tree::TEST_DEFAULT_RESTART_INTERVAL;
-    use crate::MemObjectStore;
+    use crate::{MemObjectStore, ObjectStore};
 
     fn build_sstable_for_test() -> (SstableMeta, Vec<u8>) {
         let options = SstableBuilderOptions {
@@ -157,6 +855,7 @@ mod tests {
             restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
             bloom_false_positive: 0.1,
             compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
         };
         let mut builder = SstableBuilder::new(options);
         builder.add(b"k01", 1, Some(b"v01")).unwrap();
@@ -170,12 +869,7 @@ mod tests {
     async fn test_sstable_store() {
         let object_store = Arc::new(MemObjectStore::default());
         let block_cache = BlockCache::new(65536);
-        let options = SstableStoreOptions {
-            path: "test".to_string(),
-            object_store,
-            block_cache,
-            meta_cache_capacity: 1024,
-        };
+        let options = SstableStoreOptions::new("test".to_string(), object_store, block_cache, 1024);
         let sstable_store = SstableStore::new(options);
         let (meta, data) = build_sstable_for_test();
         let meta = Arc::new(meta);
@@ -196,6 +890,13 @@ mod tests {
             let origin_block = Block::decode(&data[block_meta.data_range()]).unwrap();
             assert_eq!(origin_block.data(), block.data());
         }
+        assert_eq!(
+            BlockCacheStats {
+                hits: sst.blocks_len() as u64,
+                misses: 0,
+            },
+            sstable_store.block_cache_stats()
+        );
         // Test fetch from object store.
         for (block_idx, block_meta) in sst.block_metas_iter().enumerate() {
             let block = sstable_store
@@ -206,4 +907,912 @@ mod tests {
             assert_eq!(origin_block.data(), block.data());
         }
     }
+
+    #[test(tokio::test)]
+    async fn test_meta_on_miss() {
+        let object_store = Arc::new(MemObjectStore::default());
+        let options = || {
+            SstableStoreOptions::new(
+                "test".to_string(),
+                object_store.clone(),
+                BlockCache::new(65536),
+                1024,
+            )
+        };
+        let (meta, data) = build_sstable_for_test();
+        let meta = Arc::new(meta);
+        SstableStore::new(options())
+            .put(&Sstable::new(1, meta.clone()), data, CachePolicy::Disable)
+            .await
+            .unwrap();
+
+        // A new store, e.g. after restart, loads the meta from the object store and caches it.
+        let sstable_store = SstableStore::new(options());
+        assert!(sstable_store.meta_cache.is_empty());
+        assert_eq!(sstable_store.meta(1).await.unwrap(), meta);
+        assert_eq!(sstable_store.meta_cache.get(1), Some(meta.clone()));
+        assert_eq!(sstable_store.meta(1).await.unwrap(), meta);
+        assert!(matches!(
+            sstable_store.meta(2).await,
+            Err(Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(_)))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_cache_policy() {
+        let sstable_store = SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            Arc::new(MemObjectStore::default()),
+            BlockCache::new(65536),
+            1024,
+        ));
+        let (meta, data) = build_sstable_for_test();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data.clone(), CachePolicy::Disable)
+            .await
+            .unwrap();
+        let origin_block = |block_idx: usize| {
+            Block::decode(&data[sst.block_meta(block_idx).unwrap().data_range()]).unwrap()
+        };
+
+        // Misses with `NotFill` don't insert blocks.
+        for block_idx in 0..sst.blocks_len() {
+            let block = sstable_store
+                .block(&sst, block_idx, CachePolicy::NotFill)
+                .await
+                .unwrap();
+            assert_eq!(origin_block(block_idx).data(), block.data());
+            assert!(sstable_store.block_cache.get(1, block_idx).is_none());
+        }
+
+        // Misses with `Fill` insert blocks, which are then served to `NotFill`.
+        sstable_store
+            .block(&sst, 0, CachePolicy::Fill)
+            .await
+            .unwrap();
+        assert!(sstable_store.block_cache.get(1, 0).is_some());
+        let stats = sstable_store.block_cache_stats();
+        let block = sstable_store
+            .block(&sst, 0, CachePolicy::NotFill)
+            .await
+            .unwrap();
+        assert_eq!(origin_block(0).data(), block.data());
+        assert_eq!(sstable_store.block_cache_stats().hits, stats.hits + 1);
+    }
+
+    #[test]
+    fn test_meta_cache() {
+        let (meta, _) = build_sstable_for_test();
+        let meta = Arc::new(meta);
+        let cache = MetaCache::new(2);
+        cache.insert(1, meta.clone());
+        cache.insert(2, meta.clone());
+        // Access 1 so that 2 is the least recently used one.
+        assert_eq!(cache.get(1), Some(meta.clone()));
+        cache.insert(3, meta.clone());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(1), Some(meta.clone()));
+        assert_eq!(cache.get(3), Some(meta.clone()));
+        assert_eq!(
+            cache.stats(),
+            MetaCacheStats {
+                hits: 3,
+                misses: 1,
+                evictions: 1,
+            }
+        );
+
+        // Reinserting doesn't evict.
+        cache.insert(3, meta.clone());
+        assert_eq!(cache.len(), 2);
+        cache.invalidate(1);
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_sstable_store_get() {
+        let object_store = Arc::new(MemObjectStore::default());
+        let block_cache = BlockCache::new(65536);
+        let options =
+            SstableStoreOptions::new("test".to_string(), object_store.clone(), block_cache, 1024);
+        let sstable_store = SstableStore::new(options);
+        let options = SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        let mut builder = SstableBuilder::new(options);
+        builder.add(b"k01", 3, Some(b"v01-3")).unwrap();
+        builder.add(b"k01", 1, Some(b"v01-1")).unwrap();
+        builder.add(b"k02", 2, None).unwrap();
+        builder.add(b"k04", 4, Some(b"v04")).unwrap();
+        builder.add(b"k05", 5, Some(b"v05")).unwrap();
+        let (meta, data) = builder.build().unwrap();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+
+        let cases: [(&[u8], u64, Option<&[u8]>); 9] = [
+            (b"k01", 5, Some(b"v01-3")),
+            (b"k01", 3, Some(b"v01-3")),
+            (b"k01", 2, Some(b"v01-1")),
+            (b"k01", 0, None),
+            // Tombstone.
+            (b"k02", 2, None),
+            (b"k04", 4, Some(b"v04")),
+            (b"k04", 3, None),
+            (b"k05", 10, Some(b"v05")),
+            (b"k06", 6, None),
+        ];
+        for (key, ts, expected) in cases {
+            let v = sstable_store
+                .get(&sst, key, ts, CachePolicy::Disable)
+                .await
+                .unwrap();
+            assert_eq!(expected.map(Bytes::copy_from_slice), v);
+        }
+
+        // Keys filtered by bloom filter never touch the object store.
+        object_store
+            .remove(&sstable_store.data_path(1))
+            .await
+            .unwrap();
+        let mut filtered = 0;
+        for i in 100..200 {
+            let key = format!("k{:03}", i);
+            if sst.may_contain_key(key.as_bytes()) {
+                continue;
+            }
+            filtered += 1;
+            assert_eq!(
+                None,
+                sstable_store
+                    .get(&sst, key.as_bytes(), u64::MAX, CachePolicy::Disable)
+                    .await
+                    .unwrap()
+            );
+        }
+        assert!(filtered > 0);
+    }
+
+    #[test(tokio::test)]
+    async fn test_verify_checksum() {
+        let object_store = Arc::new(MemObjectStore::default());
+        let block_cache = BlockCache::new(65536);
+        let build_sstable_store = |verify_checksum| {
+            SstableStore::new(SstableStoreOptions {
+                verify_checksum,
+                ..SstableStoreOptions::new(
+                    "test".to_string(),
+                    object_store.clone(),
+                    block_cache.clone(),
+                    1024,
+                )
+            })
+        };
+        let verified_sstable_store = build_sstable_store(true);
+        let unverified_sstable_store = build_sstable_store(false);
+
+        let (meta, data) = build_sstable_for_test();
+        let sst = Sstable::new(1, Arc::new(meta));
+        verified_sstable_store
+            .put(&sst, data.clone(), CachePolicy::Disable)
+            .await
+            .unwrap();
+        // Corrupt the value of the first entry: `v01` -> `v09`.
+        let mut corrupted_data = data.clone();
+        let offset = sst.block_meta(0).unwrap().offset + 17;
+        assert_eq!(b'1', corrupted_data[offset]);
+        corrupted_data[offset] = b'9';
+        object_store
+            .put(&verified_sstable_store.data_path(1), corrupted_data)
+            .await
+            .unwrap();
+
+        for policy in [CachePolicy::Disable, CachePolicy::NotFill] {
+            assert!(matches!(
+                verified_sstable_store.block(&sst, 0, policy).await,
+                Err(Error::BlockChecksumMismatch)
+            ));
+        }
+
+        // Fill the cache with the unverified block.
+        let block = unverified_sstable_store
+            .block(&sst, 0, CachePolicy::Fill)
+            .await
+            .unwrap();
+        assert!(!block.is_verified());
+        let origin_block = Block::decode(&data[sst.block_meta(0).unwrap().data_range()]).unwrap();
+        assert_ne!(origin_block.data(), block.data());
+        assert_eq!(origin_block.data().len(), block.data().len());
+
+        // The unverified cached block must not be served as verified.
+        for policy in [CachePolicy::NotFill, CachePolicy::Fill] {
+            assert!(matches!(
+                verified_sstable_store.block(&sst, 0, policy).await,
+                Err(Error::BlockChecksumMismatch)
+            ));
+        }
+
+        // Uncorrupted blocks are not affected.
+        let block = verified_sstable_store
+            .block(&sst, 1, CachePolicy::Fill)
+            .await
+            .unwrap();
+        assert!(block.is_verified());
+    }
+
+    #[test(tokio::test)]
+    async fn test_read_block_from_replica() {
+        let object_store = Arc::new(MemObjectStore::default());
+        let replica_object_store = Arc::new(MemObjectStore::default());
+        let sstable_store = SstableStore::new(SstableStoreOptions {
+            replica_object_store: Some(replica_object_store.clone()),
+            ..SstableStoreOptions::new(
+                "test".to_string(),
+                object_store.clone(),
+                BlockCache::new(65536),
+                1024,
+            )
+        });
+
+        let (meta, data) = build_sstable_for_test();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data.clone(), CachePolicy::Disable)
+            .await
+            .unwrap();
+        let data_path = sstable_store.data_path(1);
+        replica_object_store
+            .put(&data_path, data.clone())
+            .await
+            .unwrap();
+        // Corrupt the value of the first entry on the primary: `v01` -> `v09`.
+        let mut corrupted_data = data.clone();
+        let offset = sst.block_meta(0).unwrap().offset + 17;
+        corrupted_data[offset] = b'9';
+        object_store
+            .put(&data_path, corrupted_data.clone())
+            .await
+            .unwrap();
+
+        let origin_block = Block::decode(&data[sst.block_meta(0).unwrap().data_range()]).unwrap();
+        for policy in [
+            CachePolicy::Disable,
+            CachePolicy::NotFill,
+            CachePolicy::Fill,
+        ] {
+            let block = sstable_store.block(&sst, 0, policy).await.unwrap();
+            assert!(block.is_verified());
+            assert_eq!(origin_block.data(), block.data());
+        }
+
+        // Fail if the replica is corrupted too.
+        replica_object_store
+            .put(&data_path, corrupted_data)
+            .await
+            .unwrap();
+        assert!(matches!(
+            sstable_store.block(&sst, 0, CachePolicy::Disable).await,
+            Err(Error::BlockChecksumMismatch)
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_sstable_uploader() {
+        let object_store = Arc::new(MemObjectStore::default());
+        let sstable_store = SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            object_store.clone(),
+            BlockCache::new(65536),
+            1024,
+        ));
+
+        let mut builder = SstableBuilder::new(SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        });
+        let mut uploader = sstable_store.uploader(1).await.unwrap();
+        for i in 0..16u64 {
+            builder
+                .add(format!("k{:02}", i).as_bytes(), i, Some(b"v"))
+                .unwrap();
+            uploader.write(builder.drain_data()).await.unwrap();
+        }
+        // Invisible before finished.
+        assert!(object_store
+            .get(&sstable_store.data_path(1))
+            .await
+            .unwrap()
+            .is_none());
+        let (meta, data) = builder.build().unwrap();
+        let sst = Sstable::new(1, Arc::new(meta));
+        uploader.finish(&sst, data).await.unwrap();
+        assert_eq!(sstable_store.meta_cache.get(1), Some(sst.meta().clone()));
+
+        let sst = sstable_store.sstable(1).await.unwrap();
+        assert!(sst.blocks_len() > 1);
+        for i in 0..16u64 {
+            assert_eq!(
+                sstable_store
+                    .get(
+                        &sst,
+                        format!("k{:02}", i).as_bytes(),
+                        i,
+                        CachePolicy::Disable
+                    )
+                    .await
+                    .unwrap(),
+                Some(Bytes::from_static(b"v"))
+            );
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_remove() {
+        let object_store = Arc::new(MemObjectStore::default());
+        let sstable_store = SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            object_store.clone(),
+            BlockCache::new(65536),
+            1024,
+        ));
+        let (meta, data) = build_sstable_for_test();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Fill)
+            .await
+            .unwrap();
+        sstable_store.sstable(1).await.unwrap();
+        assert!(!sstable_store.meta_cache.is_empty());
+
+        sstable_store.remove(1).await.unwrap();
+        assert!(sstable_store.list_ssts().await.unwrap().is_empty());
+        assert_eq!(object_store.size(), 0);
+        assert!(sstable_store.meta_cache.is_empty());
+        for block_idx in 0..sst.blocks_len() {
+            assert!(sstable_store.block_cache.get(1, block_idx).is_none());
+        }
+        assert!(matches!(
+            sstable_store.sstable(1).await,
+            Err(Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(_)))
+        ));
+        assert!(matches!(
+            sstable_store.block(&sst, 0, CachePolicy::Disable).await,
+            Err(Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(_)))
+        ));
+
+        // Removing is idempotent.
+        sstable_store.remove(1).await.unwrap();
+    }
+
+    #[test(tokio::test)]
+    async fn test_put_batch() {
+        let (meta, data) = build_sstable_for_test();
+        let meta = Arc::new(meta);
+        let data = Bytes::from(data);
+        let ssts = (1..=3)
+            .map(|sst_id| Sstable::new(sst_id, meta.clone()))
+            .collect::<Vec<_>>();
+
+        let object_store = Arc::new(MemObjectStore::default());
+        let sstable_store = SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            object_store.clone(),
+            BlockCache::new(65536),
+            1024,
+        ));
+        sstable_store
+            .put_batch(
+                ssts.iter()
+                    .map(|sst| (sst, data.clone(), CachePolicy::Fill))
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(sstable_store.list_ssts().await.unwrap(), vec![1, 2, 3]);
+        for sst in ssts.iter() {
+            assert_eq!(sstable_store.meta_cache.get(sst.id()), Some(meta.clone()));
+            assert_eq!(
+                sstable_store
+                    .get(sst, b"k04", 4, CachePolicy::Fill)
+                    .await
+                    .unwrap(),
+                Some(Bytes::from_static(b"v04"))
+            );
+        }
+        assert_eq!(sstable_store.block_cache_stats().misses, 0);
+
+        // Not enough space for all sstables, all uploaded objects are rolled back.
+        let object_store = Arc::new(MemObjectStore::with_capacity(data.len() * 2));
+        let sstable_store = SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            object_store.clone(),
+            BlockCache::new(65536),
+            1024,
+        ));
+        assert!(matches!(
+            sstable_store
+                .put_batch(
+                    ssts.iter()
+                        .map(|sst| (sst, data.clone(), CachePolicy::Disable))
+                        .collect(),
+                )
+                .await,
+            Err(Error::ObjectStoreError(ObjectStoreError::OutOfSpace(_)))
+        ));
+        assert_eq!(object_store.size(), 0);
+        assert!(sstable_store.meta_cache.is_empty());
+    }
+
+    /// [`MemObjectStore`] wrapper that counts gets and range gets.
+    #[derive(Default)]
+    struct CountedObjectStore {
+        inner: MemObjectStore,
+        get_count: AtomicU64,
+        get_range_count: AtomicU64,
+    }
+
+    #[async_trait]
+    impl ObjectStore for CountedObjectStore {
+        async fn put(&self, path: &str, obj: Vec<u8>) -> Result<()> {
+            self.inner.put(path, obj).await
+        }
+
+        async fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+            self.get_count.fetch_add(1, AtomicOrdering::SeqCst);
+            self.inner.get(path).await
+        }
+
+        async fn get_range(&self, path: &str, range: Range<usize>) -> Result<Option<Vec<u8>>> {
+            self.get_range_count.fetch_add(1, AtomicOrdering::SeqCst);
+            self.inner.get_range(path, range).await
+        }
+
+        async fn remove(&self, path: &str) -> Result<()> {
+            self.inner.remove(path).await
+        }
+
+        async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+            self.inner.list(prefix).await
+        }
+    }
+
+    /// [`MemObjectStore`] wrapper that delays puts and records the max count of puts in flight.
+    #[derive(Default)]
+    struct SlowObjectStore {
+        inner: MemObjectStore,
+        in_flight: AtomicU64,
+        max_in_flight: AtomicU64,
+    }
+
+    #[async_trait]
+    impl ObjectStore for SlowObjectStore {
+        async fn put(&self, path: &str, obj: Vec<u8>) -> Result<()> {
+            let in_flight = self.in_flight.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+            self.max_in_flight
+                .fetch_max(in_flight, AtomicOrdering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.in_flight.fetch_sub(1, AtomicOrdering::SeqCst);
+            self.inner.put(path, obj).await
+        }
+
+        async fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+            self.inner.get(path).await
+        }
+
+        async fn get_range(&self, path: &str, range: Range<usize>) -> Result<Option<Vec<u8>>> {
+            self.inner.get_range(path, range).await
+        }
+
+        async fn remove(&self, path: &str) -> Result<()> {
+            self.inner.remove(path).await
+        }
+
+        async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+            self.inner.list(prefix).await
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_max_concurrent_uploads() {
+        let (meta, data) = build_sstable_for_test();
+        let meta = Arc::new(meta);
+        let ssts = (1..=12)
+            .map(|sst_id| Sstable::new(sst_id, meta.clone()))
+            .collect::<Vec<_>>();
+
+        let object_store = Arc::new(SlowObjectStore::default());
+        let sstable_store = SstableStore::new(SstableStoreOptions {
+            max_concurrent_uploads: 2,
+            ..SstableStoreOptions::new(
+                "test".to_string(),
+                object_store.clone(),
+                BlockCache::new(65536),
+                1024,
+            )
+        });
+
+        futures::future::try_join_all(
+            ssts[..4]
+                .iter()
+                .map(|sst| sstable_store.put(sst, data.clone(), CachePolicy::Disable)),
+        )
+        .await
+        .unwrap();
+        sstable_store
+            .put_batch(
+                ssts[4..8]
+                    .iter()
+                    .map(|sst| (sst, Bytes::from(data.clone()), CachePolicy::Disable))
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(object_store.max_in_flight.load(AtomicOrdering::SeqCst), 2);
+
+        // Streaming uploads are limited too.
+        object_store.max_in_flight.store(0, AtomicOrdering::SeqCst);
+        futures::future::try_join_all(ssts[8..].iter().map(|sst| {
+            let sstable_store = &sstable_store;
+            let data = &data;
+            async move {
+                let mut uploader = sstable_store.uploader(sst.id()).await?;
+                uploader.write(data[..data.len() / 2].to_vec()).await?;
+                uploader.finish(sst, data[data.len() / 2..].to_vec()).await
+            }
+        }))
+        .await
+        .unwrap();
+        assert_eq!(object_store.max_in_flight.load(AtomicOrdering::SeqCst), 2);
+        assert_eq!(
+            sstable_store.list_ssts().await.unwrap(),
+            (1..=12).collect::<Vec<_>>()
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_negative_meta_cache() {
+        let object_store = Arc::new(CountedObjectStore::default());
+        let sstable_store = SstableStore::new(SstableStoreOptions {
+            negative_meta_cache_ttl: Duration::from_millis(200),
+            ..SstableStoreOptions::new(
+                "test".to_string(),
+                object_store.clone(),
+                BlockCache::new(65536),
+                1024,
+            )
+        });
+        let get_count = || object_store.get_count.load(AtomicOrdering::SeqCst);
+
+        for _ in 0..2 {
+            assert!(matches!(
+                sstable_store.sstable(1).await,
+                Err(Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(_)))
+            ));
+        }
+        assert_eq!(get_count(), 1);
+
+        // Expired.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(sstable_store.sstable(1).await.is_err());
+        assert_eq!(get_count(), 2);
+
+        // Invalidated by put.
+        let (meta, data) = build_sstable_for_test();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+        sstable_store.sstable(1).await.unwrap();
+        assert_eq!(get_count(), 3);
+    }
+
+    /// [`KeyProvider`] that encrypts new sstables with the latest key and keeps keys in memory.
+    #[derive(Default)]
+    struct MockKeyProvider {
+        current: AtomicU64,
+        keys: Mutex<HashMap<u64, [u8; 32]>>,
+    }
+
+    impl MockKeyProvider {
+        fn rotate(&self) -> u64 {
+            let key_id = self.current.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+            self.keys.lock().insert(key_id, [key_id as u8; 32]);
+            key_id
+        }
+
+        fn destroy(&self, key_id: u64) {
+            self.keys.lock().remove(&key_id);
+        }
+    }
+
+    #[async_trait]
+    impl KeyProvider for MockKeyProvider {
+        async fn data_key(&self, _sst_id: u64) -> Result<DataKey> {
+            let id = self.current.load(AtomicOrdering::SeqCst);
+            let key = self.keys.lock()[&id];
+            Ok(DataKey { id, key })
+        }
+
+        async fn key(&self, key_id: u64) -> Result<Option<DataKey>> {
+            Ok(self.keys.lock().get(&key_id).map(|key| DataKey {
+                id: key_id,
+                key: *key,
+            }))
+        }
+    }
+
+    fn build_encrypted_sstable_store(
+        object_store: ObjectStoreRef,
+        key_provider: Option<KeyProviderRef>,
+    ) -> SstableStore {
+        SstableStore::new(SstableStoreOptions {
+            key_provider,
+            ..SstableStoreOptions::new(
+                "test".to_string(),
+                object_store,
+                BlockCache::new(65536),
+                1024,
+            )
+        })
+    }
+
+    #[test(tokio::test)]
+    async fn test_encrypted_sstable() {
+        let object_store = Arc::new(MemObjectStore::default());
+        let key_provider = Arc::new(MockKeyProvider::default());
+        key_provider.rotate();
+        let sstable_store =
+            build_encrypted_sstable_store(object_store.clone(), Some(key_provider.clone()));
+
+        let (meta, data) = build_sstable_for_test();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data.clone(), CachePolicy::Disable)
+            .await
+            .unwrap();
+        // Written with a rotated key through the uploader.
+        key_provider.rotate();
+        let (meta, data) = build_sstable_for_test();
+        let sst = Sstable::new(2, Arc::new(meta));
+        let uploader = sstable_store.uploader(2).await.unwrap();
+        uploader.finish(&sst, data.clone()).await.unwrap();
+
+        for sst_id in [1, 2] {
+            let raw_data = object_store
+                .get(&sstable_store.data_path(sst_id))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(raw_data.len(), data.len());
+            assert_ne!(raw_data, data);
+        }
+
+        // Read with empty caches.
+        let sstable_store = build_encrypted_sstable_store(object_store, Some(key_provider));
+        for sst_id in [1, 2] {
+            let sst = sstable_store.sstable(sst_id).await.unwrap();
+            assert_eq!(
+                sstable_store.meta(sst_id).await.unwrap().as_ref(),
+                &build_sstable_for_test().0
+            );
+            for (k, v) in [(b"k01", b"v01"), (b"k05", b"v05")] {
+                assert_eq!(
+                    sstable_store
+                        .get(&sst, k, 10, CachePolicy::Fill)
+                        .await
+                        .unwrap(),
+                    Some(Bytes::copy_from_slice(v))
+                );
+            }
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_encrypted_sstable_key_unavailable() {
+        let object_store = Arc::new(MemObjectStore::default());
+        let key_provider = Arc::new(MockKeyProvider::default());
+        let key_id = key_provider.rotate();
+        let sstable_store =
+            build_encrypted_sstable_store(object_store.clone(), Some(key_provider.clone()));
+        let (meta, data) = build_sstable_for_test();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+
+        // Without key provider.
+        let sstable_store = build_encrypted_sstable_store(object_store.clone(), None);
+        assert!(matches!(
+            sstable_store.sstable(1).await,
+            Err(Error::KeyUnavailable {
+                sst_id: 1,
+                key_id: id
+            }) if id == key_id
+        ));
+
+        // The key has been destroyed after rotation.
+        key_provider.rotate();
+        key_provider.destroy(key_id);
+        let sstable_store = build_encrypted_sstable_store(object_store, Some(key_provider));
+        assert!(matches!(
+            sstable_store.sstable(1).await,
+            Err(Error::KeyUnavailable {
+                sst_id: 1,
+                key_id: id
+            }) if id == key_id
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_data_format_version() {
+        let object_store = Arc::new(CountedObjectStore::default());
+        let (meta, data) = build_sstable_for_test();
+        assert_eq!(meta.format_version, SSTABLE_FORMAT_VERSION);
+        assert_eq!(
+            decode_data_header(1, &data).unwrap(),
+            SSTABLE_FORMAT_VERSION
+        );
+        let sst = Sstable::new(1, Arc::new(meta));
+        build_encrypted_sstable_store(object_store.clone(), None)
+            .put(&sst, data.clone(), CachePolicy::Disable)
+            .await
+            .unwrap();
+
+        // Round trip with the current version, the data is not read to check the version.
+        let sstable_store = build_encrypted_sstable_store(object_store.clone(), None);
+        let sst = sstable_store.sstable(1).await.unwrap();
+        assert_eq!(object_store.get_range_count.load(AtomicOrdering::SeqCst), 0);
+        assert_eq!(
+            sstable_store
+                .get(&sst, b"k01", 1, CachePolicy::Disable)
+                .await
+                .unwrap(),
+            Some(Bytes::from("v01"))
+        );
+
+        // Reject unknown versions.
+        let (mut meta_v2, _) = build_sstable_for_test();
+        meta_v2.format_version = SSTABLE_FORMAT_VERSION + 1;
+        let mut data_v2 = data.clone();
+        data_v2[SSTABLE_DATA_HEADER_LEN - 1] = SSTABLE_FORMAT_VERSION + 1;
+        sstable_store
+            .put(
+                &Sstable::new(3, Arc::new(meta_v2)),
+                data_v2,
+                CachePolicy::Disable,
+            )
+            .await
+            .unwrap();
+        let sstable_store = build_encrypted_sstable_store(object_store.clone(), None);
+        assert!(matches!(
+            sstable_store.sstable(3).await,
+            Err(Error::UnsupportedSstableVersion {
+                sst_id: 3,
+                version: 2
+            })
+        ));
+
+        // Data without the header is regarded as version 0.
+        let (mut legacy_meta, _) = build_sstable_for_test();
+        for block_meta in &mut legacy_meta.block_metas {
+            block_meta.offset -= SSTABLE_DATA_HEADER_LEN;
+        }
+        legacy_meta.data_size -= SSTABLE_DATA_HEADER_LEN;
+        legacy_meta.format_version = 0;
+        let sst = Sstable::new(2, Arc::new(legacy_meta));
+        sstable_store
+            .put(
+                &sst,
+                data[SSTABLE_DATA_HEADER_LEN..].to_vec(),
+                CachePolicy::Disable,
+            )
+            .await
+            .unwrap();
+        let sstable_store = build_encrypted_sstable_store(object_store, None);
+        if cfg!(feature = "legacy-sstable-format") {
+            let sst = sstable_store.sstable(2).await.unwrap();
+            assert_eq!(
+                sstable_store
+                    .get(&sst, b"k05", 5, CachePolicy::Disable)
+                    .await
+                    .unwrap(),
+                Some(Bytes::from("v05"))
+            );
+        } else {
+            assert!(matches!(
+                sstable_store.sstable(2).await,
+                Err(Error::UnsupportedSstableVersion {
+                    sst_id: 2,
+                    version: 0
+                })
+            ));
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_path_builder() {
+        struct SplitPathBuilder;
+
+        impl PathBuilder for SplitPathBuilder {
+            fn meta_path(&self, sst_id: u64) -> String {
+                format!("sst/meta/{:020}", sst_id)
+            }
+
+            fn data_path(&self, sst_id: u64) -> String {
+                format!("sst/data/{:020}", sst_id)
+            }
+
+            fn prefix(&self) -> String {
+                "sst/".to_string()
+            }
+
+            fn sst_id(&self, path: &str) -> Option<u64> {
+                path.strip_prefix("sst/meta/")
+                    .or_else(|| path.strip_prefix("sst/data/"))?
+                    .parse()
+                    .ok()
+            }
+        }
+
+        let object_store: ObjectStoreRef = Arc::new(MemObjectStore::default());
+        let build_sstable_store = || {
+            SstableStore::new(SstableStoreOptions {
+                path_builder: Some(Arc::new(SplitPathBuilder)),
+                ..SstableStoreOptions::new(
+                    "test".to_string(),
+                    object_store.clone(),
+                    BlockCache::new(65536),
+                    1024,
+                )
+            })
+        };
+        let sstable_store = build_sstable_store();
+        let (meta, data) = build_sstable_for_test();
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+        let mut paths = object_store.list("").await.unwrap();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                "sst/data/00000000000000000001".to_string(),
+                "sst/meta/00000000000000000001".to_string(),
+            ]
+        );
+
+        let sstable_store = build_sstable_store();
+        let sst = sstable_store.sstable(1).await.unwrap();
+        assert_eq!(
+            sstable_store
+                .get(&sst, b"k04", 4, CachePolicy::Fill)
+                .await
+                .unwrap(),
+            Some(Bytes::from("v04"))
+        );
+        assert_eq!(sstable_store.list_ssts().await.unwrap(), vec![1]);
+
+        sstable_store.remove(1).await.unwrap();
+        assert!(object_store.list("").await.unwrap().is_empty());
+        assert!(sstable_store.list_ssts().await.unwrap().is_empty());
+    }
 }
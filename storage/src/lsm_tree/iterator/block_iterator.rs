@@ -4,7 +4,7 @@ use std::sync::Arc;
 
 use super::Seek;
 use crate::components::{Block, KeyPrefix};
-use crate::utils::compare_full_key;
+use crate::utils::{compare_full_key, full_key, user_key};
 use crate::Result;
 
 /// [`BlockIterator`] is used to read kv pairs in a block.
@@ -75,10 +75,24 @@ impl BlockIterator {
         }
     }
 
-    /// Move backward until reach the first key that equals or smaller than the given `key`.
-    fn prev_until_key(&mut self, key: &[u8]) {
-        while self.is_valid() && compare_full_key(&self.key[..], key) == Ordering::Greater {
-            self.prev_inner();
+    /// Move forward until the next key is larger than the given `key` or reaches the last entry.
+    ///
+    /// Note: Ensure that the current state is valid and the current key is not larger than `key`.
+    fn next_until_last_le_key(&mut self, key: &[u8]) {
+        let mut next_key = Vec::with_capacity(self.key.len());
+        loop {
+            let offset = self.offset + self.entry_len;
+            if offset >= self.block.len() {
+                return;
+            }
+            let prefix = self.decode_prefix_at(offset);
+            next_key.clear();
+            next_key.extend_from_slice(&self.key[..prefix.overlap_len()]);
+            next_key.extend_from_slice(self.block.slice(prefix.diff_key_range()));
+            if compare_full_key(&next_key[..], key) == Ordering::Greater {
+                return;
+            }
+            self.next_inner();
         }
     }
 
@@ -131,6 +145,19 @@ impl BlockIterator {
         self.seek_restart_point_by_index(index)
     }
 
+    /// Seek to the last key that equals or smaller than the given `key` within the block, or
+    /// invalidate the iterator if there is no such key.
+    fn seek_for_prev_inner(&mut self, key: &[u8]) {
+        let index = self.search_restart_point_index_by_key(key);
+        self.seek_restart_point_by_index(index);
+        // Only the first restart point can start with a key larger than the given `key`.
+        if compare_full_key(&self.key[..], key) == Ordering::Greater {
+            self.invalid();
+            return;
+        }
+        self.next_until_last_le_key(key);
+    }
+
     /// Seek to the restart point by given restart point index.
     fn seek_restart_point_by_index(&mut self, index: usize) {
         let offset = self.block.restart_point(index) as usize;
@@ -186,14 +213,15 @@ impl BlockIterator {
                 self.is_valid() && self.key() == key
             }
             Seek::RandomBackward(key) => {
-                self.seek_restart_point_by_key(key);
-                self.next_until_key(key);
-                if !self.is_valid() {
-                    self.seek(Seek::Last)?;
-                }
-                self.prev_until_key(key);
+                self.seek_for_prev_inner(key);
                 self.is_valid() && self.key() == key
             }
+            Seek::Prefix(prefix) => {
+                let key = full_key(prefix, u64::MAX);
+                self.seek_restart_point_by_key(&key);
+                self.next_until_key(&key);
+                self.is_valid() && user_key(self.key()).starts_with(prefix)
+            }
         };
         Ok(found)
     }
@@ -207,7 +235,7 @@ pub mod tests {
     use super::*;
     use crate::components::{BlockBuilder, BlockBuilderOptions};
     use crate::iterator::Iterator;
-    use crate::utils::full_key;
+    use crate::utils::{raw_value, value};
 
     pub struct AsyncBlockIterator(BlockIterator);
 
@@ -247,11 +275,11 @@ pub mod tests {
     fn build_iterator_for_test() -> BlockIterator {
         let options = BlockBuilderOptions::default();
         let mut builder = BlockBuilder::new(options);
-        builder.add(&full_key(b"k01", 1), b"v01");
-        builder.add(&full_key(b"k02", 2), b"v02");
-        builder.add(&full_key(b"k04", 4), b"v04");
-        builder.add(&full_key(b"k05", 5), b"v05");
-        let buf = builder.build();
+        builder.add(&full_key(b"k01", 1), b"v01").unwrap();
+        builder.add(&full_key(b"k02", 2), b"v02").unwrap();
+        builder.add(&full_key(b"k04", 4), b"v04").unwrap();
+        builder.add(&full_key(b"k05", 5), b"v05").unwrap();
+        let buf = builder.build().unwrap();
         BlockIterator::new(Arc::new(Block::decode(&buf).unwrap()))
     }
 
@@ -315,6 +343,132 @@ pub mod tests {
         assert_eq!(&full_key(format!("k{:02}", 2).as_bytes(), 2)[..], it.key());
     }
 
+    fn build_multi_restart_iterator_for_test() -> BlockIterator {
+        let options = BlockBuilderOptions {
+            restart_interval: 2,
+            ..Default::default()
+        };
+        let mut builder = BlockBuilder::new(options);
+        for i in [1, 2, 4, 5, 7, 8, 10] {
+            builder
+                .add(
+                    &full_key(format!("k{:02}", i).as_bytes(), i),
+                    format!("v{:02}", i).as_bytes(),
+                )
+                .unwrap();
+        }
+        let buf = builder.build().unwrap();
+        let block = Arc::new(Block::decode(&buf).unwrap());
+        assert_eq!(4, block.restart_point_len());
+        BlockIterator::new(block)
+    }
+
+    #[test]
+    fn test_restart_intervals() {
+        const ENTRIES: u64 = 20;
+
+        let key = |i: u64| full_key(format!("k{:02}", i).as_bytes(), i);
+        let value = |i: u64| format!("v{:02}", i).into_bytes();
+        for restart_interval in [1, 8] {
+            let mut builder = BlockBuilder::new(BlockBuilderOptions {
+                restart_interval,
+                ..Default::default()
+            });
+            for i in 0..ENTRIES {
+                builder.add(&key(i), &value(i)).unwrap();
+            }
+            let block = Arc::new(Block::decode(&builder.build().unwrap()).unwrap());
+            assert_eq!(
+                block.restart_point_len(),
+                (ENTRIES as usize + restart_interval - 1) / restart_interval
+            );
+            let mut it = BlockIterator::new(block);
+
+            it.seek(Seek::First).unwrap();
+            for i in 0..ENTRIES {
+                assert_eq!(&key(i)[..], it.key());
+                assert_eq!(&value(i)[..], it.value());
+                it.next().unwrap();
+            }
+            assert!(!it.is_valid());
+
+            it.seek(Seek::Last).unwrap();
+            for i in (0..ENTRIES).rev() {
+                assert_eq!(&key(i)[..], it.key());
+                it.prev().unwrap();
+            }
+            assert!(!it.is_valid());
+
+            for i in 0..ENTRIES {
+                assert!(it.seek(Seek::RandomForward(&key(i))).unwrap());
+                assert_eq!(&value(i)[..], it.value());
+                assert!(it.seek(Seek::RandomBackward(&key(i))).unwrap());
+                assert_eq!(&value(i)[..], it.value());
+            }
+        }
+    }
+
+    #[test]
+    fn test_seek_backward_at_key() {
+        let mut it = build_multi_restart_iterator_for_test();
+        for i in [1, 2, 4, 5, 7, 8, 10] {
+            let key = full_key(format!("k{:02}", i).as_bytes(), i);
+            assert!(it.seek(Seek::RandomBackward(&key[..])).unwrap());
+            assert!(it.is_valid());
+            assert_eq!(&key[..], it.key());
+            assert_eq!(format!("v{:02}", i).as_bytes(), it.value());
+        }
+    }
+
+    #[test]
+    fn test_seek_backward_between_keys() {
+        let mut it = build_multi_restart_iterator_for_test();
+        for (target, expected) in [(3, 2), (6, 5), (9, 8)] {
+            assert!(!it
+                .seek(Seek::RandomBackward(
+                    &full_key(format!("k{:02}", target).as_bytes(), target)[..]
+                ))
+                .unwrap());
+            assert!(it.is_valid());
+            assert_eq!(
+                &full_key(format!("k{:02}", expected).as_bytes(), expected)[..],
+                it.key()
+            );
+        }
+
+        // Older version of an existing user key is smaller in full key order.
+        assert!(!it
+            .seek(Seek::RandomBackward(&full_key(b"k04", 3)[..]))
+            .unwrap());
+        assert_eq!(&full_key(b"k04", 4)[..], it.key());
+        // Newer version of an existing user key is larger in full key order.
+        assert!(!it
+            .seek(Seek::RandomBackward(&full_key(b"k04", 5)[..]))
+            .unwrap());
+        assert_eq!(&full_key(b"k02", 2)[..], it.key());
+    }
+
+    #[test]
+    fn test_seek_backward_out_of_range() {
+        let mut it = build_multi_restart_iterator_for_test();
+        assert!(!it
+            .seek(Seek::RandomBackward(&full_key(b"k00", 0)[..]))
+            .unwrap());
+        assert!(!it.is_valid());
+
+        assert!(!it
+            .seek(Seek::RandomBackward(&full_key(b"k11", 11)[..]))
+            .unwrap());
+        assert!(it.is_valid());
+        assert_eq!(&full_key(b"k10", 10)[..], it.key());
+
+        // Iterate backward after seeking.
+        it.prev().unwrap();
+        assert_eq!(&full_key(b"k08", 8)[..], it.key());
+        it.prev().unwrap();
+        assert_eq!(&full_key(b"k07", 7)[..], it.key());
+    }
+
     #[test]
     fn test_forward_iterate() {
         let mut it = build_iterator_for_test();
@@ -385,4 +539,34 @@ pub mod tests {
         it.next().unwrap();
         assert_eq!(&full_key(format!("k{:02}", 4).as_bytes(), 4)[..], it.key());
     }
+
+    #[test]
+    fn test_tombstone() {
+        let options = BlockBuilderOptions::default();
+        let mut builder = BlockBuilder::new(options);
+        builder
+            .add(&full_key(b"k01", 2), &raw_value(Some(b"v01")))
+            .unwrap();
+        builder.add_tombstone(b"k01", 1).unwrap();
+        builder.add_tombstone(b"k02", 2).unwrap();
+        let buf = builder.build().unwrap();
+        let mut it = BlockIterator::new(Arc::new(Block::decode(&buf).unwrap()));
+
+        it.seek(Seek::First).unwrap();
+        assert_eq!(&full_key(b"k01", 2)[..], it.key());
+        assert_eq!(Some(&b"v01"[..]), value(it.value()));
+        it.next().unwrap();
+        assert_eq!(&full_key(b"k01", 1)[..], it.key());
+        assert_eq!(None, value(it.value()));
+        it.next().unwrap();
+        assert_eq!(&full_key(b"k02", 2)[..], it.key());
+        assert_eq!(None, value(it.value()));
+        it.next().unwrap();
+        assert!(!it.is_valid());
+
+        it.seek(Seek::RandomBackward(&full_key(b"k01", 1)[..]))
+            .unwrap();
+        assert_eq!(&full_key(b"k01", 1)[..], it.key());
+        assert_eq!(None, value(it.value()));
+    }
 }
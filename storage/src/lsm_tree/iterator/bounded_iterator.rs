@@ -0,0 +1,292 @@
+use std::ops::Bound;
+
+use async_trait::async_trait;
+
+use super::{BoxedIterator, Iterator, Seek};
+use crate::Result;
+
+/// [`BoundedIterator`] wraps an iterator and becomes invalid once its key crosses the `lower` bound
+/// backward or the `upper` bound forward.
+///
+/// Note: Bounds are compared with the keys of the inner iterator in bytewise order, so the inner
+/// iterator should iterate on user keys, e.g. [`super::UserKeyIterator`].
+pub struct BoundedIterator {
+    /// Inner iterator.
+    iter: BoxedIterator,
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
+}
+
+impl BoundedIterator {
+    pub fn new(iter: BoxedIterator, lower: Bound<Vec<u8>>, upper: Bound<Vec<u8>>) -> Self {
+        Self { iter, lower, upper }
+    }
+
+    fn is_above_lower(&self, key: &[u8]) -> bool {
+        match &self.lower {
+            Bound::Included(lower) => key >= &lower[..],
+            Bound::Excluded(lower) => key > &lower[..],
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn is_below_upper(&self, key: &[u8]) -> bool {
+        match &self.upper {
+            Bound::Included(upper) => key <= &upper[..],
+            Bound::Excluded(upper) => key < &upper[..],
+            Bound::Unbounded => true,
+        }
+    }
+
+    /// Seek to the first key above the lower bound.
+    async fn seek_lower(&mut self) -> Result<()> {
+        match &self.lower {
+            Bound::Included(lower) | Bound::Excluded(lower) => {
+                self.iter.seek(Seek::RandomForward(lower)).await?;
+                if matches!(self.lower, Bound::Excluded(_))
+                    && self.iter.is_valid()
+                    && self.iter.key() == &lower[..]
+                {
+                    self.iter.next().await?;
+                }
+            }
+            Bound::Unbounded => {
+                self.iter.seek(Seek::First).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Seek to the last key below the upper bound.
+    async fn seek_upper(&mut self) -> Result<()> {
+        match &self.upper {
+            Bound::Included(upper) | Bound::Excluded(upper) => {
+                self.iter.seek(Seek::RandomBackward(upper)).await?;
+                if matches!(self.upper, Bound::Excluded(_))
+                    && self.iter.is_valid()
+                    && self.iter.key() == &upper[..]
+                {
+                    self.iter.prev().await?;
+                }
+            }
+            Bound::Unbounded => {
+                self.iter.seek(Seek::Last).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Iterator for BoundedIterator {
+    async fn next(&mut self) -> Result<()> {
+        assert!(self.is_valid());
+        self.iter.next().await
+    }
+
+    async fn prev(&mut self) -> Result<()> {
+        assert!(self.is_valid());
+        self.iter.prev().await
+    }
+
+    async fn skip_to_user_key(&mut self) -> Result<()> {
+        assert!(self.is_valid());
+        self.iter.skip_to_user_key().await
+    }
+
+    fn key(&self) -> &[u8] {
+        assert!(self.is_valid());
+        self.iter.key()
+    }
+
+    fn value(&self) -> &[u8] {
+        assert!(self.is_valid());
+        self.iter.value()
+    }
+
+    fn skip_value(&mut self) {
+        self.iter.skip_value()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.iter.is_valid()
+            && self.is_above_lower(self.iter.key())
+            && self.is_below_upper(self.iter.key())
+    }
+
+    /// Note: [`Seek::First`] and [`Seek::Last`] start from the bounds. Seeks that start out of the
+    /// bounds are clamped to them, and are regarded as not found.
+    async fn seek<'s>(&mut self, seek: Seek<'s>) -> Result<bool> {
+        let found = match seek {
+            Seek::First => {
+                self.seek_lower().await?;
+                true
+            }
+            Seek::Last => {
+                self.seek_upper().await?;
+                true
+            }
+            Seek::RandomForward(key) if !self.is_above_lower(key) => {
+                self.seek_lower().await?;
+                false
+            }
+            Seek::RandomBackward(key) if !self.is_below_upper(key) => {
+                self.seek_upper().await?;
+                false
+            }
+            Seek::Prefix(prefix) if !self.is_above_lower(prefix) => {
+                // Keys with the prefix may still be above the lower bound.
+                self.seek_lower().await?;
+                self.iter.is_valid() && self.iter.key().starts_with(prefix)
+            }
+            Seek::RandomForward(_) | Seek::RandomBackward(_) | Seek::Prefix(_) => {
+                self.iter.seek(seek).await?
+            }
+        };
+        Ok(found && self.is_valid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use test_log::test;
+
+    use super::*;
+    use crate::components::Memtable;
+    use crate::iterator::MemtableIterator;
+    use crate::lsm_tree::DEFAULT_MEMTABLE_SIZE;
+
+    fn build_iterator_for_test(lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> BoundedIterator {
+        let memtable = Memtable::new(DEFAULT_MEMTABLE_SIZE);
+        for k in ["k01", "k03", "k05", "k07"] {
+            memtable.put(&Bytes::from(k), Some(&Bytes::from(k.replace('k', "v"))), 1);
+        }
+        BoundedIterator::new(
+            Box::new(MemtableIterator::new(&memtable, u64::MAX)),
+            to_owned_bound(lower),
+            to_owned_bound(upper),
+        )
+    }
+
+    fn to_owned_bound(bound: Bound<&[u8]>) -> Bound<Vec<u8>> {
+        match bound {
+            Bound::Included(key) => Bound::Included(key.to_vec()),
+            Bound::Excluded(key) => Bound::Excluded(key.to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
+
+    async fn forward(iter: &mut BoundedIterator) -> Vec<Vec<u8>> {
+        let mut keys = vec![];
+        iter.seek(Seek::First).await.unwrap();
+        while iter.is_valid() {
+            keys.push(iter.key().to_vec());
+            iter.next().await.unwrap();
+        }
+        keys
+    }
+
+    async fn backward(iter: &mut BoundedIterator) -> Vec<Vec<u8>> {
+        let mut keys = vec![];
+        iter.seek(Seek::Last).await.unwrap();
+        while iter.is_valid() {
+            keys.push(iter.key().to_vec());
+            iter.prev().await.unwrap();
+        }
+        keys
+    }
+
+    fn keys(keys: &[&str]) -> Vec<Vec<u8>> {
+        keys.iter().map(|key| key.as_bytes().to_vec()).collect()
+    }
+
+    #[test(tokio::test)]
+    async fn test_bounded_forward() {
+        let cases: [(Bound<&[u8]>, Vec<Vec<u8>>); 5] = [
+            (Bound::Unbounded, keys(&["k01", "k03", "k05", "k07"])),
+            // Bound equal to an existing key.
+            (Bound::Excluded(b"k05"), keys(&["k01", "k03"])),
+            (Bound::Included(b"k05"), keys(&["k01", "k03", "k05"])),
+            // Bound between keys.
+            (Bound::Excluded(b"k04"), keys(&["k01", "k03"])),
+            (Bound::Included(b"k04"), keys(&["k01", "k03"])),
+        ];
+        for (upper, expected) in cases {
+            let mut iter = build_iterator_for_test(Bound::Unbounded, upper);
+            assert_eq!(forward(&mut iter).await, expected);
+        }
+
+        let mut iter = build_iterator_for_test(Bound::Unbounded, Bound::Excluded(b"k00"));
+        assert!(forward(&mut iter).await.is_empty());
+        let mut iter = build_iterator_for_test(Bound::Unbounded, Bound::Excluded(b"k05"));
+        assert!(iter.seek(Seek::RandomForward(b"k03")).await.unwrap());
+        assert!(!iter.seek(Seek::RandomForward(b"k05")).await.unwrap());
+        assert!(!iter.is_valid());
+    }
+
+    #[test(tokio::test)]
+    async fn test_bounded_backward() {
+        let cases: [(Bound<&[u8]>, Vec<Vec<u8>>); 5] = [
+            (Bound::Unbounded, keys(&["k07", "k05", "k03", "k01"])),
+            // Bound equal to an existing key.
+            (Bound::Excluded(b"k03"), keys(&["k07", "k05"])),
+            (Bound::Included(b"k03"), keys(&["k07", "k05", "k03"])),
+            // Bound between keys.
+            (Bound::Excluded(b"k04"), keys(&["k07", "k05"])),
+            (Bound::Included(b"k04"), keys(&["k07", "k05"])),
+        ];
+        for (lower, expected) in cases {
+            let mut iter = build_iterator_for_test(lower, Bound::Unbounded);
+            assert_eq!(backward(&mut iter).await, expected);
+        }
+
+        let mut iter = build_iterator_for_test(Bound::Excluded(b"k07"), Bound::Unbounded);
+        assert!(backward(&mut iter).await.is_empty());
+        let mut iter = build_iterator_for_test(Bound::Excluded(b"k03"), Bound::Unbounded);
+        assert!(iter.seek(Seek::RandomBackward(b"k05")).await.unwrap());
+        assert!(!iter.seek(Seek::RandomBackward(b"k03")).await.unwrap());
+        assert!(!iter.is_valid());
+    }
+
+    #[test(tokio::test)]
+    async fn test_seek_out_of_bounds() {
+        // Forward seeks below the lower bound start from the lower bound.
+        let mut iter = build_iterator_for_test(Bound::Included(b"k03"), Bound::Unbounded);
+        assert!(!iter.seek(Seek::RandomForward(b"k01")).await.unwrap());
+        assert_eq!(iter.key(), b"k03");
+        iter.next().await.unwrap();
+        assert_eq!(iter.key(), b"k05");
+        let mut iter = build_iterator_for_test(Bound::Excluded(b"k03"), Bound::Unbounded);
+        assert!(!iter.seek(Seek::RandomForward(b"k03")).await.unwrap());
+        assert_eq!(iter.key(), b"k05");
+        assert!(iter.seek(Seek::Prefix(b"k0")).await.unwrap());
+        assert_eq!(iter.key(), b"k05");
+        assert!(!iter.seek(Seek::Prefix(b"k03")).await.unwrap());
+        assert!(iter.is_valid());
+
+        // Backward seeks above the upper bound start from the upper bound.
+        let mut iter = build_iterator_for_test(Bound::Unbounded, Bound::Excluded(b"k05"));
+        assert!(!iter.seek(Seek::RandomBackward(b"k09")).await.unwrap());
+        assert_eq!(iter.key(), b"k03");
+        iter.prev().await.unwrap();
+        assert_eq!(iter.key(), b"k01");
+        let mut iter = build_iterator_for_test(Bound::Unbounded, Bound::Included(b"k05"));
+        assert!(!iter.seek(Seek::RandomBackward(b"k06")).await.unwrap());
+        assert_eq!(iter.key(), b"k05");
+    }
+
+    #[test(tokio::test)]
+    async fn test_bounded_both() {
+        let mut iter = build_iterator_for_test(Bound::Excluded(b"k01"), Bound::Included(b"k05"));
+        assert_eq!(forward(&mut iter).await, keys(&["k03", "k05"]));
+        assert_eq!(backward(&mut iter).await, keys(&["k05", "k03"]));
+        assert_eq!(
+            iter.collect_all().await.unwrap(),
+            vec![
+                (b"k03".to_vec(), b"v03".to_vec()),
+                (b"k05".to_vec(), b"v05".to_vec())
+            ]
+        );
+    }
+}
@@ -3,7 +3,7 @@ use std::cmp::Ordering;
 use async_trait::async_trait;
 
 use super::{BoxedIterator, Iterator, Seek};
-use crate::utils::compare_full_key;
+use crate::utils::{compare_full_key, full_key, user_key};
 use crate::Result;
 
 pub struct ConcatIterator {
@@ -24,6 +24,40 @@ impl ConcatIterator {
         }
     }
 
+    /// Create an empty [`ConcatIterator`] with room for `capacity` iterators, which are added by
+    /// [`ConcatIterator::push`].
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::new(Vec::with_capacity(capacity))
+    }
+
+    /// Append `iter` after the inner iterators and clear the current state.
+    ///
+    /// Note: `iter` must be after the inner iterators in ASC order.
+    pub fn push(&mut self, iter: BoxedIterator) {
+        self.iters.push(iter);
+        self.offset = usize::MAX;
+    }
+
+    /// Reserve room for at least `additional` more iterators.
+    pub fn reserve(&mut self, additional: usize) {
+        self.iters.reserve(additional);
+    }
+
+    /// Consume the [`ConcatIterator`] and return the inner iterators.
+    pub fn drain_to_vec(self) -> Vec<BoxedIterator> {
+        self.iters
+    }
+
+    /// Replace the inner iterators with `iters` and clear the current state, reusing the
+    /// allocated capacity.
+    ///
+    /// Note: Input iterators must be in ASC order.
+    pub fn reset(&mut self, iters: Vec<BoxedIterator>) {
+        self.iters.clear();
+        self.iters.extend(iters);
+        self.offset = usize::MAX;
+    }
+
     /// Invalidate current state after reaching a invalid state.
     fn invalid(&mut self) {
         self.offset = self.iters.len()
@@ -146,11 +180,19 @@ impl Iterator for ConcatIterator {
         self.iters[self.offset].value()
     }
 
+    fn skip_value(&mut self) {
+        self.iters.iter_mut().for_each(|iter| iter.skip_value());
+    }
+
     fn is_valid(&self) -> bool {
         self.offset < self.iters.len()
     }
 
     async fn seek<'s>(&mut self, seek: Seek<'s>) -> Result<bool> {
+        if self.iters.is_empty() {
+            self.invalid();
+            return Ok(false);
+        }
         let found = match seek {
             Seek::First => {
                 self.offset = 0;
@@ -174,6 +216,10 @@ impl Iterator for ConcatIterator {
                 self.prev_until_key(key).await?;
                 self.is_valid() && self.key() == key
             }
+            Seek::Prefix(prefix) => {
+                self.binary_seek(&full_key(prefix, u64::MAX)).await?;
+                self.is_valid() && user_key(self.key()).starts_with(prefix)
+            }
         };
         Ok(found)
     }
@@ -204,12 +250,14 @@ mod tests {
         let options = BlockBuilderOptions::default();
         let mut builder = BlockBuilder::new(options);
         for i in range {
-            builder.add(
-                &full_key(format!("k{:02}", i).as_bytes(), i as u64),
-                &Bytes::from(format!("v{:02}", i)),
-            );
+            builder
+                .add(
+                    &full_key(format!("k{:02}", i).as_bytes(), i as u64),
+                    &Bytes::from(format!("v{:02}", i)),
+                )
+                .unwrap();
         }
-        let buf = builder.build();
+        let buf = builder.build().unwrap();
         Arc::new(Block::decode(&buf).unwrap())
     }
 
@@ -326,4 +374,84 @@ mod tests {
         it.next().await.unwrap();
         assert_eq!(&full_key(format!("k{:02}", 6).as_bytes(), 6)[..], it.key());
     }
+
+    #[test(tokio::test)]
+    async fn test_seek_empty() {
+        let mut it = ConcatIterator::new(vec![]);
+        assert!(!it.is_valid());
+        for seek in [
+            Seek::First,
+            Seek::Last,
+            Seek::RandomForward(&full_key(b"k01", 1)[..]),
+            Seek::RandomBackward(&full_key(b"k01", 1)[..]),
+        ] {
+            assert!(!it.seek(seek).await.unwrap());
+            assert!(!it.is_valid());
+        }
+
+        // Reset to empty after iterating.
+        let mut it = build_iterator_for_test();
+        it.seek(Seek::First).await.unwrap();
+        assert!(it.is_valid());
+        it.reset(vec![]);
+        assert!(!it.is_valid());
+        assert!(!it.seek(Seek::Last).await.unwrap());
+        assert!(!it.is_valid());
+    }
+
+    #[test(tokio::test)]
+    async fn test_push() {
+        let mut it = ConcatIterator::with_capacity(3);
+        let capacity = it.iters.capacity();
+        assert!(capacity >= 3);
+        assert!(!it.is_valid());
+        for range in [1..=3, 5..=7] {
+            it.push(Box::new(AsyncBlockIterator::new(build_block_for_test(
+                range,
+            ))));
+        }
+        it.seek(Seek::Last).await.unwrap();
+        assert_eq!(&full_key(b"k07", 7)[..], it.key());
+
+        // Pushing clears the current state.
+        it.push(Box::new(AsyncBlockIterator::new(build_block_for_test(
+            9..=11,
+        ))));
+        assert!(!it.is_valid());
+        assert_eq!(it.iters.capacity(), capacity);
+        it.seek(Seek::Last).await.unwrap();
+        assert_eq!(&full_key(b"k11", 11)[..], it.key());
+
+        it.reserve(10);
+        assert!(it.iters.capacity() >= 13);
+        let mut iters = it.drain_to_vec();
+        assert_eq!(iters.len(), 3);
+        iters[1].seek(Seek::First).await.unwrap();
+        assert_eq!(&full_key(b"k05", 5)[..], iters[1].key());
+    }
+
+    #[test(tokio::test)]
+    async fn test_reset() {
+        let mut it = build_iterator_for_test();
+        it.seek(Seek::Last).await.unwrap();
+        let capacity = it.iters.capacity();
+
+        it.reset(vec![
+            Box::new(AsyncBlockIterator::new(build_block_for_test(4..=4))),
+            Box::new(AsyncBlockIterator::new(build_block_for_test(8..=9))),
+        ]);
+        assert!(!it.is_valid());
+        assert_eq!(it.iters.capacity(), capacity);
+
+        it.seek(Seek::First).await.unwrap();
+        for i in [4, 8, 9] {
+            assert!(it.is_valid());
+            assert_eq!(
+                &full_key(format!("k{:02}", i).as_bytes(), i as u64)[..],
+                it.key()
+            );
+            it.next().await.unwrap();
+        }
+        assert!(!it.is_valid());
+    }
 }
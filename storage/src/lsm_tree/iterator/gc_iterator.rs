@@ -0,0 +1,259 @@
+use async_trait::async_trait;
+
+use super::{BoxedIterator, Iterator, Seek};
+use crate::utils::{timestamp, user_key};
+use crate::{Error, Result};
+
+/// [`GcIterator`] wraps a full key iterator and skips the versions that are invisible to all
+/// readers at or above the GC `watermark`.
+///
+/// For each user key, all versions above the watermark are kept, along with the newest version at
+/// or below the watermark, which is visible to reads at the watermark. Tombstones are kept the
+/// same way, so that they still shadow older versions in other sstables.
+///
+/// Only forward iteration is supported, backward iteration returns an error.
+pub struct GcIterator {
+    /// Inner full key iterator.
+    ///
+    /// Note: `iter` is always valid when [`GcIterator`] is valid.
+    iter: BoxedIterator,
+    watermark: u64,
+    /// Current user key.
+    key: Vec<u8>,
+    /// Whether a version of the current user key at or below the watermark has been yielded.
+    visible_yielded: bool,
+}
+
+impl GcIterator {
+    pub fn new(iter: BoxedIterator, watermark: u64) -> Self {
+        Self {
+            iter,
+            watermark,
+            key: Vec::default(),
+            visible_yielded: false,
+        }
+    }
+
+    /// Move forward until reach a version needed above the watermark.
+    async fn skip_garbage(&mut self) -> Result<()> {
+        while self.iter.is_valid() {
+            let uk = user_key(self.iter.key());
+            if uk != self.key {
+                self.key = uk.to_vec();
+                self.visible_yielded = false;
+            }
+            if timestamp(self.iter.key()) > self.watermark {
+                return Ok(());
+            }
+            if !self.visible_yielded {
+                self.visible_yielded = true;
+                return Ok(());
+            }
+            self.iter.next().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Iterator for GcIterator {
+    async fn next(&mut self) -> Result<()> {
+        assert!(self.is_valid());
+        self.iter.next().await?;
+        self.skip_garbage().await
+    }
+
+    async fn prev(&mut self) -> Result<()> {
+        Err(backward_unsupported())
+    }
+
+    fn key(&self) -> &[u8] {
+        assert!(self.is_valid());
+        self.iter.key()
+    }
+
+    fn value(&self) -> &[u8] {
+        assert!(self.is_valid());
+        self.iter.value()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.iter.is_valid()
+    }
+
+    /// Note: With [`Seek::RandomForward`], the sought position is regarded as the newest version
+    /// of its user key.
+    async fn seek<'s>(&mut self, seek: Seek<'s>) -> Result<bool> {
+        match seek {
+            Seek::First | Seek::RandomForward(_) => {
+                self.key.clear();
+                self.visible_yielded = false;
+                self.iter.seek(seek).await?;
+                self.skip_garbage().await?;
+                Ok(self.is_valid())
+            }
+            Seek::Prefix(prefix) => {
+                self.key.clear();
+                self.visible_yielded = false;
+                self.iter.seek(Seek::Prefix(prefix)).await?;
+                self.skip_garbage().await?;
+                Ok(self.is_valid() && user_key(self.iter.key()).starts_with(prefix))
+            }
+            Seek::Last | Seek::RandomBackward(_) => Err(backward_unsupported()),
+        }
+    }
+}
+
+fn backward_unsupported() -> Error {
+    Error::Other("backward iteration is not supported by GcIterator".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use test_log::test;
+
+    use super::*;
+    use crate::components::{Block, BlockBuilder, BlockBuilderOptions};
+    use crate::iterator::tests::AsyncBlockIterator;
+    use crate::utils::{full_key, raw_value, value};
+
+    /// `(user key, timestamp, value)` in full key order, `None` value for tombstone.
+    #[allow(clippy::type_complexity)]
+    const DATASET: &[(&[u8], u64, Option<&[u8]>)] = &[
+        // Multiple versions.
+        (b"k1", 30, Some(b"v1-30")),
+        (b"k1", 20, Some(b"v1-20")),
+        (b"k1", 10, Some(b"v1-10")),
+        (b"k1", 5, Some(b"v1-5")),
+        // Tombstone above the watermark.
+        (b"k2", 25, None),
+        (b"k2", 15, Some(b"v2-15")),
+        (b"k2", 5, Some(b"v2-5")),
+        // Tombstone below the watermark.
+        (b"k3", 12, None),
+        (b"k3", 8, Some(b"v3-8")),
+        // Single version above the watermark.
+        (b"k4", 40, Some(b"v4-40")),
+        // Single version below the watermark.
+        (b"k5", 1, Some(b"v5-1")),
+        // All versions below the watermark.
+        (b"k6", 9, Some(b"v6-9")),
+        (b"k6", 3, None),
+        (b"k6", 2, Some(b"v6-2")),
+    ];
+
+    fn build_iterator_for_test(watermark: u64) -> GcIterator {
+        let mut builder = BlockBuilder::new(BlockBuilderOptions::default());
+        for (key, timestamp, value) in DATASET {
+            builder
+                .add(&full_key(key, *timestamp), &raw_value(*value))
+                .unwrap();
+        }
+        let block = Arc::new(Block::decode(&builder.build().unwrap()).unwrap());
+        GcIterator::new(Box::new(AsyncBlockIterator::new(block)), watermark)
+    }
+
+    async fn collect(iter: &mut GcIterator) -> Vec<(Vec<u8>, u64, Option<Vec<u8>>)> {
+        let mut versions = vec![];
+        while iter.is_valid() {
+            versions.push((
+                user_key(iter.key()).to_vec(),
+                timestamp(iter.key()),
+                value(iter.value()).map(|v| v.to_vec()),
+            ));
+            iter.next().await.unwrap();
+        }
+        versions
+    }
+
+    fn expected(versions: &[(&[u8], u64)]) -> Vec<(Vec<u8>, u64, Option<Vec<u8>>)> {
+        versions
+            .iter()
+            .map(|(key, ts)| {
+                let (_, _, value) = DATASET
+                    .iter()
+                    .find(|(k, t, _)| k == key && t == ts)
+                    .unwrap();
+                (key.to_vec(), *ts, value.map(|v| v.to_vec()))
+            })
+            .collect()
+    }
+
+    #[test(tokio::test)]
+    async fn test_gc() {
+        let mut iter = build_iterator_for_test(12);
+        iter.seek(Seek::First).await.unwrap();
+        assert_eq!(
+            collect(&mut iter).await,
+            expected(&[
+                (b"k1", 30),
+                (b"k1", 20),
+                (b"k1", 10),
+                (b"k2", 25),
+                (b"k2", 15),
+                (b"k2", 5),
+                (b"k3", 12),
+                (b"k4", 40),
+                (b"k5", 1),
+                (b"k6", 9),
+            ])
+        );
+
+        // Nothing is dropped with the zero watermark.
+        let mut iter = build_iterator_for_test(0);
+        iter.seek(Seek::First).await.unwrap();
+        assert_eq!(collect(&mut iter).await.len(), DATASET.len());
+
+        // Only the newest version of each user key is kept with the max watermark.
+        let mut iter = build_iterator_for_test(u64::MAX);
+        iter.seek(Seek::First).await.unwrap();
+        assert_eq!(
+            collect(&mut iter).await,
+            expected(&[
+                (b"k1", 30),
+                (b"k2", 25),
+                (b"k3", 12),
+                (b"k4", 40),
+                (b"k5", 1),
+                (b"k6", 9),
+            ])
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_seek_forward() {
+        let mut iter = build_iterator_for_test(12);
+        assert!(iter
+            .seek(Seek::RandomForward(&full_key(b"k2", u64::MAX)))
+            .await
+            .unwrap());
+        assert_eq!(&full_key(b"k2", 25)[..], iter.key());
+        assert!(iter
+            .seek(Seek::RandomForward(&full_key(b"k6", 9)))
+            .await
+            .unwrap());
+        assert_eq!(&full_key(b"k6", 9)[..], iter.key());
+        iter.next().await.unwrap();
+        assert!(!iter.is_valid());
+        assert!(!iter
+            .seek(Seek::RandomForward(&full_key(b"k7", u64::MAX)))
+            .await
+            .unwrap());
+    }
+
+    #[test(tokio::test)]
+    async fn test_backward_unsupported() {
+        let mut iter = build_iterator_for_test(12);
+        assert!(matches!(iter.seek(Seek::Last).await, Err(Error::Other(_))));
+        assert!(matches!(
+            iter.seek(Seek::RandomBackward(&full_key(b"k2", 0))).await,
+            Err(Error::Other(_))
+        ));
+        iter.seek(Seek::First).await.unwrap();
+        assert!(matches!(iter.prev().await, Err(Error::Other(_))));
+        // The iterator is still usable forward.
+        assert_eq!(&full_key(b"k1", 30)[..], iter.key());
+    }
+}
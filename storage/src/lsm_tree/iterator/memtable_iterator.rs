@@ -16,6 +16,14 @@ pub struct MemtableIterator {
     timestamp: u64,
     /// Current user key.
     key: Bytes,
+    /// Inclusive lower bound of user keys.
+    lower: Option<Bytes>,
+    /// Exclusive upper bound of user keys.
+    upper: Option<Bytes>,
+    /// Whether the iteration has moved out of `[lower, upper)`.
+    out_of_range: bool,
+    /// User key prefix of the last [`Seek::Prefix`], empty for other seeks.
+    prefix: Vec<u8>,
 }
 
 impl MemtableIterator {
@@ -24,6 +32,57 @@ impl MemtableIterator {
             iter: memtable.iter(),
             timestamp,
             key: Bytes::default(),
+            lower: None,
+            upper: None,
+            out_of_range: false,
+            prefix: Vec::default(),
+        }
+    }
+
+    /// Create an iterator on user keys in `[lower, upper)`. `None` means unbounded.
+    ///
+    /// The iterator becomes invalid once it moves out of the range, and seeks out of the range are
+    /// clamped to it.
+    pub fn new_range(
+        memtable: &Memtable,
+        timestamp: u64,
+        lower: Option<Bytes>,
+        upper: Option<Bytes>,
+    ) -> Self {
+        Self {
+            lower,
+            upper,
+            ..Self::new(memtable, timestamp)
+        }
+    }
+
+    /// Timestamp of the current entry, which is the version of the current user key visible at the
+    /// snapshot timestamp.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the iterator is invalid.
+    pub fn current_timestamp(&self) -> u64 {
+        assert!(self.is_valid());
+        timestamp(self.iter.key())
+    }
+
+    /// Whether the iterator is valid and the current user key starts with the prefix of the last
+    /// [`Seek::Prefix`]. Equals [`Iterator::is_valid`] after other seeks.
+    pub fn prefix_valid(&self) -> bool {
+        self.is_valid() && self.key().starts_with(&self.prefix)
+    }
+
+    /// Invalidate the iterator if the current user key is out of `[lower, upper)`.
+    fn check_bounds(&mut self) {
+        if !self.iter.valid() {
+            return;
+        }
+        let uk = user_key(self.iter.key());
+        if self.upper.as_ref().map_or(false, |upper| uk >= &upper[..])
+            || self.lower.as_ref().map_or(false, |lower| uk < &lower[..])
+        {
+            self.out_of_range = true;
         }
     }
 
@@ -103,23 +162,52 @@ impl MemtableIterator {
     }
 }
 
+/// Count distinct user keys whose latest version visible at `timestamp` is not a tombstone, with a
+/// single forward pass over the given `memtable`.
+pub fn count_visible_keys(memtable: &Memtable, timestamp: u64) -> usize {
+    let mut iter = memtable.iter();
+    iter.seek_to_first();
+    let mut count = 0;
+    // Last user key whose latest visible version has been checked.
+    let mut last_key: Option<Vec<u8>> = None;
+    while iter.valid() {
+        let uk = user_key(iter.key());
+        let ts = crate::utils::timestamp(iter.key());
+        if timestamp >= ts && last_key.as_deref() != Some(uk) {
+            if value(iter.value()).is_some() {
+                count += 1;
+            }
+            last_key = Some(uk.to_vec());
+        }
+        iter.next();
+    }
+    count
+}
+
 #[async_trait]
 impl Iterator for MemtableIterator {
     async fn next(&mut self) -> Result<()> {
         assert!(self.is_valid());
         self.next_inner(&[]);
+        self.check_bounds();
         Ok(())
     }
 
+    async fn skip_to_user_key(&mut self) -> Result<()> {
+        // Only the visible version of each user key is iterated.
+        self.next().await
+    }
+
     async fn prev(&mut self) -> Result<()> {
         assert!(self.is_valid());
         self.prev_inner(&[]);
+        self.check_bounds();
         Ok(())
     }
 
     fn key(&self) -> &[u8] {
         assert!(self.is_valid());
-        &self.iter.key()[..self.iter.key().len() - 8]
+        user_key(self.iter.key())
     }
 
     fn value(&self) -> &[u8] {
@@ -128,35 +216,59 @@ impl Iterator for MemtableIterator {
     }
 
     fn is_valid(&self) -> bool {
-        self.iter.valid()
+        !self.out_of_range && self.iter.valid()
     }
 
     async fn seek<'s>(&mut self, seek: Seek<'s>) -> Result<bool> {
+        self.key.clear();
+        self.out_of_range = false;
+        self.prefix.clear();
         let found = match seek {
             Seek::First => {
-                self.key.clear();
-                self.iter.seek_to_first();
+                match &self.lower {
+                    Some(lower) => self.iter.seek(&full_key(lower, u64::MAX)),
+                    None => self.iter.seek_to_first(),
+                }
                 self.next_inner(&[]);
-                self.is_valid()
+                true
             }
             Seek::Last => {
-                self.key.clear();
-                self.iter.seek_to_last();
+                match &self.upper {
+                    Some(upper) => self.iter.seek_for_prev(&full_key(upper, u64::MAX)),
+                    None => self.iter.seek_to_last(),
+                }
                 self.prev_inner(&[]);
-                self.is_valid()
+                true
             }
             Seek::RandomForward(key) => {
-                self.key.clear();
-                self.iter.seek(&full_key(key, u64::MAX));
+                let target = match &self.lower {
+                    Some(lower) if key < &lower[..] => full_key(lower, u64::MAX),
+                    _ => full_key(key, u64::MAX),
+                };
+                self.iter.seek(&target);
                 self.next_inner(key)
             }
             Seek::RandomBackward(key) => {
-                self.key.clear();
-                self.iter.seek_for_prev(&full_key(key, 0));
+                let target = match &self.upper {
+                    Some(upper) if key >= &upper[..] => full_key(upper, u64::MAX),
+                    _ => full_key(key, 0),
+                };
+                self.iter.seek_for_prev(&target);
                 self.prev_inner(key)
             }
+            Seek::Prefix(prefix) => {
+                self.prefix = prefix.to_vec();
+                let target = match &self.lower {
+                    Some(lower) if prefix < &lower[..] => full_key(lower, u64::MAX),
+                    _ => full_key(prefix, u64::MAX),
+                };
+                self.iter.seek(&target);
+                self.next_inner(&[]);
+                true
+            }
         };
-        Ok(found)
+        self.check_bounds();
+        Ok(found && self.prefix_valid())
     }
 }
 
@@ -205,6 +317,142 @@ mod tests {
         MemtableIterator::new(&memtable, timestamp)
     }
 
+    #[test(tokio::test)]
+    async fn test_collect() {
+        let mut iter = build_iterator_for_test(u64::MAX);
+        let mut expected = vec![];
+        iter.seek(Seek::First).await.unwrap();
+        while iter.is_valid() {
+            expected.push((iter.key().to_vec(), iter.value().to_vec()));
+            iter.next().await.unwrap();
+        }
+        assert_eq!(expected.len(), 5);
+
+        // The position is reset before collecting.
+        iter.seek(Seek::Last).await.unwrap();
+        assert_eq!(iter.collect_all().await.unwrap(), expected);
+        assert!(!iter.is_valid());
+        assert_eq!(iter.collect_n(3).await.unwrap(), &expected[..3]);
+        assert!(iter.is_valid());
+        assert_eq!(iter.collect_n(100).await.unwrap(), expected);
+        assert!(build_iterator_for_test(0)
+            .collect_all()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_seek_prefix() {
+        // Visible user keys: k03, k05, k07, k09, k11.
+        let mut iter = build_iterator_for_test(u64::MAX);
+
+        // Present prefix.
+        assert!(iter.seek(Seek::Prefix(b"k0")).await.unwrap());
+        let mut keys = vec![];
+        while iter.prefix_valid() {
+            keys.push(iter.key().to_vec());
+            iter.next().await.unwrap();
+        }
+        assert_eq!(keys, vec![b"k03", b"k05", b"k07", b"k09"]);
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), b"k11");
+
+        // Absent prefix, deleted or beyond the last key.
+        assert!(!iter.seek(Seek::Prefix(b"k04")).await.unwrap());
+        assert!(iter.is_valid());
+        assert!(!iter.prefix_valid());
+        assert_eq!(iter.key(), b"k05");
+        assert!(!iter.seek(Seek::Prefix(b"k2")).await.unwrap());
+        assert!(!iter.is_valid());
+        assert!(!iter.prefix_valid());
+
+        // Prefix that is a full user key.
+        assert!(iter.seek(Seek::Prefix(b"k05")).await.unwrap());
+        assert!(iter.prefix_valid());
+        assert_eq!(iter.key(), b"k05");
+        iter.next().await.unwrap();
+        assert!(iter.is_valid());
+        assert!(!iter.prefix_valid());
+
+        // Other seeks clear the prefix.
+        iter.seek(Seek::First).await.unwrap();
+        assert!(iter.prefix_valid());
+    }
+
+    #[test(tokio::test)]
+    async fn test_range() {
+        let memtable = build_memtable_for_test();
+        let range = |lower: Option<&'static str>, upper: Option<&'static str>| {
+            MemtableIterator::new_range(
+                &memtable,
+                u64::MAX,
+                lower.map(Bytes::from),
+                upper.map(Bytes::from),
+            )
+        };
+        let keys = |kvs: Vec<(Vec<u8>, Vec<u8>)>| {
+            kvs.into_iter()
+                .map(|(key, _)| String::from_utf8(key).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // Visible keys: k03, k05, k07, k09, k11.
+        assert_eq!(
+            keys(range(Some("k04"), Some("k09")).collect_all().await.unwrap()),
+            vec!["k05", "k07"]
+        );
+        assert_eq!(
+            keys(range(Some("k05"), None).collect_all().await.unwrap()),
+            vec!["k05", "k07", "k09", "k11"]
+        );
+        assert_eq!(
+            keys(range(None, Some("k05")).collect_all().await.unwrap()),
+            vec!["k03"]
+        );
+        assert!(range(Some("k06"), Some("k07"))
+            .collect_all()
+            .await
+            .unwrap()
+            .is_empty());
+
+        let mut it = range(Some("k05"), Some("k11"));
+        assert!(it.seek(Seek::Last).await.unwrap());
+        assert_eq!(b"k09", it.key());
+        it.prev().await.unwrap();
+        it.prev().await.unwrap();
+        assert_eq!(b"k05", it.key());
+        it.prev().await.unwrap();
+        assert!(!it.is_valid());
+
+        // Seeks out of the range are clamped.
+        assert!(!it.seek(Seek::RandomForward(b"k01")).await.unwrap());
+        assert_eq!(b"k05", it.key());
+        assert!(!it.seek(Seek::RandomForward(b"k10")).await.unwrap());
+        assert!(!it.is_valid());
+        assert!(!it.seek(Seek::RandomForward(b"k11")).await.unwrap());
+        assert!(!it.seek(Seek::RandomBackward(b"k12")).await.unwrap());
+        assert_eq!(b"k09", it.key());
+        assert!(!it.seek(Seek::RandomBackward(b"k04")).await.unwrap());
+        assert!(!it.is_valid());
+    }
+
+    #[test]
+    fn test_count_visible_keys() {
+        let memtable = build_memtable_for_test();
+        assert_eq!(5, count_visible_keys(&memtable, u64::MAX));
+        assert_eq!(5, count_visible_keys(&memtable, 4));
+        assert_eq!(5, count_visible_keys(&memtable, 3));
+        assert_eq!(11, count_visible_keys(&memtable, 2));
+        assert_eq!(2, count_visible_keys(&memtable, 1));
+        assert_eq!(0, count_visible_keys(&memtable, 0));
+
+        assert_eq!(
+            0,
+            count_visible_keys(&Memtable::new(DEFAULT_MEMTABLE_SIZE), u64::MAX)
+        );
+    }
+
     #[test(tokio::test)]
     async fn test_seek_first() {
         let mut it = build_iterator_for_test(u64::MAX);
@@ -499,6 +747,50 @@ mod tests {
         assert!(!it.is_valid());
     }
 
+    #[test(tokio::test)]
+    async fn test_current_timestamp() {
+        let timestamps = |ts| async move {
+            let mut it = build_iterator_for_test(ts);
+            it.seek(Seek::First).await.unwrap();
+            let mut timestamps = vec![];
+            while it.is_valid() {
+                timestamps.push((it.key().to_vec(), it.current_timestamp()));
+                it.next().await.unwrap();
+            }
+            timestamps
+        };
+        let expected = |kts: &[(usize, u64)]| {
+            kts.iter()
+                .map(|(k, ts)| (format!("k{:02}", k).into_bytes(), *ts))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            timestamps(u64::MAX).await,
+            expected(&[(3, 4), (5, 5), (7, 4), (9, 5), (11, 4)])
+        );
+        assert_eq!(
+            timestamps(4).await,
+            expected(&[(3, 4), (5, 4), (7, 4), (9, 4), (11, 4)])
+        );
+        assert_eq!(
+            timestamps(2).await,
+            (2..=12)
+                .map(|k| (format!("k{:02}", k).into_bytes(), 2))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(timestamps(1).await, expected(&[(5, 1), (9, 1)]));
+
+        // Backward.
+        let mut it = build_iterator_for_test(3);
+        assert!(it.seek(Seek::RandomBackward(b"k08")).await.unwrap());
+        assert_eq!(b"k07", it.key());
+        assert_eq!(3, it.current_timestamp());
+        it.prev().await.unwrap();
+        assert_eq!(b"k05", it.key());
+        assert_eq!(3, it.current_timestamp());
+    }
+
     #[test(tokio::test)]
     async fn test_seek_forward_backward_iterate() {
         let mut it = build_iterator_for_test(3);
@@ -39,6 +39,18 @@ impl MergeIterator {
         }
     }
 
+    /// Replace the inner iterators with `iters` and clear the current state, reusing the
+    /// allocated heap capacity.
+    pub fn reset(&mut self, iters: Vec<BoxedIterator>) {
+        self.direction = Direction::Forward;
+        self.iters.clear();
+        self.min_heap.clear();
+        self.max_heap.clear();
+        self.min_heap.reserve(iters.len());
+        self.max_heap.reserve(iters.len());
+        self.iters.extend(iters);
+    }
+
     async fn may_rebuild_heap(&mut self, direction: Direction) -> Result<()> {
         if self.direction == direction {
             return Ok(());
@@ -120,6 +132,17 @@ impl Iterator for MergeIterator {
         }
     }
 
+    fn skip_value(&mut self) {
+        self.iters.iter_mut().for_each(|iter| iter.skip_value());
+        // Skipping values never changes the order of the heaps.
+        let mut min_heap = std::mem::take(&mut self.min_heap).into_vec();
+        min_heap.iter_mut().for_each(|iter| iter.0.skip_value());
+        self.min_heap = min_heap.into();
+        let mut max_heap = std::mem::take(&mut self.max_heap).into_vec();
+        max_heap.iter_mut().for_each(|iter| iter.skip_value());
+        self.max_heap = max_heap.into();
+    }
+
     fn is_valid(&self) -> bool {
         match self.direction {
             Direction::Forward => !self.min_heap.is_empty(),
@@ -162,7 +185,23 @@ impl Iterator for MergeIterator {
                         self.min_heap.push(Reverse(iter));
                     }
                 }
-                self.is_valid() && self.key() == key
+                self.peek_key() == Some(key)
+            }
+            Seek::Prefix(prefix) => {
+                self.direction = Direction::Forward;
+                self.iters.extend(self.min_heap.drain().map(|r| r.0));
+                self.iters.extend(self.max_heap.drain());
+                // The smallest key is between the prefix and any key starting with it, so it starts
+                // with the prefix too if any inner iterator finds one.
+                let mut found = false;
+                while !self.iters.is_empty() {
+                    let mut iter = self.iters.pop_back().unwrap();
+                    found |= iter.seek(Seek::Prefix(prefix)).await?;
+                    if iter.is_valid() {
+                        self.min_heap.push(Reverse(iter));
+                    }
+                }
+                found
             }
             Seek::RandomBackward(key) => {
                 self.direction = Direction::Backward;
@@ -175,7 +214,7 @@ impl Iterator for MergeIterator {
                         self.max_heap.push(iter);
                     }
                 }
-                self.is_valid() && self.key() == key
+                self.peek_key() == Some(key)
             }
         };
         Ok(found)
@@ -206,12 +245,14 @@ mod tests {
         let options = BlockBuilderOptions::default();
         let mut builder = BlockBuilder::new(options);
         for i in range {
-            builder.add(
-                &full_key(format!("k{:02}", i).as_bytes(), *i as u64),
-                &Bytes::from(format!("v{:02}", i)),
-            );
+            builder
+                .add(
+                    &full_key(format!("k{:02}", i).as_bytes(), *i as u64),
+                    &Bytes::from(format!("v{:02}", i)),
+                )
+                .unwrap();
         }
-        let buf = builder.build();
+        let buf = builder.build().unwrap();
         Arc::new(Block::decode(&buf).unwrap())
     }
 
@@ -328,4 +369,35 @@ mod tests {
         it.next().await.unwrap();
         assert_eq!(&full_key(format!("k{:02}", 6).as_bytes(), 6)[..], it.key());
     }
+
+    #[test(tokio::test)]
+    async fn test_reset() {
+        let mut it = build_iterator_for_test();
+        it.seek(Seek::First).await.unwrap();
+        it.seek(Seek::Last).await.unwrap();
+        let min_heap_capacity = it.min_heap.capacity();
+        let max_heap_capacity = it.max_heap.capacity();
+
+        it.reset(vec![
+            Box::new(AsyncBlockIterator::new(build_block_for_test(&[4, 12]))),
+            Box::new(AsyncBlockIterator::new(build_block_for_test(&[8]))),
+        ]);
+        assert!(!it.is_valid());
+        assert_eq!(it.min_heap.capacity(), min_heap_capacity);
+        assert_eq!(it.max_heap.capacity(), max_heap_capacity);
+
+        it.seek(Seek::First).await.unwrap();
+        for i in [4, 8, 12] {
+            assert!(it.is_valid());
+            assert_eq!(
+                &full_key(format!("k{:02}", i).as_bytes(), i as u64)[..],
+                it.key()
+            );
+            it.next().await.unwrap();
+        }
+        assert!(!it.is_valid());
+
+        it.seek(Seek::Last).await.unwrap();
+        assert_eq!(&full_key(b"k12", 12)[..], it.key());
+    }
 }
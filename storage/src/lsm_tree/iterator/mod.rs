@@ -1,5 +1,7 @@
 mod block_iterator;
+mod bounded_iterator;
 mod concat_iterator;
+mod gc_iterator;
 mod memtable_iterator;
 mod merge_iterator;
 mod sstable_iterator;
@@ -7,13 +9,15 @@ mod user_key_iterator;
 
 use async_trait::async_trait;
 pub use block_iterator::*;
+pub use bounded_iterator::*;
 pub use concat_iterator::*;
+pub use gc_iterator::*;
 pub use memtable_iterator::*;
 pub use merge_iterator::*;
 pub use sstable_iterator::*;
 pub use user_key_iterator::*;
 
-use crate::utils::compare_full_key;
+use crate::utils::{compare_full_key, user_key};
 use crate::Result;
 
 pub enum Seek<'s> {
@@ -25,6 +29,12 @@ pub enum Seek<'s> {
     RandomForward(&'s [u8]),
     /// Seek backward for the first key equals the given key or the first key smaller than it.
     RandomBackward(&'s [u8]),
+    /// Seek forward like [`Seek::RandomForward`] for the first key starting with the given prefix.
+    /// Returns whether such a key exists.
+    ///
+    /// Note: The prefix is always a user key prefix, iterators on full keys match it against the
+    /// user keys.
+    Prefix(&'s [u8]),
 }
 
 /// [`Iterator`] defines shared behaviours for all iterators.
@@ -64,6 +74,27 @@ pub trait Iterator: Send + Sync {
     /// This function will panic if the iterator is invalid.
     async fn prev(&mut self) -> Result<()>;
 
+    /// Move a valid iterator to the first key of the next user key, skipping all the remaining
+    /// versions of the current user key.
+    ///
+    /// Note:
+    ///
+    /// - Before calling this function, make sure the iterator `is_valid`.
+    /// - Iterators that already iterate on user keys should override it with `next`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the iterator is invalid.
+    async fn skip_to_user_key(&mut self) -> Result<()> {
+        assert!(self.is_valid());
+        let current = user_key(self.key()).to_vec();
+        self.next().await?;
+        while self.is_valid() && user_key(self.key()) == current {
+            self.next().await?;
+        }
+        Ok(())
+    }
+
     /// Retrieve the current key.
     ///
     /// Note:
@@ -88,6 +119,15 @@ pub trait Iterator: Send + Sync {
     /// This function will panic if the iterator is invalid.
     fn value(&self) -> &[u8];
 
+    /// Hint the iterator that only keys will be read, so that it may skip reading values and
+    /// return an empty slice from `value`. `key` is not affected.
+    ///
+    /// Note:
+    ///
+    /// - Iterators that ignore the hint still return the values.
+    /// - An iterator hinted MUST NOT be used for value reads, including tombstone checks.
+    fn skip_value(&mut self) {}
+
     /// Indicate whether the iterator can be used.
     ///
     /// Note:
@@ -96,6 +136,24 @@ pub trait Iterator: Send + Sync {
     /// - This function should be straightforward and return immediately.
     fn is_valid(&self) -> bool;
 
+    /// Retrieve the current key if the iterator `is_valid`, otherwise `None`.
+    fn peek_key(&self) -> Option<&[u8]> {
+        if self.is_valid() {
+            Some(self.key())
+        } else {
+            None
+        }
+    }
+
+    /// Retrieve the current value if the iterator `is_valid`, otherwise `None`.
+    fn peek_value(&self) -> Option<&[u8]> {
+        if self.is_valid() {
+            Some(self.value())
+        } else {
+            None
+        }
+    }
+
     /// Initialize or reset iterator with the given seek mode. For more details, refer to [`Seek`].
     ///
     /// `seek` returns a bool which means a visible version of the given seek condition is found in
@@ -107,6 +165,27 @@ pub trait Iterator: Send + Sync {
     ///   function. This function WON'T return an `Err` if invalid. You should check `is_valid`
     ///   before starting iteration.
     async fn seek<'s>(&mut self, seek: Seek<'s>) -> Result<bool>;
+
+    /// Collect all `(key, value)` pairs in order.
+    ///
+    /// NOTE: The iterator is reset with `seek(Seek::First)` first, and is left invalid after.
+    async fn collect_all(&mut self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.collect_n(usize::MAX).await
+    }
+
+    /// Collect at most `limit` `(key, value)` pairs in order.
+    ///
+    /// NOTE: The iterator is reset with `seek(Seek::First)` first, and is left at the position
+    /// after the last collected pair.
+    async fn collect_n(&mut self, limit: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.seek(Seek::First).await?;
+        let mut kvs = vec![];
+        while self.is_valid() && kvs.len() < limit {
+            kvs.push((self.key().to_vec(), self.value().to_vec()));
+            self.next().await?;
+        }
+        Ok(kvs)
+    }
 }
 
 pub type BoxedIterator = Box<dyn Iterator>;
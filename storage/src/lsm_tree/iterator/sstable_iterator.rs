@@ -4,7 +4,7 @@ use async_trait::async_trait;
 
 use super::{BlockIterator, Iterator, Seek};
 use crate::components::{CachePolicy, Sstable, SstableStoreRef};
-use crate::utils::compare_full_key;
+use crate::utils::{compare_full_key, full_key, user_key};
 use crate::Result;
 
 pub struct SstableIterator {
@@ -18,6 +18,14 @@ pub struct SstableIterator {
     iter: Option<BlockIterator>,
     /// Cache policy.
     cache_policy: CachePolicy,
+    /// Count of blocks to prefetch ahead of the current block in scan mode. `0` means disabled.
+    prefetch_blocks: usize,
+    /// Blocks before this index have been prefetched.
+    prefetched: usize,
+    /// Whether to skip values, see [`Iterator::skip_value`].
+    keys_only: bool,
+    /// User key prefix of the last [`Seek::Prefix`], empty for other seeks.
+    prefix: Vec<u8>,
 }
 
 impl SstableIterator {
@@ -32,6 +40,46 @@ impl SstableIterator {
             offset: usize::MAX,
             iter: None,
             cache_policy,
+            prefetch_blocks: 0,
+            prefetched: 0,
+            keys_only: false,
+            prefix: Vec::default(),
+        }
+    }
+
+    /// Create an iterator in scan mode, which prefetches `prefetch_blocks` blocks ahead of the
+    /// current block in the background while iterating forward.
+    ///
+    /// Note: Prefetching only takes effect with [`CachePolicy::Fill`].
+    pub fn scan(
+        sstable_store: SstableStoreRef,
+        sstable: Sstable,
+        cache_policy: CachePolicy,
+        prefetch_blocks: usize,
+    ) -> Self {
+        Self {
+            prefetch_blocks,
+            ..Self::new(sstable_store, sstable, cache_policy)
+        }
+    }
+
+    /// Whether the iterator is valid and the current user key starts with the prefix of the last
+    /// [`Seek::Prefix`]. Equals [`Iterator::is_valid`] after other seeks.
+    pub fn prefix_valid(&self) -> bool {
+        self.is_valid() && user_key(self.key()).starts_with(&self.prefix)
+    }
+
+    /// Prefetch blocks after the block of the given index if in scan mode.
+    fn prefetch(&mut self, offset: usize) {
+        if self.prefetch_blocks == 0 {
+            return;
+        }
+        let start = std::cmp::max(self.prefetched, offset + 1);
+        let end = std::cmp::min(offset + 1 + self.prefetch_blocks, self.sstable.blocks_len());
+        if start < end {
+            self.sstable_store
+                .prefetch(&self.sstable, start, end - start, self.cache_policy);
+            self.prefetched = end;
         }
     }
 
@@ -48,6 +96,7 @@ impl SstableIterator {
         if !iter.is_valid() {
             if self.offset + 1 < self.sstable.blocks_len() {
                 self.offset += 1;
+                self.prefetch(self.offset);
                 let block = self
                     .sstable_store
                     .block(&self.sstable, self.offset, self.cache_policy)
@@ -81,14 +130,6 @@ impl SstableIterator {
         Ok(())
     }
 
-    /// Move backward until the position that the given `key` can be inserted in DESC order or EOF.
-    async fn prev_until_key(&mut self, key: &[u8]) -> Result<()> {
-        while self.is_valid() && compare_full_key(self.key(), key) == Ordering::Greater {
-            self.prev_inner().await?;
-        }
-        Ok(())
-    }
-
     async fn binary_seek_inner(&mut self, key: &[u8]) -> Result<usize> {
         let mut size = self.sstable.blocks_len();
         let mut left = 0;
@@ -117,12 +158,41 @@ impl SstableIterator {
         Ok(left.saturating_sub(1))
     }
 
+    /// Seek to the last key that equals or smaller than the given `key`.
+    ///
+    /// The candidate block is located by block metas, so only one block needs to be fetched.
+    async fn binary_seek_for_prev(&mut self, key: &[u8]) -> Result<()> {
+        // Index of the first block whose first key is larger than the given `key`.
+        let offset = self
+            .sstable
+            .block_metas_iter()
+            .as_slice()
+            .partition_point(|meta| compare_full_key(&meta.first_key, key) != Ordering::Greater);
+        if offset == 0 {
+            self.invalid();
+            return Ok(());
+        }
+        let offset = offset - 1;
+        let block = self
+            .sstable_store
+            .block(&self.sstable, offset, self.cache_policy)
+            .await?;
+        let mut iter = BlockIterator::new(block);
+        iter.seek(Seek::RandomBackward(key))?;
+        debug_assert!(iter.is_valid());
+        self.offset = offset;
+        self.iter = Some(iter);
+        Ok(())
+    }
+
     async fn binary_seek(&mut self, key: &[u8]) -> Result<()> {
         let offset = self.binary_seek_inner(key).await?;
         if offset >= self.sstable.blocks_len() {
             self.invalid();
             return Ok(());
         }
+        self.prefetched = 0;
+        self.prefetch(offset);
         let block = self
             .sstable_store
             .block(&self.sstable, offset, self.cache_policy)
@@ -159,6 +229,37 @@ impl Iterator for SstableIterator {
         self.next_inner().await
     }
 
+    /// Blocks that only contain versions of the current user key are skipped without being
+    /// fetched.
+    async fn skip_to_user_key(&mut self) -> Result<()> {
+        assert!(self.is_valid());
+        let current = user_key(self.key()).to_vec();
+        let mut offset = self.offset;
+        while offset < self.sstable.blocks_len()
+            && user_key(&self.sstable.block_meta(offset).unwrap().last_key) == current
+        {
+            offset += 1;
+        }
+        if offset != self.offset {
+            if offset >= self.sstable.blocks_len() {
+                self.invalid();
+                return Ok(());
+            }
+            self.offset = offset;
+            self.prefetch(offset);
+            let block = self
+                .sstable_store
+                .block(&self.sstable, offset, self.cache_policy)
+                .await?;
+            self.iter = Some(BlockIterator::new(block));
+            self.iter.as_mut().unwrap().seek(Seek::First)?;
+        }
+        while self.is_valid() && user_key(self.key()) == current {
+            self.next_inner().await?;
+        }
+        Ok(())
+    }
+
     async fn prev(&mut self) -> Result<()> {
         assert!(self.is_valid());
         self.prev_inner().await
@@ -169,19 +270,31 @@ impl Iterator for SstableIterator {
         self.iter.as_ref().unwrap().key()
     }
 
+    /// Note: Returns an empty slice in keys only mode.
     fn value(&self) -> &[u8] {
         assert!(self.is_valid());
+        if self.keys_only {
+            return &[];
+        }
         self.iter.as_ref().unwrap().value()
     }
 
+    /// Enter keys only mode. Keys only iterators MUST NOT be used for value reads.
+    fn skip_value(&mut self) {
+        self.keys_only = true;
+    }
+
     fn is_valid(&self) -> bool {
         self.offset < self.sstable.blocks_len()
     }
 
     async fn seek<'s>(&mut self, seek: Seek<'s>) -> Result<bool> {
+        self.prefix.clear();
         let found = match seek {
             Seek::First => {
                 self.offset = 0;
+                self.prefetched = 0;
+                self.prefetch(0);
                 let block = self
                     .sstable_store
                     .block(&self.sstable, self.offset, self.cache_policy)
@@ -205,13 +318,14 @@ impl Iterator for SstableIterator {
                 self.is_valid() && self.key() == key
             }
             Seek::RandomBackward(key) => {
-                self.binary_seek(key).await?;
-                if !self.is_valid() {
-                    self.seek(Seek::Last).await?;
-                }
-                self.prev_until_key(key).await?;
+                self.binary_seek_for_prev(key).await?;
                 self.is_valid() && self.key() == key
             }
+            Seek::Prefix(prefix) => {
+                self.prefix = prefix.to_vec();
+                self.binary_seek(&full_key(prefix, u64::MAX)).await?;
+                self.prefix_valid()
+            }
         };
         Ok(found)
     }
@@ -219,10 +333,14 @@ impl Iterator for SstableIterator {
 
 #[cfg(test)]
 mod tests {
+    use std::ops::Range;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
     use std::sync::Arc;
+    use std::time::Duration;
 
     use runkv_common::coding::CompressionAlgorithm;
     use test_log::test;
+    use tokio::time::Instant;
 
     use super::*;
     use crate::components::{
@@ -230,8 +348,8 @@ mod tests {
         SstableStoreOptions,
     };
     use crate::lsm_tree::TEST_DEFAULT_RESTART_INTERVAL;
-    use crate::utils::full_key;
-    use crate::MemObjectStore;
+    use crate::utils::{full_key, value};
+    use crate::{MemObjectStore, ObjectStore};
 
     fn build_sstable_for_test() -> (SstableMeta, Vec<u8>) {
         let options = SstableBuilderOptions {
@@ -240,6 +358,7 @@ mod tests {
             restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
             bloom_false_positive: 0.1,
             compression_algorithm: CompressionAlgorithm::Lz4,
+            ..Default::default()
         };
         let mut builder = SstableBuilder::new(options);
         builder.add(b"k01", 1, Some(b"v01")).unwrap();
@@ -256,12 +375,7 @@ mod tests {
     async fn build_iterator_for_test() -> SstableIterator {
         let object_store = Arc::new(MemObjectStore::default());
         let block_cache = BlockCache::new(65536);
-        let options = SstableStoreOptions {
-            path: "test".to_string(),
-            object_store,
-            block_cache,
-            meta_cache_capacity: 1024,
-        };
+        let options = SstableStoreOptions::new("test".to_string(), object_store, block_cache, 1024);
         let sstable_store = Arc::new(SstableStore::new(options));
         let (meta, data) = build_sstable_for_test();
         let sstable = Sstable::new(1, Arc::new(meta));
@@ -272,6 +386,44 @@ mod tests {
         SstableIterator::new(sstable_store, sstable, CachePolicy::Fill)
     }
 
+    #[test(tokio::test)]
+    async fn test_keys_only() {
+        let mut it = build_iterator_for_test().await;
+        let kvs = it.collect_all().await.unwrap();
+
+        let mut it = build_iterator_for_test().await;
+        it.skip_value();
+        let mut keys = vec![];
+        it.seek(Seek::First).await.unwrap();
+        while it.is_valid() {
+            assert!(it.value().is_empty());
+            keys.push(it.key().to_vec());
+            it.next().await.unwrap();
+        }
+        assert_eq!(
+            keys,
+            kvs.into_iter().map(|(key, _)| key).collect::<Vec<_>>()
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_peek() {
+        let mut it = build_iterator_for_test().await;
+        it.seek(Seek::First).await.unwrap();
+        assert_eq!(it.peek_key(), Some(&full_key(b"k01", 1)[..]));
+        assert_eq!(value(it.peek_value().unwrap()), Some(&b"v01"[..]));
+        // Peeking doesn't advance.
+        assert_eq!(it.peek_key(), Some(it.key()));
+        assert_eq!(it.key(), &full_key(b"k01", 1)[..]);
+
+        it.seek(Seek::RandomForward(&full_key(b"k09", 9)))
+            .await
+            .unwrap();
+        assert!(!it.is_valid());
+        assert_eq!(it.peek_key(), None);
+        assert_eq!(it.peek_value(), None);
+    }
+
     #[test(tokio::test)]
     async fn test_seek_first() {
         let mut it = build_iterator_for_test().await;
@@ -279,6 +431,38 @@ mod tests {
         assert_eq!(&full_key(b"k01", 1)[..], it.key());
     }
 
+    #[test(tokio::test)]
+    async fn test_seek_prefix() {
+        let mut it = build_iterator_for_test().await;
+
+        // Present prefix, across blocks.
+        assert!(it.seek(Seek::Prefix(b"k0")).await.unwrap());
+        let mut keys = vec![];
+        while it.prefix_valid() {
+            keys.push(user_key(it.key()).to_vec());
+            it.next().await.unwrap();
+        }
+        assert_eq!(keys, vec![b"k01", b"k02", b"k04", b"k05", b"k07", b"k08"]);
+        assert!(!it.is_valid());
+
+        // Absent prefix, between keys or beyond the last key.
+        assert!(!it.seek(Seek::Prefix(b"k03")).await.unwrap());
+        assert!(it.is_valid());
+        assert!(!it.prefix_valid());
+        assert_eq!(&full_key(b"k04", 4)[..], it.key());
+        assert!(!it.seek(Seek::Prefix(b"k1")).await.unwrap());
+        assert!(!it.is_valid());
+        assert!(!it.prefix_valid());
+
+        // Prefix that is a full user key.
+        assert!(it.seek(Seek::Prefix(b"k05")).await.unwrap());
+        assert!(it.prefix_valid());
+        assert_eq!(&full_key(b"k05", 5)[..], it.key());
+        it.next().await.unwrap();
+        assert!(it.is_valid());
+        assert!(!it.prefix_valid());
+    }
+
     #[test(tokio::test)]
     async fn test_seek_last() {
         let mut it = build_iterator_for_test().await;
@@ -330,6 +514,27 @@ mod tests {
         assert_eq!(&full_key(b"k02", 2)[..], it.key());
     }
 
+    #[test(tokio::test)]
+    async fn test_seek_backward_across_blocks() {
+        let mut it = build_iterator_for_test().await;
+        for i in (1..=2).chain(4..=5).chain(7..=8) {
+            let key = full_key(format!("k{:02}", i).as_bytes(), i as u64);
+            assert!(it.seek(Seek::RandomBackward(&key[..])).await.unwrap());
+            assert_eq!(&key[..], it.key());
+        }
+
+        // Target falls in the gap between two blocks.
+        it.seek(Seek::RandomBackward(&full_key(b"k06", 6)[..]))
+            .await
+            .unwrap();
+        assert_eq!(&full_key(b"k05", 5)[..], it.key());
+        it.prev().await.unwrap();
+        assert_eq!(&full_key(b"k04", 4)[..], it.key());
+        it.next().await.unwrap();
+        it.next().await.unwrap();
+        assert_eq!(&full_key(b"k07", 7)[..], it.key());
+    }
+
     #[test(tokio::test)]
     async fn test_forward_iterate() {
         let mut it = build_iterator_for_test().await;
@@ -375,4 +580,214 @@ mod tests {
         it.next().await.unwrap();
         assert_eq!(&full_key(b"k04", 4)[..], it.key());
     }
+
+    const LATENCY: Duration = Duration::from_millis(20);
+
+    /// [`MemObjectStore`] with injected latency on range reads.
+    #[derive(Default)]
+    struct SlowObjectStore {
+        inner: MemObjectStore,
+        get_range_count: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ObjectStore for SlowObjectStore {
+        async fn put(&self, path: &str, obj: Vec<u8>) -> Result<()> {
+            self.inner.put(path, obj).await
+        }
+
+        async fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+            self.inner.get(path).await
+        }
+
+        async fn get_range(&self, path: &str, range: Range<usize>) -> Result<Option<Vec<u8>>> {
+            self.get_range_count.fetch_add(1, AtomicOrdering::SeqCst);
+            tokio::time::sleep(LATENCY).await;
+            self.inner.get_range(path, range).await
+        }
+
+        async fn remove(&self, path: &str) -> Result<()> {
+            self.inner.remove(path).await
+        }
+
+        async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+            self.inner.list(prefix).await
+        }
+    }
+
+    /// Iterate forward over an sstable of 8 blocks and count the operations that stall on
+    /// fetching blocks from the object store.
+    ///
+    /// Note: Run it with the tokio clock paused, so that only fetches that are not prefetched yet
+    /// take [`LATENCY`], and the iteration itself takes no time.
+    async fn forward_iterate_stalls(prefetch_blocks: usize) -> usize {
+        let options = SstableStoreOptions::new(
+            "test".to_string(),
+            Arc::new(SlowObjectStore::default()),
+            BlockCache::new(65536),
+            1024,
+        );
+        let sstable_store = Arc::new(SstableStore::new(options));
+        let options = SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        let mut builder = SstableBuilder::new(options);
+        for i in 1..=16 {
+            builder
+                .add(
+                    format!("k{:02}", i).as_bytes(),
+                    i,
+                    Some(format!("v{:02}", i).as_bytes()),
+                )
+                .unwrap();
+        }
+        let (meta, data) = builder.build().unwrap();
+        assert_eq!(8, meta.block_metas.len());
+        let sstable = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sstable, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+
+        let mut it =
+            SstableIterator::scan(sstable_store, sstable, CachePolicy::Fill, prefetch_blocks);
+        let mut stalls = 0;
+        let now = Instant::now();
+        it.seek(Seek::First).await.unwrap();
+        if now.elapsed() >= LATENCY {
+            stalls += 1;
+        }
+        let mut count = 1;
+        while it.is_valid() {
+            let now = Instant::now();
+            it.next().await.unwrap();
+            if now.elapsed() >= LATENCY {
+                stalls += 1;
+            }
+            count += 1;
+        }
+        assert_eq!(17, count);
+        stalls
+    }
+
+    #[test(tokio::test(start_paused = true))]
+    async fn test_scan_prefetch() {
+        // Every block is fetched on demand.
+        assert_eq!(8, forward_iterate_stalls(0).await);
+        // Blocks 1..8 are prefetched while fetching block 0.
+        assert_eq!(1, forward_iterate_stalls(8).await);
+        // Blocks 1, 2 are prefetched while fetching block 0, then each block entered prefetches
+        // one more block. Blocks 3 and 6 are entered before their prefetches finish.
+        assert_eq!(3, forward_iterate_stalls(2).await);
+    }
+
+    #[test(tokio::test)]
+    async fn test_skip_to_user_key() {
+        let object_store = Arc::new(SlowObjectStore::default());
+        let options = SstableStoreOptions::new(
+            "test".to_string(),
+            object_store.clone(),
+            BlockCache::new(65536),
+            1024,
+        );
+        let sstable_store = Arc::new(SstableStore::new(options));
+        let options = SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        };
+        let mut builder = SstableBuilder::new(options);
+        builder.add(b"k01", 1, Some(b"v01")).unwrap();
+        for ts in (1..=20).rev() {
+            builder.add(b"k02", ts, Some(b"v02")).unwrap();
+        }
+        for ts in (1..=3).rev() {
+            builder.add(b"k03", ts, Some(b"v03")).unwrap();
+        }
+        let (meta, data) = builder.build().unwrap();
+        assert!(meta.block_metas.len() > 5);
+        let sstable = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sstable, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+
+        let mut it = SstableIterator::new(sstable_store, sstable, CachePolicy::Disable);
+        it.seek(Seek::First).await.unwrap();
+        assert_eq!(&full_key(b"k01", 1)[..], it.key());
+        it.skip_to_user_key().await.unwrap();
+        assert_eq!(&full_key(b"k02", 20)[..], it.key());
+
+        object_store
+            .get_range_count
+            .store(0, AtomicOrdering::SeqCst);
+        it.skip_to_user_key().await.unwrap();
+        assert_eq!(&full_key(b"k03", 3)[..], it.key());
+        // Blocks that only contain versions of "k02" are not fetched.
+        assert!(object_store.get_range_count.load(AtomicOrdering::SeqCst) <= 2);
+
+        it.skip_to_user_key().await.unwrap();
+        assert!(!it.is_valid());
+    }
+
+    #[test(tokio::test)]
+    async fn test_tombstone() {
+        let sstable_store = Arc::new(SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            Arc::new(MemObjectStore::default()),
+            BlockCache::new(65536),
+            1024,
+        )));
+        let mut builder = SstableBuilder::new(SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: TEST_DEFAULT_RESTART_INTERVAL,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        });
+        builder.add(b"k01", 2, Some(b"v01")).unwrap();
+        builder.add_tombstone(b"k01", 1).unwrap();
+        builder.add_tombstone(b"k02", 2).unwrap();
+        builder.add(b"k03", 3, Some(b"v03")).unwrap();
+        let (meta, data) = builder.build().unwrap();
+        let sstable = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sstable, data, CachePolicy::Fill)
+            .await
+            .unwrap();
+        let mut it = SstableIterator::new(sstable_store, sstable, CachePolicy::Fill);
+
+        let expected: [(&[u8], u64, Option<&[u8]>); 4] = [
+            (b"k01", 2, Some(b"v01")),
+            (b"k01", 1, None),
+            (b"k02", 2, None),
+            (b"k03", 3, Some(b"v03")),
+        ];
+        it.seek(Seek::First).await.unwrap();
+        for (k, ts, v) in expected {
+            assert!(it.is_valid());
+            assert_eq!(&full_key(k, ts)[..], it.key());
+            assert_eq!(v, value(it.value()));
+            it.next().await.unwrap();
+        }
+        assert!(!it.is_valid());
+
+        it.seek(Seek::Last).await.unwrap();
+        for (k, ts, v) in expected.into_iter().rev() {
+            assert!(it.is_valid());
+            assert_eq!(&full_key(k, ts)[..], it.key());
+            assert_eq!(v, value(it.value()));
+            it.prev().await.unwrap();
+        }
+        assert!(!it.is_valid());
+    }
 }
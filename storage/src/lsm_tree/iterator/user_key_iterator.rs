@@ -2,6 +2,7 @@ use async_recursion::async_recursion;
 use async_trait::async_trait;
 
 use super::{BoxedIterator, Iterator, Seek};
+use crate::components::{max_covering_timestamp, RangeTombstone};
 use crate::utils::{full_key, timestamp, user_key, value};
 use crate::Result;
 
@@ -15,17 +16,38 @@ pub struct UserKeyIterator {
     timestamp: u64,
     /// Current user key.
     key: Vec<u8>,
+    /// Range tombstones of the memtables and sstables under `iter`.
+    range_tombstones: Vec<RangeTombstone>,
 }
 
 impl UserKeyIterator {
     pub fn new(iter: BoxedIterator, timestamp: u64) -> Self {
+        Self::with_range_tombstones(iter, timestamp, vec![])
+    }
+
+    /// Create a [`UserKeyIterator`] that skips the versions deleted by `range_tombstones` as well
+    /// as point tombstones.
+    pub fn with_range_tombstones(
+        iter: BoxedIterator,
+        timestamp: u64,
+        range_tombstones: Vec<RangeTombstone>,
+    ) -> Self {
         Self {
             iter,
             timestamp,
             key: Vec::default(),
+            range_tombstones,
         }
     }
 
+    /// Judge whether the version of `user_key` at `timestamp` is deleted, either by a point
+    /// tombstone or by a range tombstone visible at the snapshot.
+    fn is_deleted(&self, user_key: &[u8], timestamp: u64, raw_value: &[u8]) -> bool {
+        value(raw_value).is_none()
+            || max_covering_timestamp(&self.range_tombstones, user_key, self.timestamp)
+                .map_or(false, |covering| timestamp <= covering)
+    }
+
     /// Note: Ensure that the current state is valid.
     async fn next_inner(&mut self, key: &[u8]) -> Result<bool> {
         let mut found = false;
@@ -39,7 +61,7 @@ impl UserKeyIterator {
             if key == uk && self.timestamp >= ts {
                 found = true;
             }
-            if self.timestamp >= ts && value(self.iter.value()).is_none() {
+            if self.timestamp >= ts && self.is_deleted(uk, ts, self.iter.value()) {
                 // Get tombstone, skip the former versions of this user key.
                 self.key = uk.to_vec();
             }
@@ -69,11 +91,12 @@ impl UserKeyIterator {
             if self.timestamp >= ts && uk != self.key {
                 self.key = uk.to_vec();
                 self.seek_latest_visiable_current_user_key().await?;
-                match value(self.iter.value()) {
-                    Some(_) => return Ok(found),
-                    // Current user key has been deleted. Keep finding.
-                    None => return self.prev_inner(key).await,
+                let ts = timestamp(self.iter.key());
+                if !self.is_deleted(&self.key, ts, self.iter.value()) {
+                    return Ok(found);
                 }
+                // Current user key has been deleted. Keep finding.
+                return self.prev_inner(key).await;
             }
             // Call inner iter `prev` later. It's useful to impl `Seel::Last`.
             self.iter.prev().await?;
@@ -108,6 +131,11 @@ impl Iterator for UserKeyIterator {
         Ok(())
     }
 
+    async fn skip_to_user_key(&mut self) -> Result<()> {
+        // Only the visible version of each user key is iterated.
+        self.next().await
+    }
+
     async fn prev(&mut self) -> Result<()> {
         assert!(self.is_valid());
         self.prev_inner(&[]).await?;
@@ -156,6 +184,14 @@ impl Iterator for UserKeyIterator {
                     .await?;
                 self.prev_inner(key).await?
             }
+            Seek::Prefix(prefix) => {
+                self.key.clear();
+                self.iter
+                    .seek(Seek::RandomForward(&full_key(prefix, u64::MAX)))
+                    .await?;
+                self.next_inner(&[]).await?;
+                self.is_valid() && self.key().starts_with(prefix)
+            }
         };
         Ok(found)
     }
@@ -176,14 +212,16 @@ mod tests {
     use crate::MemObjectStore;
 
     async fn build_iterator_for_test(timestamp: u64) -> UserKeyIterator {
+        build_iterator_with_range_tombstones_for_test(timestamp, vec![]).await
+    }
+
+    async fn build_iterator_with_range_tombstones_for_test(
+        timestamp: u64,
+        range_tombstones: Vec<RangeTombstone>,
+    ) -> UserKeyIterator {
         let object_store = Arc::new(MemObjectStore::default());
         let block_cache = BlockCache::new(65536);
-        let options = SstableStoreOptions {
-            path: "path".to_string(),
-            object_store,
-            block_cache,
-            meta_cache_capacity: 1024,
-        };
+        let options = SstableStoreOptions::new("path".to_string(), object_store, block_cache, 1024);
         let sstable_store = Arc::new(SstableStore::new(options));
 
         let (meta, data) = build_sstable_for_test();
@@ -194,7 +232,7 @@ mod tests {
             .unwrap();
 
         let si = SstableIterator::new(sstable_store, sstable, CachePolicy::Fill);
-        UserKeyIterator::new(Box::new(si), timestamp)
+        UserKeyIterator::with_range_tombstones(Box::new(si), timestamp, range_tombstones)
     }
 
     fn build_sstable_for_test() -> (SstableMeta, Vec<u8>) {
@@ -542,4 +580,46 @@ mod tests {
         it.next().await.unwrap();
         assert_eq!(b"v07-03", it.value());
     }
+
+    #[test(tokio::test)]
+    async fn test_range_tombstone() {
+        let range_tombstones = vec![RangeTombstone::new(b"k05".to_vec(), b"k10".to_vec(), 3)];
+
+        // Versions not newer than the range tombstone are deleted.
+        let mut it =
+            build_iterator_with_range_tombstones_for_test(3, range_tombstones.clone()).await;
+        assert!(it.seek(Seek::First).await.unwrap());
+        assert_eq!(b"v03-03", it.value());
+        it.next().await.unwrap();
+        assert_eq!(b"v11-03", it.value());
+        it.next().await.unwrap();
+        assert!(!it.is_valid());
+
+        assert!(it.seek(Seek::Last).await.unwrap());
+        assert_eq!(b"v11-03", it.value());
+        it.prev().await.unwrap();
+        assert_eq!(b"v03-03", it.value());
+        it.prev().await.unwrap();
+        assert!(!it.is_valid());
+
+        it.seek(Seek::RandomForward(b"k07")).await.unwrap();
+        assert_eq!(b"v11-03", it.value());
+        it.seek(Seek::RandomBackward(b"k09")).await.unwrap();
+        assert_eq!(b"v03-03", it.value());
+
+        // Newer versions are kept.
+        let mut it =
+            build_iterator_with_range_tombstones_for_test(4, range_tombstones.clone()).await;
+        assert!(it.seek(Seek::RandomForward(b"k05")).await.unwrap());
+        assert_eq!(b"v05-04", it.value());
+        it.next().await.unwrap();
+        assert_eq!(b"v07-04", it.value());
+
+        // The range tombstone is invisible to older snapshots.
+        let mut it = build_iterator_with_range_tombstones_for_test(2, range_tombstones).await;
+        assert!(it.seek(Seek::RandomBackward(b"k07")).await.unwrap());
+        assert_eq!(b"v07-02", it.value());
+        it.prev().await.unwrap();
+        assert_eq!(b"v06-02", it.value());
+    }
 }
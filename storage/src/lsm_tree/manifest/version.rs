@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::ops::{Range, RangeInclusive};
 use std::sync::Arc;
 
@@ -70,6 +70,10 @@ impl VersionManagerCore {
         self.levels_data_size[level_idx]
     }
 
+    fn ssts(&self) -> Vec<Vec<u64>> {
+        self.levels.clone()
+    }
+
     fn watermark(&self) -> u64 {
         self.watermark
     }
@@ -353,6 +357,18 @@ impl VersionManagerCore {
     }
 }
 
+async fn orphan_ssts(core: &VersionManagerCore) -> Result<Vec<u64>> {
+    let referenced = core
+        .levels
+        .iter()
+        .flatten()
+        .copied()
+        .collect::<HashSet<_>>();
+    let mut ssts = core.sstable_store.list_ssts().await?;
+    ssts.retain(|sst_id| !referenced.contains(sst_id));
+    Ok(ssts)
+}
+
 #[derive(Clone)]
 pub struct VersionManager {
     inner: Arc<RwLock<VersionManagerCore>>,
@@ -373,6 +389,11 @@ impl VersionManager {
         self.inner.read().await.level_data_size(level_idx)
     }
 
+    /// Sst ids of each level of the latest version.
+    pub async fn ssts(&self) -> Vec<Vec<u64>> {
+        self.inner.read().await.ssts()
+    }
+
     pub async fn watermark(&self) -> u64 {
         self.inner.read().await.watermark()
     }
@@ -389,6 +410,32 @@ impl VersionManager {
         self.inner.write().await.update(diff, sync).await
     }
 
+    /// Sstables that have objects in the object store but are not referenced by the latest
+    /// version, e.g. left by a crash after putting the sstable but before updating the manifest.
+    pub async fn orphan_ssts(&self) -> Result<Vec<u64>> {
+        let inner = self.inner.read().await;
+        orphan_ssts(&inner).await
+    }
+
+    /// Remove orphan sstables whose ids are not greater than `max_sst_id`. Returns the removed
+    /// sstable ids.
+    ///
+    /// NOTE: Sstables being put are orphans too before they are recorded in the manifest. The
+    /// caller must guarantee that no sstable with id not greater than `max_sst_id` is being put.
+    pub async fn gc_orphan_ssts(&self, max_sst_id: u64) -> Result<Vec<u64>> {
+        // Hold the lock to prevent the orphans from being referenced during removal.
+        let inner = self.inner.read().await;
+        let mut removed = orphan_ssts(&inner).await?;
+        removed.retain(|sst_id| *sst_id <= max_sst_id);
+        for sst_id in removed.iter() {
+            inner.sstable_store.remove(*sst_id).await?;
+        }
+        if !removed.is_empty() {
+            trace!("remove orphan ssts: {:?}", removed);
+        }
+        Ok(removed)
+    }
+
     /// Revoke all version diffs whose id is smaller than given `diff_id`.
     pub async fn squash(&self, diff_id: u64) {
         self.inner.write().await.squash(diff_id)
@@ -493,6 +540,7 @@ impl VersionManager {
 mod tests {
     use std::assert_matches::assert_matches;
 
+    use bytes::Bytes;
     use itertools::Itertools;
     use runkv_proto::manifest::SstableDiff;
     use test_log::test;
@@ -500,7 +548,7 @@ mod tests {
     use super::*;
     use crate::lsm_tree::components::{
         BlockCache, BlockMeta, CachePolicy, Sstable, SstableBuilder, SstableBuilderOptions,
-        SstableMeta, SstableStore, SstableStoreOptions,
+        SstableMeta, SstableStore, SstableStoreOptions, SSTABLE_FORMAT_VERSION,
     };
     use crate::utils::full_key;
     use crate::MemObjectStore;
@@ -822,6 +870,68 @@ mod tests {
         assert!(!version_manager.verify_non_overlap().await.unwrap());
     }
 
+    #[test(tokio::test)]
+    async fn test_gc_orphan_ssts() {
+        let sstable_store = build_sstable_store_for_test();
+        let version_manager = VersionManager {
+            inner: Arc::new(RwLock::new(build_version_manager_for_test(
+                sstable_store.clone(),
+            ))),
+        };
+        build_and_ingest_sst(sstable_store.clone(), 1, &[(b"k1", b"v1")], 1).await;
+        version_manager
+            .update(
+                VersionDiff {
+                    id: 0,
+                    sstable_diffs: vec![SstableDiff {
+                        id: 1,
+                        level: 0,
+                        op: SstableOp::Insert.into(),
+                        data_size: 0,
+                    }],
+                },
+                false,
+            )
+            .await
+            .unwrap();
+
+        // Crash after putting sst 2 but before updating the manifest.
+        build_and_ingest_sst(sstable_store.clone(), 2, &[(b"k2", b"v2")], 2).await;
+        // Crash after putting the data of sst 3 but before putting its meta.
+        sstable_store
+            .store()
+            .put(&sstable_store.data_path(3), b"data".to_vec())
+            .await
+            .unwrap();
+
+        // Recover the manifest with the recorded ssts only.
+        let version_manager = VersionManager::new(VersionManagerOptions {
+            levels_options: version_manager.inner.read().await.level_options.clone(),
+            levels: version_manager.ssts().await,
+            sstable_store: sstable_store.clone(),
+        });
+        assert_eq!(version_manager.ssts().await[0], vec![1]);
+        assert_eq!(sstable_store.list_ssts().await.unwrap(), vec![1, 2, 3]);
+        assert_eq!(version_manager.orphan_ssts().await.unwrap(), vec![2, 3]);
+
+        // Sst 3 may be still being put.
+        assert_eq!(version_manager.gc_orphan_ssts(2).await.unwrap(), vec![2]);
+        assert_eq!(sstable_store.list_ssts().await.unwrap(), vec![1, 3]);
+        assert_eq!(version_manager.gc_orphan_ssts(3).await.unwrap(), vec![3]);
+        assert_eq!(sstable_store.list_ssts().await.unwrap(), vec![1]);
+        assert!(version_manager.orphan_ssts().await.unwrap().is_empty());
+
+        // Referenced sst is intact.
+        let sst = sstable_store.sstable(1).await.unwrap();
+        assert_eq!(
+            sstable_store
+                .get(&sst, b"k1", 1, CachePolicy::Disable)
+                .await
+                .unwrap(),
+            Some(Bytes::from("v1"))
+        );
+    }
+
     async fn ingest_meta(
         sstable_store: &SstableStoreRef,
         sst_id: u64,
@@ -841,6 +951,9 @@ mod tests {
                         }],
                         bloom_filter_bytes: vec![],
                         data_size: 0,
+                        range_tombstones: vec![],
+                        key_count: 0,
+                        format_version: SSTABLE_FORMAT_VERSION,
                     }),
                 ),
                 Vec::default(),
@@ -874,12 +987,8 @@ mod tests {
     fn build_sstable_store_for_test() -> SstableStoreRef {
         let object_store = Arc::new(MemObjectStore::default());
         let block_cache = BlockCache::new(0);
-        let sstable_store_options = SstableStoreOptions {
-            path: "test".to_string(),
-            object_store,
-            block_cache,
-            meta_cache_capacity: 65536,
-        };
+        let sstable_store_options =
+            SstableStoreOptions::new("test".to_string(), object_store, block_cache, 65536);
         Arc::new(SstableStore::new(sstable_store_options))
     }
 
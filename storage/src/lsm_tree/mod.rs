@@ -8,5 +8,7 @@ pub const DEFAULT_RESTART_INTERVAL: usize = 16;
 pub const TEST_DEFAULT_RESTART_INTERVAL: usize = 2;
 pub const DEFAULT_ENTRY_SIZE: usize = 1024; // 1 KiB
 pub const DEFAULT_BLOOM_FALSE_POSITIVE: f64 = 0.1;
+pub const DEFAULT_MIN_BLOOM_BITS_PER_KEY: usize = 4;
+pub const DEFAULT_MAX_BLOOM_BITS_PER_KEY: usize = 64;
 pub const DEFAULT_SSTABLE_META_SIZE: usize = 4 * 1024; // 4 KiB
 pub const DEFAULT_MEMTABLE_SIZE: usize = 4 * 1024 * 1024; // 4 MiB
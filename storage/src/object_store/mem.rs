@@ -4,39 +4,321 @@ use std::ops::Range;
 use async_trait::async_trait;
 use parking_lot::RwLock;
 
-use super::ObjectStore;
+use super::{ObjectStore, WritableObjectStore};
 use crate::{ObjectStoreError, Result};
 
+#[derive(Clone, Default)]
+struct MemObjects {
+    objects: BTreeMap<String, Vec<u8>>,
+    /// Total bytes of objects.
+    size: usize,
+}
+
+impl MemObjects {
+    /// Insert the object, returns [`ObjectStoreError::OutOfSpace`] if the total bytes of objects
+    /// would exceed `capacity`.
+    fn insert(&mut self, path: &str, obj: Vec<u8>, capacity: Option<usize>) -> Result<()> {
+        let replaced = self.objects.get(path).map(|obj| obj.len()).unwrap_or(0);
+        let size = self.size - replaced + obj.len();
+        if let Some(capacity) = capacity {
+            if size > capacity {
+                return Err(ObjectStoreError::OutOfSpace(format!(
+                    "put {} bytes to {}: [size: {}] [capacity: {}]",
+                    obj.len(),
+                    path,
+                    self.size,
+                    capacity
+                ))
+                .into());
+            }
+        }
+        self.objects.insert(path.to_string(), obj);
+        self.size = size;
+        Ok(())
+    }
+}
+
 #[derive(Default)]
 pub struct MemObjectStore {
-    objects: RwLock<BTreeMap<String, Vec<u8>>>,
+    objects: RwLock<MemObjects>,
+    /// Max total bytes of objects, unbounded if `None`.
+    capacity: Option<usize>,
+}
+
+impl MemObjectStore {
+    /// Returns a [`MemObjectStore`] that rejects puts with [`ObjectStoreError::OutOfSpace`] once
+    /// the total bytes of objects would exceed `max_bytes`.
+    pub fn with_capacity(max_bytes: usize) -> Self {
+        Self {
+            objects: RwLock::default(),
+            capacity: Some(max_bytes),
+        }
+    }
+
+    /// Total bytes of objects.
+    pub fn size(&self) -> usize {
+        self.objects.read().size
+    }
+
+    /// Returns a new [`MemObjectStore`] with a copy of the current objects.
+    pub fn snapshot(&self) -> MemObjectStore {
+        MemObjectStore {
+            objects: RwLock::new(self.objects.read().clone()),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Replace all objects with the ones of `snapshot`.
+    pub fn restore(&self, snapshot: &MemObjectStore) {
+        // Copy before locking for write, `snapshot` may be `self`.
+        let objects = snapshot.objects.read().clone();
+        *self.objects.write() = objects;
+    }
 }
 
 #[async_trait]
 impl ObjectStore for MemObjectStore {
     async fn put(&self, path: &str, obj: Vec<u8>) -> Result<()> {
         let mut objects = self.objects.write();
-        objects.insert(path.to_string(), obj);
-        Ok(())
+        objects.insert(path, obj, self.capacity)
+    }
+
+    async fn put_if_absent(&self, path: &str, obj: Vec<u8>) -> Result<bool> {
+        let mut objects = self.objects.write();
+        if objects.objects.contains_key(path) {
+            return Ok(false);
+        }
+        objects.insert(path, obj, self.capacity)?;
+        Ok(true)
     }
 
     async fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
         let objects = self.objects.read();
-        let obj = objects.get(path).cloned();
+        let obj = objects.objects.get(path).cloned();
         Ok(obj)
     }
 
+    async fn contains(&self, path: &str) -> Result<bool> {
+        Ok(self.objects.read().objects.contains_key(path))
+    }
+
     async fn get_range(&self, path: &str, range: Range<usize>) -> Result<Option<Vec<u8>>> {
         let objects = self.objects.read();
-        let obj = objects.get(path).map(|obj| obj[range].to_vec());
+        let obj = objects.objects.get(path).map(|obj| obj[range].to_vec());
         Ok(obj)
     }
 
     async fn remove(&self, path: &str) -> Result<()> {
         let mut objects = self.objects.write();
-        objects
+        let obj = objects
+            .objects
             .remove(path)
             .ok_or_else(|| ObjectStoreError::ObjectNotFound(path.to_string()))?;
+        objects.size -= obj.len();
         Ok(())
     }
+
+    async fn remove_prefix(&self, prefix: &str) -> Result<usize> {
+        let mut objects = self.objects.write();
+        let paths = objects
+            .objects
+            .range(prefix.to_string()..)
+            .take_while(|(path, _)| path.starts_with(prefix))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        for path in paths.iter() {
+            let obj = objects.objects.remove(path).unwrap();
+            objects.size -= obj.len();
+        }
+        Ok(paths.len())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let objects = self.objects.read();
+        let paths = objects
+            .objects
+            .range(prefix.to_string()..)
+            .take_while(|(path, _)| path.starts_with(prefix))
+            .map(|(path, _)| path.clone())
+            .collect();
+        Ok(paths)
+    }
+}
+
+#[async_trait]
+impl WritableObjectStore for MemObjectStore {
+    async fn copy(&self, src: &str, dst: &str) -> Result<()> {
+        let mut objects = self.objects.write();
+        let obj = objects
+            .objects
+            .get(src)
+            .cloned()
+            .ok_or_else(|| ObjectStoreError::ObjectNotFound(src.to_string()))?;
+        objects.insert(dst, obj, self.capacity)
+    }
+
+    /// Rename under a single write lock, so `src` and `dst` are never both or neither visible.
+    async fn rename(&self, src: &str, dst: &str) -> Result<()> {
+        let mut objects = self.objects.write();
+        let obj = objects
+            .objects
+            .remove(src)
+            .ok_or_else(|| ObjectStoreError::ObjectNotFound(src.to_string()))?;
+        objects.size -= obj.len();
+        // Never exceeds the capacity, for the size doesn't grow.
+        objects.insert(dst, obj, self.capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::Error;
+
+    #[test(tokio::test)]
+    async fn test_put_if_absent() {
+        let store = MemObjectStore::default();
+        assert!(store.put_if_absent("test/1", b"v1".to_vec()).await.unwrap());
+        assert_eq!(Some(b"v1".to_vec()), store.get("test/1").await.unwrap());
+
+        assert!(!store.put_if_absent("test/1", b"v2".to_vec()).await.unwrap());
+        assert_eq!(Some(b"v1".to_vec()), store.get("test/1").await.unwrap());
+
+        store.remove("test/1").await.unwrap();
+        assert!(store.put_if_absent("test/1", b"v3".to_vec()).await.unwrap());
+        assert_eq!(Some(b"v3".to_vec()), store.get("test/1").await.unwrap());
+    }
+
+    #[test(tokio::test)]
+    async fn test_contains() {
+        let store = MemObjectStore::default();
+        assert!(!store.contains("test/1").await.unwrap());
+        store.put("test/1", b"v1".to_vec()).await.unwrap();
+        assert!(store.contains("test/1").await.unwrap());
+        assert!(!store.contains("test/").await.unwrap());
+        assert!(!store.contains("test/10").await.unwrap());
+        store.remove("test/1").await.unwrap();
+        assert!(!store.contains("test/1").await.unwrap());
+    }
+
+    #[test(tokio::test)]
+    async fn test_capacity() {
+        let store = MemObjectStore::with_capacity(10);
+        store.put("test/1", vec![b'v'; 4]).await.unwrap();
+        store.put("test/2", vec![b'v'; 6]).await.unwrap();
+        assert_eq!(store.size(), 10);
+
+        assert!(matches!(
+            store.put("test/3", vec![b'v'; 1]).await,
+            Err(Error::ObjectStoreError(ObjectStoreError::OutOfSpace(_)))
+        ));
+        assert!(matches!(
+            store.put_if_absent("test/3", vec![b'v'; 1]).await,
+            Err(Error::ObjectStoreError(ObjectStoreError::OutOfSpace(_)))
+        ));
+        assert_eq!(None, store.get("test/3").await.unwrap());
+        // Overwriting counts the size of the replaced object.
+        store.put("test/2", vec![b'v'; 2]).await.unwrap();
+        assert_eq!(store.size(), 6);
+        assert!(store.put("test/1", vec![b'v'; 9]).await.is_err());
+        assert_eq!(Some(vec![b'v'; 4]), store.get("test/1").await.unwrap());
+
+        store.remove("test/1").await.unwrap();
+        assert_eq!(store.size(), 2);
+        store.put("test/3", vec![b'v'; 8]).await.unwrap();
+        assert_eq!(Some(vec![b'v'; 8]), store.get("test/3").await.unwrap());
+
+        // The default store is unbounded.
+        let store = MemObjectStore::default();
+        store.put("test/1", vec![b'v'; 1 << 20]).await.unwrap();
+    }
+
+    #[test(tokio::test)]
+    async fn test_remove_prefix() {
+        let store = MemObjectStore::default();
+        for i in 0..4 {
+            store.put(&format!("a/{}", i), vec![b'v'; 2]).await.unwrap();
+            store.put(&format!("b/{}", i), vec![b'v'; 2]).await.unwrap();
+        }
+        assert_eq!(store.remove_prefix("a/").await.unwrap(), 4);
+        assert!(store.list("a/").await.unwrap().is_empty());
+        assert_eq!(store.list("b/").await.unwrap().len(), 4);
+        assert_eq!(store.size(), 8);
+        assert_eq!(store.remove_prefix("a/").await.unwrap(), 0);
+        assert_eq!(store.remove_prefix("").await.unwrap(), 4);
+        assert_eq!(store.size(), 0);
+    }
+
+    #[test(tokio::test)]
+    async fn test_snapshot_restore() {
+        let store = MemObjectStore::default();
+        store.put("test/1", b"v1".to_vec()).await.unwrap();
+        store.put("test/2", b"v2".to_vec()).await.unwrap();
+        let snapshot = store.snapshot();
+
+        // The snapshot is not affected by later changes.
+        store.put("test/1", b"v1-new".to_vec()).await.unwrap();
+        store.remove("test/2").await.unwrap();
+        store.put("test/3", b"v3".to_vec()).await.unwrap();
+        assert_eq!(Some(b"v1".to_vec()), snapshot.get("test/1").await.unwrap());
+        assert_eq!(Some(b"v2".to_vec()), snapshot.get("test/2").await.unwrap());
+        assert_eq!(None, snapshot.get("test/3").await.unwrap());
+
+        store.restore(&snapshot);
+        assert_eq!(Some(b"v1".to_vec()), store.get("test/1").await.unwrap());
+        assert_eq!(Some(b"v2".to_vec()), store.get("test/2").await.unwrap());
+        assert_eq!(None, store.get("test/3").await.unwrap());
+
+        // The snapshot can be restored repeatedly, and the restored store is independent of it.
+        store.put("test/4", b"v4".to_vec()).await.unwrap();
+        store.restore(&snapshot);
+        assert_eq!(None, store.get("test/4").await.unwrap());
+        store.put("test/5", b"v5".to_vec()).await.unwrap();
+        assert_eq!(None, snapshot.get("test/5").await.unwrap());
+
+        // Restoring from itself is a no-op.
+        store.restore(&store);
+        assert_eq!(Some(b"v5".to_vec()), store.get("test/5").await.unwrap());
+    }
+
+    #[test(tokio::test)]
+    async fn test_copy_and_rename() {
+        let store = MemObjectStore::with_capacity(10);
+        store.put("test/1", vec![b'1'; 4]).await.unwrap();
+        store.put("test/2", vec![b'2'; 2]).await.unwrap();
+
+        store.copy("test/1", "test/3").await.unwrap();
+        assert_eq!(Some(vec![b'1'; 4]), store.get("test/1").await.unwrap());
+        assert_eq!(Some(vec![b'1'; 4]), store.get("test/3").await.unwrap());
+        assert_eq!(store.size(), 10);
+        assert!(matches!(
+            store.copy("test/1", "test/4").await,
+            Err(Error::ObjectStoreError(ObjectStoreError::OutOfSpace(_)))
+        ));
+
+        // Renaming replaces the destination.
+        store.rename("test/1", "test/2").await.unwrap();
+        assert_eq!(None, store.get("test/1").await.unwrap());
+        assert_eq!(Some(vec![b'1'; 4]), store.get("test/2").await.unwrap());
+        assert_eq!(store.size(), 8);
+        store.rename("test/2", "test/2").await.unwrap();
+        assert_eq!(Some(vec![b'1'; 4]), store.get("test/2").await.unwrap());
+        assert_eq!(
+            store.list("test/").await.unwrap(),
+            vec!["test/2".to_string(), "test/3".to_string()]
+        );
+
+        for result in [
+            store.copy("test/1", "test/4").await,
+            store.rename("test/1", "test/4").await,
+        ] {
+            assert!(matches!(
+                result,
+                Err(Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(_)))
+            ));
+        }
+        assert_eq!(store.size(), 8);
+    }
 }
@@ -7,7 +7,7 @@ use std::sync::Arc;
 use async_trait::async_trait;
 pub use s3::*;
 
-use crate::Result;
+use crate::{Error, Result};
 
 #[derive(thiserror::Error, Debug)]
 pub enum ObjectStoreError {
@@ -15,6 +15,8 @@ pub enum ObjectStoreError {
     ObjectNotFound(String),
     #[error("invalid range: {0}")]
     InvalidRange(String),
+    #[error("out of space: {0}")]
+    OutOfSpace(String),
     #[error("S3 error: {0}")]
     S3(String),
     #[error("other: {0}")]
@@ -25,11 +27,110 @@ pub enum ObjectStoreError {
 pub trait ObjectStore: Send + Sync {
     async fn put(&self, path: &str, obj: Vec<u8>) -> Result<()>;
 
+    /// Put object only if there is no object on the given `path`. Returns `true` if the object is
+    /// written, `false` if the object already exists.
+    ///
+    /// NOTE: The default implementation does get-then-put, which is racy for concurrent writers.
+    /// Backends that support conditional writes should override it.
+    async fn put_if_absent(&self, path: &str, obj: Vec<u8>) -> Result<bool> {
+        if self.get(path).await?.is_some() {
+            return Ok(false);
+        }
+        self.put(path, obj).await?;
+        Ok(true)
+    }
+
+    /// Start a streaming upload of the object on the given `path`. The object is visible only after
+    /// [`ObjectUploader::finish`] succeeds.
+    ///
+    /// NOTE: The default implementation buffers the whole object in memory and puts it on finish.
+    /// Backends that support multipart upload should override it to bound memory usage.
+    async fn upload<'a>(&'a self, path: &str) -> Result<BoxedObjectUploader<'a>> {
+        Ok(Box::new(BufferedObjectUploader {
+            object_store: self,
+            path: path.to_string(),
+            buf: vec![],
+        }))
+    }
+
     async fn get(&self, path: &str) -> Result<Option<Vec<u8>>>;
 
+    /// Check whether there is an object on the given `path`.
+    ///
+    /// NOTE: The default implementation fetches the whole object. Backends that support metadata
+    /// requests should override it.
+    async fn contains(&self, path: &str) -> Result<bool> {
+        match self.get(path).await {
+            Ok(obj) => Ok(obj.is_some()),
+            Err(Error::ObjectStoreError(ObjectStoreError::ObjectNotFound(_))) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
     async fn get_range(&self, path: &str, range: Range<usize>) -> Result<Option<Vec<u8>>>;
 
     async fn remove(&self, path: &str) -> Result<()>;
+
+    /// Remove all objects whose path starts with `prefix`. Returns the count of removed objects.
+    ///
+    /// NOTE: The default implementation lists the objects and removes them one by one. Backends
+    /// that support batch deletion should override it.
+    async fn remove_prefix(&self, prefix: &str) -> Result<usize> {
+        let paths = self.list(prefix).await?;
+        for path in paths.iter() {
+            self.remove(path).await?;
+        }
+        Ok(paths.len())
+    }
+
+    /// List paths of objects whose path starts with `prefix`, in ASC order.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>>;
 }
 
 pub type ObjectStoreRef = Arc<dyn ObjectStore>;
+
+/// [`WritableObjectStore`] supports server-side copying and renaming objects, e.g. to publish a
+/// verified temporary object under its final path.
+#[async_trait]
+pub trait WritableObjectStore: ObjectStore {
+    /// Copy the object on `src` to `dst`, the object on `dst` is replaced if exists.
+    async fn copy(&self, src: &str, dst: &str) -> Result<()>;
+
+    /// Move the object on `src` to `dst`, the object on `dst` is replaced if exists.
+    async fn rename(&self, src: &str, dst: &str) -> Result<()>;
+}
+
+/// [`ObjectUploader`] uploads an object part by part, see [`ObjectStore::upload`].
+#[async_trait]
+pub trait ObjectUploader: Send + Sync {
+    /// Append `buf` to the object.
+    async fn write(&mut self, buf: Vec<u8>) -> Result<()>;
+
+    /// Finish the upload and make the object visible.
+    async fn finish(self: Box<Self>) -> Result<()>;
+}
+
+pub type BoxedObjectUploader<'a> = Box<dyn ObjectUploader + 'a>;
+
+struct BufferedObjectUploader<'a, S: ObjectStore + ?Sized> {
+    object_store: &'a S,
+    path: String,
+    buf: Vec<u8>,
+}
+
+#[async_trait]
+impl<'a, S: ObjectStore + ?Sized> ObjectUploader for BufferedObjectUploader<'a, S> {
+    async fn write(&mut self, mut buf: Vec<u8>) -> Result<()> {
+        if self.buf.is_empty() {
+            self.buf = buf;
+        } else {
+            self.buf.append(&mut buf);
+        }
+        Ok(())
+    }
+
+    async fn finish(self: Box<Self>) -> Result<()> {
+        let this = *self;
+        this.object_store.put(&this.path, this.buf).await
+    }
+}
@@ -2,13 +2,17 @@ use std::ops::Range;
 
 use async_trait::async_trait;
 use aws_sdk_s3::error::{GetObjectError, GetObjectErrorKind};
+use aws_sdk_s3::model::{CompletedMultipartUpload, CompletedPart};
 use aws_sdk_s3::types::SdkError;
 use aws_sdk_s3::{Client, Endpoint, Region};
 use aws_smithy_http::body::SdkBody;
 
-use super::ObjectStore;
+use super::{BoxedObjectUploader, ObjectStore, ObjectUploader};
 use crate::{ObjectStoreError, Result};
 
+/// Part size of multipart upload. S3 requires parts except the last one to be at least 5 MiB.
+const UPLOAD_PART_SIZE: usize = 8 << 20;
+
 pub struct S3ObjectStore {
     client: Client,
     bucket: String,
@@ -66,6 +70,16 @@ impl ObjectStore for S3ObjectStore {
         Ok(())
     }
 
+    async fn upload<'a>(&'a self, path: &str) -> Result<BoxedObjectUploader<'a>> {
+        Ok(Box::new(S3ObjectUploader {
+            object_store: self,
+            path: path.to_string(),
+            buf: Vec::with_capacity(UPLOAD_PART_SIZE),
+            upload_id: None,
+            parts: vec![],
+        }))
+    }
+
     async fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
         let req = self.client.get_object().bucket(&self.bucket).key(path);
         let rsp = match req.send().await {
@@ -117,4 +131,120 @@ impl ObjectStore for S3ObjectStore {
             .map_err(err)?;
         Ok(())
     }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut paths = vec![];
+        let mut continuation_token = None;
+        loop {
+            let rsp = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(err)?;
+            paths.extend(
+                rsp.contents()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|object| object.key().map(|key| key.to_string())),
+            );
+            if !rsp.is_truncated() {
+                break;
+            }
+            continuation_token = rsp.next_continuation_token().map(|token| token.to_string());
+        }
+        Ok(paths)
+    }
+}
+
+/// [`S3ObjectUploader`] buffers at most one part in memory. Objects smaller than a part are
+/// uploaded with a single put.
+///
+/// NOTE: Parts of an unfinished upload are left on S3 until they are cleaned up by the bucket
+/// lifecycle rules.
+struct S3ObjectUploader<'a> {
+    object_store: &'a S3ObjectStore,
+    path: String,
+    buf: Vec<u8>,
+    upload_id: Option<String>,
+    parts: Vec<CompletedPart>,
+}
+
+impl<'a> S3ObjectUploader<'a> {
+    async fn upload_part(&mut self) -> Result<()> {
+        let client = &self.object_store.client;
+        let bucket = &self.object_store.bucket;
+        if self.upload_id.is_none() {
+            let rsp = client
+                .create_multipart_upload()
+                .bucket(bucket)
+                .key(&self.path)
+                .send()
+                .await
+                .map_err(err)?;
+            let upload_id = rsp
+                .upload_id
+                .ok_or_else(|| err("no upload id in create multipart upload response"))?;
+            self.upload_id = Some(upload_id);
+        }
+        let part_number = self.parts.len() as i32 + 1;
+        let buf = std::mem::replace(&mut self.buf, Vec::with_capacity(UPLOAD_PART_SIZE));
+        let rsp = client
+            .upload_part()
+            .bucket(bucket)
+            .key(&self.path)
+            .upload_id(self.upload_id.as_ref().unwrap())
+            .part_number(part_number)
+            .body(SdkBody::from(buf).into())
+            .send()
+            .await
+            .map_err(err)?;
+        self.parts.push(
+            CompletedPart::builder()
+                .set_e_tag(rsp.e_tag)
+                .part_number(part_number)
+                .build(),
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<'a> ObjectUploader for S3ObjectUploader<'a> {
+    async fn write(&mut self, buf: Vec<u8>) -> Result<()> {
+        self.buf.extend_from_slice(&buf);
+        if self.buf.len() >= UPLOAD_PART_SIZE {
+            self.upload_part().await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.upload_id.is_none() {
+            let buf = std::mem::take(&mut self.buf);
+            return self.object_store.put(&self.path, buf).await;
+        }
+        if !self.buf.is_empty() {
+            self.upload_part().await?;
+        }
+        let parts = std::mem::take(&mut self.parts);
+        self.object_store
+            .client
+            .complete_multipart_upload()
+            .bucket(&self.object_store.bucket)
+            .key(&self.path)
+            .upload_id(self.upload_id.as_ref().unwrap())
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(err)?;
+        Ok(())
+    }
 }
@@ -6,7 +6,7 @@ use tracing::trace;
 
 use super::error::RaftLogStoreError;
 use super::DEFAULT_LOG_BATCH_SIZE;
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 struct BlockIndex {
@@ -69,7 +69,20 @@ impl BlockCache {
             .await
         {
             Ok(block) => Ok(block),
-            Err(arc_error) => Err(RaftLogStoreError::Other(arc_error.to_string()).into()),
+            // Keep the location of corrupted entries for debugging.
+            Err(arc_error) => match arc_error.as_ref() {
+                Error::RaftLogStoreError(RaftLogStoreError::CorruptedEntry {
+                    file_id,
+                    offset,
+                    reason,
+                }) => Err(RaftLogStoreError::CorruptedEntry {
+                    file_id: *file_id,
+                    offset: *offset,
+                    reason: reason.clone(),
+                }
+                .into()),
+                _ => Err(RaftLogStoreError::Other(arc_error.to_string()).into()),
+            },
         }
     }
 }
@@ -12,6 +12,9 @@ use crate::utils::{
     BufExt, BufMutExt,
 };
 
+/// Length of the header of an entry record.
+pub const ENTRY_RECORD_HEADER_LEN: usize = 8;
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Entry {
     RaftLogBatch(RaftLogBatch),
@@ -19,6 +22,7 @@ pub enum Entry {
     Compact(Compact),
     Mask(Mask),
     Kv(Kv),
+    Snapshot(Snapshot),
 }
 
 impl From<RaftLogBatch> for Entry {
@@ -39,6 +43,12 @@ impl From<Kv> for Entry {
     }
 }
 
+impl From<Snapshot> for Entry {
+    fn from(f: Snapshot) -> Self {
+        Self::Snapshot(f)
+    }
+}
+
 impl Entry {
     pub fn encode(&self, buf: &mut Vec<u8>) {
         match self {
@@ -62,6 +72,10 @@ impl Entry {
                 buf.put_u8(4);
                 kv.encode(buf);
             }
+            Self::Snapshot(snapshot) => {
+                buf.put_u8(5);
+                snapshot.encode(buf);
+            }
         }
     }
 
@@ -72,9 +86,71 @@ impl Entry {
             2 => Self::Compact(Compact::decode(buf)),
             3 => Self::Mask(Mask::decode(buf)),
             4 => Self::Kv(Kv::decode(buf)),
+            5 => Self::Snapshot(Snapshot::decode(buf)),
             _ => unreachable!(),
         }
     }
+
+    /// Raft group of the entry.
+    pub fn group(&self) -> u64 {
+        match self {
+            Self::RaftLogBatch(batch) => batch.group(),
+            Self::Truncate(Truncate { group, .. })
+            | Self::Compact(Compact { group, .. })
+            | Self::Mask(Mask { group, .. })
+            | Self::Kv(Kv::Put { group, .. })
+            | Self::Kv(Kv::Delete { group, .. })
+            | Self::Snapshot(Snapshot { group, .. }) => *group,
+        }
+    }
+
+    /// Encode [`Entry`] as a record with checksum, so that torn writes can be detected. Returns
+    /// the offset and the length of the encoded entry in `buf`.
+    ///
+    /// Format:
+    ///
+    /// ```plain
+    /// | entry len (4B) | crc32sum (4B) | entry |
+    /// ```
+    pub fn encode_record(&self, buf: &mut Vec<u8>) -> (usize, usize) {
+        let start = buf.len();
+        buf.put_u32_le(0);
+        buf.put_u32_le(0);
+        let offset = buf.len();
+        self.encode(buf);
+        let len = buf.len() - offset;
+        let checksum = crc32sum(&buf[offset..]);
+        (&mut buf[start..start + 4]).put_u32_le(len as u32);
+        (&mut buf[start + 4..offset]).put_u32_le(checksum);
+        (offset, len)
+    }
+
+    /// Decode an entry record. Returns `None` and leaves `buf` untouched if the record is
+    /// incomplete or its checksum mismatches.
+    pub fn decode_record(buf: &mut &[u8]) -> Option<Self> {
+        if buf.len() < ENTRY_RECORD_HEADER_LEN {
+            return None;
+        }
+        let len = (&buf[..4]).get_u32_le() as usize;
+        let checksum = (&buf[4..ENTRY_RECORD_HEADER_LEN]).get_u32_le();
+        if len == 0 || buf.len() < ENTRY_RECORD_HEADER_LEN + len {
+            return None;
+        }
+        let mut entry = &buf[ENTRY_RECORD_HEADER_LEN..ENTRY_RECORD_HEADER_LEN + len];
+        if !crc32check(entry, checksum) {
+            return None;
+        }
+        let entry = Self::decode(&mut entry);
+        buf.advance(ENTRY_RECORD_HEADER_LEN + len);
+        Some(entry)
+    }
+
+    /// Whether `buf` holds the whole record at its head, according to the record length. Only an
+    /// incomplete record can be left by a torn write.
+    pub fn is_record_complete(buf: &[u8]) -> bool {
+        buf.len() >= ENTRY_RECORD_HEADER_LEN
+            && buf.len() >= ENTRY_RECORD_HEADER_LEN + (&buf[..4]).get_u32_le() as usize
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -135,6 +211,12 @@ impl RaftLogBatch {
         self.offsets.len() - 1
     }
 
+    /// Total bytes of entry data and ctxs, before compression.
+    pub fn size(&self) -> usize {
+        self.offsets.last().copied().unwrap_or(0)
+            + self.ctxs.iter().map(|ctx| ctx.len()).sum::<usize>()
+    }
+
     pub fn data_segment_location(&self) -> (usize, usize) {
         let offset = 8 // group
             + 8 // term
@@ -176,22 +258,28 @@ impl RaftLogBatch {
     /// ```plain
     /// | data block (compressed) | compression algorithm (1B) | crc32sum (4B) |
     /// ```
-    fn encode_data(&mut self) {
-        let mut buf = {
-            let mut encoder = lz4::EncoderBuilder::new()
-                .level(4)
-                .build(Vec::with_capacity(self.raw.len()).writer())
-                .map_err(RaftLogStoreError::encode_error)
-                .unwrap();
-            encoder
-                .write(&self.raw[..])
+    fn encode_data(&mut self, compression_algorithm: CompressionAlgorithm) {
+        let mut buf = match compression_algorithm {
+            CompressionAlgorithm::None => self.raw.clone(),
+            CompressionAlgorithm::Lz4 => {
+                let mut encoder = lz4::EncoderBuilder::new()
+                    .level(4)
+                    .build(Vec::with_capacity(self.raw.len()).writer())
+                    .map_err(RaftLogStoreError::encode_error)
+                    .unwrap();
+                encoder
+                    .write(&self.raw[..])
+                    .map_err(RaftLogStoreError::encode_error)
+                    .unwrap();
+                let (writer, result) = encoder.finish();
+                result.map_err(RaftLogStoreError::encode_error).unwrap();
+                writer.into_inner()
+            }
+            CompressionAlgorithm::Zstd { level } => zstd::bulk::compress(&self.raw[..], level)
                 .map_err(RaftLogStoreError::encode_error)
-                .unwrap();
-            let (writer, result) = encoder.finish();
-            result.map_err(RaftLogStoreError::encode_error).unwrap();
-            writer.into_inner()
+                .unwrap(),
         };
-        CompressionAlgorithm::Lz4.encode(&mut buf);
+        compression_algorithm.encode(&mut buf);
         let checksum = crc32sum(&buf);
         buf.put_u32_le(checksum);
         self.data = buf;
@@ -270,36 +358,106 @@ impl RaftLogBatch {
         let buf = match compression {
             CompressionAlgorithm::None => buf.to_vec(),
             CompressionAlgorithm::Lz4 => {
-                let mut decoder = Decoder::new(buf.reader())
-                    .map_err(RaftLogStoreError::decode_error)
-                    .unwrap();
+                let mut decoder =
+                    Decoder::new(buf.reader()).map_err(RaftLogStoreError::decode_error)?;
                 let mut decoded = Vec::with_capacity(buf.len());
                 decoder
                     .read_to_end(&mut decoded)
-                    .map_err(RaftLogStoreError::decode_error)
-                    .unwrap();
+                    .map_err(RaftLogStoreError::decode_error)?;
                 decoded
             }
+            CompressionAlgorithm::Zstd { .. } => {
+                zstd::stream::decode_all(buf).map_err(RaftLogStoreError::decode_error)?
+            }
         };
         Ok(buf)
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct RaftLogBatchBuilderOptions {
+    /// Max total bytes of entry data and ctxs of a batch, see [`RaftLogBatch::size`].
+    ///
+    /// An entry larger than it is built into a batch of its own.
+    pub max_batch_size: usize,
+    /// Max entry count of a batch.
+    pub max_batch_len: usize,
+    /// Compression algorithm of the data segments of batches. The algorithm is recorded in each
+    /// data segment, so batches with different algorithms can be mixed in the log.
+    pub compression_algorithm: CompressionAlgorithm,
+}
+
+impl Default for RaftLogBatchBuilderOptions {
+    fn default() -> Self {
+        Self {
+            max_batch_size: usize::MAX,
+            max_batch_len: usize::MAX,
+            compression_algorithm: CompressionAlgorithm::Lz4,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct RaftLogBatchBuilder {
+    options: RaftLogBatchBuilderOptions,
     pub current: RaftLogBatch,
+    /// Size of the current batch.
+    current_size: usize,
+    /// `(group, term, index)` of the last added entry.
+    last: Option<(u64, u64, u64)>,
     pub batches: Vec<RaftLogBatch>,
 }
 
 impl RaftLogBatchBuilder {
-    pub fn add(&mut self, group: u64, term: u64, index: u64, ctx: &[u8], data: &[u8]) {
+    pub fn new(options: RaftLogBatchBuilderOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Add a raft log entry.
+    ///
+    /// Entries of a group added in a row must have contiguous indices and non-decreasing terms,
+    /// otherwise [`RaftLogStoreError::InvalidBatch`] is returned and the entry is not added. An
+    /// entry of another group starts a new batch.
+    pub fn add(
+        &mut self,
+        group: u64,
+        term: u64,
+        index: u64,
+        ctx: &[u8],
+        data: &[u8],
+    ) -> Result<()> {
         // TODO: For adaptation with openraft, which test suits has log entry with both term and
         // index equals 0.
         // debug_assert_ne!(group, 0);
         // debug_assert_ne!(term, 0);
         // debug_assert_ne!(index, 0);
 
-        self.may_rotate(group, term, index);
+        if let Some((last_group, last_term, last_index)) = self.last {
+            if last_group == group && term < last_term {
+                return Err(RaftLogStoreError::InvalidBatch {
+                    reason: format!(
+                        "term of group {} decreases from {} to {} at index {}",
+                        group, last_term, term, index
+                    ),
+                }
+                .into());
+            }
+            if last_group == group && index != last_index + 1 {
+                return Err(RaftLogStoreError::InvalidBatch {
+                    reason: format!(
+                        "index of group {} is not contiguous: {} after {}",
+                        group, index, last_index
+                    ),
+                }
+                .into());
+            }
+        }
+        self.last = Some((group, term, index));
+
+        self.may_rotate(group, term, index, ctx.len() + data.len());
 
         if self.current.offsets.is_empty() {
             self.current.group = group;
@@ -309,30 +467,36 @@ impl RaftLogBatchBuilder {
         self.current.offsets.push(self.current.raw.len());
         self.current.ctxs.push(ctx.to_vec());
         self.current.raw.put_slice(data);
+        self.current_size += ctx.len() + data.len();
+        Ok(())
     }
 
     /// Build [`RaftLogBatch`]s.
     pub fn build(mut self) -> Vec<RaftLogBatch> {
-        self.may_rotate(0, 0, 0);
+        self.may_rotate(0, 0, 0, 0);
         for batch in self.batches.iter_mut() {
-            batch.encode_data();
+            batch.encode_data(self.options.compression_algorithm);
         }
         self.batches
     }
 
-    fn may_rotate(&mut self, group: u64, term: u64, index: u64) {
+    /// Rotate the current batch if the next entry of `size` bytes cannot be added to it.
+    fn may_rotate(&mut self, group: u64, term: u64, index: u64, size: usize) {
         if self.current.offsets.is_empty() {
             return;
         }
         if self.current.group != group
             || self.current.term != term
             || self.current.first_index + self.current.offsets.len() as u64 != index
+            || self.current.offsets.len() >= self.options.max_batch_len
+            || self.current_size.saturating_add(size) > self.options.max_batch_size
         {
             // Phantom offset.
             self.current.offsets.push(self.current.raw.len());
             let mut current = RaftLogBatch::default();
             std::mem::swap(&mut self.current, &mut current);
             self.batches.push(current);
+            self.current_size = 0;
         }
     }
 }
@@ -438,6 +602,44 @@ impl Kv {
     }
 }
 
+/// [`Snapshot`] replaces all kv pairs of the group, and resets its raft log to start from
+/// `first_index`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Snapshot {
+    pub group: u64,
+    pub first_index: u64,
+    pub kvs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Snapshot {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_u64_le(self.group);
+        buf.put_u64_le(self.first_index);
+        buf.put_u64_le(self.kvs.len() as u64);
+        for (key, value) in self.kvs.iter() {
+            put_length_prefixed_slice(buf, key);
+            put_length_prefixed_slice(buf, value);
+        }
+    }
+
+    pub fn decode(buf: &mut &[u8]) -> Self {
+        let group = buf.get_u64_le();
+        let first_index = buf.get_u64_le();
+        let len = buf.get_u64_le() as usize;
+        let mut kvs = Vec::with_capacity(len);
+        for _ in 0..len {
+            let key = get_length_prefixed_slice(buf);
+            let value = get_length_prefixed_slice(buf);
+            kvs.push((key, value));
+        }
+        Self {
+            group,
+            first_index,
+            kvs,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -451,17 +653,17 @@ mod tests {
         let dataset: Vec<(u64, u64, u64, &'static [u8], &'static [u8])> = vec![
             (1, 1, 1, b"aaa", b"data-1-1-1"),
             (1, 1, 2, b"", b"data-1-1-2"),
-            (1, 2, 1, b"ccc", b"data-1-2-1"),
-            (1, 2, 2, b"ddd", b"data-1-2-2"),
+            (1, 2, 3, b"ccc", b"data-1-2-3"),
+            (1, 2, 4, b"ddd", b"data-1-2-4"),
             (2, 1, 1, b"", b"data-2-1-1"),
             (2, 1, 2, b"fff", b"data-2-1-2"),
-            (2, 2, 1, b"", b"data-2-2-1"),
-            (2, 2, 2, b"", b"data-2-2-2"),
+            (2, 2, 3, b"", b"data-2-2-3"),
+            (2, 2, 4, b"", b"data-2-2-4"),
         ];
 
         let mut builder = RaftLogBatchBuilder::default();
         for (group, term, index, ctx, data) in dataset {
-            builder.add(group, term, index, ctx, data);
+            builder.add(group, term, index, ctx, data).unwrap();
         }
         let batches = builder.build();
         assert_eq!(batches.len(), 4);
@@ -480,6 +682,14 @@ mod tests {
                 group: 1,
                 key: b"some-key".to_vec(),
             }),
+            Entry::Snapshot(Snapshot {
+                group: 1,
+                first_index: 10,
+                kvs: vec![
+                    (b"k1".to_vec(), b"v1".to_vec()),
+                    (b"k2".to_vec(), b"".to_vec()),
+                ],
+            }),
         ];
 
         let mut buf = vec![];
@@ -493,4 +703,134 @@ mod tests {
         }
         assert_eq!(decoded_logs, logs);
     }
+
+    #[test]
+    fn test_batch_validation() {
+        let is_invalid_batch = |r: Result<()>| {
+            matches!(
+                r,
+                Err(crate::Error::RaftLogStoreError(
+                    RaftLogStoreError::InvalidBatch { .. }
+                ))
+            )
+        };
+
+        let mut builder = RaftLogBatchBuilder::default();
+        builder.add(1, 2, 1, b"", b"data-1-2-1").unwrap();
+        builder.add(1, 2, 2, b"", b"data-1-2-2").unwrap();
+        // Gap.
+        assert!(is_invalid_batch(builder.add(1, 2, 4, b"", b"data-1-2-4")));
+        // Rewind.
+        assert!(is_invalid_batch(builder.add(1, 2, 2, b"", b"data-1-2-2")));
+        // Lower term.
+        assert!(is_invalid_batch(builder.add(1, 1, 3, b"", b"data-1-1-3")));
+        // Higher term.
+        builder.add(1, 3, 3, b"", b"data-1-3-3").unwrap();
+        // Another group.
+        builder.add(2, 1, 1, b"", b"data-2-1-1").unwrap();
+
+        let batches = builder.build();
+        assert_eq!(
+            batches
+                .iter()
+                .map(|batch| (
+                    batch.group(),
+                    batch.term(),
+                    batch.first_index(),
+                    batch.len()
+                ))
+                .collect::<Vec<_>>(),
+            vec![(1, 2, 1, 2), (1, 3, 3, 1), (2, 1, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_entry_record() {
+        let entries = vec![
+            Entry::Truncate(Truncate {
+                group: 1,
+                index: 10,
+            }),
+            Entry::Kv(Kv::Put {
+                group: 1,
+                key: b"some-key".to_vec(),
+                value: b"some-value".to_vec(),
+            }),
+        ];
+        let mut buf = vec![];
+        for entry in entries.iter() {
+            let (offset, len) = entry.encode_record(&mut buf);
+            assert_eq!(Entry::decode(&mut &buf[offset..offset + len]), *entry);
+        }
+
+        let mut cursor = &buf[..];
+        for entry in entries.iter() {
+            assert_eq!(Entry::decode_record(&mut cursor).as_ref(), Some(entry));
+        }
+        assert!(cursor.is_empty());
+
+        // Torn tail.
+        let mut cursor = &buf[..buf.len() - 3];
+        assert_eq!(Entry::decode_record(&mut cursor), Some(entries[0].clone()));
+        let len = cursor.len();
+        assert_eq!(Entry::decode_record(&mut cursor), None);
+        assert_eq!(cursor.len(), len);
+        assert!(!Entry::is_record_complete(cursor));
+        assert!(!Entry::is_record_complete(&cursor[..4]));
+
+        // Corrupted tail.
+        let last = buf.len() - 1;
+        buf[last] ^= 0xff;
+        let mut cursor = &buf[..];
+        assert_eq!(Entry::decode_record(&mut cursor), Some(entries[0].clone()));
+        assert_eq!(Entry::decode_record(&mut cursor), None);
+        assert!(Entry::is_record_complete(cursor));
+
+        // Zeroed tail.
+        let mut cursor = &[0; 16][..];
+        assert_eq!(Entry::decode_record(&mut cursor), None);
+    }
+
+    #[test]
+    fn test_batch_cap() {
+        let mut builder = RaftLogBatchBuilder::new(RaftLogBatchBuilderOptions {
+            max_batch_size: 100,
+            max_batch_len: 4,
+            ..Default::default()
+        });
+        let mut expected = vec![];
+        for index in 1..=32 {
+            let data = vec![b'x'; index as usize % 7 * 5];
+            builder.add(1, 1, index, b"ctx", &data).unwrap();
+            expected.push((index, data));
+        }
+        // Entry larger than the cap.
+        let data = vec![b'y'; 200];
+        builder.add(1, 1, 33, b"ctx", &data).unwrap();
+        expected.push((33, data));
+        builder.add(1, 1, 34, b"", b"z").unwrap();
+        expected.push((34, b"z".to_vec()));
+
+        let batches = builder.build();
+        assert!(batches.len() > 1);
+        let mut entries = vec![];
+        for batch in batches {
+            assert!(batch.len() <= 4);
+            assert!(batch.size() <= 100 || batch.len() == 1);
+            assert_eq!(batch.first_index(), entries.len() as u64 + 1);
+            let data = RaftLogBatch::extract_data_segment(&batch.data).unwrap();
+            assert_eq!(
+                batch.size(),
+                data.len() + batch.ctxs.iter().map(|ctx| ctx.len()).sum::<usize>()
+            );
+            for i in 0..batch.len() {
+                let (offset, len) = batch.location(i);
+                entries.push((
+                    batch.first_index() + i as u64,
+                    data[offset..offset + len].to_vec(),
+                ));
+            }
+        }
+        assert_eq!(entries, expected);
+    }
 }
@@ -10,8 +10,20 @@ pub enum RaftLogStoreError {
     DecodeError(String),
     #[error("checksum mismatch: [expected: {expected}] [get: {get}]")]
     ChecksumMismatch { expected: u32, get: u32 },
+    #[error("corrupted entry in log file {file_id} at offset {offset}: {reason}")]
+    CorruptedEntry {
+        file_id: u64,
+        offset: u64,
+        reason: String,
+    },
     #[error("io error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("group {0} is locked by a concurrent operation")]
+    GroupLocked(u64),
+    #[error("invalid raft log batch: {reason}")]
+    InvalidBatch { reason: String },
+    #[error("kv versions of group {group} at {ts} are compacted: [min ts: {min_ts}]")]
+    KvVersionCompacted { group: u64, ts: u64, min_ts: u64 },
     #[error("raft log gap exists: [{start}, {end})")]
     RaftLogGap { start: u64, end: u64 },
     #[error("other: {0}")]
@@ -1,34 +1,60 @@
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
+use bytes::{Buf, BufMut};
 use futures_async_stream::try_stream;
 use itertools::Itertools;
-use tokio::fs::{create_dir_all, read_dir, File, OpenOptions};
+use tokio::fs::{create_dir_all, read_dir, remove_file, File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tokio::sync::Mutex;
-use tracing::trace;
+use tracing::{trace, warn};
 
-use super::entry::Entry;
+use super::entry::{Entry, ENTRY_RECORD_HEADER_LEN};
 use super::error::RaftLogStoreError;
 use super::DEFAULT_LOG_BATCH_SIZE;
 use crate::error::Result;
 
+/// Magic number at the head of each log file.
+const LOG_FILE_MAGIC: u32 = 0x524b_4c47;
+/// Version of the log file format.
+const LOG_FILE_VERSION: u32 = 1;
+/// Length of the log file header.
+///
+/// Format:
+///
+/// ```plain
+/// | magic (4B) | version (4B) |
+/// ```
+pub const LOG_FILE_HEADER_LEN: usize = 8;
+
 #[derive(Clone, Debug)]
 pub struct LogOptions {
     pub path: String,
     pub log_file_capacity: usize,
+    /// Pushes within the window share a single sync. Zero means sync immediately.
+    pub group_commit_window: Duration,
 }
 
 struct LogCore {
     active_file: File,
     frozen_files: Vec<File>,
     first_log_file_id: u64,
+    /// Sequence of the last write.
+    written_seq: u64,
 }
 
 pub struct Log {
     path: String,
     log_file_capacity: usize,
+    group_commit_window: Duration,
     core: Mutex<LogCore>,
+    /// Sequence of the last durable write.
+    synced_seq: Mutex<u64>,
+    sync_count: AtomicUsize,
+    /// Length of the torn tail truncated on replay.
+    truncated_len: AtomicUsize,
 }
 
 impl Log {
@@ -80,12 +106,17 @@ impl Log {
             active_file,
             frozen_files,
             first_log_file_id,
+            written_seq: 0,
         };
 
         Ok(Self {
             core: Mutex::new(core),
             path: options.path,
             log_file_capacity: options.log_file_capacity,
+            group_commit_window: options.group_commit_window,
+            synced_seq: Mutex::new(0),
+            sync_count: AtomicUsize::new(0),
+            truncated_len: AtomicUsize::new(0),
         })
     }
 
@@ -96,20 +127,78 @@ impl Log {
     }
 
     /// Push [`entry`] to log file. Returns `(file id, offset, len)`.
+    ///
+    /// Returns after the entry is durable.
     pub async fn push(&self, entry: Entry) -> Result<(u64, usize, usize)> {
+        let locations = self.push_many(vec![entry]).await?;
+        Ok(locations[0])
+    }
+
+    /// Push [`entries`] to log file with a single write. Returns `(file id, offset, len)` of each
+    /// entry.
+    ///
+    /// Returns after the entries are durable.
+    pub async fn push_many(&self, entries: Vec<Entry>) -> Result<Vec<(u64, usize, usize)>> {
         let mut guard = self.core.lock().await;
         let file_id = guard.first_log_file_id + guard.frozen_files.len() as u64;
         let start = guard.active_file.metadata().await?.len() as usize;
-        let mut buf = Vec::with_capacity(DEFAULT_LOG_BATCH_SIZE);
-        entry.encode(&mut buf);
+        let mut buf = Vec::with_capacity(DEFAULT_LOG_BATCH_SIZE * entries.len());
+        let mut locations = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let (offset, len) = entry.encode_record(&mut buf);
+            locations.push((file_id, start + offset, len));
+        }
         guard.active_file.write_all(&buf).await?;
-        guard.active_file.sync_data().await?;
+        guard.written_seq += 1;
+        let seq = guard.written_seq;
         let end = guard.active_file.metadata().await?.len() as usize;
+        drop(guard);
         if end >= self.log_file_capacity {
-            drop(guard);
             self.rotate().await?;
         }
-        Ok((file_id, start, end - start))
+        self.wait_synced(seq).await?;
+        Ok(locations)
+    }
+
+    /// Wait until the write of `seq` is durable.
+    ///
+    /// Only one sync is in flight at a time. The syncing caller waits for the group commit window
+    /// first, so that writes of concurrent callers can be synced together.
+    async fn wait_synced(&self, seq: u64) -> Result<()> {
+        let mut synced_seq = self.synced_seq.lock().await;
+        if *synced_seq >= seq {
+            return Ok(());
+        }
+        if !self.group_commit_window.is_zero() {
+            tokio::time::sleep(self.group_commit_window).await;
+        }
+        // Writes of rotated files are synced when rotating, only the active file needs syncing.
+        let guard = self.core.lock().await;
+        let written_seq = guard.written_seq;
+        guard.active_file.sync_data().await?;
+        drop(guard);
+        self.sync_count.fetch_add(1, Ordering::Relaxed);
+        *synced_seq = written_seq;
+        Ok(())
+    }
+
+    /// Flush and sync the active log file immediately, regardless of the group commit window.
+    ///
+    /// Once it returns, all writes of previously returned pushes are durable.
+    pub async fn sync(&self) -> Result<()> {
+        let mut synced_seq = self.synced_seq.lock().await;
+        let mut guard = self.core.lock().await;
+        let written_seq = guard.written_seq;
+        guard.active_file.flush().await?;
+        guard.active_file.sync_all().await?;
+        drop(guard);
+        *synced_seq = written_seq;
+        Ok(())
+    }
+
+    /// Count of syncs issued by pushes.
+    pub fn sync_count(&self) -> usize {
+        self.sync_count.load(Ordering::Relaxed)
     }
 
     pub async fn read(&self, log_file_id: u64, offset: u64, len: usize) -> Result<Vec<u8>> {
@@ -126,7 +215,19 @@ impl Log {
         Ok(buf)
     }
 
+    /// Length of the torn tail truncated on replay.
+    pub fn truncated_len(&self) -> usize {
+        self.truncated_len.load(Ordering::Relaxed)
+    }
+
     /// Yield [`(file id, offset, Entry)`] of all frozen logs in order.
+    ///
+    /// A record that runs past the end of the last frozen log is regarded as a torn write, the log
+    /// file is truncated before it and replay stops. Any other record that fails decoding is
+    /// reported as [`RaftLogStoreError::CorruptedEntry`], and nothing is truncated.
+    ///
+    /// Log files without a valid header, e.g. written in an older format, are refused with
+    /// [`RaftLogStoreError::DecodeError`].
     // TODO: Remove clippy exception. Currently clippy reports `needless_lifetime` with
     // `try_stream`.
     #[allow(clippy::needless_lifetimes)]
@@ -147,11 +248,47 @@ impl Log {
             buf.clear();
             guard.frozen_files[i].read_to_end(&mut buf).await?;
             drop(guard);
-            let cursor = &mut &buf[..];
+            let is_last = current_log_file_id + 1 == end_log_file_id;
+            if !Self::verify_header(current_log_file_id, &buf)? {
+                // The log file is created but its header is not fully written.
+                if !buf.is_empty() {
+                    if !is_last {
+                        return Err(RaftLogStoreError::CorruptedEntry {
+                            file_id: current_log_file_id,
+                            offset: 0,
+                            reason: "incomplete log file header".to_string(),
+                        });
+                    }
+                    self.truncate_torn_tail(current_log_file_id, 0).await?;
+                    self.truncated_len.store(buf.len(), Ordering::Relaxed);
+                }
+                continue;
+            }
+            let cursor = &mut &buf[LOG_FILE_HEADER_LEN..];
             while !cursor.is_empty() {
                 let offset = buf.len() - cursor.len();
-                let entry = Entry::decode(cursor);
-                yield (current_log_file_id, offset, entry);
+                let entry = match Entry::decode_record(cursor) {
+                    Some(entry) => entry,
+                    None => {
+                        let complete = Entry::is_record_complete(cursor);
+                        if !is_last || complete {
+                            return Err(RaftLogStoreError::CorruptedEntry {
+                                file_id: current_log_file_id,
+                                offset: offset as u64,
+                                reason: if complete {
+                                    "invalid record length or checksum mismatch".to_string()
+                                } else {
+                                    "incomplete record".to_string()
+                                },
+                            });
+                        }
+                        self.truncate_torn_tail(current_log_file_id, offset).await?;
+                        self.truncated_len
+                            .store(buf.len() - offset, Ordering::Relaxed);
+                        break;
+                    }
+                };
+                yield (current_log_file_id, offset + ENTRY_RECORD_HEADER_LEN, entry);
             }
         }
     }
@@ -159,6 +296,39 @@ impl Log {
     pub async fn frozen_file_count(&self) -> usize {
         self.core.lock().await.frozen_files.len()
     }
+
+    /// Id of the first log file.
+    pub async fn first_file_id(&self) -> u64 {
+        self.core.lock().await.first_log_file_id
+    }
+
+    /// Id of the active log file.
+    pub async fn active_file_id(&self) -> u64 {
+        let guard = self.core.lock().await;
+        guard.first_log_file_id + guard.frozen_files.len() as u64
+    }
+
+    /// Delete frozen log files whose ids are less than `file_id`. The active log file is never
+    /// deleted. Returns the count of deleted log files.
+    ///
+    /// Files are deleted from the oldest, so the remaining log files are always continuous.
+    pub async fn delete_files_before(&self, file_id: u64) -> Result<usize> {
+        let mut guard = self.core.lock().await;
+        let mut deleted = 0;
+        while !guard.frozen_files.is_empty() && guard.first_log_file_id < file_id {
+            let log_file_id = guard.first_log_file_id;
+            remove_file(Path::new(&self.path).join(Self::filename(log_file_id))).await?;
+            guard.frozen_files.remove(0);
+            guard.first_log_file_id += 1;
+            deleted += 1;
+            trace!("delete log file {}", Self::filename(log_file_id));
+        }
+        drop(guard);
+        if deleted > 0 {
+            self.sync_dir().await?;
+        }
+        Ok(deleted)
+    }
 }
 
 impl Log {
@@ -183,6 +353,52 @@ impl Log {
         Ok(())
     }
 
+    /// Verify the header of a log file. Returns `false` if the header is incomplete, which is a
+    /// prefix of a valid header.
+    fn verify_header(log_file_id: u64, buf: &[u8]) -> std::result::Result<bool, RaftLogStoreError> {
+        let header = Self::header();
+        if buf.len() < LOG_FILE_HEADER_LEN && buf == &header[..buf.len()] {
+            return Ok(false);
+        }
+        if buf.len() < LOG_FILE_HEADER_LEN || buf[..4] != header[..4] {
+            return Err(RaftLogStoreError::DecodeError(format!(
+                "log file {} has no valid header, it may be written in an older format",
+                Self::filename(log_file_id)
+            )));
+        }
+        let version = (&buf[4..LOG_FILE_HEADER_LEN]).get_u32_le();
+        if version != LOG_FILE_VERSION {
+            return Err(RaftLogStoreError::DecodeError(format!(
+                "log file {} has unsupported format version {}",
+                Self::filename(log_file_id),
+                version
+            )));
+        }
+        Ok(true)
+    }
+
+    fn header() -> [u8; LOG_FILE_HEADER_LEN] {
+        let mut header = [0; LOG_FILE_HEADER_LEN];
+        (&mut header[..4]).put_u32_le(LOG_FILE_MAGIC);
+        (&mut header[4..]).put_u32_le(LOG_FILE_VERSION);
+        header
+    }
+
+    async fn truncate_torn_tail(&self, log_file_id: u64, len: usize) -> std::io::Result<()> {
+        warn!(
+            "torn write found in log file {} at offset {}, truncate",
+            Self::filename(log_file_id),
+            len
+        );
+        let file = OpenOptions::new()
+            .write(true)
+            .open(Path::new(&self.path).join(Self::filename(log_file_id)))
+            .await?;
+        file.set_len(len as u64).await?;
+        file.sync_all().await?;
+        Ok(())
+    }
+
     fn filename(id: u64) -> String {
         format!("{:08}", id)
     }
@@ -197,9 +413,12 @@ impl Log {
         active_file_open_options.create(true);
         active_file_open_options.read(true);
         active_file_open_options.append(true);
-        let file = active_file_open_options
+        let mut file = active_file_open_options
             .open(path.as_ref().join(Self::filename(active_file_id)))
             .await?;
+        if file.metadata().await?.len() == 0 {
+            file.write_all(&Self::header()).await?;
+        }
         Ok(file)
     }
 }
@@ -208,6 +427,7 @@ pub type LogRef = Arc<Log>;
 
 #[cfg(test)]
 mod tests {
+    use futures_async_stream::for_await;
     use test_log::test;
 
     use super::*;
@@ -220,6 +440,7 @@ mod tests {
             path: tempdir.path().to_str().unwrap().to_string(),
             // Estimated size of each compressed entry is 111.
             log_file_capacity: 100,
+            group_commit_window: Duration::ZERO,
         };
         let log = Log::open(options.clone()).await.unwrap();
         let entries = generate_entries(4, 16, vec![b'x'; 64]);
@@ -231,15 +452,17 @@ mod tests {
         assert_eq!(log.core.lock().await.frozen_files.len(), 4);
         let mut buf = vec![];
         for i in 0..4 {
+            let mut file_buf = vec![];
             log.core.lock().await.frozen_files[i]
-                .read_to_end(&mut buf)
+                .read_to_end(&mut file_buf)
                 .await
                 .unwrap();
+            buf.extend_from_slice(&file_buf[LOG_FILE_HEADER_LEN..]);
         }
         let mut buf = &buf[..];
         let decoded_entries = (0..4)
             .into_iter()
-            .map(|_| Entry::decode(&mut buf))
+            .map(|_| Entry::decode_record(&mut buf).unwrap())
             .collect_vec();
         assert_eq!(decoded_entries, entries);
         log.close().await.unwrap();
@@ -250,26 +473,81 @@ mod tests {
         assert_eq!(log.core.lock().await.frozen_files.len(), 5);
         let mut buf = vec![];
         for i in 0..4 {
+            let mut file_buf = vec![];
             log.core.lock().await.frozen_files[i]
-                .read_to_end(&mut buf)
+                .read_to_end(&mut file_buf)
                 .await
                 .unwrap();
+            buf.extend_from_slice(&file_buf[LOG_FILE_HEADER_LEN..]);
         }
         let mut buf = &buf[..];
         let decoded_entries = (0..4)
             .into_iter()
-            .map(|_| Entry::decode(&mut buf))
+            .map(|_| Entry::decode_record(&mut buf).unwrap())
             .collect_vec();
         assert_eq!(decoded_entries, entries);
     }
 
+    #[test(tokio::test(flavor = "multi_thread"))]
+    async fn test_group_commit() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = LogOptions {
+            path: tempdir.path().to_str().unwrap().to_string(),
+            log_file_capacity: 64 << 20,
+            group_commit_window: Duration::from_millis(50),
+        };
+        let log = Arc::new(Log::open(options.clone()).await.unwrap());
+        let entries = generate_entries(64, 1, vec![b'x'; 64]);
+
+        // Hold back syncing until all pushes are written, so that they share a single sync
+        // regardless of scheduling.
+        let synced_seq = log.synced_seq.lock().await;
+        let handles = entries
+            .iter()
+            .cloned()
+            .map(|entry| {
+                let log = log.clone();
+                tokio::spawn(async move { log.push(entry).await.unwrap() })
+            })
+            .collect_vec();
+        while log.core.lock().await.written_seq < entries.len() as u64 {
+            tokio::task::yield_now().await;
+        }
+        drop(synced_seq);
+        let mut locations = vec![];
+        for handle in handles {
+            locations.push(handle.await.unwrap());
+        }
+        assert_eq!(log.sync_count(), 1);
+
+        // Each entry can be read from its location.
+        for (file_id, offset, len) in locations {
+            let buf = log.read(file_id, offset as u64, len).await.unwrap();
+            let entry = Entry::decode(&mut &buf[..]);
+            assert!(entries.contains(&entry));
+        }
+
+        // All entries are recovered.
+        drop(log);
+        let log = Log::open(options).await.unwrap();
+        let mut recovered = vec![];
+        #[for_await]
+        for item in log.replay() {
+            recovered.push(item.unwrap().2);
+        }
+        assert_eq!(recovered.len(), entries.len());
+        for entry in entries {
+            assert!(recovered.contains(&entry));
+        }
+    }
+
     fn generate_entries(groups: usize, group_size: usize, data: Vec<u8>) -> Vec<Entry> {
         let mut builder = RaftLogBatchBuilder::default();
 
         for group in 1..=groups as u64 {
             let term = 1;
             for index in 1..=group_size as u64 {
-                builder.add(group, term, index, b"some-ctx", &data);
+                builder.add(group, term, index, b"some-ctx", &data).unwrap();
             }
         }
         let batches = builder.build();
@@ -1,5 +1,9 @@
-use std::collections::btree_map::{BTreeMap, Entry};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
 
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
 use itertools::Itertools;
 use tokio::sync::RwLock;
 use tracing::trace;
@@ -22,54 +26,207 @@ pub struct EntryIndex {
     pub len: usize,
 }
 
+/// Result of looking up the term of a raft log index.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TermLookup {
+    /// The entry exists with the given term.
+    Present(u64),
+    /// The entry has been compacted, the leader should send a snapshot.
+    Compacted,
+    /// The entry is beyond the last index, the append should be rejected.
+    Future,
+}
+
+/// States of a group besides its raft log entries, which need to be rewritten before the log files
+/// they were written to are deleted.
+#[derive(Clone, Debug)]
+pub struct GroupStateDump {
+    pub group: u64,
+    pub first_index: u64,
+    pub mask_index: u64,
+    pub has_entries: bool,
+    pub kvs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Raft log indices of a group captured by [`MemStates::snapshot`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct GroupSnapshotMeta {
+    pub group: u64,
+    pub first_index: u64,
+    pub next_index: u64,
+    pub mask_index: u64,
+}
+
+/// Versions of a kv pair, `{ timestamp -> value }`, `None` value for deletion.
+type KvVersions = BTreeMap<u64, Option<Vec<u8>>>;
+
 pub struct MemState {
     first_index: u64,
     mask_index: u64,
     indices: Vec<EntryIndex>,
-    kvs: BTreeMap<Vec<u8>, Vec<u8>>,
+    kvs: BTreeMap<Vec<u8>, KvVersions>,
+    /// Count of keys in `kvs` whose latest version is not a deletion.
+    kv_count: usize,
+    /// Timestamp of the latest kv write. Timestamps are assigned in memory, and restart from the
+    /// replayed writes after reopening.
+    kv_ts: u64,
+    /// Versions visible at `kv_watermark` or later are retained. Only the latest versions are
+    /// retained if `None`.
+    kv_watermark: Option<u64>,
 }
 
-pub struct MemStates {
-    /// Mapping [`group`] to [`MemState`].
-    states: RwLock<BTreeMap<u64, RwLock<MemState>>>,
-}
-
-impl Default for MemStates {
-    fn default() -> Self {
+impl MemState {
+    fn new() -> Self {
         Self {
-            states: RwLock::new(BTreeMap::default()),
+            first_index: 0,
+            mask_index: 0,
+            indices: Vec::with_capacity(DEFAULT_INDICES_INIT_CAPACITY),
+            kvs: BTreeMap::default(),
+            kv_count: 0,
+            kv_ts: 0,
+            kv_watermark: None,
+        }
+    }
+
+    /// Min timestamp that kv pairs can be read at.
+    fn kv_min_ts(&self) -> u64 {
+        self.kv_watermark.unwrap_or(self.kv_ts)
+    }
+
+    /// Write a new version of `key` at the next timestamp, `None` value for deletion.
+    fn write_kv(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+        if value.is_none() && !self.kvs.contains_key(&key) {
+            return;
+        }
+        self.kv_ts += 1;
+        let (ts, min_ts) = (self.kv_ts, self.kv_min_ts());
+        let versions = self.kvs.entry(key.clone()).or_default();
+        let was_live = matches!(versions.values().next_back(), Some(Some(_)));
+        match (was_live, value.is_some()) {
+            (false, true) => self.kv_count += 1,
+            (true, false) => self.kv_count -= 1,
+            _ => {}
+        }
+        versions.insert(ts, value);
+        if Self::prune_versions(versions, min_ts) {
+            self.kvs.remove(&key);
+        }
+    }
+
+    /// Remove versions invisible at `min_ts` or later. Returns `true` if no version is left.
+    fn prune_versions(versions: &mut KvVersions, min_ts: u64) -> bool {
+        let mut retained = versions.split_off(&(min_ts + 1));
+        // The latest version at or below `min_ts` is still visible at `min_ts`.
+        if let Some(ts) = versions.keys().next_back().copied() {
+            if let Some(Some(value)) = versions.remove(&ts) {
+                retained.insert(ts, Some(value));
+            }
+        }
+        *versions = retained;
+        versions.is_empty()
+    }
+
+    fn get_kv_at(&self, key: &[u8], ts: u64) -> Option<&Vec<u8>> {
+        self.kvs
+            .get(key)
+            .and_then(|versions| versions.range(..=ts).next_back())
+            .and_then(|(_, value)| value.as_ref())
+    }
+
+    /// Latest kv pairs in `range`, ordered by key.
+    fn latest_kvs<'a>(
+        &'a self,
+        range: (Bound<&'a [u8]>, Bound<&'a [u8]>),
+    ) -> impl std::iter::Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a {
+        self.kvs
+            .range::<[u8], _>(range)
+            .filter_map(|(key, versions)| {
+                versions
+                    .values()
+                    .next_back()
+                    .and_then(|value| value.as_ref())
+                    .map(|value| (key.clone(), value.clone()))
+            })
+    }
+
+    /// Release the spare capacity of `indices` if it greatly exceeds the length, e.g. after a
+    /// group that grew huge is compacted.
+    fn may_shrink(&mut self) {
+        let capacity = self.indices.capacity();
+        if capacity > DEFAULT_INDICES_INIT_CAPACITY && capacity > self.indices.len() * 4 {
+            self.indices.shrink_to(std::cmp::max(
+                self.indices.len() * 2,
+                DEFAULT_INDICES_INIT_CAPACITY,
+            ));
         }
     }
+
+    /// Estimated bytes held by `indices` and `kvs`.
+    fn memory_usage(&self) -> usize {
+        self.indices.capacity() * std::mem::size_of::<EntryIndex>()
+            + self
+                .indices
+                .iter()
+                .map(|index| index.ctx.capacity())
+                .sum::<usize>()
+            + self
+                .kvs
+                .iter()
+                .map(|(key, versions)| {
+                    key.capacity()
+                        + versions
+                            .values()
+                            .map(|value| {
+                                std::mem::size_of::<u64>()
+                                    + value.as_ref().map_or(0, |value| value.capacity())
+                            })
+                            .sum::<usize>()
+                })
+                .sum::<usize>()
+    }
+}
+
+#[derive(Default)]
+pub struct MemStates {
+    /// Mapping [`group`] to [`MemState`].
+    ///
+    /// Only a shard of the map is locked briefly to look up or add a group, the group is locked
+    /// after the shard lock is released. So operations on a group never block the ones on other
+    /// groups.
+    states: DashMap<u64, Arc<RwLock<MemState>>>,
 }
 
 impl MemStates {
+    fn state(&self, group: u64) -> Result<Arc<RwLock<MemState>>> {
+        self.states
+            .get(&group)
+            .map(|state| state.value().clone())
+            .ok_or_else(|| RaftLogStoreError::GroupNotExists(group).into())
+    }
+
+    /// States of all groups, in no particular order.
+    fn all_states(&self) -> Vec<(u64, Arc<RwLock<MemState>>)> {
+        self.states
+            .iter()
+            .map(|state| (*state.key(), state.value().clone()))
+            .collect()
+    }
+
     pub async fn add_group(&self, group: u64) -> Result<()> {
-        let mut guard = self.states.write().await;
-        match guard.entry(group) {
+        match self.states.entry(group) {
             Entry::Occupied(_) => return Err(RaftLogStoreError::GroupAlreadyExists(group).into()),
             Entry::Vacant(v) => {
-                v.insert(RwLock::new(MemState {
-                    first_index: 0,
-                    mask_index: 0,
-                    indices: Vec::with_capacity(DEFAULT_INDICES_INIT_CAPACITY),
-                    kvs: BTreeMap::default(),
-                }));
+                v.insert(Arc::new(RwLock::new(MemState::new())));
             }
         }
         Ok(())
     }
 
     pub async fn may_add_group(&self, group: u64) -> bool {
-        let mut guard = self.states.write().await;
-        match guard.entry(group) {
+        match self.states.entry(group) {
             Entry::Occupied(_) => false,
             Entry::Vacant(v) => {
-                v.insert(RwLock::new(MemState {
-                    first_index: 0,
-                    mask_index: 0,
-                    indices: Vec::with_capacity(DEFAULT_INDICES_INIT_CAPACITY),
-                    kvs: BTreeMap::default(),
-                }));
+                v.insert(Arc::new(RwLock::new(MemState::new())));
                 true
             }
         }
@@ -79,26 +236,80 @@ impl MemStates {
     ///
     /// Removed group needs to be guaranteed never be used again.
     pub async fn remove_group(&self, group: u64) -> Result<()> {
-        let mut guard = self.states.write().await;
-        match guard.entry(group) {
-            Entry::Occupied(o) => {
-                let mut state = o.into_mut().write().await;
-                state.first_index = u64::MAX;
-                state.indices.clear();
-                state.kvs.clear();
+        let mut state = self.state(group)?.write_owned().await;
+        state.first_index = u64::MAX;
+        state.indices.clear();
+        state.kvs.clear();
+        state.kv_count = 0;
+        Ok(())
+    }
+
+    /// Min id of the log files that live raft log entries of all groups are in. Returns `None` if
+    /// there is no live raft log entry.
+    pub async fn min_file_id(&self) -> Option<u64> {
+        let mut min_file_id: Option<u64> = None;
+        for (_, state) in self.all_states() {
+            let state = state.read().await;
+            // Rewritten indices may be in newer log files than the following ones.
+            if let Some(file_id) = state.indices.iter().map(|index| index.file_id).min() {
+                min_file_id = Some(min_file_id.map_or(file_id, |min| min.min(file_id)));
             }
-            Entry::Vacant(_) => return Err(RaftLogStoreError::GroupNotExists(group).into()),
         }
-        Ok(())
+        min_file_id
+    }
+
+    /// Dump states of all groups besides raft log entries. Removed groups are skipped.
+    pub async fn dump(&self) -> Vec<GroupStateDump> {
+        let mut states = self.all_states();
+        states.sort_by_key(|(group, _)| *group);
+        let mut dumps = Vec::with_capacity(states.len());
+        for (group, state) in states {
+            let state = state.read().await;
+            if state.first_index == u64::MAX {
+                continue;
+            }
+            dumps.push(GroupStateDump {
+                group,
+                first_index: state.first_index,
+                mask_index: state.mask_index,
+                has_entries: !state.indices.is_empty(),
+                kvs: state
+                    .latest_kvs((Bound::Unbounded, Bound::Unbounded))
+                    .collect(),
+            });
+        }
+        dumps
+    }
+
+    /// Estimated bytes held by raft log indices and kv pairs of `group`.
+    pub async fn memory_usage(&self, group: u64) -> Result<usize> {
+        let state = self.state(group)?.read_owned().await;
+        Ok(state.memory_usage())
+    }
+
+    /// Capture the raft log indices of `groups` at a single point in time.
+    ///
+    /// Read locks of all the groups are held until all of them are captured, so writers of the
+    /// groups are blocked briefly.
+    pub async fn snapshot(&self, groups: &[u64]) -> Result<Vec<GroupSnapshotMeta>> {
+        let mut states = Vec::with_capacity(groups.len());
+        for group in groups {
+            let state = self.state(*group)?.read_owned().await;
+            states.push((*group, state));
+        }
+        Ok(states
+            .iter()
+            .map(|(group, state)| GroupSnapshotMeta {
+                group: *group,
+                first_index: state.first_index,
+                next_index: state.first_index + state.indices.len() as u64,
+                mask_index: state.mask_index,
+            })
+            .collect())
     }
 
     pub async fn term(&self, group: u64, index: u64) -> Result<Option<u64>> {
-        let guard = self.states.read().await;
-        let state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .read()
-            .await;
+        let state = self.state(group)?.read_owned().await;
         if index < state.first_index || index >= state.first_index + state.indices.len() as u64 {
             Ok(None)
         } else {
@@ -108,13 +319,21 @@ impl MemStates {
         }
     }
 
+    /// Like [`MemStates::term`], but distinguishes compacted entries from entries not appended yet.
+    pub async fn lookup_term(&self, group: u64, index: u64) -> Result<TermLookup> {
+        let state = self.state(group)?.read_owned().await;
+        if index < state.first_index {
+            Ok(TermLookup::Compacted)
+        } else if index >= state.first_index + state.indices.len() as u64 {
+            Ok(TermLookup::Future)
+        } else {
+            let i = (index - state.first_index) as usize;
+            Ok(TermLookup::Present(state.indices[i].term))
+        }
+    }
+
     pub async fn ctx(&self, group: u64, index: u64) -> Result<Option<Vec<u8>>> {
-        let guard = self.states.read().await;
-        let state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .read()
-            .await;
+        let state = self.state(group)?.read_owned().await;
         if index < state.first_index || index >= state.first_index + state.indices.len() as u64 {
             Ok(None)
         } else {
@@ -129,12 +348,7 @@ impl MemStates {
         group: u64,
         unmask: bool,
     ) -> Result<core::result::Result<u64, u64>> {
-        let guard = self.states.read().await;
-        let state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .read()
-            .await;
+        let state = self.state(group)?.read_owned().await;
 
         let index = if unmask {
             state.first_index
@@ -154,12 +368,7 @@ impl MemStates {
         group: u64,
         unmask: bool,
     ) -> Result<core::result::Result<u64, u64>> {
-        let guard = self.states.read().await;
-        let state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .read()
-            .await;
+        let state = self.state(group)?.read_owned().await;
 
         let index = if unmask {
             state.first_index
@@ -175,20 +384,15 @@ impl MemStates {
         }
     }
 
-    /// Append raft log indices.
+    /// Append raft log indices. Returns the count of outdated indices ignored.
     pub async fn append(
         &self,
         group: u64,
         mut first_index: u64,
         mut indices: Vec<EntryIndex>,
-    ) -> Result<()> {
+    ) -> Result<usize> {
         debug_assert!(!indices.is_empty());
-        let guard = self.states.read().await;
-        let mut state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .write()
-            .await;
+        let mut state = self.state(group)?.write_owned().await;
 
         let mut state_next_index = state.first_index + state.indices.len() as u64;
 
@@ -207,11 +411,13 @@ impl MemStates {
         }
 
         // Ignore outdated indices.
+        let mut outdated = 0;
         if first_index < state.first_index {
-            indices.drain(..(state.first_index - first_index) as usize);
+            outdated = std::cmp::min((state.first_index - first_index) as usize, indices.len());
+            indices.drain(..outdated);
             first_index = state.first_index;
             if indices.is_empty() {
-                return Ok(());
+                return Ok(outdated);
             }
         }
 
@@ -230,23 +436,19 @@ impl MemStates {
 
             // Ignore outdated rewrite indices.
             if state_index.term > index.term {
+                outdated += 1;
                 continue;
             }
 
             *state_index = index.clone();
         }
 
-        Ok(())
+        Ok(outdated)
     }
 
     /// Truncate raft log of given `group` since given `index`.
     pub async fn truncate(&self, group: u64, index: u64) -> Result<()> {
-        let guard = self.states.read().await;
-        let mut state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .write()
-            .await;
+        let mut state = self.state(group)?.write_owned().await;
 
         if index < state.first_index {
             return Err(RaftLogStoreError::RaftLogGap {
@@ -268,18 +470,14 @@ impl MemStates {
 
         let len = (index - state.first_index) as usize;
         state.indices.truncate(len);
+        state.may_shrink();
 
         Ok(())
     }
 
     /// Compact any indices before the given index.
     pub async fn compact(&self, group: u64, index: u64) -> Result<()> {
-        let guard = self.states.read().await;
-        let mut state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .write()
-            .await;
+        let mut state = self.state(group)?.write_owned().await;
 
         trace!("compact log before {} of group {}", index, group);
 
@@ -291,6 +489,7 @@ impl MemStates {
         // If given index is greater than `next_index`, truncate all indices and accepts anyway.
         if index > state.first_index + state.indices.len() as u64 {
             state.indices.clear();
+            state.may_shrink();
             state.first_index = 0;
 
             trace!("first index after compact: {}", state.first_index);
@@ -301,6 +500,7 @@ impl MemStates {
         // Truncate indices.
         let len = (index - state.first_index) as usize;
         state.indices.drain(..len);
+        state.may_shrink();
         state.first_index = index;
 
         trace!("first index after compact: {}", state.first_index);
@@ -308,17 +508,41 @@ impl MemStates {
         Ok(())
     }
 
+    /// Replace all kv pairs of the group with `kvs`, and reset the raft log to start from
+    /// `first_index`. Kv versions before the snapshot are dropped.
+    pub async fn apply_snapshot(
+        &self,
+        group: u64,
+        first_index: u64,
+        kvs: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
+        let mut state = self.state(group)?.write_owned().await;
+
+        trace!("apply snapshot of group {} at {}", group, first_index);
+
+        state.first_index = first_index;
+        state.mask_index = first_index;
+        state.indices.clear();
+        state.kv_ts += 1;
+        let ts = state.kv_ts;
+        state.kvs = kvs
+            .into_iter()
+            .map(|(key, value)| (key, BTreeMap::from([(ts, Some(value))])))
+            .collect();
+        state.kv_count = state.kvs.len();
+        if state.kv_watermark.is_some() {
+            state.kv_watermark = Some(ts);
+        }
+
+        Ok(())
+    }
+
     /// Mask any indices before the given index.
     ///
     /// Masked indices are not deleted from the state, but can only be accessed with `unmask` set to
     /// `true`.
     pub async fn mask(&self, group: u64, index: u64) -> Result<()> {
-        let guard = self.states.read().await;
-        let mut state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .write()
-            .await;
+        let mut state = self.state(group)?.write_owned().await;
 
         trace!("mask log before {} of group {}", index, group);
 
@@ -333,6 +557,11 @@ impl MemStates {
         Ok(())
     }
 
+    /// Get indices of `group` in `[index, index + max_len)` if exist. Returns the first index of
+    /// the returned indices and the indices.
+    ///
+    /// The range is calculated and copied under a single lock hold, so the returned indices are
+    /// always a consistent snapshot even if entries are appended concurrently.
     pub async fn may_entries(
         &self,
         group: u64,
@@ -340,12 +569,7 @@ impl MemStates {
         max_len: usize,
         unmask: bool,
     ) -> Result<(u64, Vec<EntryIndex>)> {
-        let guard = self.states.read().await;
-        let state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .read()
-            .await;
+        let state = self.state(group)?.read_owned().await;
 
         let start_index = std::cmp::max(
             index,
@@ -381,13 +605,12 @@ impl MemStates {
         Ok((start_index, indices))
     }
 
+    /// Get indices of `group` in `[index, index + max_len)`.
+    ///
+    /// The range is calculated and copied under a single lock hold, so the returned indices are
+    /// always a consistent snapshot even if entries are appended concurrently.
     pub async fn entries(&self, group: u64, index: u64, max_len: usize) -> Result<Vec<EntryIndex>> {
-        let guard = self.states.read().await;
-        let state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .read()
-            .await;
+        let state = self.state(group)?.read_owned().await;
 
         if index < state.first_index {
             return Err(RaftLogStoreError::RaftLogGap {
@@ -412,36 +635,93 @@ impl MemStates {
         Ok(indices)
     }
 
+    /// Count of raft log entries of the group, including the masked ones.
+    pub async fn log_entry_count(&self, group: u64) -> Result<usize> {
+        let state = self.state(group)?.read_owned().await;
+        Ok(state.indices.len())
+    }
+
+    /// Count of kv pairs of the group, deleted keys are not counted.
+    pub async fn kv_count(&self, group: u64) -> Result<usize> {
+        let state = self.state(group)?.read_owned().await;
+        Ok(state.kv_count)
+    }
+
     pub async fn put(&self, group: u64, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
-        let guard = self.states.read().await;
-        let mut state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .write()
-            .await;
-        state.kvs.insert(key, value);
+        let mut state = self.state(group)?.write_owned().await;
+        state.write_kv(key, Some(value));
         Ok(())
     }
 
     pub async fn delete(&self, group: u64, key: Vec<u8>) -> Result<()> {
-        let guard = self.states.read().await;
-        let mut state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .write()
-            .await;
-        state.kvs.remove(&key);
+        let mut state = self.state(group)?.write_owned().await;
+        state.write_kv(key, None);
         Ok(())
     }
 
     pub async fn get(&self, group: u64, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
-        let guard = self.states.read().await;
-        let state = guard
-            .get(&group)
-            .ok_or(RaftLogStoreError::GroupNotExists(group))?
-            .read()
-            .await;
-        Ok(state.kvs.get(&key).cloned())
+        let state = self.state(group)?.read_owned().await;
+        Ok(state.get_kv_at(&key, state.kv_ts).cloned())
+    }
+
+    /// Get the value of `key` visible at timestamp `ts`. Returns
+    /// [`RaftLogStoreError::KvVersionCompacted`] if the versions at `ts` are no longer retained.
+    pub async fn get_at(&self, group: u64, key: Vec<u8>, ts: u64) -> Result<Option<Vec<u8>>> {
+        let state = self.state(group)?.read_owned().await;
+        if ts < state.kv_min_ts() {
+            return Err(RaftLogStoreError::KvVersionCompacted {
+                group,
+                ts,
+                min_ts: state.kv_min_ts(),
+            }
+            .into());
+        }
+        Ok(state.get_kv_at(&key, ts).cloned())
+    }
+
+    /// Timestamp of the latest kv write of the group, reads at which see all previous writes.
+    pub async fn kv_timestamp(&self, group: u64) -> Result<u64> {
+        let state = self.state(group)?.read_owned().await;
+        Ok(state.kv_ts)
+    }
+
+    /// Retain kv versions visible at `watermark` or later, so that they can be read with
+    /// [`MemStates::get_at`]. Only the latest versions are retained if `watermark` is `None`.
+    ///
+    /// Versions already dropped cannot be recovered, so the watermark never goes below the min
+    /// readable timestamp.
+    pub async fn retain_kv_versions(&self, group: u64, watermark: Option<u64>) -> Result<()> {
+        let mut state = self.state(group)?.write_owned().await;
+        let min_ts = state.kv_min_ts();
+        state.kv_watermark = watermark.map(|watermark| watermark.max(min_ts));
+        let min_ts = state.kv_min_ts();
+        state
+            .kvs
+            .retain(|_, versions| !MemState::prune_versions(versions, min_ts));
+        Ok(())
+    }
+
+    pub async fn scan_kv(&self, group: u64) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let state = self.state(group)?.read_owned().await;
+        Ok(state
+            .latest_kvs((Bound::Unbounded, Bound::Unbounded))
+            .collect_vec())
+    }
+
+    /// Scan kv pairs of the group in `[start, end)`.
+    pub async fn scan_kv_range(
+        &self,
+        group: u64,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let state = self.state(group)?.read_owned().await;
+        if start >= end {
+            return Ok(vec![]);
+        }
+        Ok(state
+            .latest_kvs((Bound::Included(start), Bound::Excluded(end)))
+            .collect_vec())
     }
 }
 
@@ -449,10 +729,12 @@ impl MemStates {
 mod tests {
 
     use std::ops::Range;
+    use std::sync::Arc;
 
     use test_log::test;
 
     use super::*;
+    use crate::error::Error;
 
     #[test(tokio::test)]
     async fn test_raft_log() {
@@ -504,6 +786,149 @@ mod tests {
         states.remove_group(1).await.unwrap();
     }
 
+    #[test(tokio::test)]
+    async fn test_memory_usage() {
+        const N: usize = 100 * DEFAULT_INDICES_INIT_CAPACITY;
+
+        let states = MemStates::default();
+        states.add_group(1).await.unwrap();
+        assert!(states.memory_usage(2).await.is_err());
+
+        states.append(1, 1, gen_indices(1, N)).await.unwrap();
+        states.put(1, b"k1".to_vec(), b"v1".to_vec()).await.unwrap();
+        let grown = states.memory_usage(1).await.unwrap();
+        assert!(grown >= N * std::mem::size_of::<EntryIndex>());
+
+        // Compact to near-empty.
+        states.compact(1, N as u64 - 9).await.unwrap();
+        assert_range(&states, 1, N as u64 - 9..N as u64 + 1).await;
+        assert!(states.memory_usage(1).await.unwrap() * 50 < grown);
+
+        // Truncation releases memory, too.
+        states
+            .append(1, N as u64 + 1, gen_indices(1, N))
+            .await
+            .unwrap();
+        assert!(states.memory_usage(1).await.unwrap() >= N * std::mem::size_of::<EntryIndex>());
+        states.truncate(1, N as u64 + 1).await.unwrap();
+        assert!(states.memory_usage(1).await.unwrap() * 50 < grown);
+    }
+
+    #[test(tokio::test)]
+    async fn test_counts() {
+        let states = MemStates::default();
+        states.add_group(1).await.unwrap();
+        assert!(states.kv_count(4).await.is_err());
+        assert!(states.log_entry_count(4).await.is_err());
+
+        states.append(1, 1, gen_indices(1, 100)).await.unwrap();
+        assert_eq!(states.log_entry_count(1).await.unwrap(), 100);
+        states.mask(1, 11).await.unwrap();
+        assert_eq!(states.log_entry_count(1).await.unwrap(), 100);
+        states.compact(1, 51).await.unwrap();
+        assert_eq!(states.log_entry_count(1).await.unwrap(), 50);
+        states.truncate(1, 91).await.unwrap();
+        assert_eq!(states.log_entry_count(1).await.unwrap(), 40);
+
+        // Only the latest versions are retained by group 2, all versions are retained by group 3.
+        for (group, watermark) in [(2, None), (3, Some(0))] {
+            states.add_group(group).await.unwrap();
+            states.retain_kv_versions(group, watermark).await.unwrap();
+            assert_eq!(states.kv_count(group).await.unwrap(), 0);
+
+            states
+                .put(group, b"k1".to_vec(), b"v1".to_vec())
+                .await
+                .unwrap();
+            states
+                .put(group, b"k2".to_vec(), b"v2".to_vec())
+                .await
+                .unwrap();
+            assert_eq!(states.kv_count(group).await.unwrap(), 2);
+            // Overwrite.
+            states
+                .put(group, b"k1".to_vec(), b"v1-2".to_vec())
+                .await
+                .unwrap();
+            assert_eq!(states.kv_count(group).await.unwrap(), 2);
+            states.delete(group, b"k1".to_vec()).await.unwrap();
+            assert_eq!(states.kv_count(group).await.unwrap(), 1);
+            // Delete deleted or absent keys.
+            states.delete(group, b"k1".to_vec()).await.unwrap();
+            states.delete(group, b"k3".to_vec()).await.unwrap();
+            assert_eq!(states.kv_count(group).await.unwrap(), 1);
+            // Put deleted keys.
+            states
+                .put(group, b"k1".to_vec(), b"v1-3".to_vec())
+                .await
+                .unwrap();
+            assert_eq!(states.kv_count(group).await.unwrap(), 2);
+            states.retain_kv_versions(group, None).await.unwrap();
+            assert_eq!(states.kv_count(group).await.unwrap(), 2);
+
+            states.remove_group(group).await.unwrap();
+            assert_eq!(states.kv_count(group).await.unwrap(), 0);
+        }
+
+        states
+            .apply_snapshot(
+                1,
+                101,
+                vec![
+                    (b"k1".to_vec(), b"v1".to_vec()),
+                    (b"k4".to_vec(), b"v4".to_vec()),
+                    (b"k5".to_vec(), b"v5".to_vec()),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(states.kv_count(1).await.unwrap(), 3);
+        assert_eq!(states.log_entry_count(1).await.unwrap(), 0);
+    }
+
+    #[test(tokio::test)]
+    async fn test_lookup_term() {
+        let states = MemStates::default();
+        states.add_group(1).await.unwrap();
+        assert_eq!(states.lookup_term(1, 1).await.unwrap(), TermLookup::Future);
+
+        states.append(1, 1, gen_indices(1, 100)).await.unwrap();
+        states.append(1, 101, gen_indices(2, 100)).await.unwrap();
+        states.compact(1, 51).await.unwrap();
+
+        assert_eq!(
+            states.lookup_term(1, 1).await.unwrap(),
+            TermLookup::Compacted
+        );
+        assert_eq!(
+            states.lookup_term(1, 50).await.unwrap(),
+            TermLookup::Compacted
+        );
+        assert_eq!(
+            states.lookup_term(1, 51).await.unwrap(),
+            TermLookup::Present(1)
+        );
+        assert_eq!(
+            states.lookup_term(1, 100).await.unwrap(),
+            TermLookup::Present(1)
+        );
+        assert_eq!(
+            states.lookup_term(1, 101).await.unwrap(),
+            TermLookup::Present(2)
+        );
+        assert_eq!(
+            states.lookup_term(1, 200).await.unwrap(),
+            TermLookup::Present(2)
+        );
+        assert_eq!(
+            states.lookup_term(1, 201).await.unwrap(),
+            TermLookup::Future
+        );
+        assert!(states.lookup_term(2, 1).await.is_err());
+
+        states.remove_group(1).await.unwrap();
+    }
+
     #[test(tokio::test)]
     async fn test_kv() {
         let states = MemStates::default();
@@ -523,9 +948,243 @@ mod tests {
         states.remove_group(1).await.unwrap();
     }
 
+    #[test(tokio::test)]
+    async fn test_kv_get_at() {
+        let states = MemStates::default();
+        states.add_group(1).await.unwrap();
+        states.retain_kv_versions(1, Some(0)).await.unwrap();
+        let ts0 = states.kv_timestamp(1).await.unwrap();
+        states.put(1, b"k1".to_vec(), b"v1".to_vec()).await.unwrap();
+        let ts1 = states.kv_timestamp(1).await.unwrap();
+        states.put(1, b"k1".to_vec(), b"v2".to_vec()).await.unwrap();
+        let ts2 = states.kv_timestamp(1).await.unwrap();
+        states.delete(1, b"k1".to_vec()).await.unwrap();
+        let ts3 = states.kv_timestamp(1).await.unwrap();
+        assert!(ts0 < ts1 && ts1 < ts2 && ts2 < ts3);
+
+        assert_eq!(states.get(1, b"k1".to_vec()).await.unwrap(), None);
+        assert_eq!(states.get_at(1, b"k1".to_vec(), ts0).await.unwrap(), None);
+        assert_eq!(
+            states.get_at(1, b"k1".to_vec(), ts1).await.unwrap(),
+            Some(b"v1".to_vec())
+        );
+        assert_eq!(
+            states.get_at(1, b"k1".to_vec(), ts2).await.unwrap(),
+            Some(b"v2".to_vec())
+        );
+        assert_eq!(states.get_at(1, b"k1".to_vec(), ts3).await.unwrap(), None);
+
+        // Versions invisible at the watermark are dropped.
+        states.retain_kv_versions(1, Some(ts2)).await.unwrap();
+        assert!(matches!(
+            states.get_at(1, b"k1".to_vec(), ts1).await,
+            Err(Error::RaftLogStoreError(
+                RaftLogStoreError::KvVersionCompacted { .. }
+            ))
+        ));
+        assert_eq!(
+            states.get_at(1, b"k1".to_vec(), ts2).await.unwrap(),
+            Some(b"v2".to_vec())
+        );
+        // The watermark never goes back.
+        states.retain_kv_versions(1, Some(ts1)).await.unwrap();
+        assert!(states.get_at(1, b"k1".to_vec(), ts1).await.is_err());
+
+        // Only the latest versions are retained without a watermark.
+        states.retain_kv_versions(1, None).await.unwrap();
+        assert!(states.state(1).unwrap().read().await.kvs.is_empty());
+        states.put(1, b"k2".to_vec(), b"v1".to_vec()).await.unwrap();
+        let ts = states.kv_timestamp(1).await.unwrap();
+        states.put(1, b"k2".to_vec(), b"v2".to_vec()).await.unwrap();
+        assert!(states.get_at(1, b"k2".to_vec(), ts).await.is_err());
+        assert_eq!(
+            states.get(1, b"k2".to_vec()).await.unwrap(),
+            Some(b"v2".to_vec())
+        );
+
+        states.remove_group(1).await.unwrap();
+    }
+
+    #[test(tokio::test)]
+    async fn test_scan_kv() {
+        let states = MemStates::default();
+        states.add_group(1).await.unwrap();
+        states.add_group(2).await.unwrap();
+        assert!(states.scan_kv(1).await.unwrap().is_empty());
+        for i in [3, 1, 4, 2, 5] {
+            states
+                .put(
+                    1,
+                    format!("k{}", i).into_bytes(),
+                    format!("v{}", i).into_bytes(),
+                )
+                .await
+                .unwrap();
+        }
+        states.put(2, b"k0".to_vec(), b"v0".to_vec()).await.unwrap();
+        states.delete(1, b"k4".to_vec()).await.unwrap();
+
+        let kv = |i: usize| {
+            (
+                format!("k{}", i).into_bytes(),
+                format!("v{}", i).into_bytes(),
+            )
+        };
+        assert_eq!(
+            states.scan_kv(1).await.unwrap(),
+            vec![kv(1), kv(2), kv(3), kv(5)]
+        );
+        assert_eq!(states.scan_kv(2).await.unwrap(), vec![kv(0)]);
+        assert_eq!(
+            states.scan_kv_range(1, b"k2", b"k5").await.unwrap(),
+            vec![kv(2), kv(3)]
+        );
+        assert_eq!(
+            states.scan_kv_range(1, b"k", b"k9").await.unwrap(),
+            vec![kv(1), kv(2), kv(3), kv(5)]
+        );
+        assert!(states
+            .scan_kv_range(1, b"k5", b"k2")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(states.scan_kv(3).await.is_err());
+        assert!(states.scan_kv_range(3, b"k1", b"k2").await.is_err());
+
+        states.remove_group(1).await.unwrap();
+        states.remove_group(2).await.unwrap();
+    }
+
+    #[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+    async fn test_entries_while_appending() {
+        const APPENDS: u64 = 1000;
+        const BATCH: u64 = 4;
+
+        let states = Arc::new(MemStates::default());
+        states.add_group(1).await.unwrap();
+        states.append(1, 1, gen_indices_at(1, 1)).await.unwrap();
+
+        let appender = {
+            let states = states.clone();
+            tokio::spawn(async move {
+                for i in 0..APPENDS {
+                    let first_index = 2 + i * BATCH;
+                    states
+                        .append(1, first_index, gen_indices_at(first_index, BATCH as usize))
+                        .await
+                        .unwrap();
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        let readers = (0..3)
+            .map(|_| {
+                let states = states.clone();
+                tokio::spawn(async move {
+                    let last_index = 1 + APPENDS * BATCH;
+                    loop {
+                        let indices = states.entries(1, 1, usize::MAX).await.unwrap();
+                        assert_contiguous(1, &indices);
+                        let finished = indices.len() as u64 == last_index;
+
+                        let (start, indices) = states.may_entries(1, 1, 7, false).await.unwrap();
+                        assert_eq!(start, 1);
+                        assert!(indices.len() <= 7);
+                        assert_contiguous(start, &indices);
+
+                        if finished {
+                            break;
+                        }
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect_vec();
+
+        appender.await.unwrap();
+        for reader in readers {
+            reader.await.unwrap();
+        }
+        assert_range(&states, 1, 1..2 + APPENDS * BATCH).await;
+    }
+
+    #[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+    async fn test_concurrent_groups() {
+        const GROUPS: u64 = 16;
+        const APPENDS: u64 = 200;
+        const BATCH: u64 = 4;
+
+        let states = Arc::new(MemStates::default());
+        let appenders = (1..=GROUPS)
+            .map(|group| {
+                let states = states.clone();
+                tokio::spawn(async move {
+                    states.add_group(group).await.unwrap();
+                    for i in 0..APPENDS {
+                        let first_index = 1 + i * BATCH;
+                        states
+                            .append(
+                                group,
+                                first_index,
+                                gen_indices_at(first_index, BATCH as usize),
+                            )
+                            .await
+                            .unwrap();
+                        states
+                            .put(group, b"k".to_vec(), i.to_be_bytes().to_vec())
+                            .await
+                            .unwrap();
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect_vec();
+        // Groups are added and removed concurrently.
+        let adder = {
+            let states = states.clone();
+            tokio::spawn(async move {
+                for group in GROUPS + 1..=GROUPS + APPENDS {
+                    states.add_group(group).await.unwrap();
+                    states.min_file_id().await;
+                    states.remove_group(group).await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        for appender in appenders {
+            appender.await.unwrap();
+        }
+        adder.await.unwrap();
+        for group in 1..=GROUPS {
+            assert_range(&states, group, 1..1 + APPENDS * BATCH).await;
+            assert_contiguous(1, &states.entries(group, 1, usize::MAX).await.unwrap());
+            assert_eq!(
+                states.get(group, b"k".to_vec()).await.unwrap(),
+                Some((APPENDS - 1).to_be_bytes().to_vec())
+            );
+        }
+        assert_eq!(
+            states
+                .dump()
+                .await
+                .into_iter()
+                .map(|dump| dump.group)
+                .collect_vec(),
+            (1..=GROUPS).collect_vec()
+        );
+    }
+
+    fn assert_contiguous(first_index: u64, indices: &[EntryIndex]) {
+        assert!(!indices.is_empty());
+        for (i, index) in indices.iter().enumerate() {
+            assert_eq!(index.offset as u64, first_index + i as u64);
+        }
+    }
+
     async fn assert_range(target: &MemStates, group: u64, range: Range<u64>) {
-        let guard = target.states.read().await;
-        let state = guard.get(&group).unwrap().read().await;
+        let state = target.state(group).unwrap().read_owned().await;
         assert_eq!(
             (
                 state.first_index,
@@ -549,4 +1208,19 @@ mod tests {
             len
         ]
     }
+
+    /// Generate indices whose `offset` records their own raft log index.
+    fn gen_indices_at(first_index: u64, len: usize) -> Vec<EntryIndex> {
+        (first_index..first_index + len as u64)
+            .map(|index| EntryIndex {
+                term: 1,
+                ctx: vec![],
+                file_id: 1,
+                block_offset: 0,
+                block_len: 0,
+                offset: index as usize,
+                len: 0,
+            })
+            .collect_vec()
+    }
 }
@@ -3,8 +3,11 @@ pub mod entry;
 pub mod error;
 pub mod log;
 pub mod mem;
+pub mod rate_limiter;
 pub mod store;
 
 const DEFAULT_LOG_BATCH_SIZE: usize = 8 << 10;
 
-pub use store::RaftLogStore;
+pub use mem::{GroupSnapshotMeta, TermLookup};
+pub use rate_limiter::WriteRateLimit;
+pub use store::{OpenReport, RaftLogStore};
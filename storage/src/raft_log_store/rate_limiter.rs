@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Write rate limit of a raft group.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WriteRateLimit {
+    /// Max bytes of raft log entries and kv pairs written per second.
+    BytesPerSec(u64),
+    /// Max appends and kv writes per second.
+    OpsPerSec(u64),
+}
+
+impl WriteRateLimit {
+    fn rate(&self) -> u64 {
+        match self {
+            Self::BytesPerSec(rate) | Self::OpsPerSec(rate) => *rate,
+        }
+    }
+
+    /// Cost of a write of `bytes` under the limit.
+    fn cost(&self, bytes: usize) -> u64 {
+        match self {
+            Self::BytesPerSec(_) => bytes as u64,
+            Self::OpsPerSec(_) => 1,
+        }
+    }
+}
+
+struct Bucket {
+    /// Available tokens, negative for the debt of the last acquirer.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// [`RateLimiter`] is a token bucket holding up to one second of tokens.
+///
+/// Acquirers are served in order. A write larger than the bucket is admitted once the bucket is
+/// full and then paid off by waiting, so it never blocks forever.
+pub struct RateLimiter {
+    limit: WriteRateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: WriteRateLimit) -> Self {
+        Self {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens: limit.rate() as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Wait until a write of `bytes` is allowed.
+    pub async fn acquire(&self, bytes: usize) {
+        let rate = self.limit.rate() as f64;
+        let cost = self.limit.cost(bytes) as f64;
+        // Hold the lock while waiting, so later acquirers queue up behind.
+        let mut bucket = self.bucket.lock().await;
+        let now = Instant::now();
+        bucket.tokens =
+            (bucket.tokens + now.duration_since(bucket.last_refill).as_secs_f64() * rate).min(rate);
+        bucket.last_refill = now;
+        bucket.tokens -= cost;
+        if bucket.tokens < 0.0 && rate > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-bucket.tokens / rate)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_rate_limiter() {
+        tokio::time::pause();
+
+        let limiter = RateLimiter::new(WriteRateLimit::BytesPerSec(100));
+        let start = Instant::now();
+        // The full bucket is consumed without waiting.
+        limiter.acquire(100).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        limiter.acquire(50).await;
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+        // Writes larger than the bucket are paid off by waiting.
+        limiter.acquire(200).await;
+        assert_eq!(start.elapsed(), Duration::from_millis(2500));
+
+        let limiter = RateLimiter::new(WriteRateLimit::OpsPerSec(10));
+        let start = Instant::now();
+        for _ in 0..20 {
+            limiter.acquire(1 << 20).await;
+        }
+        // 10 ops are allowed by the full bucket, the others are paced.
+        assert!(start.elapsed() >= Duration::from_millis(990));
+        assert!(start.elapsed() < Duration::from_millis(1100));
+    }
+}
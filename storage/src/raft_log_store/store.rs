@@ -1,13 +1,32 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
 
+use futures::{stream, Stream, StreamExt, TryStreamExt};
 use futures_async_stream::for_await;
+use itertools::Itertools;
+use tokio::sync::{mpsc, Mutex, OwnedMutexGuard, RwLock};
 use tracing::trace;
 
 use super::block_cache::BlockCache;
-use super::entry::{Compact, Entry as LogEntry, Kv, Mask, RaftLogBatch, Truncate};
+use super::entry::{
+    Compact, Entry as LogEntry, Kv, Mask, RaftLogBatch, RaftLogBatchBuilder,
+    RaftLogBatchBuilderOptions, Snapshot, Truncate,
+};
+use super::error::RaftLogStoreError;
 use super::log::{Log, LogOptions, LogRef};
-use super::mem::{EntryIndex, MemStates};
-use crate::error::Result;
+use super::mem::{EntryIndex, GroupSnapshotMeta, GroupStateDump, MemStates, TermLookup};
+use super::rate_limiter::{RateLimiter, WriteRateLimit};
+use crate::error::{Error, Result};
+
+/// Max count of entry reads in flight of [`RaftLogStore::entries_stream`].
+pub const ENTRIES_STREAM_READ_AHEAD: usize = 16;
+
+/// Default count of tasks replaying groups concurrently on open.
+pub const DEFAULT_REPLAY_CONCURRENCY: usize = 8;
+
+/// Max count of log entries buffered for each replay task.
+const REPLAY_CHANNEL_CAPACITY: usize = 64;
 
 #[derive(Clone, Debug)]
 pub struct Entry {
@@ -23,12 +42,63 @@ pub struct RaftLogStoreOptions {
     pub log_dir_path: String,
     pub log_file_capacity: usize,
     pub block_cache_capacity: usize,
+    /// Appends within the window share a single log sync. Zero means sync immediately.
+    pub group_commit_window: Duration,
+    /// Write rate limits of raft groups. Groups without a limit are not throttled.
+    pub group_write_limits: BTreeMap<u64, WriteRateLimit>,
+    /// Count of tasks replaying groups concurrently on open. Groups are assigned to the tasks by
+    /// group id, and entries are streamed to the tasks through bounded channels. `0` or `1`
+    /// replays serially.
+    pub replay_concurrency: usize,
+}
+
+impl RaftLogStoreOptions {
+    /// Options that sync every append immediately, without write rate limits, and replay with
+    /// [`DEFAULT_REPLAY_CONCURRENCY`] tasks.
+    pub fn new(
+        log_dir_path: String,
+        log_file_capacity: usize,
+        block_cache_capacity: usize,
+    ) -> Self {
+        Self {
+            log_dir_path,
+            log_file_capacity,
+            block_cache_capacity,
+            group_commit_window: Duration::ZERO,
+            group_write_limits: BTreeMap::default(),
+            replay_concurrency: DEFAULT_REPLAY_CONCURRENCY,
+        }
+    }
+}
+
+/// [`OpenReport`] summarizes the replay of [`RaftLogStore::open_with_report`].
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct OpenReport {
+    /// Count of groups recovered.
+    pub groups_recovered: usize,
+    /// Count of raft log entries replayed.
+    pub entries_replayed: u64,
+    /// Count of kv puts and deletes replayed.
+    pub kv_ops_replayed: u64,
+    /// Count of raft log entries skipped for being outdated, e.g. compacted or rewritten by a
+    /// higher term.
+    pub skipped_outdated: u64,
+    /// Length of the torn tail truncated from the log, in bytes.
+    pub truncated_bytes: u64,
 }
 
 struct RaftLogStoreCore {
     log: LogRef,
-    states: MemStates,
+    log_file_capacity: usize,
+    states: Arc<MemStates>,
     block_cache: BlockCache,
+    /// Locks held by appends and compactions of each group, to detect concurrent misuse.
+    group_locks: parking_lot::Mutex<HashMap<u64, Arc<Mutex<()>>>>,
+    rate_limiters: BTreeMap<u64, RateLimiter>,
+    /// Held shared by writes from pushing to the log until applying to the states, and held
+    /// exclusively by [`RaftLogStore::gc`], so that no write is pushed between dumping the states
+    /// and rewriting them.
+    write_lock: RwLock<()>,
 }
 
 /// [`RaftLogStore`] is designed for storing raft log entries and some small kv pairs from multiple
@@ -38,6 +108,9 @@ struct RaftLogStoreCore {
 ///
 /// [`RaftLogStore`] ensure that operations across multiple raft groups are safe. But operations of
 /// a same raft group MUST be performed in order.
+///
+/// Concurrent appends or compactions of a same raft group are rejected with
+/// [`RaftLogStoreError::GroupLocked`].
 #[derive(Clone)]
 pub struct RaftLogStore {
     core: Arc<RaftLogStoreCore>,
@@ -45,75 +118,167 @@ pub struct RaftLogStore {
 
 impl RaftLogStore {
     pub async fn open(options: RaftLogStoreOptions) -> Result<Self> {
-        let states = MemStates::default();
+        let (store, _) = Self::open_with_report(options).await?;
+        Ok(store)
+    }
+
+    /// Open [`RaftLogStore`] and replay the log. Returns the store and a report of the replay.
+    pub async fn open_with_report(options: RaftLogStoreOptions) -> Result<(Self, OpenReport)> {
+        let states = Arc::new(MemStates::default());
+        let mut report = OpenReport::default();
+        let mut groups = BTreeSet::default();
 
+        let log_file_capacity = options.log_file_capacity;
         let log_options = LogOptions {
             path: options.log_dir_path,
-            log_file_capacity: options.log_file_capacity,
+            log_file_capacity,
+            group_commit_window: options.group_commit_window,
         };
 
         let log = Log::open(log_options).await?;
 
-        #[for_await]
-        for item in log.replay() {
-            let (file_id, write_offset, entry) = item?;
-            match entry {
-                LogEntry::RaftLogBatch(batch) => {
-                    let (data_segment_offset, data_segment_len) = batch.data_segment_location();
-                    let group = batch.group();
-                    let term = batch.term();
-                    let first_index = batch.first_index();
-                    let block_offset = write_offset + data_segment_offset + 1;
-                    let block_len = data_segment_len;
-                    let mut indices = Vec::with_capacity(batch.len());
-                    for i in 0..batch.len() {
-                        let (offset, len) = batch.location(i);
-                        let index = EntryIndex {
-                            term,
-                            ctx: batch.ctx(i).to_vec(),
-                            file_id,
-                            block_offset,
-                            block_len,
-                            offset,
-                            len,
-                        };
-                        indices.push(index);
+        if options.replay_concurrency <= 1 {
+            #[for_await]
+            for item in log.replay() {
+                let (file_id, write_offset, entry) = item?;
+                let group = entry.group();
+                states.may_add_group(group).await;
+                Self::replay_entry(&states, &mut report, file_id, write_offset, entry).await?;
+                groups.insert(group);
+            }
+        } else {
+            // Entries of a group are always sent to the same task, so they are replayed in order,
+            // while groups on different tasks are replayed concurrently.
+            let concurrency = options.replay_concurrency;
+            let mut txs = Vec::with_capacity(concurrency);
+            let mut handles = Vec::with_capacity(concurrency);
+            for _ in 0..concurrency {
+                let (tx, mut rx) = mpsc::channel::<(u64, usize, LogEntry)>(REPLAY_CHANNEL_CAPACITY);
+                let states = states.clone();
+                txs.push(tx);
+                handles.push(tokio::spawn(async move {
+                    let mut report = OpenReport::default();
+                    while let Some((file_id, write_offset, entry)) = rx.recv().await {
+                        Self::replay_entry(&states, &mut report, file_id, write_offset, entry)
+                            .await?;
                     }
+                    Ok::<_, Error>(report)
+                }));
+            }
+            #[for_await]
+            for item in log.replay() {
+                let (file_id, write_offset, entry) = item?;
+                let group = entry.group();
+                if groups.insert(group) {
                     states.may_add_group(group).await;
-                    states.append(group, first_index, indices).await?;
-                }
-                LogEntry::Truncate(Truncate { group, index }) => {
-                    states.may_add_group(group).await;
-                    states.truncate(group, index).await?;
-                }
-                LogEntry::Compact(Compact { group, index }) => {
-                    states.may_add_group(group).await;
-                    states.compact(group, index).await?;
-                }
-                LogEntry::Mask(Mask { group, index }) => {
-                    states.may_add_group(group).await;
-                    states.mask(group, index).await?;
-                }
-                LogEntry::Kv(Kv::Put { group, key, value }) => {
-                    states.may_add_group(group).await;
-                    states.put(group, key, value).await?;
                 }
-                LogEntry::Kv(Kv::Delete { group, key }) => {
-                    states.may_add_group(group).await;
-                    states.delete(group, key).await?;
+                let tx = &txs[(group % concurrency as u64) as usize];
+                if tx.send((file_id, write_offset, entry)).await.is_err() {
+                    // The task has failed, its error is returned when joined.
+                    break;
                 }
             }
+            drop(txs);
+            for handle in handles {
+                let task_report = handle.await.map_err(|e| {
+                    RaftLogStoreError::Other(format!("raft log replay task failed: {}", e))
+                })??;
+                report.entries_replayed += task_report.entries_replayed;
+                report.kv_ops_replayed += task_report.kv_ops_replayed;
+                report.skipped_outdated += task_report.skipped_outdated;
+            }
         }
 
+        report.groups_recovered = groups.len();
+        report.truncated_bytes = log.truncated_len() as u64;
+        trace!("raft log store replayed: {:?}", report);
+
         let log = Arc::new(log);
 
-        Ok(Self {
+        let store = Self {
             core: Arc::new(RaftLogStoreCore {
                 log,
+                log_file_capacity,
                 states,
                 block_cache: BlockCache::new(options.block_cache_capacity),
+                group_locks: parking_lot::Mutex::new(HashMap::default()),
+                rate_limiters: options
+                    .group_write_limits
+                    .into_iter()
+                    .map(|(group, limit)| (group, RateLimiter::new(limit)))
+                    .collect(),
+                write_lock: RwLock::new(()),
             }),
-        })
+        };
+        Ok((store, report))
+    }
+
+    /// Replay a log entry to the states of its group, which must have been added.
+    async fn replay_entry(
+        states: &MemStates,
+        report: &mut OpenReport,
+        file_id: u64,
+        write_offset: usize,
+        entry: LogEntry,
+    ) -> Result<()> {
+        match entry {
+            LogEntry::RaftLogBatch(batch) => {
+                let (data_segment_offset, data_segment_len) = batch.data_segment_location();
+                let group = batch.group();
+                let term = batch.term();
+                let first_index = batch.first_index();
+                let block_offset = write_offset + data_segment_offset + 1;
+                let block_len = data_segment_len;
+                let mut indices = Vec::with_capacity(batch.len());
+                for i in 0..batch.len() {
+                    let (offset, len) = batch.location(i);
+                    let index = EntryIndex {
+                        term,
+                        ctx: batch.ctx(i).to_vec(),
+                        file_id,
+                        block_offset,
+                        block_len,
+                        offset,
+                        len,
+                    };
+                    indices.push(index);
+                }
+                report.entries_replayed += indices.len() as u64;
+                report.skipped_outdated += states.append(group, first_index, indices).await? as u64;
+            }
+            LogEntry::Truncate(Truncate { group, index }) => {
+                states.truncate(group, index).await?;
+            }
+            LogEntry::Compact(Compact { group, index }) => {
+                states.compact(group, index).await?;
+            }
+            LogEntry::Mask(Mask { group, index }) => {
+                states.mask(group, index).await?;
+            }
+            LogEntry::Kv(Kv::Put { group, key, value }) => {
+                states.put(group, key, value).await?;
+                report.kv_ops_replayed += 1;
+            }
+            LogEntry::Kv(Kv::Delete { group, key }) => {
+                states.delete(group, key).await?;
+                report.kv_ops_replayed += 1;
+            }
+            LogEntry::Snapshot(Snapshot {
+                group,
+                first_index,
+                kvs,
+            }) => {
+                states.apply_snapshot(group, first_index, kvs).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush and sync the log.
+    ///
+    /// Once it returns, all previously returned appends and kv writes are durable.
+    pub async fn sync(&self) -> Result<()> {
+        self.core.log.sync().await
     }
 
     pub async fn add_group(&self, group: u64) -> Result<()> {
@@ -124,56 +289,103 @@ impl RaftLogStore {
     ///
     /// Removed group needs to be guaranteed never be used again.
     pub async fn remove_group(&self, group: u64) -> Result<()> {
-        // TODO: Advance GC safe point.
-        self.core.states.remove_group(group).await
+        self.core.states.remove_group(group).await?;
+        self.core.group_locks.lock().remove(&group);
+        Ok(())
     }
 
     /// Append raft log batch to [`RaftLogStore`].
-    pub async fn append(&self, mut batch: RaftLogBatch) -> Result<()> {
-        let (data_segment_offset, data_segment_len) = batch.data_segment_location();
-        let group = batch.group();
-        let term = batch.term();
-        let first_index = batch.first_index();
-
-        let mut indices = Vec::with_capacity(batch.len());
-        for i in 0..batch.len() {
-            let (offset, len) = batch.location(i);
-            let index = EntryIndex {
-                term,
-                ctx: batch.ctx(i).to_vec(),
-                file_id: 0,
-                block_offset: 0,
-                block_len: 0,
-                offset,
-                len,
-            };
-            indices.push(index);
+    /// Returns a [`RaftLogBatchBuilder`] whose batches never exceed the log file capacity.
+    pub fn batch_builder(&self) -> RaftLogBatchBuilder {
+        RaftLogBatchBuilder::new(RaftLogBatchBuilderOptions {
+            max_batch_size: self.core.log_file_capacity,
+            ..Default::default()
+        })
+    }
+
+    pub async fn append(&self, batch: RaftLogBatch) -> Result<()> {
+        self.append_many(vec![batch]).await
+    }
+
+    /// Append batches of multiple groups with a single log write and sync.
+    ///
+    /// Batches of the same group are appended in order.
+    pub async fn append_many(&self, batches: Vec<RaftLogBatch>) -> Result<()> {
+        let _guards = batches
+            .iter()
+            .map(|batch| batch.group())
+            .unique()
+            .map(|group| self.lock_group(group))
+            .collect::<Result<Vec<_>>>()?;
+        for batch in batches.iter() {
+            self.throttle(batch.group(), batch.size()).await;
         }
+        let _write_guard = self.core.write_lock.read().await;
 
-        let raw = batch.take_raw();
-        let entry = LogEntry::RaftLogBatch(batch);
-        let (file_id, write_offset, _write_len) = self.core.log.push(entry).await?;
+        let mut appends = Vec::with_capacity(batches.len());
+        let mut entries = Vec::with_capacity(batches.len());
+        for mut batch in batches {
+            let (data_segment_offset, data_segment_len) = batch.data_segment_location();
+            let group = batch.group();
+            let term = batch.term();
+            let first_index = batch.first_index();
 
-        let block_offset = write_offset + data_segment_offset + 1;
-        let block_len = data_segment_len;
-        for index in indices.iter_mut() {
-            index.file_id = file_id;
-            index.block_offset = block_offset;
-            index.block_len = block_len;
+            let mut indices = Vec::with_capacity(batch.len());
+            for i in 0..batch.len() {
+                let (offset, len) = batch.location(i);
+                let index = EntryIndex {
+                    term,
+                    ctx: batch.ctx(i).to_vec(),
+                    file_id: 0,
+                    block_offset: 0,
+                    block_len: 0,
+                    offset,
+                    len,
+                };
+                indices.push(index);
+            }
+
+            let raw = batch.take_raw();
+            appends.push((
+                group,
+                first_index,
+                indices,
+                raw,
+                data_segment_offset,
+                data_segment_len,
+            ));
+            entries.push(LogEntry::RaftLogBatch(batch));
         }
 
-        self.core
-            .block_cache
-            .insert(file_id, block_offset, Arc::new(raw))
-            .await;
+        let locations = self.core.log.push_many(entries).await?;
+
+        for (
+            (group, first_index, mut indices, raw, data_segment_offset, data_segment_len),
+            (file_id, write_offset, _write_len),
+        ) in appends.into_iter().zip(locations.into_iter())
+        {
+            let block_offset = write_offset + data_segment_offset + 1;
+            let block_len = data_segment_len;
+            for index in indices.iter_mut() {
+                index.file_id = file_id;
+                index.block_offset = block_offset;
+                index.block_len = block_len;
+            }
+
+            self.core
+                .block_cache
+                .insert(file_id, block_offset, Arc::new(raw))
+                .await;
 
-        self.core.states.append(group, first_index, indices).await?;
+            self.core.states.append(group, first_index, indices).await?;
+        }
 
         Ok(())
     }
 
     /// Truncate raft log of given `group` since given `index`.
     pub async fn truncate(&self, group: u64, index: u64) -> Result<()> {
+        let _write_guard = self.core.write_lock.read().await;
         self.core
             .log
             .push(LogEntry::Truncate(Truncate { group, index }))
@@ -184,6 +396,8 @@ impl RaftLogStore {
 
     /// Mark all raft log entries before given `index` of the given `group` can be safely deleted.
     pub async fn compact(&self, group: u64, index: u64) -> Result<()> {
+        let _guard = self.lock_group(group)?;
+        let _write_guard = self.core.write_lock.read().await;
         self.core
             .log
             .push(LogEntry::Compact(Compact { group, index }))
@@ -192,11 +406,36 @@ impl RaftLogStore {
         Ok(())
     }
 
+    /// Atomically replace all kv pairs of the group with the ones of a snapshot, and reset the raft
+    /// log of the group to start from `first_index`.
+    pub async fn apply_snapshot(
+        &self,
+        group: u64,
+        first_index: u64,
+        kvs: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
+        let _write_guard = self.core.write_lock.read().await;
+        self.core
+            .log
+            .push(LogEntry::Snapshot(Snapshot {
+                group,
+                first_index,
+                kvs: kvs.clone(),
+            }))
+            .await?;
+        self.core
+            .states
+            .apply_snapshot(group, first_index, kvs)
+            .await?;
+        Ok(())
+    }
+
     /// Mask any indices before the given index.
     ///
     /// Masked indices are not deleted from the state, but can only be accessed with `unmask` set to
     /// `true`.
     pub async fn mask(&self, group: u64, index: u64) -> Result<()> {
+        let _write_guard = self.core.write_lock.read().await;
         self.core
             .log
             .push(LogEntry::Mask(Mask { group, index }))
@@ -235,9 +474,23 @@ impl RaftLogStore {
         Ok(entries)
     }
 
+    /// Get raft log entries from [`RaftLogStore`], including the masked ones.
+    ///
+    /// Shorthand of [`RaftLogStore::may_entries`] with `unmask` set.
+    pub async fn unmask_entries(
+        &self,
+        group: u64,
+        index: u64,
+        max_len: usize,
+    ) -> Result<Vec<Entry>> {
+        self.may_entries(group, index, max_len, true).await
+    }
+
     /// Get raft log entries from [`RaftLogStore`].
     ///
     /// Returns `Err` when given `index` is not valid.
+    ///
+    /// Note: Masked entries are returned, too. Use [`RaftLogStore::may_entries`] to skip them.
     pub async fn entries(&self, group: u64, index: u64, max_len: usize) -> Result<Vec<Entry>> {
         let indices = self.core.states.entries(group, index, max_len).await?;
         let mut entries = Vec::with_capacity(indices.len());
@@ -255,14 +508,44 @@ impl RaftLogStore {
         Ok(entries)
     }
 
+    /// Get `(index, data)` of raft log entries from [`RaftLogStore`] as a stream. Entries are
+    /// yielded in index order as soon as their data are read, with at most
+    /// [`ENTRIES_STREAM_READ_AHEAD`] reads in flight.
+    ///
+    /// Yields an `Err` when given `index` is not valid.
+    pub fn entries_stream(
+        &self,
+        group: u64,
+        index: u64,
+        max_len: usize,
+    ) -> impl Stream<Item = Result<(u64, Vec<u8>)>> + '_ {
+        stream::once(self.core.states.entries(group, index, max_len))
+            .map_ok(|indices| stream::iter(indices.into_iter().enumerate().map(Ok)))
+            .try_flatten()
+            .map_ok(move |(i, ei)| async move {
+                let data = self.entry_data(&ei).await?;
+                Ok((index + i as u64, data))
+            })
+            .try_buffered(ENTRIES_STREAM_READ_AHEAD)
+    }
+
     pub async fn term(&self, group: u64, index: u64) -> Result<Option<u64>> {
         self.core.states.term(group, index).await
     }
 
+    /// Get the term of the entry at `index`, or whether the entry is compacted or not appended yet.
+    pub async fn lookup_term(&self, group: u64, index: u64) -> Result<TermLookup> {
+        self.core.states.lookup_term(group, index).await
+    }
+
     pub async fn ctx(&self, group: u64, index: u64) -> Result<Option<Vec<u8>>> {
         self.core.states.ctx(group, index).await
     }
 
+    /// Get the index of the first entry of the group that is still kept.
+    ///
+    /// Entries before the mask index are skipped unless `unmask` is set. Returns `Err(index)` if
+    /// there is no such entry, where `index` is the index the next appended entry will have.
     pub async fn first_index(
         &self,
         group: u64,
@@ -271,6 +554,14 @@ impl RaftLogStore {
         self.core.states.first_index(group, unmask).await
     }
 
+    /// Get the index of the next entry to append to the group, which is the last appended index
+    /// plus 1.
+    ///
+    /// Unlike [`RaftLogStore::first_index`], the returned index is not the index of an existing
+    /// entry. Returns `Err(next_index)` if there is no kept entry (after the mask index unless
+    /// `unmask` is set), so the last appended index is unavailable, e.g. after compaction.
+    /// Callers that only need the index can flatten the result with
+    /// `unwrap_or_else(|index| index)`.
     pub async fn next_index(
         &self,
         group: u64,
@@ -279,7 +570,17 @@ impl RaftLogStore {
         self.core.states.next_index(group, unmask).await
     }
 
+    /// Capture `(first index, next index, mask index)` of `groups` atomically, for a coordinated
+    /// snapshot across groups.
+    ///
+    /// Note: Writers of the groups are blocked briefly while capturing.
+    pub async fn snapshot(&self, groups: &[u64]) -> Result<Vec<GroupSnapshotMeta>> {
+        self.core.states.snapshot(groups).await
+    }
+
     pub async fn put(&self, group: u64, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        self.throttle(group, key.len() + value.len()).await;
+        let _write_guard = self.core.write_lock.read().await;
         self.core
             .log
             .push(LogEntry::Kv(Kv::Put {
@@ -293,6 +594,8 @@ impl RaftLogStore {
     }
 
     pub async fn delete(&self, group: u64, key: Vec<u8>) -> Result<()> {
+        self.throttle(group, key.len()).await;
+        let _write_guard = self.core.write_lock.read().await;
         self.core
             .log
             .push(LogEntry::Kv(Kv::Delete {
@@ -304,12 +607,205 @@ impl RaftLogStore {
         Ok(())
     }
 
+    /// Delete the log files that contain no live raft log entry of any group. Returns the count of
+    /// deleted log files.
+    ///
+    /// Before deleting, kv pairs, first indices and mask indices of all groups are rewritten to the
+    /// log, so that they survive the replay. Writes of all groups wait until the rewrite is done.
+    pub async fn gc(&self) -> Result<usize> {
+        let write_guard = self.core.write_lock.write().await;
+        let safe_file_id = match self.core.states.min_file_id().await {
+            Some(file_id) => file_id,
+            None => self.core.log.active_file_id().await,
+        };
+        if safe_file_id <= self.core.log.first_file_id().await {
+            return Ok(0);
+        }
+
+        let mut entries = vec![];
+        for dump in self.core.states.dump().await {
+            let GroupStateDump {
+                group,
+                first_index,
+                mask_index,
+                has_entries,
+                kvs,
+            } = dump;
+            if !has_entries {
+                entries.push(LogEntry::Snapshot(Snapshot {
+                    group,
+                    first_index,
+                    kvs,
+                }));
+                continue;
+            }
+            // Batches in the remaining log files may start before the first index.
+            entries.push(LogEntry::Compact(Compact {
+                group,
+                index: first_index,
+            }));
+            if mask_index > first_index {
+                entries.push(LogEntry::Mask(Mask {
+                    group,
+                    index: mask_index,
+                }));
+            }
+            for (key, value) in kvs {
+                entries.push(LogEntry::Kv(Kv::Put { group, key, value }));
+            }
+        }
+        if !entries.is_empty() {
+            self.core.log.push_many(entries).await?;
+        }
+        drop(write_guard);
+
+        let deleted = self.core.log.delete_files_before(safe_file_id).await?;
+        trace!("gc {} log files before {}", deleted, safe_file_id);
+        Ok(deleted)
+    }
+
+    /// Cross-check the raft log entries of the group in memory, including the masked ones, against
+    /// the log files. Returns [`RaftLogStoreError::CorruptedEntry`] on the first entry whose block
+    /// fails checksum verification or doesn't contain its data.
+    ///
+    /// Blocks are read from the log files directly, bypassing the block cache. It is a maintenance
+    /// tool and reads all entries of the group.
+    pub async fn verify(&self, group: u64) -> Result<()> {
+        let first_index = match self.core.states.first_index(group, true).await? {
+            Ok(index) => index,
+            Err(_) => return Ok(()),
+        };
+        let indices = self
+            .core
+            .states
+            .entries(group, first_index, usize::MAX)
+            .await?;
+        let first_file_id = self.core.log.first_file_id().await;
+        // Entries of a block are consecutive, only the last read block is kept.
+        let mut last_block: Option<((u64, usize), Vec<u8>)> = None;
+        for (i, ei) in indices.into_iter().enumerate() {
+            let corrupted = |reason: String| RaftLogStoreError::CorruptedEntry {
+                file_id: ei.file_id,
+                offset: ei.block_offset as u64,
+                reason: format!(
+                    "entry {} of group {}: {}",
+                    first_index + i as u64,
+                    group,
+                    reason
+                ),
+            };
+            let block_key = (ei.file_id, ei.block_offset);
+            if last_block.as_ref().map(|(key, _)| *key) != Some(block_key) {
+                if ei.file_id < first_file_id {
+                    return Err(corrupted(format!("log file {} is deleted", ei.file_id)).into());
+                }
+                let raw = self
+                    .core
+                    .log
+                    .read(ei.file_id, ei.block_offset as u64, ei.block_len)
+                    .await
+                    .map_err(|e| corrupted(e.to_string()))?;
+                // Compression algorithm (1B) and checksum (4B).
+                if raw.len() < 5 {
+                    return Err(
+                        corrupted(format!("block of len {} is too short", raw.len())).into(),
+                    );
+                }
+                let block = RaftLogBatch::extract_data_segment(&raw)
+                    .map_err(|e| corrupted(e.to_string()))?;
+                last_block = Some((block_key, block));
+            }
+            let block = &last_block.as_ref().unwrap().1;
+            if ei
+                .offset
+                .checked_add(ei.len)
+                .map_or(true, |end| end > block.len())
+            {
+                return Err(corrupted(format!(
+                    "data [{}, +{}) is out of block of len {}",
+                    ei.offset,
+                    ei.len,
+                    block.len()
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+
     pub async fn get(&self, group: u64, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
         self.core.states.get(group, key).await
     }
+
+    /// Get the value of `key` visible at timestamp `ts`, see [`RaftLogStore::kv_timestamp`].
+    ///
+    /// Only the latest versions are retained by default, see
+    /// [`RaftLogStore::retain_kv_versions`].
+    pub async fn get_at(&self, group: u64, key: Vec<u8>, ts: u64) -> Result<Option<Vec<u8>>> {
+        self.core.states.get_at(group, key, ts).await
+    }
+
+    /// Timestamp of the latest kv write of the group, which can be pinned for consistent reads.
+    ///
+    /// Note: Timestamps are not persisted, and restart after reopening.
+    pub async fn kv_timestamp(&self, group: u64) -> Result<u64> {
+        self.core.states.kv_timestamp(group).await
+    }
+
+    /// Count of raft log entries of the group, including the masked ones.
+    pub async fn log_entry_count(&self, group: u64) -> Result<usize> {
+        self.core.states.log_entry_count(group).await
+    }
+
+    /// Count of kv pairs of the group, deleted keys are not counted.
+    pub async fn kv_count(&self, group: u64) -> Result<usize> {
+        self.core.states.kv_count(group).await
+    }
+
+    /// Retain kv versions of the group visible at `watermark` or later. Only the latest versions
+    /// are retained if `watermark` is `None`.
+    pub async fn retain_kv_versions(&self, group: u64, watermark: Option<u64>) -> Result<()> {
+        self.core.states.retain_kv_versions(group, watermark).await
+    }
+
+    /// Get all kv pairs of the group, ordered by key.
+    pub async fn scan_kv(&self, group: u64) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.core.states.scan_kv(group).await
+    }
+
+    /// Get kv pairs of the group in `[start, end)`, ordered by key.
+    pub async fn scan_kv_range(
+        &self,
+        group: u64,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.core.states.scan_kv_range(group, start, end).await
+    }
 }
 
 impl RaftLogStore {
+    /// Lock `group` for an append or compaction. Returns [`RaftLogStoreError::GroupLocked`] if
+    /// it is held by a concurrent one.
+    fn lock_group(&self, group: u64) -> Result<OwnedMutexGuard<()>> {
+        let lock = self
+            .core
+            .group_locks
+            .lock()
+            .entry(group)
+            .or_default()
+            .clone();
+        lock.try_lock_owned()
+            .map_err(|_| RaftLogStoreError::GroupLocked(group).into())
+    }
+
+    /// Wait until the write of `bytes` to `group` is allowed by its rate limit, if any.
+    async fn throttle(&self, group: u64, bytes: usize) {
+        if let Some(rate_limiter) = self.core.rate_limiters.get(&group) {
+            rate_limiter.acquire(bytes).await;
+        }
+    }
+
     async fn entry_data(&self, index: &EntryIndex) -> Result<Vec<u8>> {
         trace!("read entry: {:?}", index);
         let log = self.core.log.clone();
@@ -322,7 +818,13 @@ impl RaftLogStore {
                     index_clone.block_len,
                 )
                 .await?;
-            let block = RaftLogBatch::extract_data_segment(&raw)?;
+            let block = RaftLogBatch::extract_data_segment(&raw).map_err(|e| {
+                RaftLogStoreError::CorruptedEntry {
+                    file_id: index_clone.file_id,
+                    offset: index_clone.block_offset as u64,
+                    reason: e.to_string(),
+                }
+            })?;
             Ok(Arc::new(block))
         };
 
@@ -339,11 +841,15 @@ impl RaftLogStore {
 #[cfg(test)]
 mod tests {
 
+    use std::sync::atomic::{AtomicBool, Ordering};
+
     use itertools::Itertools;
+    use runkv_common::coding::CompressionAlgorithm;
     use test_log::test;
 
     use super::*;
-    use crate::raft_log_store::entry::RaftLogBatchBuilder;
+    use crate::raft_log_store::entry::{RaftLogBatchBuilder, ENTRY_RECORD_HEADER_LEN};
+    use crate::raft_log_store::log::LOG_FILE_HEADER_LEN;
 
     fn is_send_sync<T: Send + Sync>() {}
 
@@ -358,19 +864,21 @@ mod tests {
         let mut builder = RaftLogBatchBuilder::default();
         for group in 1..=4 {
             for index in 1..=16 {
-                builder.add(group, 1, index, b"some-ctx", &data(group, 1, index));
+                builder
+                    .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                    .unwrap();
             }
         }
         let batches = builder.build();
         assert_eq!(batches.len(), 4);
 
         let tempdir = tempfile::tempdir().unwrap();
-        let options = RaftLogStoreOptions {
-            log_dir_path: tempdir.path().to_str().unwrap().to_string(),
+        let options = RaftLogStoreOptions::new(
+            tempdir.path().to_str().unwrap().to_string(),
             // Estimated size of each compressed entry is 111.
-            log_file_capacity: 100,
-            block_cache_capacity: 1024,
-        };
+            100,
+            1024,
+        );
 
         let store = RaftLogStore::open(options.clone()).await.unwrap();
         store.add_group(1).await.unwrap();
@@ -469,71 +977,1163 @@ mod tests {
     }
 
     #[test(tokio::test)]
-    async fn test_kv() {
-        let tempdir = tempfile::tempdir().unwrap();
-        let options = RaftLogStoreOptions {
-            log_dir_path: tempdir.path().to_str().unwrap().to_string(),
-            // Estimated size of each compressed entry is 111.
-            log_file_capacity: 100,
-            block_cache_capacity: 1024,
-        };
+    async fn test_append_many() {
+        let mut builder = RaftLogBatchBuilder::default();
+        for index in 1..=8 {
+            for group in 1..=4 {
+                builder
+                    .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                    .unwrap();
+            }
+        }
+        let batches = builder.build();
+        // Groups are interleaved, so each entry is in its own batch.
+        assert_eq!(batches.len(), 32);
 
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions::new(
+            tempdir.path().to_str().unwrap().to_string(),
+            1024 * 1024,
+            1024,
+        );
         let store = RaftLogStore::open(options.clone()).await.unwrap();
-        store.add_group(1).await.unwrap();
-        store.add_group(2).await.unwrap();
-        store.add_group(3).await.unwrap();
-        store.add_group(4).await.unwrap();
-
         for group in 1..=4 {
-            store
-                .put(group, b"k1".to_vec(), b"v1".to_vec())
-                .await
-                .unwrap();
+            store.add_group(group).await.unwrap();
         }
+        store.append_many(batches).await.unwrap();
 
         for group in 1..=4 {
+            let entries = store.entries(group, 1, usize::MAX).await.unwrap();
             assert_eq!(
-                store.get(group, b"k1".to_vec()).await.unwrap(),
-                Some(b"v1".to_vec())
+                entries.into_iter().map(|entry| entry.data).collect_vec(),
+                (1..=8).map(|index| data(group, 1, index)).collect_vec()
             );
         }
 
+        drop(store);
+        let store = RaftLogStore::open(options).await.unwrap();
         for group in 1..=4 {
-            store
-                .put(group, b"k1".to_vec(), b"v2".to_vec())
-                .await
+            let entries = store.entries(group, 1, usize::MAX).await.unwrap();
+            assert_eq!(
+                entries.into_iter().map(|entry| entry.data).collect_vec(),
+                (1..=8).map(|index| data(group, 1, index)).collect_vec()
+            );
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_ctx_replay() {
+        let mut builder = RaftLogBatchBuilder::default();
+        for index in 1..=8 {
+            builder
+                .add(1, 1, index, &ctx(index), &data(1, 1, index))
                 .unwrap();
         }
+        // Empty ctx.
+        builder.add(1, 1, 9, b"", &data(1, 1, 9)).unwrap();
+        let batches = builder.build();
 
-        for group in 1..=4 {
-            assert_eq!(
-                store.get(group, b"k1".to_vec()).await.unwrap(),
-                Some(b"v2".to_vec())
-            );
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions::new(
+            tempdir.path().to_str().unwrap().to_string(),
+            1024 * 1024,
+            1024,
+        );
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        for batch in batches {
+            store.append(batch).await.unwrap();
         }
 
         drop(store);
-        let store = RaftLogStore::open(options.clone()).await.unwrap();
-        for group in 1..=4 {
-            assert_eq!(
-                store.get(group, b"k1".to_vec()).await.unwrap(),
-                Some(b"v2".to_vec())
-            );
+        let store = RaftLogStore::open(options).await.unwrap();
+        for index in 1..=8 {
+            assert_eq!(store.ctx(1, index).await.unwrap(), Some(ctx(index)));
         }
+        assert_eq!(store.ctx(1, 9).await.unwrap(), Some(vec![]));
+        let entries = store.entries(1, 1, usize::MAX).await.unwrap();
+        assert_eq!(
+            entries.into_iter().map(|entry| entry.ctx).collect_vec(),
+            (1..=8)
+                .map(ctx)
+                .chain(std::iter::once(vec![]))
+                .collect_vec()
+        );
+    }
 
-        for group in 1..=4 {
-            store.delete(group, b"k1".to_vec()).await.unwrap();
+    #[test(tokio::test)]
+    async fn test_open_report() {
+        let mut builder = RaftLogBatchBuilder::default();
+        for group in 1..=2 {
+            for index in 1..=16 {
+                builder
+                    .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                    .unwrap();
+            }
         }
+        let batches = builder.build();
 
-        for group in 1..=4 {
-            assert_eq!(store.get(group, b"k1".to_vec()).await.unwrap(), None,);
+        let tempdir = tempfile::tempdir().unwrap();
+        let options =
+            RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 1024, 1024);
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        store.add_group(2).await.unwrap();
+        for batch in batches {
+            store.append(batch).await.unwrap();
+        }
+        store.put(1, b"k1".to_vec(), b"v1".to_vec()).await.unwrap();
+        store.put(2, b"k2".to_vec(), b"v2".to_vec()).await.unwrap();
+        store.delete(1, b"k1".to_vec()).await.unwrap();
+        store.compact(1, 9).await.unwrap();
+
+        // Indices 5..=8 are compacted and will be skipped.
+        let mut builder = RaftLogBatchBuilder::default();
+        for index in 5..=10 {
+            builder
+                .add(1, 1, index, b"some-ctx", &data(1, 1, index))
+                .unwrap();
+        }
+        for batch in builder.build() {
+            store.append(batch).await.unwrap();
         }
 
         drop(store);
+        let (store, report) = RaftLogStore::open_with_report(options).await.unwrap();
+        assert_eq!(
+            report,
+            OpenReport {
+                groups_recovered: 2,
+                entries_replayed: 38,
+                kv_ops_replayed: 3,
+                skipped_outdated: 4,
+                truncated_bytes: 0,
+            }
+        );
+        let entries = store.entries(1, 9, usize::MAX).await.unwrap();
+        assert_eq!(
+            entries.into_iter().map(|entry| entry.data).collect_vec(),
+            (9..=16).map(|index| data(1, 1, index)).collect_vec()
+        );
+    }
+
+    #[allow(clippy::type_complexity)]
+    async fn dump_for_test(
+        store: &RaftLogStore,
+        groups: &[u64],
+    ) -> Vec<(
+        GroupSnapshotMeta,
+        Vec<(u64, u64, Vec<u8>, Vec<u8>)>,
+        Vec<(Vec<u8>, Vec<u8>)>,
+    )> {
+        let mut dump = vec![];
+        for meta in store.snapshot(groups).await.unwrap() {
+            let entries = match store.first_index(meta.group, true).await.unwrap() {
+                Ok(first_index) => store
+                    .entries(meta.group, first_index, usize::MAX)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|entry| (entry.term, entry.index, entry.ctx, entry.data))
+                    .collect_vec(),
+                Err(_) => vec![],
+            };
+            let kvs = store.scan_kv(meta.group).await.unwrap();
+            dump.push((meta, entries, kvs));
+        }
+        dump
+    }
+
+    #[test(tokio::test)]
+    async fn test_parallel_replay() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut options = RaftLogStoreOptions {
+            replay_concurrency: 1,
+            ..RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 1024, 1024)
+        };
         let store = RaftLogStore::open(options.clone()).await.unwrap();
+        let mut builder = RaftLogBatchBuilder::default();
         for group in 1..=4 {
-            assert_eq!(store.get(group, b"k1".to_vec()).await.unwrap(), None,);
+            store.add_group(group).await.unwrap();
+            for index in 1..=32 {
+                builder
+                    .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                    .unwrap();
+            }
+        }
+        for batch in builder.build() {
+            store.append(batch).await.unwrap();
+        }
+        // Operations of each group, which must be replayed in order.
+        store.truncate(2, 20).await.unwrap();
+        let mut builder = RaftLogBatchBuilder::default();
+        for index in 20..=24 {
+            builder
+                .add(2, 2, index, b"some-ctx", &data(2, 2, index))
+                .unwrap();
         }
+        for batch in builder.build() {
+            store.append(batch).await.unwrap();
+        }
+        store.compact(3, 10).await.unwrap();
+        store.mask(4, 5).await.unwrap();
+        for group in 1..=4 {
+            store
+                .put(group, b"k1".to_vec(), b"v1".to_vec())
+                .await
+                .unwrap();
+            store
+                .put(group, b"k2".to_vec(), b"v2".to_vec())
+                .await
+                .unwrap();
+            store.delete(group, b"k1".to_vec()).await.unwrap();
+            store
+                .put(group, b"k2".to_vec(), format!("v2-{}", group).into_bytes())
+                .await
+                .unwrap();
+        }
+        let expected = dump_for_test(&store, &[1, 2, 3, 4]).await;
+        drop(store);
+
+        let (store, serial_report) = RaftLogStore::open_with_report(options.clone())
+            .await
+            .unwrap();
+        assert_eq!(dump_for_test(&store, &[1, 2, 3, 4]).await, expected);
+        drop(store);
+
+        for replay_concurrency in [2, 4, 8] {
+            options.replay_concurrency = replay_concurrency;
+            let (store, report) = RaftLogStore::open_with_report(options.clone())
+                .await
+                .unwrap();
+            assert_eq!(report, serial_report);
+            assert_eq!(dump_for_test(&store, &[1, 2, 3, 4]).await, expected);
+            drop(store);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_parallel_replay_single_group() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut options = RaftLogStoreOptions {
+            replay_concurrency: 1,
+            ..RaftLogStoreOptions::new(
+                tempdir.path().to_str().unwrap().to_string(),
+                64 * 1024,
+                1024,
+            )
+        };
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        let mut builder = RaftLogBatchBuilder::default();
+        for index in 1..=4096 {
+            builder
+                .add(1, 1, index, b"some-ctx", &data(1, 1, index))
+                .unwrap();
+        }
+        for batch in builder.build() {
+            store.append(batch).await.unwrap();
+        }
+        drop(store);
+
+        let (store, serial_report) = RaftLogStore::open_with_report(options.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.next_index(1, true).await.unwrap(), Ok(4097));
+        drop(store);
+
+        // All entries go to a single replay task.
+        options.replay_concurrency = DEFAULT_REPLAY_CONCURRENCY;
+        let (store, report) = RaftLogStore::open_with_report(options).await.unwrap();
+        assert_eq!(report, serial_report);
+        assert_eq!(store.next_index(1, true).await.unwrap(), Ok(4097));
+    }
+
+    #[test(tokio::test)]
+    async fn test_torn_write_recovery() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions::new(
+            tempdir.path().to_str().unwrap().to_string(),
+            1024 * 1024,
+            1024,
+        );
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        for index in 1..=8 {
+            let mut builder = RaftLogBatchBuilder::default();
+            builder
+                .add(1, 1, index, b"some-ctx", &data(1, 1, index))
+                .unwrap();
+            for batch in builder.build() {
+                store.append(batch).await.unwrap();
+            }
+        }
+        drop(store);
+
+        // Cut the last few bytes of the log file, as if the last write is torn.
+        let path = tempdir.path().join("00000001");
+        let mut buf = std::fs::read(&path).unwrap();
+        buf.truncate(buf.len() - 3);
+        std::fs::write(&path, &buf).unwrap();
+
+        let (store, report) = RaftLogStore::open_with_report(options.clone())
+            .await
+            .unwrap();
+        assert_eq!(report.entries_replayed, 7);
+        assert!(report.truncated_bytes > 0);
+        let entries = store.entries(1, 1, usize::MAX).await.unwrap();
+        assert_eq!(
+            entries.into_iter().map(|entry| entry.data).collect_vec(),
+            (1..=7).map(|index| data(1, 1, index)).collect_vec()
+        );
+
+        // The torn entry can be appended again.
+        let mut builder = RaftLogBatchBuilder::default();
+        builder.add(1, 1, 8, b"some-ctx", &data(1, 1, 8)).unwrap();
+        for batch in builder.build() {
+            store.append(batch).await.unwrap();
+        }
+        drop(store);
+
+        let (store, report) = RaftLogStore::open_with_report(options).await.unwrap();
+        assert_eq!(report.entries_replayed, 8);
+        assert_eq!(report.truncated_bytes, 0);
+        let entries = store.entries(1, 1, usize::MAX).await.unwrap();
+        assert_eq!(
+            entries.into_iter().map(|entry| entry.data).collect_vec(),
+            (1..=8).map(|index| data(1, 1, index)).collect_vec()
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_corrupted_last_log_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions::new(
+            tempdir.path().to_str().unwrap().to_string(),
+            1024 * 1024,
+            1024,
+        );
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        for index in 1..=8 {
+            let mut builder = RaftLogBatchBuilder::default();
+            builder
+                .add(1, 1, index, b"some-ctx", &data(1, 1, index))
+                .unwrap();
+            for batch in builder.build() {
+                store.append(batch).await.unwrap();
+            }
+        }
+        drop(store);
+
+        // Corrupt the last byte of the first record, which is followed by other records.
+        let path = tempdir.path().join("00000001");
+        let mut buf = std::fs::read(&path).unwrap();
+        let len = u32::from_le_bytes(
+            buf[LOG_FILE_HEADER_LEN..LOG_FILE_HEADER_LEN + 4]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        buf[LOG_FILE_HEADER_LEN + ENTRY_RECORD_HEADER_LEN + len - 1] ^= 0xff;
+        std::fs::write(&path, &buf).unwrap();
+
+        let err = RaftLogStore::open(options.clone()).await.err().unwrap();
+        assert!(
+            matches!(
+                err,
+                Error::RaftLogStoreError(RaftLogStoreError::CorruptedEntry { file_id: 1, .. })
+            ),
+            "{:?}",
+            err
+        );
+        // Nothing is truncated.
+        assert_eq!(std::fs::read(&path).unwrap(), buf);
+
+        // Log files written in the format without header are refused.
+        std::fs::write(&path, &buf[LOG_FILE_HEADER_LEN..]).unwrap();
+        let err = RaftLogStore::open(options).await.err().unwrap();
+        assert!(
+            matches!(
+                err,
+                Error::RaftLogStoreError(RaftLogStoreError::DecodeError(_))
+            ),
+            "{:?}",
+            err
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), &buf[LOG_FILE_HEADER_LEN..]);
+    }
+
+    #[test(tokio::test(flavor = "multi_thread"))]
+    async fn test_gc_concurrent_writes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options =
+            RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 100, 1024);
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        let keys = (0..64)
+            .map(|i| format!("k{:02}", i).into_bytes())
+            .collect_vec();
+        for key in keys.iter() {
+            store.put(1, key.clone(), b"v".to_vec()).await.unwrap();
+        }
+
+        // Deleted keys must not be resurrected by the kv pairs rewritten by gc.
+        let done = Arc::new(AtomicBool::new(false));
+        let deletes = {
+            let store = store.clone();
+            let keys = keys.clone();
+            let done = done.clone();
+            tokio::spawn(async move {
+                for key in keys {
+                    store.delete(1, key).await.unwrap();
+                }
+                done.store(true, Ordering::SeqCst);
+            })
+        };
+        while !done.load(Ordering::SeqCst) {
+            store.gc().await.unwrap();
+        }
+        deletes.await.unwrap();
+        drop(store);
+
+        let store = RaftLogStore::open(options).await.unwrap();
+        for key in keys {
+            assert_eq!(store.get(1, key).await.unwrap(), None);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_gc() {
+        let mut builder = RaftLogBatchBuilder::default();
+        for group in 1..=4 {
+            for index in 1..=16 {
+                builder
+                    .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                    .unwrap();
+            }
+        }
+        let batches = builder.build();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions::new(
+            tempdir.path().to_str().unwrap().to_string(),
+            // Estimated size of each compressed entry is 111.
+            100,
+            1024,
+        );
+
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        for group in 1..=4 {
+            store.add_group(group).await.unwrap();
+        }
+        store.put(1, b"k1".to_vec(), b"v1".to_vec()).await.unwrap();
+        for batch in batches {
+            store.append(batch).await.unwrap();
+        }
+        // The batch of group `g` is in log file `g`, the kv pair is in log file 1.
+        assert_eq!(store.core.log.frozen_file_count().await, 4);
+        // Nothing to delete before compaction.
+        assert_eq!(store.gc().await.unwrap(), 0);
+
+        store.compact(1, 17).await.unwrap();
+        store.compact(2, 9).await.unwrap();
+        assert_eq!(store.gc().await.unwrap(), 1);
+        store.compact(2, 17).await.unwrap();
+        assert_eq!(store.gc().await.unwrap(), 1);
+        store.mask(3, 5).await.unwrap();
+        store.compact(4, 9).await.unwrap();
+
+        drop(store);
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        for group in 1..=2 {
+            assert_eq!(store.first_index(group, true).await.unwrap(), Err(17));
+        }
+        assert_eq!(store.first_index(3, true).await.unwrap(), Ok(1));
+        assert_eq!(store.first_index(3, false).await.unwrap(), Ok(5));
+        assert_eq!(store.first_index(4, true).await.unwrap(), Ok(9));
+        for group in 1..=2 {
+            assert_eq!(store.next_index(group, true).await.unwrap(), Err(17));
+        }
+        for group in 3..=4 {
+            assert_eq!(store.next_index(group, false).await.unwrap(), Ok(17));
+        }
+        for group in 3..=4 {
+            let entries = store.entries(group, 9, usize::MAX).await.unwrap();
+            assert_eq!(
+                entries.into_iter().map(|entry| entry.data).collect_vec(),
+                (9..=16).map(|index| data(group, 1, index)).collect_vec()
+            );
+        }
+
+        store.compact(3, 17).await.unwrap();
+        store.compact(4, 17).await.unwrap();
+        assert!(store.gc().await.unwrap() > 0);
+        // Only the log file with the rewritten states may be left.
+        assert!(store.core.log.frozen_file_count().await <= 1);
+
+        drop(store);
+        let store = RaftLogStore::open(options).await.unwrap();
+        for group in 1..=4 {
+            assert_eq!(store.first_index(group, true).await.unwrap(), Err(17));
+        }
+        assert_eq!(
+            store.get(1, b"k1".to_vec()).await.unwrap(),
+            Some(b"v1".to_vec())
+        );
+        let mut builder = RaftLogBatchBuilder::default();
+        builder.add(1, 1, 17, b"some-ctx", &data(1, 1, 17)).unwrap();
+        for batch in builder.build() {
+            store.append(batch).await.unwrap();
+        }
+        let entries = store.entries(1, 17, usize::MAX).await.unwrap();
+        assert_eq!(entries[0].data, data(1, 1, 17));
+    }
+
+    #[test(tokio::test)]
+    async fn test_compression_algorithm() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options =
+            RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 1024, 1024);
+        let mut store = RaftLogStore::open(options.clone()).await.unwrap();
+        let algorithms = [CompressionAlgorithm::None, CompressionAlgorithm::Lz4];
+        for (group, compression_algorithm) in (1..).zip(algorithms) {
+            store.add_group(group).await.unwrap();
+            let mut builder = RaftLogBatchBuilder::new(RaftLogBatchBuilderOptions {
+                compression_algorithm,
+                ..Default::default()
+            });
+            for index in 1..=16 {
+                builder
+                    .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                    .unwrap();
+            }
+            for batch in builder.build() {
+                store.append(batch).await.unwrap();
+            }
+        }
+
+        // Batches are read back both before and after replaying the log.
+        for reopen in [false, true] {
+            if reopen {
+                drop(store);
+                store = RaftLogStore::open(options.clone()).await.unwrap();
+            }
+            for group in 1..=algorithms.len() as u64 {
+                let entries = store.entries(group, 1, usize::MAX).await.unwrap();
+                assert_eq!(
+                    entries.into_iter().map(|entry| entry.data).collect_vec(),
+                    (1..=16).map(|index| data(group, 1, index)).collect_vec()
+                );
+            }
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_snapshot() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options =
+            RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 64 << 10, 1024);
+        let store = RaftLogStore::open(options).await.unwrap();
+        store.add_group(1).await.unwrap();
+        store.add_group(2).await.unwrap();
+        assert!(store.snapshot(&[1, 3]).await.is_err());
+
+        // Append to both groups and compact to keep 4 entries of each.
+        let done = Arc::new(AtomicBool::new(false));
+        let appender = tokio::spawn({
+            let store = store.clone();
+            let done = done.clone();
+            async move {
+                for index in 1..=64 {
+                    for group in 1..=2 {
+                        let mut builder = RaftLogBatchBuilder::default();
+                        builder
+                            .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                            .unwrap();
+                        for batch in builder.build() {
+                            store.append(batch).await.unwrap();
+                        }
+                        if index > 4 {
+                            store.compact(group, index - 3).await.unwrap();
+                        }
+                    }
+                }
+                done.store(true, Ordering::SeqCst);
+            }
+        });
+
+        let mut last = vec![(0, 0); 2];
+        loop {
+            let finished = done.load(Ordering::SeqCst);
+            let snapshot = store.snapshot(&[1, 2]).await.unwrap();
+            for (meta, last) in snapshot.iter().zip(last.iter_mut()) {
+                assert!(meta.first_index <= meta.next_index);
+                // One more entry is appended before compaction.
+                assert!(meta.next_index - meta.first_index <= 5);
+                assert!(meta.first_index >= last.0 && meta.next_index >= last.1);
+                *last = (meta.first_index, meta.next_index);
+            }
+            // Group 1 is always appended first.
+            assert!(snapshot[0].next_index >= snapshot[1].next_index);
+            assert!(snapshot[0].next_index <= snapshot[1].next_index + 1);
+            if finished {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        appender.await.unwrap();
+        assert_eq!(last, vec![(61, 65); 2]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_sync() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions {
+            group_commit_window: Duration::from_millis(10),
+            ..RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 1024, 1024)
+        };
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        let mut builder = RaftLogBatchBuilder::default();
+        for index in 1..=16 {
+            builder
+                .add(1, 1, index, b"some-ctx", &data(1, 1, index))
+                .unwrap();
+        }
+        for batch in builder.build() {
+            store.append(batch).await.unwrap();
+        }
+        store.put(1, b"k1".to_vec(), b"v1".to_vec()).await.unwrap();
+        store.sync().await.unwrap();
+        // Syncing without new writes is fine.
+        store.sync().await.unwrap();
+
+        // Crash without closing the log.
+        drop(store);
+        let store = RaftLogStore::open(options).await.unwrap();
+        let entries = store.entries(1, 1, usize::MAX).await.unwrap();
+        assert_eq!(
+            entries.into_iter().map(|entry| entry.data).collect_vec(),
+            (1..=16).map(|index| data(1, 1, index)).collect_vec()
+        );
+        assert_eq!(
+            store.get(1, b"k1".to_vec()).await.unwrap(),
+            Some(b"v1".to_vec())
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_concurrent_append() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions {
+            // Keep the first append in flight while the second one begins.
+            group_commit_window: Duration::from_millis(100),
+            ..RaftLogStoreOptions::new(
+                tempdir.path().to_str().unwrap().to_string(),
+                1024 * 1024,
+                1024,
+            )
+        };
+        let store = RaftLogStore::open(options).await.unwrap();
+        store.add_group(1).await.unwrap();
+        store.add_group(2).await.unwrap();
+        let batch = |group, index| {
+            let mut builder = RaftLogBatchBuilder::default();
+            builder
+                .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                .unwrap();
+            builder.build().remove(0)
+        };
+
+        let (r1, r2, r3) = tokio::join!(
+            store.append(batch(1, 1)),
+            store.append(batch(1, 1)),
+            store.append(batch(2, 1))
+        );
+        r1.unwrap();
+        assert!(matches!(
+            r2,
+            Err(Error::RaftLogStoreError(RaftLogStoreError::GroupLocked(1)))
+        ));
+        // Other groups are not affected.
+        r3.unwrap();
+        let (r1, r2) = tokio::join!(store.compact(1, 2), store.append(batch(1, 2)));
+        r1.unwrap();
+        assert!(matches!(
+            r2,
+            Err(Error::RaftLogStoreError(RaftLogStoreError::GroupLocked(1)))
+        ));
+
+        // Locks are released after the operations return.
+        store.append(batch(1, 2)).await.unwrap();
+        store.compact(2, 2).await.unwrap();
+    }
+
+    #[test(tokio::test)]
+    async fn test_group_write_limits() {
+        tokio::time::pause();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions {
+            group_write_limits: BTreeMap::from_iter([(1, WriteRateLimit::OpsPerSec(10))]),
+            ..RaftLogStoreOptions::new(
+                tempdir.path().to_str().unwrap().to_string(),
+                1024 * 1024,
+                1024,
+            )
+        };
+        let store = RaftLogStore::open(options).await.unwrap();
+        store.add_group(1).await.unwrap();
+        store.add_group(2).await.unwrap();
+        let append = |group, index| {
+            let store = store.clone();
+            async move {
+                let mut builder = RaftLogBatchBuilder::default();
+                builder
+                    .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                    .unwrap();
+                for batch in builder.build() {
+                    store.append(batch).await.unwrap();
+                }
+            }
+        };
+
+        // The unthrottled group proceeds freely.
+        let start = tokio::time::Instant::now();
+        for index in 1..=30 {
+            append(2, index).await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // The throttled group is allowed 10 ops by the full bucket, then paced at 10 ops/s.
+        let start = tokio::time::Instant::now();
+        for index in 1..=30 {
+            append(1, index).await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(1990));
+        store.put(1, b"k".to_vec(), b"v".to_vec()).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(2090));
+        assert_eq!(store.entries(1, 1, usize::MAX).await.unwrap().len(), 30);
+    }
+
+    #[test(tokio::test)]
+    async fn test_corrupted_entry() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options =
+            RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 1024, 1024);
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        for index in 1..=32 {
+            let mut builder = RaftLogBatchBuilder::default();
+            builder
+                .add(1, 1, index, b"some-ctx", &data(1, 1, index))
+                .unwrap();
+            for batch in builder.build() {
+                store.append(batch).await.unwrap();
+            }
+        }
+        drop(store);
+        assert!(tempdir.path().join("00000002").exists());
+
+        let corrupt = |name: &str| {
+            let path = tempdir.path().join(name);
+            let mut buf = std::fs::read(&path).unwrap();
+            let len = buf.len();
+            buf[len - 1] ^= 0xff;
+            std::fs::write(&path, &buf).unwrap();
+        };
+
+        // Corrupted data segment is reported with its location when read.
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        corrupt("00000001");
+        let err = store.entries(1, 1, usize::MAX).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::RaftLogStoreError(RaftLogStoreError::CorruptedEntry { file_id: 1, .. })
+            ),
+            "{:?}",
+            err
+        );
+        drop(store);
+
+        // Corrupted record that is not the tail of the log fails the replay.
+        let err = RaftLogStore::open(options).await.err().unwrap();
+        assert!(
+            matches!(
+                err,
+                Error::RaftLogStoreError(RaftLogStoreError::CorruptedEntry { file_id: 1, .. })
+            ),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_verify() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options =
+            RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 1024, 1024);
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        for group in 1..=2 {
+            store.add_group(group).await.unwrap();
+            for index in 1..=32 {
+                let mut builder = RaftLogBatchBuilder::default();
+                builder
+                    .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                    .unwrap();
+                for batch in builder.build() {
+                    store.append(batch).await.unwrap();
+                }
+            }
+        }
+        store.verify(1).await.unwrap();
+        store.verify(2).await.unwrap();
+        assert!(store.verify(3).await.is_err());
+
+        // Index out of its block.
+        let mut index = store.core.states.entries(1, 32, 1).await.unwrap().remove(0);
+        index.len += 1;
+        store.core.states.append(1, 33, vec![index]).await.unwrap();
+        let err = store.verify(1).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::RaftLogStoreError(RaftLogStoreError::CorruptedEntry { ref reason, .. })
+                    if reason.starts_with("entry 33 of group 1")
+            ),
+            "{:?}",
+            err
+        );
+        store.verify(2).await.unwrap();
+        drop(store);
+
+        // Corrupted block.
+        let store = RaftLogStore::open(options).await.unwrap();
+        store.verify(1).await.unwrap();
+        let path = tempdir.path().join("00000001");
+        let mut buf = std::fs::read(&path).unwrap();
+        let len = buf.len();
+        buf[len - 1] ^= 0xff;
+        std::fs::write(&path, &buf).unwrap();
+        assert!(matches!(
+            store.verify(1).await,
+            Err(Error::RaftLogStoreError(
+                RaftLogStoreError::CorruptedEntry { file_id: 1, .. }
+            ))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_entries_stream() {
+        let mut builder = RaftLogBatchBuilder::default();
+        for index in 1..=64 {
+            builder
+                .add(1, 1, index, b"some-ctx", &data(1, 1, index))
+                .unwrap();
+        }
+        let batches = builder.build();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let options =
+            RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 1024, 1024);
+        let store = RaftLogStore::open(options).await.unwrap();
+        store.add_group(1).await.unwrap();
+        for batch in batches {
+            store.append(batch).await.unwrap();
+        }
+        store.compact(1, 5).await.unwrap();
+
+        for (index, max_len) in [(5, usize::MAX), (5, 10), (20, 40), (64, 1)] {
+            let entries = store.entries(1, index, max_len).await.unwrap();
+            let streamed: Vec<(u64, Vec<u8>)> = store
+                .entries_stream(1, index, max_len)
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(
+                entries
+                    .into_iter()
+                    .map(|entry| (entry.index, entry.data))
+                    .collect_vec(),
+                streamed
+            );
+        }
+        assert!(store.entries(1, 4, usize::MAX).await.is_err());
+        assert!(store
+            .entries_stream(1, 4, usize::MAX)
+            .try_collect::<Vec<_>>()
+            .await
+            .is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_mask() {
+        let mut builder = RaftLogBatchBuilder::default();
+        for index in 1..=16 {
+            builder
+                .add(1, 1, index, b"some-ctx", &data(1, 1, index))
+                .unwrap();
+        }
+        let batches = builder.build();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let options =
+            RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 1024, 1024);
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        for batch in batches {
+            store.append(batch).await.unwrap();
+        }
+        store.mask(1, 9).await.unwrap();
+
+        for store in [store, RaftLogStore::open(options).await.unwrap()] {
+            let indices =
+                |entries: Vec<Entry>| entries.into_iter().map(|entry| entry.index).collect_vec();
+            // Masked entries are skipped by masked reads.
+            assert!(store.may_entries(1, 1, 8, false).await.unwrap().is_empty());
+            assert_eq!(
+                indices(store.may_entries(1, 1, usize::MAX, false).await.unwrap()),
+                (9..=16).collect_vec()
+            );
+            // Masked entries are still accessible with unmask.
+            let entries = store.unmask_entries(1, 1, usize::MAX).await.unwrap();
+            assert_eq!(
+                entries.iter().map(|entry| entry.data.clone()).collect_vec(),
+                (1..=16).map(|index| data(1, 1, index)).collect_vec()
+            );
+            assert_eq!(
+                indices(store.unmask_entries(1, 5, 8).await.unwrap()),
+                (5..13).collect_vec()
+            );
+            assert_eq!(store.first_index(1, false).await.unwrap(), Ok(9));
+            assert_eq!(store.first_index(1, true).await.unwrap(), Ok(1));
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_entries_stream_partial() {
+        // Put each entry in a block of its own, so reads can be told apart by the block cache.
+        let mut builder = RaftLogBatchBuilder::new(RaftLogBatchBuilderOptions {
+            max_batch_len: 1,
+            ..Default::default()
+        });
+        for index in 1..=64 {
+            builder
+                .add(1, 1, index, b"some-ctx", &data(1, 1, index))
+                .unwrap();
+        }
+        let batches = builder.build();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions::new(
+            tempdir.path().to_str().unwrap().to_string(),
+            64 * 1024,
+            1024 * 1024,
+        );
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        for batch in batches {
+            store.append(batch).await.unwrap();
+        }
+        // Reopen with an empty block cache, which is filled by appends.
+        drop(store);
+        let store = RaftLogStore::open(options).await.unwrap();
+
+        let consumed = 4;
+        let mut stream = Box::pin(store.entries_stream(1, 1, usize::MAX));
+        for index in 1..=consumed {
+            assert_eq!(
+                stream.next().await.unwrap().unwrap(),
+                (index, data(1, 1, index))
+            );
+        }
+        drop(stream);
+
+        let indices = store.core.states.entries(1, 1, usize::MAX).await.unwrap();
+        let is_read = |index: u64| {
+            let ei = &indices[index as usize - 1];
+            store
+                .core
+                .block_cache
+                .get(ei.file_id, ei.block_offset)
+                .is_some()
+        };
+        assert!((1..=consumed).all(is_read));
+        // Nothing is read beyond the read-ahead window of the consumed prefix.
+        assert!(!(consumed + ENTRIES_STREAM_READ_AHEAD as u64 + 1..=64).any(is_read));
+    }
+
+    #[test(tokio::test)]
+    async fn test_apply_snapshot() {
+        let mut builder = RaftLogBatchBuilder::default();
+        for group in 1..=2 {
+            for index in 1..=16 {
+                builder
+                    .add(group, 1, index, b"some-ctx", &data(group, 1, index))
+                    .unwrap();
+            }
+        }
+        let batches = builder.build();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let options =
+            RaftLogStoreOptions::new(tempdir.path().to_str().unwrap().to_string(), 1024, 1024);
+        let mut store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        store.add_group(2).await.unwrap();
+        for batch in batches {
+            store.append(batch).await.unwrap();
+        }
+        for group in 1..=2 {
+            store
+                .put(group, b"k1".to_vec(), b"v1".to_vec())
+                .await
+                .unwrap();
+            store
+                .put(group, b"k2".to_vec(), b"v2".to_vec())
+                .await
+                .unwrap();
+        }
+
+        store
+            .apply_snapshot(1, 17, vec![(b"k3".to_vec(), b"v3".to_vec())])
+            .await
+            .unwrap();
+        // Writes after the snapshot.
+        store.put(1, b"k4".to_vec(), b"v4".to_vec()).await.unwrap();
+        let mut builder = RaftLogBatchBuilder::default();
+        for index in 17..=20 {
+            builder
+                .add(1, 2, index, b"some-ctx", &data(1, 2, index))
+                .unwrap();
+        }
+        for batch in builder.build() {
+            store.append(batch).await.unwrap();
+        }
+
+        for i in 0..2 {
+            if i == 1 {
+                drop(store);
+                store = RaftLogStore::open(options.clone()).await.unwrap();
+            }
+            assert_eq!(
+                store.scan_kv(1).await.unwrap(),
+                vec![
+                    (b"k3".to_vec(), b"v3".to_vec()),
+                    (b"k4".to_vec(), b"v4".to_vec())
+                ]
+            );
+            assert_eq!(store.first_index(1, true).await.unwrap(), Ok(17));
+            assert_eq!(
+                store.lookup_term(1, 16).await.unwrap(),
+                TermLookup::Compacted
+            );
+            assert!(store.entries(1, 16, usize::MAX).await.is_err());
+            let entries = store.entries(1, 17, usize::MAX).await.unwrap();
+            assert_eq!(
+                entries.into_iter().map(|entry| entry.data).collect_vec(),
+                (17..=20).map(|index| data(1, 2, index)).collect_vec()
+            );
+
+            // Other groups are not affected.
+            assert_eq!(
+                store.scan_kv(2).await.unwrap(),
+                vec![
+                    (b"k1".to_vec(), b"v1".to_vec()),
+                    (b"k2".to_vec(), b"v2".to_vec())
+                ]
+            );
+            let entries = store.entries(2, 1, usize::MAX).await.unwrap();
+            assert_eq!(
+                entries.into_iter().map(|entry| entry.data).collect_vec(),
+                (1..=16).map(|index| data(2, 1, index)).collect_vec()
+            );
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_kv() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let options = RaftLogStoreOptions::new(
+            tempdir.path().to_str().unwrap().to_string(),
+            // Estimated size of each compressed entry is 111.
+            100,
+            1024,
+        );
+
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        store.add_group(1).await.unwrap();
+        store.add_group(2).await.unwrap();
+        store.add_group(3).await.unwrap();
+        store.add_group(4).await.unwrap();
+
+        for group in 1..=4 {
+            store
+                .put(group, b"k1".to_vec(), b"v1".to_vec())
+                .await
+                .unwrap();
+        }
+
+        for group in 1..=4 {
+            assert_eq!(
+                store.get(group, b"k1".to_vec()).await.unwrap(),
+                Some(b"v1".to_vec())
+            );
+        }
+
+        for group in 1..=4 {
+            store
+                .put(group, b"k1".to_vec(), b"v2".to_vec())
+                .await
+                .unwrap();
+        }
+
+        for group in 1..=4 {
+            assert_eq!(
+                store.get(group, b"k1".to_vec()).await.unwrap(),
+                Some(b"v2".to_vec())
+            );
+        }
+
+        drop(store);
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        for group in 1..=4 {
+            assert_eq!(
+                store.get(group, b"k1".to_vec()).await.unwrap(),
+                Some(b"v2".to_vec())
+            );
+        }
+
+        for group in 1..=4 {
+            assert_eq!(
+                store.scan_kv(group).await.unwrap(),
+                vec![(b"k1".to_vec(), b"v2".to_vec())]
+            );
+            assert!(store
+                .scan_kv_range(group, b"k2", b"k3")
+                .await
+                .unwrap()
+                .is_empty());
+        }
+
+        for group in 1..=4 {
+            store.delete(group, b"k1".to_vec()).await.unwrap();
+        }
+
+        for group in 1..=4 {
+            assert_eq!(store.get(group, b"k1".to_vec()).await.unwrap(), None,);
+        }
+
+        drop(store);
+        let store = RaftLogStore::open(options.clone()).await.unwrap();
+        for group in 1..=4 {
+            assert_eq!(store.get(group, b"k1".to_vec()).await.unwrap(), None,);
+        }
+    }
+
+    fn ctx(index: u64) -> Vec<u8> {
+        format!("ctx-{}", index).into_bytes()
     }
 
     fn data(group: u64, term: u64, index: u64) -> Vec<u8> {
@@ -58,8 +58,7 @@ impl<'a> Bloom<'a> {
     pub fn bloom_bits_per_key(entries: usize, false_positive_rate: f64) -> usize {
         let size =
             -1.0 * (entries as f64) * false_positive_rate.ln() / std::f64::consts::LN_2.powi(2);
-        let locs = (std::f64::consts::LN_2 * size / (entries as f64)).ceil();
-        locs as usize
+        (size / (entries as f64)).ceil() as usize
     }
 
     /// Build bloom filter from key hashes
@@ -106,10 +105,22 @@ impl<'a> Bloom<'a> {
             true
         }
     }
+
+    /// Check if a bloom filter may contain any of the data
+    pub fn may_match_any(&self, hashes: &[u32]) -> bool {
+        hashes.iter().any(|h| self.may_contain(*h))
+    }
+
+    /// Check if a bloom filter may contain each of the data
+    pub fn may_match_each(&self, hashes: &[u32]) -> Vec<bool> {
+        hashes.iter().map(|h| self.may_contain(*h)).collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use test_log::test;
 
     use super::*;
@@ -140,4 +151,79 @@ mod tests {
         assert!(!f.may_contain(check_hash[2]));
         assert!(!f.may_contain(check_hash[3]));
     }
+
+    #[test]
+    fn test_may_match_multiple() {
+        let hash: Vec<u32> = vec![b"hello".to_vec(), b"world".to_vec()]
+            .into_iter()
+            .map(|x| farmhash::fingerprint32(&x))
+            .collect();
+        let buf = Bloom::build_from_key_hashes(&hash, 10);
+        let f = Bloom::new(&buf);
+
+        let check_hash: Vec<u32> = vec![
+            b"x".to_vec(),
+            b"hello".to_vec(),
+            b"fool".to_vec(),
+            b"world".to_vec(),
+        ]
+        .into_iter()
+        .map(|x| farmhash::fingerprint32(&x))
+        .collect();
+
+        assert_eq!(
+            f.may_match_each(&check_hash),
+            vec![false, true, false, true]
+        );
+        for i in 0..check_hash.len() {
+            for j in i..=check_hash.len() {
+                let hashes = &check_hash[i..j];
+                assert_eq!(
+                    f.may_match_any(hashes),
+                    f.may_match_each(hashes).into_iter().any(|m| m)
+                );
+            }
+        }
+        assert!(!f.may_match_any(&[check_hash[0], check_hash[2]]));
+        assert!(!f.may_match_any(&[]));
+        assert!(f.may_match_each(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_bloom_false_positive_rate() {
+        const KEYS: usize = 10000;
+
+        // A fixed seed keeps the empirical rates, and thus the assertions, reproducible.
+        let mut rng = StdRng::seed_from_u64(0);
+        // Keys of different lengths never collide.
+        let keys = (0..KEYS)
+            .map(|_| rng.gen::<[u8; 16]>().to_vec())
+            .collect::<Vec<_>>();
+        let absent_keys = (0..KEYS)
+            .map(|_| rng.gen::<[u8; 17]>().to_vec())
+            .collect::<Vec<_>>();
+        let hashes = keys
+            .iter()
+            .map(|key| farmhash::fingerprint32(&key[..]))
+            .collect::<Vec<_>>();
+
+        for false_positive_rate in [0.1, 0.05, 0.01, 0.001] {
+            let bits_per_key = Bloom::bloom_bits_per_key(KEYS, false_positive_rate);
+            let buf = Bloom::build_from_key_hashes(&hashes, bits_per_key);
+            let f = Bloom::new(&buf);
+            assert!(hashes.iter().all(|h| f.may_contain(*h)));
+            let false_positives = absent_keys
+                .iter()
+                .filter(|key| f.may_contain(farmhash::fingerprint32(&key[..])))
+                .count();
+            let rate = false_positives as f64 / KEYS as f64;
+            assert!(
+                rate <= false_positive_rate * 2.0,
+                "[target: {}] [bits per key: {}] [empirical: {}]",
+                false_positive_rate,
+                bits_per_key,
+                rate
+            );
+        }
+    }
 }
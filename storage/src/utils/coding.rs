@@ -1,7 +1,10 @@
 use std::io::Read;
+use std::ops::RangeInclusive;
 use std::{cmp, ptr};
 
-use bytes::{Buf, BufMut};
+use bytes::{Buf, BufMut, Bytes};
+#[cfg(feature = "varint-timestamp")]
+use runkv_common::coding::{decode_varint, encode_varint, varint_len};
 
 use crate::components::KeyComparator;
 
@@ -149,6 +152,15 @@ pub fn crc32check(data: &[u8], crc32sum: u32) -> bool {
 ///
 /// |<------- full key ------->|
 /// ```
+///
+/// With feature `varint-timestamp`, the timestamp is encoded as a varint followed by its length:
+///
+/// ```plain
+/// | user key | timestamp (varint) | timestamp len (1B) | value |
+///
+/// |<---------------------- full key ---------------------->|
+/// ```
+#[cfg(not(feature = "varint-timestamp"))]
 pub fn full_key(user_key: &[u8], timestamp: u64) -> Vec<u8> {
     let mut buf = Vec::with_capacity(user_key.len() + 8);
     buf.put_slice(user_key);
@@ -156,16 +168,56 @@ pub fn full_key(user_key: &[u8], timestamp: u64) -> Vec<u8> {
     buf
 }
 
+#[cfg(feature = "varint-timestamp")]
+pub fn full_key(user_key: &[u8], timestamp: u64) -> Vec<u8> {
+    let timestamp_len = varint_len(timestamp);
+    let mut buf = Vec::with_capacity(user_key.len() + timestamp_len + 1);
+    buf.put_slice(user_key);
+    encode_varint(&mut buf, timestamp);
+    buf.put_u8(timestamp_len as u8);
+    buf
+}
+
+/// Length of the encoded timestamp suffix in full key.
+#[cfg(not(feature = "varint-timestamp"))]
+#[inline(always)]
+fn timestamp_suffix_len(_full_key: &[u8]) -> usize {
+    8
+}
+
+#[cfg(feature = "varint-timestamp")]
+#[inline(always)]
+fn timestamp_suffix_len(full_key: &[u8]) -> usize {
+    full_key[full_key.len() - 1] as usize + 1
+}
+
 /// Get user key in full key.
 pub fn user_key(full_key: &[u8]) -> &[u8] {
-    &full_key[..full_key.len() - 8]
+    &full_key[..full_key.len() - timestamp_suffix_len(full_key)]
 }
 
 /// Get timestamp in full key.
+#[cfg(not(feature = "varint-timestamp"))]
 pub fn timestamp(full_key: &[u8]) -> u64 {
     !(&full_key[full_key.len() - 8..]).get_u64()
 }
 
+#[cfg(feature = "varint-timestamp")]
+pub fn timestamp(full_key: &[u8]) -> u64 {
+    let start = full_key.len() - timestamp_suffix_len(full_key);
+    decode_varint(&mut &full_key[start..full_key.len() - 1]).unwrap()
+}
+
+/// Get the range of full keys of all versions of `user_key`, from the newest to the oldest.
+///
+/// Full keys are ordered by timestamp descending, so the full key with timestamp `u64::MAX` comes
+/// first. Without feature `varint-timestamp`, the timestamp is stored bitwise-inverted, which makes
+/// `u64::MAX` the smallest suffix in bytewise order as well. With feature `varint-timestamp`, the
+/// range is only ordered by [`compare_full_key`].
+pub fn full_key_range(user_key: &[u8]) -> RangeInclusive<Bytes> {
+    Bytes::from(full_key(user_key, u64::MAX))..=Bytes::from(full_key(user_key, 0))
+}
+
 /// Calculate the difference between two keys.
 pub fn key_diff<'a, 'b>(base: &'a [u8], target: &'b [u8]) -> &'b [u8] {
     bytes_diff(base, target)
@@ -186,6 +238,12 @@ pub fn value(raw: &[u8]) -> Option<&[u8]> {
     }
 }
 
+/// Full keys are ordered by user key ascending, then by timestamp descending.
+///
+/// The timestamp suffix is the bitwise inverted timestamp in big endian, so comparing it bytewise
+/// orders timestamps descending. The user keys are compared separately, because a user key may be
+/// a prefix of another one.
+#[cfg(not(feature = "varint-timestamp"))]
 #[inline]
 pub fn compare_full_key(lhs: &[u8], rhs: &[u8]) -> std::cmp::Ordering {
     let lkey = &lhs[..lhs.len() - 8];
@@ -195,16 +253,32 @@ pub fn compare_full_key(lhs: &[u8], rhs: &[u8]) -> std::cmp::Ordering {
     lkey.cmp(rkey).then_with(|| lts.cmp(rts))
 }
 
+#[cfg(feature = "varint-timestamp")]
+#[inline]
+pub fn compare_full_key(lhs: &[u8], rhs: &[u8]) -> std::cmp::Ordering {
+    user_key(lhs)
+        .cmp(user_key(rhs))
+        .then_with(|| timestamp(rhs).cmp(&timestamp(lhs)))
+}
+
+/// [`FullKeyComparator`] orders full keys the same as [`compare_full_key`].
 #[derive(Clone)]
 pub struct FullKeyComparator;
 
+impl FullKeyComparator {
+    /// Compare full keys without a comparator instance, see [`compare_full_key`].
+    pub fn compare(lhs: &[u8], rhs: &[u8]) -> std::cmp::Ordering {
+        compare_full_key(lhs, rhs)
+    }
+}
+
 impl KeyComparator for FullKeyComparator {
     fn compare_key(&self, lhs: &[u8], rhs: &[u8]) -> std::cmp::Ordering {
-        compare_full_key(lhs, rhs)
+        Self::compare(lhs, rhs)
     }
 
     fn same_key(&self, lhs: &[u8], rhs: &[u8]) -> bool {
-        lhs.len() == rhs.len() && lhs[..lhs.len() - 8] == rhs[..rhs.len() - 8]
+        user_key(lhs) == user_key(rhs)
     }
 }
 
@@ -267,4 +341,81 @@ mod tests {
         );
         assert_eq!(value(&vec![0][..]), None);
     }
+
+    #[test]
+    fn test_full_key_enc_dec() {
+        let timestamps = [0, 1, 127, 128, u32::MAX as u64, u64::MAX - 1, u64::MAX];
+        for user_key in [&b""[..], b"k", b"k1"] {
+            for ts in timestamps {
+                let fk = full_key(user_key, ts);
+                assert_eq!(user_key, super::user_key(&fk));
+                assert_eq!(ts, timestamp(&fk));
+            }
+        }
+
+        // Ordered by user key ascending, then by timestamp descending.
+        let mut fks = vec![];
+        for user_key in [&b"k"[..], b"k1", b"k2"] {
+            for ts in timestamps.iter().rev() {
+                fks.push(full_key(user_key, *ts));
+            }
+        }
+        for (lhs, rhs) in fks.iter().zip(fks.iter().skip(1)) {
+            assert_eq!(std::cmp::Ordering::Less, compare_full_key(lhs, rhs));
+            assert_eq!(std::cmp::Ordering::Greater, compare_full_key(rhs, lhs));
+        }
+        assert!(FullKeyComparator.same_key(&full_key(b"k", 1), &full_key(b"k", u64::MAX)));
+        assert!(!FullKeyComparator.same_key(&full_key(b"k", 1), &full_key(b"k1", 1)));
+    }
+
+    #[test]
+    fn test_full_key_comparator() {
+        use std::cmp::Ordering::{Equal, Greater, Less};
+
+        let cases: &[(&[u8], u64, &[u8], u64, std::cmp::Ordering)] = &[
+            // Equal user keys, newer versions come first.
+            (b"k", 2, b"k", 1, Less),
+            (b"k", 1, b"k", 2, Greater),
+            (b"k", u64::MAX, b"k", 0, Less),
+            (b"k", 1, b"k", 1, Equal),
+            // User keys of unequal lengths are ordered by user key regardless of timestamps.
+            (b"k", 0, b"k1", u64::MAX, Less),
+            (b"k1", u64::MAX, b"k", 0, Greater),
+            (b"k", 0, b"k\0", u64::MAX, Less),
+            (b"", 0, b"k", u64::MAX, Less),
+            (b"k2", u64::MAX, b"k10", 0, Greater),
+        ];
+        for (lhs, lts, rhs, rts, ordering) in cases {
+            let (lhs, rhs) = (full_key(lhs, *lts), full_key(rhs, *rts));
+            assert_eq!(FullKeyComparator::compare(&lhs, &rhs), *ordering);
+            assert_eq!(FullKeyComparator.compare_key(&lhs, &rhs), *ordering);
+            assert_eq!(compare_full_key(&lhs, &rhs), *ordering);
+        }
+    }
+
+    #[test]
+    fn test_full_key_range() {
+        let range = full_key_range(b"k1");
+        assert_eq!(timestamp(range.start()), u64::MAX);
+        assert_eq!(timestamp(range.end()), 0);
+        for ts in [0, 1, 128, u64::MAX - 1, u64::MAX] {
+            let fk = full_key(b"k1", ts);
+            assert_ne!(
+                std::cmp::Ordering::Less,
+                compare_full_key(&fk, range.start())
+            );
+            assert_ne!(
+                std::cmp::Ordering::Greater,
+                compare_full_key(&fk, range.end())
+            );
+        }
+        for fk in [full_key(b"k", 0), full_key(b"k2", u64::MAX)] {
+            assert!(
+                compare_full_key(&fk, range.start()) == std::cmp::Ordering::Less
+                    || compare_full_key(&fk, range.end()) == std::cmp::Ordering::Greater
+            );
+        }
+        #[cfg(not(feature = "varint-timestamp"))]
+        assert!(range.start() < range.end());
+    }
 }
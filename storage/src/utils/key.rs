@@ -0,0 +1,85 @@
+/// Returns `true` if the inclusive key ranges `[a_start, a_end]` and `[b_start, b_end]` overlap,
+/// comparing keys lexicographically by bytes.
+pub fn key_range_overlap(a_start: &[u8], a_end: &[u8], b_start: &[u8], b_end: &[u8]) -> bool {
+    a_start <= b_end && b_start <= a_end
+}
+
+/// Returns `true` if `key` is in the inclusive key range `[range_start, range_end]`.
+pub fn key_range_contains(range_start: &[u8], range_end: &[u8], key: &[u8]) -> bool {
+    range_start <= key && key <= range_end
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_key_range_overlap() {
+        let cases: &[(&[u8], &[u8], &[u8], &[u8], bool)] = &[
+            // Disjoint.
+            (b"a", b"b", b"c", b"d", false),
+            // Adjacent, sharing an endpoint.
+            (b"a", b"b", b"b", b"c", true),
+            // Partially overlapping.
+            (b"a", b"c", b"b", b"d", true),
+            // Contained.
+            (b"a", b"d", b"b", b"c", true),
+            // Equal.
+            (b"a", b"b", b"a", b"b", true),
+            // Single keys.
+            (b"a", b"a", b"a", b"a", true),
+            (b"a", b"a", b"b", b"b", false),
+            // Prefixes are less than the keys they prefix.
+            (b"a", b"ab", b"abc", b"b", false),
+            (b"a", b"abc", b"ab", b"b", true),
+            // Empty keys are the least.
+            (b"", b"", b"", b"a", true),
+            (b"", b"", b"a", b"b", false),
+            (b"", b"a", b"a", b"b", true),
+            // Empty ranges overlap nothing.
+            (b"b", b"a", b"a", b"b", false),
+        ];
+        for (a_start, a_end, b_start, b_end, overlap) in cases {
+            assert_eq!(
+                key_range_overlap(a_start, a_end, b_start, b_end),
+                *overlap,
+                "{:?}",
+                (a_start, a_end, b_start, b_end)
+            );
+            // Symmetric.
+            assert_eq!(key_range_overlap(b_start, b_end, a_start, a_end), *overlap);
+        }
+    }
+
+    #[test]
+    fn test_key_range_contains() {
+        let cases: &[(&[u8], &[u8], &[u8], bool)] = &[
+            (b"a", b"c", b"b", true),
+            // Endpoints are included.
+            (b"a", b"c", b"a", true),
+            (b"a", b"c", b"c", true),
+            (b"a", b"c", b"d", false),
+            (b"b", b"c", b"a", false),
+            // Prefixes.
+            (b"ab", b"b", b"a", false),
+            (b"a", b"ab", b"aba", false),
+            (b"a", b"ab", b"aa", true),
+            // Empty keys.
+            (b"", b"", b"", true),
+            (b"", b"a", b"", true),
+            (b"a", b"b", b"", false),
+            // Empty range.
+            (b"b", b"a", b"b", false),
+        ];
+        for (range_start, range_end, key, contains) in cases {
+            assert_eq!(
+                key_range_contains(range_start, range_end, key),
+                *contains,
+                "{:?}",
+                (range_start, range_end, key)
+            );
+        }
+    }
+}
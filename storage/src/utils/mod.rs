@@ -2,3 +2,5 @@ mod coding;
 pub use coding::*;
 mod bloom;
 pub use bloom::*;
+mod key;
+pub use key::*;
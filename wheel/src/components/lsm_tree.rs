@@ -1,16 +1,23 @@
 use std::collections::{BTreeMap, VecDeque};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
+use itertools::Itertools;
 use parking_lot::RwLock;
 use runkv_common::config::LevelCompactionStrategy;
+use runkv_common::time::TimeSourceRef;
 use runkv_storage::components::{
-    CachePolicy, Memtable, SstableStoreRef, SKIPLIST_NODE_TOWER_MAX_HEIGHT,
+    max_covering_timestamp, BlockCacheStats, CachePolicy, Memtable, Sstable, SstableStoreRef,
+    SKIPLIST_NODE_TOWER_MAX_HEIGHT,
+};
+use runkv_storage::iterator::{
+    BoxedIterator, ConcatIterator, Iterator, MergeIterator, Seek, SstableIterator,
 };
-use runkv_storage::iterator::{Iterator, MergeIterator, Seek, SstableIterator, UserKeyIterator};
 use runkv_storage::manifest::VersionManager;
-use runkv_storage::utils::value;
+use runkv_storage::utils::{full_key, timestamp as raw_timestamp, user_key, value};
 use runkv_storage::Result;
+use serde::Serialize;
 use tracing::trace;
 
 #[derive(Clone)]
@@ -21,12 +28,16 @@ pub struct ObjectStoreLsmTreeOptions {
     pub write_buffer_capacity: usize,
     /// Local version manager.
     pub version_manager: VersionManager,
+    /// Clock used to track memtable age.
+    pub time_source: TimeSourceRef,
 }
 
 pub struct MemtableWithCtx {
     pub table: Memtable,
     /// `{ raft group -> index }`
     pub ctx: BTreeMap<u64, u64>,
+    /// Time of the first write, `None` if the memtable is empty.
+    pub first_write_at: Option<Instant>,
 }
 
 impl MemtableWithCtx {
@@ -34,6 +45,61 @@ impl MemtableWithCtx {
         Self {
             table: Memtable::new(capacity),
             ctx: BTreeMap::default(),
+            first_write_at: None,
+        }
+    }
+}
+
+/// Structural state of the LSM-Tree, used for diagnosis.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct LsmTreeState {
+    /// The current memtable followed by immutable memtables from the newest to the oldest.
+    pub memtables: Vec<MemtableState>,
+    /// Sstables of the latest version, ordered by level.
+    pub sstables: Vec<SstableState>,
+    pub block_cache: BlockCacheStats,
+}
+
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct MemtableState {
+    pub immutable: bool,
+    /// Count of all versions of all keys.
+    pub entry_count: usize,
+    pub mem_size: usize,
+    /// Smallest user key, `None` if the memtable is empty.
+    pub first_key: Option<Vec<u8>>,
+    /// Largest user key, `None` if the memtable is empty.
+    pub last_key: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct SstableState {
+    pub id: u64,
+    pub level: usize,
+    pub first_key: Vec<u8>,
+    pub last_key: Vec<u8>,
+    /// Count of all versions of all keys, `None` if the sstable is not scanned.
+    pub entry_count: Option<usize>,
+    /// Ratio of tombstones in all entries, `None` if the sstable is not scanned.
+    pub tombstone_ratio: Option<f64>,
+}
+
+impl MemtableState {
+    fn new(memtable: Memtable, immutable: bool) -> Self {
+        let entry_count = memtable.len();
+        let mem_size = memtable.mem_size();
+        let skiplist = memtable.unwrap();
+        let mut iter = skiplist.iter();
+        iter.seek_to_first();
+        let first_key = iter.valid().then(|| user_key(iter.key()).to_vec());
+        iter.seek_to_last();
+        let last_key = iter.valid().then(|| user_key(iter.key()).to_vec());
+        Self {
+            immutable,
+            entry_count,
+            mem_size,
+            first_key,
+            last_key,
         }
     }
 }
@@ -123,39 +189,45 @@ impl ObjectStoreLsmTreeCore {
                 .level_compaction_strategy(level_idx as u64)
                 .await?;
 
-            let mut iter = match compaction_strategy {
-                LevelCompactionStrategy::Overlap => {
-                    let mut iters: Vec<Box<dyn Iterator>> = Vec::with_capacity(level.len());
-                    for sst_id in level {
-                        let sst = self.sstable_store.sstable(sst_id).await?;
-                        let iter = Box::new(SstableIterator::new(
-                            self.sstable_store.clone(),
-                            sst,
-                            CachePolicy::Fill,
-                        ));
-                        iters.push(iter);
-                    }
-                    UserKeyIterator::new(Box::new(MergeIterator::new(iters)), timestamp)
-                }
-                LevelCompactionStrategy::NonOverlap => {
-                    assert_eq!(level.len(), 1);
-                    let sst = self.sstable_store.sstable(level[0]).await?;
-                    UserKeyIterator::new(
-                        Box::new(SstableIterator::new(
-                            self.sstable_store.clone(),
-                            sst,
-                            CachePolicy::Fill,
-                        )),
-                        timestamp,
-                    )
-                }
+            let mut ssts = Vec::with_capacity(level.len());
+            for sst_id in level {
+                ssts.push(self.sstable_store.sstable(sst_id).await?);
+            }
+            let covering_timestamp = max_covering_timestamp(
+                ssts.iter().flat_map(|sst| sst.range_tombstones()),
+                key,
+                timestamp,
+            );
+            let iters = ssts
+                .into_iter()
+                .map(|sst| {
+                    Box::new(SstableIterator::new(
+                        self.sstable_store.clone(),
+                        sst,
+                        CachePolicy::Fill,
+                    )) as BoxedIterator
+                })
+                .collect_vec();
+            // Sstables picked from a non-overlap level are sorted, but there may be more than one
+            // if range tombstones of some of them cover the key.
+            let mut iter: BoxedIterator = match compaction_strategy {
+                LevelCompactionStrategy::Overlap => Box::new(MergeIterator::new(iters)),
+                LevelCompactionStrategy::NonOverlap => Box::new(ConcatIterator::new(iters)),
             };
-            if iter.seek(Seek::RandomForward(key)).await? {
-                if iter.is_valid() && iter.key() == key {
-                    return Ok(Some(Bytes::from(iter.value().to_vec())));
-                } else {
+
+            // Find the newest version not newer than `timestamp`.
+            iter.seek(Seek::RandomForward(&full_key(key, timestamp)))
+                .await?;
+            if iter.is_valid() && user_key(iter.key()) == &key[..] {
+                let deleted = covering_timestamp
+                    .map_or(false, |covering| raw_timestamp(iter.key()) <= covering);
+                if deleted {
                     return Ok(None);
                 }
+                return Ok(value(iter.value()).map(Bytes::copy_from_slice));
+            }
+            if covering_timestamp.is_some() {
+                return Ok(None);
             }
         }
         Ok(None)
@@ -188,19 +260,136 @@ impl ObjectStoreLsmTreeCore {
         //     approximate_size
         // );
 
+        self.write_memtable(approximate_size, group, index, |memtable| {
+            memtable.put(key, value, timestamp)
+        });
+        Ok(())
+    }
+
+    async fn delete_range(
+        &self,
+        start: &Bytes,
+        end: &Bytes,
+        timestamp: u64,
+        group: u64,
+        index: u64,
+    ) -> Result<()> {
+        // = point tombstone of start key + range tombstone
+        let approximate_size =
+            start.len() + 8 + 4 * SKIPLIST_NODE_TOWER_MAX_HEIGHT + 8 + start.len() + end.len() + 8;
+        self.write_memtable(approximate_size, group, index, |memtable| {
+            memtable.delete_range(start, end, timestamp)
+        });
+        Ok(())
+    }
+
+    fn write_memtable(
+        &self,
+        approximate_size: usize,
+        group: u64,
+        index: u64,
+        f: impl FnOnce(&Memtable),
+    ) {
+        let now = self.options.time_source.now();
         let mut guard = self.memtables.write();
         // Rotate memtable if needed.
         if guard.memtable.table.mem_remain() < approximate_size {
             trace!("rotate memtable");
-            let mut imm = MemtableWithCtx::new(self.options.write_buffer_capacity);
-            std::mem::swap(&mut imm, &mut guard.memtable);
-            guard.immutable_memtables.push_front(imm);
+            self.rotate_memtable(&mut guard);
         }
-        guard.memtable.table.put(key, value, timestamp);
+        f(&guard.memtable.table);
         *guard.memtable.ctx.entry(group).or_default() = index;
-        drop(guard);
+        guard.memtable.first_write_at.get_or_insert(now);
+    }
 
-        Ok(())
+    /// Rotate the current memtable if its first write is older than `max_age`. Returns `true` if
+    /// rotated.
+    fn rotate_memtable_if_older_than(&self, max_age: Duration) -> bool {
+        let now = self.options.time_source.now();
+        let mut guard = self.memtables.write();
+        match guard.memtable.first_write_at {
+            Some(first_write_at) if now.saturating_duration_since(first_write_at) >= max_age => {
+                trace!("rotate memtable by age");
+                self.rotate_memtable(&mut guard);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn rotate_memtable(&self, memtables: &mut Memtables) {
+        let mut imm = MemtableWithCtx::new(self.options.write_buffer_capacity);
+        std::mem::swap(&mut imm, &mut memtables.memtable);
+        memtables.immutable_memtables.push_front(imm);
+    }
+
+    async fn dump_state(&self, scan_sstables: bool) -> Result<LsmTreeState> {
+        // Clone the memtable handles out to release the lock, as `get` does.
+        let memtables = {
+            let guard = self.memtables.read();
+            let mut memtables = Vec::with_capacity(guard.immutable_memtables.len() + 1);
+            memtables.push((guard.memtable.table.clone(), false));
+            memtables.extend(
+                guard
+                    .immutable_memtables
+                    .iter()
+                    .map(|memtable| (memtable.table.clone(), true)),
+            );
+            drop(guard);
+            memtables
+        };
+        let memtables = memtables
+            .into_iter()
+            .map(|(memtable, immutable)| MemtableState::new(memtable, immutable))
+            .collect_vec();
+
+        let mut sstables = vec![];
+        for (level, sst_ids) in self.version_manager.ssts().await.into_iter().enumerate() {
+            for sst_id in sst_ids {
+                let sst = self.sstable_store.sstable(sst_id).await?;
+                let first_key = user_key(sst.first_key()).to_vec();
+                let last_key = user_key(sst.last_key()).to_vec();
+                let (entry_count, tombstone_ratio) = if scan_sstables {
+                    let (entry_count, tombstone_count) = self.scan_sstable(sst).await?;
+                    (
+                        Some(entry_count),
+                        Some(tombstone_count as f64 / entry_count as f64),
+                    )
+                } else {
+                    (None, None)
+                };
+                sstables.push(SstableState {
+                    id: sst_id,
+                    level,
+                    first_key,
+                    last_key,
+                    entry_count,
+                    tombstone_ratio,
+                });
+            }
+        }
+
+        Ok(LsmTreeState {
+            memtables,
+            sstables,
+            block_cache: self.sstable_store.block_cache_stats(),
+        })
+    }
+
+    /// Read through `sst`, returns the count of entries and tombstones.
+    async fn scan_sstable(&self, sst: Sstable) -> Result<(usize, usize)> {
+        let mut iter = SstableIterator::new(self.sstable_store.clone(), sst, CachePolicy::NotFill);
+        iter.seek(Seek::First).await?;
+        let mut entry_count = 0;
+        let mut tombstone_count = 0;
+        while iter.is_valid() {
+            entry_count += 1;
+            if value(iter.value()).is_none() {
+                tombstone_count += 1;
+            }
+            iter.next().await?;
+        }
+        Ok((entry_count, tombstone_count))
     }
 
     fn get_oldest_immutable_memtable(&self) -> Option<Memtable> {
@@ -268,6 +457,27 @@ impl ObjectStoreLsmTree {
         self.inner.write(key, None, timestamp, group, index).await
     }
 
+    /// Delete all versions of keys in `[start, end)` in LSM-Tree by range tombstone with given
+    /// `timestamp`.
+    ///
+    /// # Safety
+    ///
+    /// The interface exposes `timestamp` to user for the compatibility with upper system. It's
+    /// caller's responsibility to ensure that the new timestamp is higher than the old one on the
+    /// same key. Otherwise there will be consistency problems.
+    pub async fn delete_range(
+        &self,
+        start: &Bytes,
+        end: &Bytes,
+        timestamp: u64,
+        group: u64,
+        index: u64,
+    ) -> Result<()> {
+        self.inner
+            .delete_range(start, end, timestamp, group, index)
+            .await
+    }
+
     /// Get the value of the given `key` in LSM-Tree with given `timestamp`.
     ///
     /// # Safety
@@ -279,6 +489,20 @@ impl ObjectStoreLsmTree {
         self.inner.get(key, timestamp).await
     }
 
+    /// Rotate the current memtable to immutable if its first write is older than `max_age`, even
+    /// if it is not full. Returns `true` if rotated.
+    pub fn rotate_memtable_if_older_than(&self, max_age: Duration) -> bool {
+        self.inner.rotate_memtable_if_older_than(max_age)
+    }
+
+    /// Dump the structural state of memtables, sstables and caches for diagnosis.
+    ///
+    /// Only sstable metas are fetched, unless `scan_sstables` is set, in which case all sstables of
+    /// the latest version are read through to count entries and tombstones, which is expensive.
+    pub async fn dump_state(&self, scan_sstables: bool) -> Result<LsmTreeState> {
+        self.inner.dump_state(scan_sstables).await
+    }
+
     pub fn get_oldest_immutable_memtable(&self) -> Option<Memtable> {
         self.inner.get_oldest_immutable_memtable()
     }
@@ -294,21 +518,29 @@ pub mod tests {
 
     use runkv_common::coding::CompressionAlgorithm;
     use runkv_common::config::LevelOptions;
-    use runkv_storage::components::{BlockCache, SstableStore, SstableStoreOptions};
+    use runkv_common::time::SystemTimeSource;
+    use runkv_storage::components::{
+        BlockCache, SstableBuilder, SstableBuilderOptions, SstableStore, SstableStoreOptions,
+    };
     use runkv_storage::manifest::VersionManagerOptions;
     use runkv_storage::MemObjectStore;
+    use test_log::test;
 
     use super::*;
 
     pub fn build_test_lsm_tree() -> ObjectStoreLsmTree {
+        build_test_lsm_tree_with_time_source(Arc::new(SystemTimeSource))
+    }
+
+    pub fn build_test_lsm_tree_with_time_source(time_source: TimeSourceRef) -> ObjectStoreLsmTree {
         let object_store = Arc::new(MemObjectStore::default());
         let block_cache = BlockCache::new(16 << 10);
-        let sstable_store = Arc::new(SstableStore::new(SstableStoreOptions {
-            path: "path".to_string(),
+        let sstable_store = Arc::new(SstableStore::new(SstableStoreOptions::new(
+            "path".to_string(),
             object_store,
             block_cache,
-            meta_cache_capacity: 4 << 10,
-        }));
+            4 << 10,
+        )));
         let version_manager = VersionManager::new(VersionManagerOptions {
             levels_options: vec![
                 LevelOptions {
@@ -327,6 +559,119 @@ pub mod tests {
             sstable_store,
             write_buffer_capacity: 4 << 10,
             version_manager,
+            time_source,
         })
     }
+
+    #[test(tokio::test)]
+    async fn test_dump_state() {
+        let object_store = Arc::new(MemObjectStore::default());
+        let block_cache = BlockCache::new(16 << 10);
+        let sstable_store = Arc::new(SstableStore::new(SstableStoreOptions::new(
+            "path".to_string(),
+            object_store,
+            block_cache,
+            4 << 10,
+        )));
+        let mut builder = SstableBuilder::new(SstableBuilderOptions {
+            capacity: 1024,
+            block_capacity: 32,
+            restart_interval: 2,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            ..Default::default()
+        });
+        builder.add(b"k01", 1, Some(b"v01")).unwrap();
+        builder.add_tombstone(b"k02", 2).unwrap();
+        builder.add(b"k03", 3, Some(b"v03")).unwrap();
+        builder.add_tombstone(b"k04", 4).unwrap();
+        let (meta, data) = builder.build().unwrap();
+        let blocks = meta.block_metas.len() as u64;
+        let sst = Sstable::new(1, Arc::new(meta));
+        sstable_store
+            .put(&sst, data, CachePolicy::Disable)
+            .await
+            .unwrap();
+        let version_manager = VersionManager::new(VersionManagerOptions {
+            levels_options: vec![
+                LevelOptions {
+                    compaction_strategy: LevelCompactionStrategy::Overlap,
+                    compression_algorithm: CompressionAlgorithm::None,
+                },
+                LevelOptions {
+                    compaction_strategy: LevelCompactionStrategy::NonOverlap,
+                    compression_algorithm: CompressionAlgorithm::None,
+                },
+            ],
+            levels: vec![vec![], vec![1]],
+            sstable_store: sstable_store.clone(),
+        });
+        let lsm_tree = ObjectStoreLsmTree::new(ObjectStoreLsmTreeOptions {
+            sstable_store,
+            write_buffer_capacity: 4 << 10,
+            version_manager,
+            time_source: Arc::new(SystemTimeSource),
+        });
+
+        lsm_tree
+            .put(&Bytes::from("k11"), &Bytes::from("v11"), 11, 1, 1)
+            .await
+            .unwrap();
+        lsm_tree
+            .put(&Bytes::from("k12"), &Bytes::from("v12"), 12, 1, 2)
+            .await
+            .unwrap();
+        assert!(lsm_tree.rotate_memtable_if_older_than(Duration::ZERO));
+        lsm_tree
+            .delete(&Bytes::from("k13"), 13, 1, 3)
+            .await
+            .unwrap();
+
+        // Only metas are fetched without scanning sstables.
+        let state = lsm_tree.dump_state(false).await.unwrap();
+        assert_eq!(
+            vec![SstableState {
+                id: 1,
+                level: 1,
+                first_key: b"k01".to_vec(),
+                last_key: b"k04".to_vec(),
+                entry_count: None,
+                tombstone_ratio: None,
+            }],
+            state.sstables
+        );
+        assert_eq!(BlockCacheStats { hits: 0, misses: 0 }, state.block_cache);
+
+        let state = lsm_tree.dump_state(true).await.unwrap();
+
+        assert_eq!(2, state.memtables.len());
+        assert!(!state.memtables[0].immutable);
+        assert_eq!(1, state.memtables[0].entry_count);
+        assert_eq!(Some(b"k13".to_vec()), state.memtables[0].first_key);
+        assert_eq!(Some(b"k13".to_vec()), state.memtables[0].last_key);
+        assert!(state.memtables[1].immutable);
+        assert_eq!(2, state.memtables[1].entry_count);
+        assert_eq!(Some(b"k11".to_vec()), state.memtables[1].first_key);
+        assert_eq!(Some(b"k12".to_vec()), state.memtables[1].last_key);
+
+        assert_eq!(
+            vec![SstableState {
+                id: 1,
+                level: 1,
+                first_key: b"k01".to_vec(),
+                last_key: b"k04".to_vec(),
+                entry_count: Some(4),
+                tombstone_ratio: Some(0.5),
+            }],
+            state.sstables
+        );
+
+        assert_eq!(
+            BlockCacheStats {
+                hits: 0,
+                misses: blocks,
+            },
+            state.block_cache
+        );
+    }
 }
@@ -4,7 +4,6 @@ use async_trait::async_trait;
 use bytes::{Buf, BufMut};
 use itertools::Itertools;
 use openraft::EffectiveMembership;
-use runkv_storage::raft_log_store::entry::RaftLogBatchBuilder;
 use runkv_storage::raft_log_store::RaftLogStore;
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tracing::trace;
@@ -190,20 +189,23 @@ impl<F: Fsm> openraft::RaftStorage<RaftTypeConfig> for RaftGroupLogStore<F> {
 
         trace!("append to log: {:?}", entries);
 
-        let mut builder = RaftLogBatchBuilder::default();
+        let mut builder = self.core.batch_builder();
         for entry in entries.iter() {
             let data = bincode::serialize(&entry.payload)
                 .map_err(Error::serde_err)
                 .map_err(err)?;
             let mut ctx = Vec::with_capacity(8);
             ctx.put_u64_le(entry.log_id.leader_id.node_id);
-            builder.add(
-                self.group,
-                entry.log_id.leader_id.term,
-                entry.log_id.index,
-                &ctx,
-                &data,
-            );
+            builder
+                .add(
+                    self.group,
+                    entry.log_id.leader_id.term,
+                    entry.log_id.index,
+                    &ctx,
+                    &data,
+                )
+                .map_err(Error::storage_err)
+                .map_err(err)?;
         }
         let batches = builder.build();
         for batch in batches {
@@ -736,11 +738,7 @@ mod tests {
             );
             trace!("create new raft log store in path: {:?}", path);
             tokio::fs::create_dir_all(path.clone()).await.unwrap();
-            let options = RaftLogStoreOptions {
-                log_dir_path: path.to_str().unwrap().to_string(),
-                log_file_capacity: 100,
-                block_cache_capacity: 1024,
-            };
+            let options = RaftLogStoreOptions::new(path.to_str().unwrap().to_string(), 100, 1024);
             let store = RaftLogStore::open(options).await.unwrap();
             store.add_group(1).await.unwrap();
             RaftGroupLogStore::new(1, store, MockFsm::default())
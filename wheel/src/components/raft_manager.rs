@@ -2,7 +2,7 @@ use std::collections::btree_map::BTreeMap;
 use std::collections::BTreeSet;
 use std::sync::Arc;
 
-use runkv_common::Worker;
+use runkv_common::WorkerHandle;
 use runkv_storage::raft_log_store::RaftLogStore;
 use tokio::sync::{mpsc, RwLock};
 
@@ -26,6 +26,8 @@ pub struct RaftManagerOptions {
 struct RaftManagerInner {
     /// `{ raft node id -> Raft }`.
     rafts: BTreeMap<u64, Raft<Gear>>,
+    /// `{ raft node id -> kv worker handle }`.
+    kv_workers: BTreeMap<u64, WorkerHandle>,
 }
 
 #[derive(Clone)]
@@ -46,6 +48,7 @@ impl RaftManager {
             raft_network: options.raft_network,
             inner: Arc::new(RwLock::new(RaftManagerInner {
                 rafts: BTreeMap::default(),
+                kv_workers: BTreeMap::default(),
             })),
         }
     }
@@ -98,7 +101,7 @@ impl RaftManager {
         };
         let available_index = raft_group_log_store.applied_index().await?.unwrap_or(0);
 
-        let mut kv_worker = KvWorker::new(KvWorkerOptions {
+        let kv_worker = KvWorker::new(KvWorkerOptions {
             group,
             raft_node,
             available_index,
@@ -109,10 +112,10 @@ impl RaftManager {
             rx,
         });
 
-        // TODO: Hold the handle for gracefully shutdown.
-        let _handle = tokio::spawn(async move { kv_worker.run().await });
+        let kv_worker_handle = WorkerHandle::spawn(Box::new(kv_worker));
 
         inner.rafts.insert(raft_node, raft);
+        inner.kv_workers.insert(raft_node, kv_worker_handle);
 
         Ok(())
     }
@@ -242,11 +245,7 @@ mod tests {
             port: addr.port(),
         };
         channel_pool.put_node(node).await;
-        let raft_log_store_options = RaftLogStoreOptions {
-            log_dir_path: path.to_string(),
-            log_file_capacity: 128,
-            block_cache_capacity: 1024,
-        };
+        let raft_log_store_options = RaftLogStoreOptions::new(path.to_string(), 128, 1024);
         let raft_log_store = RaftLogStore::open(raft_log_store_options).await.unwrap();
         let raft_network = RaftNetwork::new(channel_pool);
         let lsm_tree = build_test_lsm_tree();
@@ -1,6 +1,10 @@
+use std::time::Duration;
+
 use runkv_common::config::{CacheConfig, LsmTreeConfig, MinioConfig, Node, S3Config};
 use serde::Deserialize;
 
+use crate::error::{Error, Result};
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct WheelConfig {
     pub id: u64,
@@ -10,6 +14,7 @@ pub struct WheelConfig {
     pub meta_path: String,
     pub poll_interval: String,
     pub heartbeat_interval: String,
+    pub channel_health_check_interval: String,
     pub rudder: Node,
     pub s3: Option<S3Config>,
     pub minio: Option<MinioConfig>,
@@ -22,6 +27,26 @@ pub struct WheelConfig {
 #[derive(Deserialize, Clone, Debug)]
 pub struct BufferConfig {
     pub write_buffer_capacity: String,
+    /// The write buffer is flushed once its oldest write is older than the interval, even if it
+    /// is not full.
+    pub write_buffer_flush_interval: String,
+}
+
+impl BufferConfig {
+    /// Parse `write_buffer_flush_interval`, which must be non-zero.
+    pub fn flush_interval(&self) -> Result<Duration> {
+        let interval: Duration = self
+            .write_buffer_flush_interval
+            .parse::<humantime::Duration>()
+            .map_err(Error::config_err)?
+            .into();
+        if interval.is_zero() {
+            return Err(Error::config_err(
+                "write buffer flush interval must be non-zero",
+            ));
+        }
+        Ok(interval)
+    }
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -29,4 +54,38 @@ pub struct RaftLogStoreConfig {
     pub log_dir_path: String,
     pub log_file_capacity: String,
     pub block_cache_capacity: String,
+    /// Raft log appends within the window share a single sync.
+    pub group_commit_window: String,
+    /// Count of tasks replaying raft groups concurrently on open. `0` or `1` replays serially.
+    pub replay_concurrency: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    fn buffer_config(write_buffer_flush_interval: &str) -> BufferConfig {
+        toml::from_str(&format!(
+            "write_buffer_capacity = \"64 MiB\"\nwrite_buffer_flush_interval = \"{}\"",
+            write_buffer_flush_interval
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_buffer_config_flush_interval() {
+        assert_eq!(
+            buffer_config("10 s").flush_interval().unwrap(),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            buffer_config("1m 500ms").flush_interval().unwrap(),
+            Duration::from_millis(60500)
+        );
+        assert!(buffer_config("0 s").flush_interval().is_err());
+        assert!(buffer_config("10").flush_interval().is_err());
+        assert!(buffer_config("forever").flush_interval().is_err());
+    }
 }
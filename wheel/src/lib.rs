@@ -5,6 +5,7 @@ pub mod meta;
 pub mod service;
 pub mod worker;
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use bytesize::ByteSize;
@@ -13,9 +14,12 @@ use components::raft_manager::{RaftManager, RaftManagerOptions};
 use error::{Error, Result};
 use meta::mem::MemoryMetaStore;
 use meta::MetaStoreRef;
-use runkv_common::channel_pool::ChannelPool;
+use runkv_common::channel_pool::{
+    ChannelPool, ChannelPoolHealthChecker, ChannelPoolHealthCheckerOptions,
+};
 use runkv_common::notify_pool::NotifyPool;
-use runkv_common::BoxedWorker;
+use runkv_common::time::{SystemTimeSource, TimeSourceRef};
+use runkv_common::{BoxedWorker, WorkerHandle};
 use runkv_proto::common::Endpoint as PbEndpoint;
 use runkv_proto::kv::kv_service_server::KvServiceServer;
 use runkv_proto::kv::TxnResponse;
@@ -30,6 +34,7 @@ use service::{Wheel, WheelOptions};
 use tonic::transport::Server;
 use tracing::info;
 use worker::heartbeater::{Heartbeater, HeartbeaterOptions};
+use worker::memtable_flusher::{MemtableFlusher, MemtableFlusherOptions};
 use worker::sstable_uploader::{SstableUploader, SstableUploaderOptions};
 
 use crate::components::lsm_tree::{ObjectStoreLsmTree, ObjectStoreLsmTreeOptions};
@@ -42,9 +47,11 @@ pub async fn bootstrap_wheel(
 ) -> Result<()> {
     let addr_str = format!("{}:{}", config.host, config.port);
 
-    for mut worker in workers.into_iter() {
-        tokio::spawn(async move { worker.run().await });
-    }
+    // Workers are shut down when the handles are dropped.
+    let _worker_handles = workers
+        .into_iter()
+        .map(WorkerHandle::spawn)
+        .collect::<Vec<_>>();
 
     Server::builder()
         .add_service(WheelServiceServer::new(wheel.clone()))
@@ -66,14 +73,27 @@ pub async fn build_wheel_with_object_store(
     config: &WheelConfig,
     object_store: ObjectStoreRef,
 ) -> Result<(Wheel, ObjectStoreLsmTree, Vec<BoxedWorker>)> {
+    config.lsm_tree.validate().map_err(Error::config_err)?;
+
     let sstable_store = build_sstable_store(config, object_store)?;
 
     let version_manager = build_version_manager(config, sstable_store.clone())?;
 
-    let lsm_tree = build_lsm_tree(config, sstable_store.clone(), version_manager.clone())?;
+    let time_source: TimeSourceRef = Arc::new(SystemTimeSource);
+
+    let lsm_tree = build_lsm_tree(
+        config,
+        sstable_store.clone(),
+        version_manager.clone(),
+        time_source,
+    )?;
+
+    let memtable_flusher = build_memtable_flusher(config, lsm_tree.clone())?;
 
     let channel_pool = build_channel_pool(config);
 
+    let channel_health_checker = build_channel_health_checker(config, channel_pool.clone())?;
+
     let sstable_uploader = build_sstable_uploader(
         config,
         lsm_tree.clone(),
@@ -117,7 +137,12 @@ pub async fn build_wheel_with_object_store(
     Ok((
         wheel,
         lsm_tree,
-        vec![Box::new(sstable_uploader), Box::new(version_syncer)],
+        vec![
+            Box::new(sstable_uploader),
+            Box::new(memtable_flusher),
+            Box::new(version_syncer),
+            Box::new(channel_health_checker),
+        ],
     ))
 }
 
@@ -146,17 +171,17 @@ fn build_sstable_store(
             .map_err(Error::config_err)?
             .0 as usize,
     );
-    let sstable_store_options = SstableStoreOptions {
-        path: config.data_path.clone(),
+    let sstable_store_options = SstableStoreOptions::new(
+        config.data_path.clone(),
         object_store,
         block_cache,
-        meta_cache_capacity: config
+        config
             .cache
             .meta_cache_capacity
             .parse::<ByteSize>()
             .map_err(Error::config_err)?
             .0 as usize,
-    };
+    );
     let sstable_store = SstableStore::new(sstable_store_options);
     Ok(Arc::new(sstable_store))
 }
@@ -178,6 +203,7 @@ fn build_lsm_tree(
     config: &WheelConfig,
     sstable_store: SstableStoreRef,
     version_manager: VersionManager,
+    time_source: TimeSourceRef,
 ) -> Result<ObjectStoreLsmTree> {
     let lsm_tree_options = ObjectStoreLsmTreeOptions {
         sstable_store,
@@ -188,10 +214,27 @@ fn build_lsm_tree(
             .map_err(Error::config_err)?
             .0 as usize,
         version_manager,
+        time_source,
     };
     Ok(ObjectStoreLsmTree::new(lsm_tree_options))
 }
 
+fn build_memtable_flusher(
+    config: &WheelConfig,
+    lsm_tree: ObjectStoreLsmTree,
+) -> Result<MemtableFlusher> {
+    let memtable_flusher_options = MemtableFlusherOptions {
+        lsm_tree,
+        max_age: config.buffer.flush_interval()?,
+        poll_interval: config
+            .poll_interval
+            .parse::<humantime::Duration>()
+            .map_err(Error::config_err)?
+            .into(),
+    };
+    Ok(MemtableFlusher::new(memtable_flusher_options))
+}
+
 fn build_sstable_uploader(
     config: &WheelConfig,
     lsm_tree: ObjectStoreLsmTree,
@@ -269,6 +312,21 @@ fn build_channel_pool(config: &WheelConfig) -> ChannelPool {
     ChannelPool::with_nodes(vec![config.rudder.clone()])
 }
 
+fn build_channel_health_checker(
+    config: &WheelConfig,
+    channel_pool: ChannelPool,
+) -> Result<ChannelPoolHealthChecker> {
+    let options = ChannelPoolHealthCheckerOptions {
+        channel_pool,
+        interval: config
+            .channel_health_check_interval
+            .parse::<humantime::Duration>()
+            .map_err(Error::config_err)?
+            .into(),
+    };
+    Ok(ChannelPoolHealthChecker::new(options))
+}
+
 async fn build_raft_log_store(config: &WheelConfig) -> Result<RaftLogStore> {
     let raft_log_store_options = RaftLogStoreOptions {
         log_dir_path: config.raft_log_store.log_dir_path.clone(),
@@ -284,10 +342,20 @@ async fn build_raft_log_store(config: &WheelConfig) -> Result<RaftLogStore> {
             .parse::<ByteSize>()
             .map_err(Error::config_err)?
             .0 as usize,
+        group_commit_window: config
+            .raft_log_store
+            .group_commit_window
+            .parse::<humantime::Duration>()
+            .map_err(Error::config_err)?
+            .into(),
+        group_write_limits: BTreeMap::default(),
+        replay_concurrency: config.raft_log_store.replay_concurrency,
     };
-    RaftLogStore::open(raft_log_store_options)
+    let (raft_log_store, report) = RaftLogStore::open_with_report(raft_log_store_options)
         .await
-        .map_err(Error::storage_err)
+        .map_err(Error::storage_err)?;
+    info!("raft log store opened: {:?}", report);
+    Ok(raft_log_store)
 }
 
 fn build_raft_network(channel_pool: ChannelPool) -> RaftNetwork {
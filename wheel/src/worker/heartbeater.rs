@@ -2,7 +2,7 @@ use std::time::Duration;
 
 use async_trait::async_trait;
 use runkv_common::channel_pool::ChannelPool;
-use runkv_common::Worker;
+use runkv_common::LoopWorker;
 use runkv_proto::common::Endpoint;
 use runkv_proto::rudder::rudder_service_client::RudderServiceClient;
 use runkv_proto::rudder::{
@@ -35,15 +35,18 @@ pub struct Heartbeater {
 }
 
 #[async_trait]
-impl Worker for Heartbeater {
-    async fn run(&mut self) -> anyhow::Result<()> {
-        // TODO: Gracefully kill.
-        loop {
-            match self.run_inner().await {
-                Ok(_) => {}
-                Err(e) => warn!("error occur when uploader running: {}", e),
-            }
-        }
+impl LoopWorker for Heartbeater {
+    fn name(&self) -> &str {
+        "heartbeater"
+    }
+
+    async fn run_once(&mut self) -> anyhow::Result<()> {
+        self.run_inner().await?;
+        Ok(())
+    }
+
+    fn interval(&self) -> Duration {
+        self.options.heartbeat_interval
     }
 }
 
@@ -94,7 +97,6 @@ impl Heartbeater {
                 );
             }
         }
-        tokio::time::sleep(self.options.heartbeat_interval).await;
         Ok(())
     }
 }
@@ -4,7 +4,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
-use runkv_common::Worker;
+use runkv_common::{CancellationToken, Worker};
 use runkv_proto::kv::{BytesSerde, TxnRequest, TxnResponse};
 use tokio::sync::mpsc;
 use tracing::{trace, warn};
@@ -51,7 +51,7 @@ pub struct KvWorker {
 
 #[async_trait]
 impl Worker for KvWorker {
-    async fn run(&mut self) -> anyhow::Result<()> {
+    async fn run(&mut self, cancel: CancellationToken) -> anyhow::Result<()> {
         let mut applier = Applier {
             group: self.group,
             raft_node: self.raft_node,
@@ -62,12 +62,11 @@ impl Worker for KvWorker {
             done_index: self.done_index.clone(),
             snapshotting: self.snapshotting.clone(),
         };
-        // TODO: Gracefully kill.
-        let _handle = tokio::spawn(async move { applier.run().await });
+        let applier_cancel = cancel.clone();
+        let _handle = tokio::spawn(async move { applier.run(applier_cancel).await });
 
-        // TODO: Gracefully kill.
         loop {
-            match self.run_inner().await {
+            match self.run_inner(&cancel).await {
                 Ok(_) => return Ok(()),
                 Err(e) => warn!("error occur when uploader running: {}", e),
             }
@@ -90,8 +89,15 @@ impl KvWorker {
         }
     }
 
-    async fn run_inner(&mut self) -> Result<()> {
-        while let Some(cmd) = self.rx.recv().await {
+    async fn run_inner(&mut self, cancel: &CancellationToken) -> Result<()> {
+        loop {
+            let cmd = tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                cmd = self.rx.recv() => match cmd {
+                    Some(cmd) => cmd,
+                    None => return Ok(()),
+                },
+            };
             trace!(
                 group = self.group,
                 raft_node = self.raft_node,
@@ -125,7 +131,6 @@ impl KvWorker {
                 }
             }
         }
-        Ok(())
     }
 
     async fn handle_apply(&mut self, group: u64, range: Range<u64>) -> Result<()> {
@@ -174,18 +179,21 @@ struct Applier {
 }
 
 impl Applier {
-    async fn run(&mut self) -> Result<()> {
-        // TODO: Gracefully kill.
+    async fn run(&mut self, cancel: CancellationToken) -> Result<()> {
         loop {
-            match self.run_inner().await {
+            match self.run_inner(&cancel).await {
                 Ok(_) => return Ok(()),
                 Err(e) => warn!("error occur when uploader running: {}", e),
             }
         }
     }
 
-    async fn run_inner(&mut self) -> Result<()> {
+    async fn run_inner(&mut self, cancel: &CancellationToken) -> Result<()> {
         loop {
+            if cancel.is_cancelled() {
+                return Ok(());
+            }
+
             // Suspend for a while if:
             //   1. [`Applier`] is not serving raft group leader.
             //   2. [`KvWorker`] is building/installing snapshot.
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use runkv_common::LoopWorker;
+use tracing::trace;
+
+use crate::components::lsm_tree::ObjectStoreLsmTree;
+use crate::error::Result;
+
+pub struct MemtableFlusherOptions {
+    pub lsm_tree: ObjectStoreLsmTree,
+    /// Max age of the first write in the current memtable before it is flushed.
+    pub max_age: Duration,
+    pub poll_interval: Duration,
+}
+
+/// [`MemtableFlusher`] flushes the current memtable to immutable once it is older than the max
+/// age, even if it is not full. Then the immutable memtable will be uploaded by the sstable
+/// uploader.
+///
+/// It prevents writes of low-traffic groups from staying in memtable for a long time.
+pub struct MemtableFlusher {
+    options: MemtableFlusherOptions,
+    lsm_tree: ObjectStoreLsmTree,
+}
+
+#[async_trait]
+impl LoopWorker for MemtableFlusher {
+    fn name(&self) -> &str {
+        "memtable flusher"
+    }
+
+    async fn run_once(&mut self) -> anyhow::Result<()> {
+        self.run_inner().await?;
+        Ok(())
+    }
+
+    fn interval(&self) -> Duration {
+        self.options.poll_interval
+    }
+}
+
+impl MemtableFlusher {
+    pub fn new(options: MemtableFlusherOptions) -> Self {
+        Self {
+            lsm_tree: options.lsm_tree.clone(),
+            options,
+        }
+    }
+
+    async fn run_inner(&mut self) -> Result<()> {
+        if self
+            .lsm_tree
+            .rotate_memtable_if_older_than(self.options.max_age)
+        {
+            trace!("memtable flushed by age");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use runkv_common::time::MockTimeSource;
+    use runkv_common::WorkerHandle;
+    use test_log::test;
+
+    use super::*;
+    use crate::components::lsm_tree::tests::build_test_lsm_tree_with_time_source;
+
+    #[test(tokio::test)]
+    async fn test_flush_by_age() {
+        let time_source = Arc::new(MockTimeSource::default());
+        let lsm_tree = build_test_lsm_tree_with_time_source(time_source.clone());
+        let mut flusher = MemtableFlusher::new(MemtableFlusherOptions {
+            lsm_tree: lsm_tree.clone(),
+            max_age: Duration::from_secs(10),
+            poll_interval: Duration::from_millis(10),
+        });
+
+        // Empty memtable never flushes.
+        time_source.advance(Duration::from_secs(20));
+        flusher.run_inner().await.unwrap();
+        assert!(lsm_tree.get_oldest_immutable_memtable().is_none());
+
+        lsm_tree
+            .put(&Bytes::from("k1"), &Bytes::from("v1"), 1, 1, 1)
+            .await
+            .unwrap();
+        time_source.advance(Duration::from_secs(5));
+        flusher.run_inner().await.unwrap();
+        assert!(lsm_tree.get_oldest_immutable_memtable().is_none());
+
+        // Later writes don't refresh the age.
+        lsm_tree
+            .put(&Bytes::from("k2"), &Bytes::from("v2"), 2, 1, 2)
+            .await
+            .unwrap();
+        time_source.advance(Duration::from_secs(5));
+        flusher.run_inner().await.unwrap();
+        let imm = lsm_tree.get_oldest_immutable_memtable().unwrap();
+        assert_eq!(Some(Bytes::from("v1")), imm.get(&Bytes::from("k1"), 1));
+        assert_eq!(Some(Bytes::from("v2")), imm.get(&Bytes::from("k2"), 2));
+
+        // The new memtable is empty, nothing to flush.
+        time_source.advance(Duration::from_secs(20));
+        flusher.run_inner().await.unwrap();
+        lsm_tree.drop_oldest_immutable_memtable();
+        assert!(lsm_tree.get_oldest_immutable_memtable().is_none());
+    }
+
+    #[test(tokio::test)]
+    async fn test_flush_on_interval() {
+        let time_source = Arc::new(MockTimeSource::default());
+        let lsm_tree = build_test_lsm_tree_with_time_source(time_source.clone());
+        let handle = WorkerHandle::spawn(Box::new(MemtableFlusher::new(MemtableFlusherOptions {
+            lsm_tree: lsm_tree.clone(),
+            max_age: Duration::from_secs(10),
+            poll_interval: Duration::from_millis(1),
+        })));
+
+        lsm_tree
+            .put(&Bytes::from("k1"), &Bytes::from("v1"), 1, 1, 1)
+            .await
+            .unwrap();
+        // The memtable age is measured by the mock time source, which only moves when advanced,
+        // so the memtable is kept however long the worker polls in real time.
+        time_source.advance(Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(lsm_tree.get_oldest_immutable_memtable().is_none());
+
+        time_source.advance(Duration::from_secs(5));
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while lsm_tree.get_oldest_immutable_memtable().is_none() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let imm = lsm_tree.get_oldest_immutable_memtable().unwrap();
+        assert_eq!(Some(Bytes::from("v1")), imm.get(&Bytes::from("k1"), 1));
+
+        handle.shutdown().await.unwrap();
+    }
+}
@@ -1,3 +1,4 @@
 pub mod heartbeater;
 pub mod kv;
+pub mod memtable_flusher;
 pub mod sstable_uploader;
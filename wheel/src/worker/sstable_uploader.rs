@@ -5,12 +5,12 @@ use std::time::Duration;
 use async_trait::async_trait;
 use runkv_common::channel_pool::ChannelPool;
 use runkv_common::coding::CompressionAlgorithm;
-use runkv_common::Worker;
+use runkv_common::LoopWorker;
 use runkv_proto::manifest::SstableInfo;
 use runkv_proto::rudder::rudder_service_client::RudderServiceClient;
 use runkv_proto::rudder::InsertL0Request;
 use runkv_storage::components::{
-    CachePolicy, Sstable, SstableBuilder, SstableBuilderOptions, SstableStoreRef,
+    CachePolicy, Memtable, Sstable, SstableBuilder, SstableBuilderOptions, SstableStoreRef,
 };
 use runkv_storage::manifest::{ManifestError, VersionManager};
 use runkv_storage::utils::{timestamp, user_key, value};
@@ -47,17 +47,14 @@ pub struct SstableUploader {
 }
 
 #[async_trait]
-impl Worker for SstableUploader {
-    async fn run(&mut self) -> anyhow::Result<()> {
-        // TODO: Gracefully kill.
-        loop {
-            match self.run_inner().await {
-                Ok(_) => {}
-                Err(e) => {
-                    warn!("error occur when uploader running: {}", e);
-                }
-            }
-        }
+impl LoopWorker for SstableUploader {
+    fn name(&self) -> &str {
+        "sstable uploader"
+    }
+
+    async fn run_once(&mut self) -> anyhow::Result<()> {
+        self.run_inner().await?;
+        Ok(())
     }
 }
 
@@ -77,53 +74,8 @@ impl SstableUploader {
 
     async fn run_inner(&mut self) -> Result<()> {
         if let Some(memtable) = self.lsm_tree.get_oldest_immutable_memtable() {
-            let mut sst_infos =
-                Vec::with_capacity(memtable.mem_size() / self.options.sstable_capacity + 1);
             if !memtable.is_empty() {
-                let sstable_builder_options = SstableBuilderOptions {
-                    capacity: self.options.sstable_capacity,
-                    block_capacity: self.options.block_capacity,
-                    restart_interval: self.options.restart_interval,
-                    bloom_false_positive: self.options.bloom_false_positive,
-                    compression_algorithm: self.options.compression_algorithm,
-                };
-                let mut sstable_builder = None;
-                let skiplist = memtable.unwrap();
-                let mut iter = skiplist.iter();
-                iter.seek_to_first();
-                let mut sst_id = 0;
-                while iter.valid() {
-                    // TODO: Get a global unique sst id from rudder.
-                    // Rotate sstable builder if necessary.
-                    if sstable_builder.is_none() {
-                        sst_id = self.gen_sstable_id();
-                        sstable_builder =
-                            Some(SstableBuilder::new(sstable_builder_options.clone()));
-                        debug!("build and upload sst {}", sst_id);
-                    }
-                    if !sstable_builder.as_ref().unwrap().is_empty()
-                        && sstable_builder.as_ref().unwrap().approximate_len()
-                            >= self.options.sstable_capacity
-                    {
-                        let builder = sstable_builder.take().unwrap();
-                        let sst_info = self.build_and_upload_sst(sst_id, builder).await?;
-                        sst_infos.push(sst_info);
-                        continue;
-                    }
-
-                    // Fill sst.
-                    let builder = sstable_builder.as_mut().unwrap();
-                    let fk = iter.key();
-                    let uk = user_key(fk);
-                    let ts = timestamp(fk);
-                    let vraw = iter.value();
-                    builder.add(uk, ts, value(vraw))?;
-                    iter.next();
-                }
-                if let Some(builder) = sstable_builder.take() {
-                    let sst_info = self.build_and_upload_sst(sst_id, builder).await?;
-                    sst_infos.push(sst_info);
-                }
+                let sst_infos = self.build_and_upload_ssts(memtable).await?;
                 self.notify_update_version(sst_infos).await?;
             }
         } else {
@@ -132,6 +84,63 @@ impl SstableUploader {
         Ok(())
     }
 
+    /// Build sstables from the non-empty `memtable` and upload them. Range tombstones of the
+    /// memtable are put into the first sstable.
+    async fn build_and_upload_ssts(&self, memtable: Memtable) -> Result<Vec<SstableInfo>> {
+        let mut sst_infos =
+            Vec::with_capacity(memtable.mem_size() / self.options.sstable_capacity + 1);
+        let sstable_builder_options = SstableBuilderOptions {
+            capacity: self.options.sstable_capacity,
+            block_capacity: self.options.block_capacity,
+            restart_interval: self.options.restart_interval,
+            bloom_false_positive: self.options.bloom_false_positive,
+            compression_algorithm: self.options.compression_algorithm,
+            ..Default::default()
+        };
+        let mut range_tombstones = Some(memtable.range_tombstones());
+        let mut sstable_builder = None;
+        let skiplist = memtable.unwrap();
+        let mut iter = skiplist.iter();
+        iter.seek_to_first();
+        let mut sst_id = 0;
+        while iter.valid() {
+            // TODO: Get a global unique sst id from rudder.
+            // Rotate sstable builder if necessary.
+            if sstable_builder.is_none() {
+                sst_id = self.gen_sstable_id();
+                let mut builder = SstableBuilder::new(sstable_builder_options.clone());
+                for range_tombstone in range_tombstones.take().unwrap_or_default() {
+                    builder.add_range_tombstone(range_tombstone);
+                }
+                sstable_builder = Some(builder);
+                debug!("build and upload sst {}", sst_id);
+            }
+            if !sstable_builder.as_ref().unwrap().is_empty()
+                && sstable_builder.as_ref().unwrap().approximate_len()
+                    >= self.options.sstable_capacity
+            {
+                let builder = sstable_builder.take().unwrap();
+                let sst_info = self.build_and_upload_sst(sst_id, builder).await?;
+                sst_infos.push(sst_info);
+                continue;
+            }
+
+            // Fill sst.
+            let builder = sstable_builder.as_mut().unwrap();
+            let fk = iter.key();
+            let uk = user_key(fk);
+            let ts = timestamp(fk);
+            let vraw = iter.value();
+            builder.add(uk, ts, value(vraw))?;
+            iter.next();
+        }
+        if let Some(builder) = sstable_builder.take() {
+            let sst_info = self.build_and_upload_sst(sst_id, builder).await?;
+            sst_infos.push(sst_info);
+        }
+        Ok(sst_infos)
+    }
+
     async fn build_and_upload_sst(&self, id: u64, builder: SstableBuilder) -> Result<SstableInfo> {
         // TODO: Async upload.
         let (meta, data) = builder.build()?;
@@ -187,3 +196,122 @@ impl SstableUploader {
         (node_id << 32) | sequential_id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use runkv_common::config::{LevelCompactionStrategy, LevelOptions};
+    use runkv_common::time::SystemTimeSource;
+    use runkv_proto::manifest::{SstableDiff, SstableOp, VersionDiff};
+    use runkv_storage::components::{BlockCache, SstableStore, SstableStoreOptions};
+    use runkv_storage::manifest::VersionManagerOptions;
+    use runkv_storage::MemObjectStore;
+    use test_log::test;
+
+    use super::*;
+    use crate::components::lsm_tree::ObjectStoreLsmTreeOptions;
+
+    #[test(tokio::test)]
+    async fn test_flush_range_tombstones() {
+        let sstable_store = Arc::new(SstableStore::new(SstableStoreOptions::new(
+            "test".to_string(),
+            Arc::new(MemObjectStore::default()),
+            BlockCache::new(64 << 10),
+            4 << 10,
+        )));
+        let version_manager = VersionManager::new(VersionManagerOptions {
+            levels_options: vec![
+                LevelOptions {
+                    compaction_strategy: LevelCompactionStrategy::Overlap,
+                    compression_algorithm: CompressionAlgorithm::None,
+                },
+                LevelOptions {
+                    compaction_strategy: LevelCompactionStrategy::NonOverlap,
+                    compression_algorithm: CompressionAlgorithm::None,
+                },
+            ],
+            levels: vec![vec![]; 2],
+            sstable_store: sstable_store.clone(),
+        });
+        let lsm_tree = ObjectStoreLsmTree::new(ObjectStoreLsmTreeOptions {
+            sstable_store: sstable_store.clone(),
+            write_buffer_capacity: 64 << 10,
+            version_manager: version_manager.clone(),
+            time_source: Arc::new(SystemTimeSource),
+        });
+        let uploader = SstableUploader::new(SstableUploaderOptions {
+            node_id: 1,
+            lsm_tree: lsm_tree.clone(),
+            sstable_store,
+            version_manager: version_manager.clone(),
+            sstable_capacity: 64 << 10,
+            block_capacity: 1024,
+            restart_interval: 16,
+            bloom_false_positive: 0.1,
+            compression_algorithm: CompressionAlgorithm::None,
+            poll_interval: Duration::from_millis(10),
+            channel_pool: ChannelPool::default(),
+            rudder_node_id: 0,
+        });
+
+        // Flush keys to L1, then flush the range tombstone to L0.
+        for i in 1..=10 {
+            lsm_tree.put(&key(i), &value(i), i, 1, i).await.unwrap();
+        }
+        flush(&uploader, &lsm_tree, &version_manager, 1).await;
+        lsm_tree
+            .delete_range(&key(3), &key(6), 20, 1, 11)
+            .await
+            .unwrap();
+        lsm_tree.put(&key(4), &value(4), 30, 1, 12).await.unwrap();
+        flush(&uploader, &lsm_tree, &version_manager, 0).await;
+
+        for i in 1..=10 {
+            let expected = match i {
+                3 | 5 => None,
+                _ => Some(value(i)),
+            };
+            assert_eq!(lsm_tree.get(&key(i), 40).await.unwrap(), expected);
+        }
+        // Range tombstone is invisible to older reads.
+        assert_eq!(lsm_tree.get(&key(5), 10).await.unwrap(), Some(value(5)));
+    }
+
+    async fn flush(
+        uploader: &SstableUploader,
+        lsm_tree: &ObjectStoreLsmTree,
+        version_manager: &VersionManager,
+        level: u64,
+    ) {
+        assert!(lsm_tree.rotate_memtable_if_older_than(Duration::ZERO));
+        let memtable = lsm_tree.get_oldest_immutable_memtable().unwrap();
+        let sst_infos = uploader.build_and_upload_ssts(memtable).await.unwrap();
+        version_manager
+            .update(
+                VersionDiff {
+                    id: 0,
+                    sstable_diffs: sst_infos
+                        .into_iter()
+                        .map(|sst_info| SstableDiff {
+                            id: sst_info.id,
+                            level,
+                            op: SstableOp::Insert.into(),
+                            data_size: sst_info.data_size,
+                        })
+                        .collect(),
+                },
+                false,
+            )
+            .await
+            .unwrap();
+        lsm_tree.drop_oldest_immutable_memtable();
+    }
+
+    fn key(i: u64) -> Bytes {
+        Bytes::from(format!("k{:08}", i))
+    }
+
+    fn value(i: u64) -> Bytes {
+        Bytes::from(format!("v{:08}", i))
+    }
+}